@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use indexer_repo::events::LiveEvent;
+use indexer_repo::expiry::OfferExpiryModel;
+use sqlx::PgPool;
+use tokio::sync::broadcast;
+
+const OFFERS_PER_ITERATION: i64 = 1_000;
+
+#[derive(Clone)]
+pub struct ExpirySweeperContext {
+    pub pool: PgPool,
+    pub idle_after_loop: u64,
+    pub live_events: broadcast::Sender<LiveEvent>,
+}
+
+pub async fn run_expiry_sweeper(context: ExpirySweeperContext) -> Result<()> {
+    log::info!("Run offers expiry sweeper");
+    let model = OfferExpiryModel::new(context.pool);
+
+    loop {
+        let Some(position) = model.get_chain_position().await? else {
+            tokio::time::sleep(Duration::from_secs(context.idle_after_loop)).await;
+            continue;
+        };
+
+        let expired = match model.expire_offers(&position, OFFERS_PER_ITERATION).await {
+            Ok(expired) => expired,
+            Err(e) => {
+                log::error!("Error while expiring offers: {:#?}", e);
+                tokio::time::sleep(Duration::from_secs(context.idle_after_loop)).await;
+                continue;
+            }
+        };
+
+        if expired.total() > 0 {
+            log::info!(
+                "Expired offers by chain time {}: auctions {}, direct sells {}, direct buys {}",
+                position.time,
+                expired.auctions,
+                expired.direct_sells,
+                expired.direct_buys
+            );
+        }
+
+        for event in expired.events {
+            // Sending fails only when nobody is subscribed
+            let _ = context.live_events.send(event);
+        }
+
+        if expired.auctions < OFFERS_PER_ITERATION as usize
+            && expired.direct_sells < OFFERS_PER_ITERATION as usize
+            && expired.direct_buys < OFFERS_PER_ITERATION as usize
+        {
+            tokio::time::sleep(Duration::from_secs(context.idle_after_loop)).await;
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use indexer_repo::fiat::FiatRateModel;
+use serde::Deserialize;
+use sqlx::{types::chrono, PgPool};
+
+const DEFAULT_UPDATE_INTERVAL_SEC: u64 = 3600;
+
+/// Source of fiat exchange rates.
+#[async_trait]
+pub trait FiatRateProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Units of every currency per one usd, keyed by the currency code.
+    async fn request_rates(&self) -> Result<HashMap<String, BigDecimal>>;
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum FiatRateProviderKind {
+    File,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FiatRatesConfig {
+    pub kind: FiatRateProviderKind,
+    /// Json object of currency codes to rates, read by a `file` provider
+    pub path: Option<PathBuf>,
+    pub update_interval_sec: Option<u64>,
+}
+
+/// Rates read from a json file on every update, a stand-in for a rate api.
+pub struct FileRateProvider {
+    path: PathBuf,
+}
+
+impl FileRateProvider {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl FiatRateProvider for FileRateProvider {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn request_rates(&self) -> Result<HashMap<String, BigDecimal>> {
+        let content = tokio::fs::read_to_string(&self.path).await?;
+
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+fn provider_from_config(config: &FiatRatesConfig) -> Result<Arc<dyn FiatRateProvider>> {
+    match config.kind {
+        FiatRateProviderKind::File => {
+            let path = config
+                .path
+                .clone()
+                .ok_or_else(|| anyhow!("File fiat rate provider has no path"))?;
+
+            Ok(Arc::new(FileRateProvider::new(path)))
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FiatRateUpdaterContext {
+    pub pool: PgPool,
+    pub provider: Arc<dyn FiatRateProvider>,
+    pub update_interval_sec: u64,
+}
+
+impl FiatRateUpdaterContext {
+    pub fn from_config(pool: PgPool, config: &FiatRatesConfig) -> Result<Self> {
+        Ok(Self {
+            pool,
+            provider: provider_from_config(config)?,
+            update_interval_sec: config
+                .update_interval_sec
+                .unwrap_or(DEFAULT_UPDATE_INTERVAL_SEC),
+        })
+    }
+}
+
+/// Stores the provider rates once per update interval, usd values are
+/// converted with them when read.
+pub async fn run_fiat_rate_updater(context: FiatRateUpdaterContext) -> Result<()> {
+    log::info!("Run fiat rate updater");
+    let model = FiatRateModel::new(context.pool);
+
+    loop {
+        match context.provider.request_rates().await {
+            Ok(rates) => {
+                let rates = rates
+                    .into_iter()
+                    .map(|(currency, rate)| (currency.to_uppercase(), rate))
+                    .collect::<Vec<_>>();
+
+                let now = chrono::Utc::now().timestamp();
+                let hour = chrono::NaiveDateTime::from_timestamp_opt(now - now % 3600, 0)
+                    .unwrap_or_default();
+
+                if let Err(e) = model
+                    .save_rates(context.provider.name(), hour, &rates)
+                    .await
+                {
+                    log::error!("Error while saving fiat rates: {:#?}", e);
+                }
+            }
+            Err(e) => {
+                log::error!(
+                    "Error while requesting fiat rates from {}: {:#?}",
+                    context.provider.name(),
+                    e
+                );
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(context.update_interval_sec)).await;
+    }
+}
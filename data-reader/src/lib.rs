@@ -1,7 +1,31 @@
+mod expiry;
+mod fiat;
+mod jrpc_pool;
+mod media;
 mod meta;
+mod meta_diff;
+mod meta_schema;
+mod offchain;
 mod price;
+mod public_host;
+mod rarity;
 mod service;
+mod stats;
+mod tokens;
+mod webhooks;
 
+pub use expiry::*;
+pub use fiat::*;
+pub use jrpc_pool::*;
+pub use media::*;
 pub use meta::*;
+pub use meta_diff::*;
+pub use meta_schema::*;
+pub use offchain::*;
 pub use price::*;
+pub use public_host::*;
+pub use rarity::*;
 pub use service::*;
+pub use stats::*;
+pub use tokens::*;
+pub use webhooks::*;
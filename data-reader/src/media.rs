@@ -0,0 +1,385 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use image::{ImageFormat, ImageOutputFormat};
+use indexer_repo::media::{Media, MediaModel, MediaSource};
+use indexer_repo::types::MediaStatus;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{types::chrono, PgPool};
+
+use crate::offchain::{Fetched, OffchainResolver};
+
+const MEDIA_PER_ITERATION: i64 = 100;
+const DEFAULT_MAX_SIZE_BYTES: usize = 20 * 1024 * 1024;
+const DEFAULT_THUMBNAIL_SIZE: u32 = 256;
+const DEFAULT_IDLE_AFTER_LOOP_SEC: u64 = 60;
+/// Larger images are not decoded
+const MAX_DIMENSION: u32 = 8192;
+const MAX_ATTEMPTS: i32 = 8;
+const FIRST_RETRY_SEC: i64 = 300;
+const MAX_RETRY_SEC: i64 = 24 * 3600;
+
+/// Place the copies and thumbnails are written to.
+#[async_trait]
+pub trait MediaStorage: Send + Sync {
+    /// Public url of the stored file
+    async fn put(&self, name: &str, body: &[u8]) -> Result<String>;
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaStorageKind {
+    Local,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MediaConfig {
+    pub storage: MediaStorageKind,
+    /// Directory of a `local` storage
+    pub dir: PathBuf,
+    /// Url the directory is served at
+    pub public_url: String,
+    pub max_size_bytes: Option<usize>,
+    /// Longest side of a thumbnail in pixels
+    pub thumbnail_size: Option<u32>,
+    pub idle_after_loop_sec: Option<u64>,
+}
+
+/// Files written to a directory served at `public_url`.
+pub struct LocalStorage {
+    dir: PathBuf,
+    public_url: String,
+}
+
+impl LocalStorage {
+    pub fn new(dir: PathBuf, public_url: &str) -> Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            public_url: public_url.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl MediaStorage for LocalStorage {
+    async fn put(&self, name: &str, body: &[u8]) -> Result<String> {
+        let path = self.dir.join(name);
+
+        if !path.exists() {
+            let tmp = self.dir.join(format!(".{name}.tmp"));
+            std::fs::write(&tmp, body)?;
+            std::fs::rename(&tmp, &path)?;
+        }
+
+        Ok(format!("{}/{}", self.public_url, name))
+    }
+}
+
+fn storage_from_config(config: &MediaConfig) -> Result<Arc<dyn MediaStorage>> {
+    match config.storage {
+        MediaStorageKind::Local => Ok(Arc::new(LocalStorage::new(
+            config.dir.clone(),
+            &config.public_url,
+        )?)),
+    }
+}
+
+#[derive(Clone)]
+pub struct MediaProcessorContext {
+    pub pool: PgPool,
+    /// Requests media with the gateways and host checks of off-chain metadata
+    pub resolver: Arc<OffchainResolver>,
+    pub storage: Arc<dyn MediaStorage>,
+    pub max_size_bytes: usize,
+    pub thumbnail_size: u32,
+    pub idle_after_loop: u64,
+}
+
+impl MediaProcessorContext {
+    pub fn from_config(
+        pool: PgPool,
+        config: &MediaConfig,
+        resolver: Arc<OffchainResolver>,
+    ) -> Result<Self> {
+        Ok(Self {
+            pool,
+            resolver,
+            storage: storage_from_config(config)?,
+            max_size_bytes: config.max_size_bytes.unwrap_or(DEFAULT_MAX_SIZE_BYTES),
+            thumbnail_size: config.thumbnail_size.unwrap_or(DEFAULT_THUMBNAIL_SIZE),
+            idle_after_loop: config
+                .idle_after_loop_sec
+                .unwrap_or(DEFAULT_IDLE_AFTER_LOOP_SEC),
+        })
+    }
+}
+
+/// Copies nft previews, collection logos and wallpapers with a thumbnail
+/// each, so clients don't load them from the hosts in metadata.
+pub async fn run_media_processor(context: MediaProcessorContext) -> Result<()> {
+    log::info!("Run media processor");
+    let model = MediaModel::new(context.pool.clone());
+
+    loop {
+        let sources = match model.get_media_for_update(MEDIA_PER_ITERATION).await {
+            Ok(sources) => sources,
+            Err(e) => {
+                log::error!("Error while reading media for update: {:#?}", e);
+                tokio::time::sleep(Duration::from_secs(context.idle_after_loop)).await;
+                continue;
+            }
+        };
+
+        for source in &sources {
+            let media = process_media(&context, source).await;
+
+            if media.status == MediaStatus::Failed {
+                log::warn!(
+                    "Media {} of {}: {}",
+                    source.source,
+                    source.address,
+                    media.error.as_deref().unwrap_or_default()
+                );
+            }
+
+            if let Err(e) = model.save_media(&media).await {
+                log::error!("Media of {}: error while saving: {:#?}", source.address, e);
+
+                // Recorded as failed, so it's not picked again before its retry
+                let failed = unstored_media(
+                    source,
+                    MediaError::Failed(format!("Error while saving: {e:#}")),
+                );
+                if let Err(e) = model.save_media(&failed).await {
+                    log::error!(
+                        "Media of {}: error while saving the failure: {:#?}",
+                        source.address,
+                        e
+                    );
+                }
+            }
+        }
+
+        if sources.len() < MEDIA_PER_ITERATION as usize {
+            tokio::time::sleep(Duration::from_secs(context.idle_after_loop)).await;
+        }
+    }
+}
+
+/// Png, jpeg, gif and webp, some gateways serve them without a proper type
+fn is_image_content_type(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+
+    mime.is_empty() || mime.starts_with("image/") || mime == "application/octet-stream"
+}
+
+struct Processed {
+    mime_type: &'static str,
+    content_hash: String,
+    width: u32,
+    height: u32,
+    extension: &'static str,
+    thumbnail: Vec<u8>,
+}
+
+/// Checks the actual format of the body, as the content type can't be trusted,
+/// and renders the thumbnail.
+fn process_image(body: &[u8], thumbnail_size: u32) -> Result<Processed, MediaError> {
+    let format = image::guess_format(body)
+        .map_err(|_| MediaError::Unsupported("Unknown image format".into()))?;
+
+    let (mime_type, extension) = match format {
+        ImageFormat::Png => ("image/png", "png"),
+        ImageFormat::Jpeg => ("image/jpeg", "jpg"),
+        ImageFormat::Gif => ("image/gif", "gif"),
+        ImageFormat::WebP => ("image/webp", "webp"),
+        other => {
+            return Err(MediaError::Unsupported(format!(
+                "{other:?} is not supported"
+            )))
+        }
+    };
+
+    let (width, height) = image::io::Reader::with_format(Cursor::new(body), format)
+        .into_dimensions()
+        .map_err(|e| MediaError::Failed(e.to_string()))?;
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(MediaError::Unsupported(format!(
+            "{width}x{height} is larger than {MAX_DIMENSION}px"
+        )));
+    }
+
+    let image = image::load_from_memory_with_format(body, format)
+        .map_err(|e| MediaError::Failed(e.to_string()))?;
+
+    let mut thumbnail = Vec::new();
+    image
+        .thumbnail(thumbnail_size, thumbnail_size)
+        .write_to(&mut Cursor::new(&mut thumbnail), ImageOutputFormat::Png)
+        .map_err(|e| MediaError::Failed(e.to_string()))?;
+
+    Ok(Processed {
+        mime_type,
+        content_hash: hex::encode(Sha256::digest(body)),
+        width,
+        height,
+        extension,
+        thumbnail,
+    })
+}
+
+enum MediaError {
+    /// Worth a retry
+    Failed(String),
+    Unsupported(String),
+}
+
+impl From<anyhow::Error> for MediaError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Failed(format!("{e:#}"))
+    }
+}
+
+async fn store(context: &MediaProcessorContext, source: &MediaSource) -> Result<Media, MediaError> {
+    let body = match context
+        .resolver
+        .fetch(
+            &source.source,
+            context.max_size_bytes,
+            is_image_content_type,
+        )
+        .await?
+    {
+        Fetched::Body(body) => body,
+        Fetched::Rejected(reason) => return Err(MediaError::Unsupported(reason)),
+    };
+
+    let thumbnail_size = context.thumbnail_size;
+    let (processed, body) =
+        tokio::task::spawn_blocking(move || (process_image(&body, thumbnail_size), body))
+            .await
+            .map_err(|e| anyhow!(e))?;
+    let processed = processed?;
+
+    let url = context
+        .storage
+        .put(
+            &format!("{}.{}", processed.content_hash, processed.extension),
+            &body,
+        )
+        .await?;
+    let thumbnail_url = context
+        .storage
+        .put(
+            &format!("{}_{}.png", processed.content_hash, thumbnail_size),
+            &processed.thumbnail,
+        )
+        .await?;
+
+    Ok(Media {
+        address: source.address.clone(),
+        kind: source.kind,
+        source: source.source.clone(),
+        status: MediaStatus::Ready,
+        mime_type: Some(processed.mime_type.to_string()),
+        size_bytes: Some(body.len() as i64),
+        content_hash: Some(processed.content_hash),
+        width: Some(processed.width as i32),
+        height: Some(processed.height as i32),
+        url: Some(url),
+        thumbnail_url: Some(thumbnail_url),
+        error: None,
+        attempts: 0,
+        next_attempt_at: None,
+        updated: chrono::Utc::now().naive_utc(),
+    })
+}
+
+async fn process_media(context: &MediaProcessorContext, source: &MediaSource) -> Media {
+    match store(context, source).await {
+        Ok(media) => media,
+        Err(error) => unstored_media(source, error),
+    }
+}
+
+/// Media that could not be stored, failures are retried with a backoff
+fn unstored_media(source: &MediaSource, error: MediaError) -> Media {
+    let now = chrono::Utc::now().naive_utc();
+
+    let (status, error, attempts, next_attempt_at) = match error {
+        MediaError::Unsupported(error) => (MediaStatus::Unsupported, error, 0, None),
+        MediaError::Failed(error) => {
+            let attempt = source.attempts + 1;
+            let next_attempt_at = (attempt < MAX_ATTEMPTS)
+                .then(|| {
+                    chrono::NaiveDateTime::from_timestamp_opt(
+                        now.timestamp() + retry_after_sec(attempt),
+                        0,
+                    )
+                })
+                .flatten();
+
+            (MediaStatus::Failed, error, attempt, next_attempt_at)
+        }
+    };
+
+    Media {
+        address: source.address.clone(),
+        kind: source.kind,
+        source: source.source.clone(),
+        status,
+        mime_type: None,
+        size_bytes: None,
+        content_hash: None,
+        width: None,
+        height: None,
+        url: None,
+        thumbnail_url: None,
+        error: Some(error),
+        attempts,
+        next_attempt_at,
+        updated: now,
+    }
+}
+
+fn retry_after_sec(attempt: i32) -> i64 {
+    FIRST_RETRY_SEC
+        .saturating_mul(1 << (attempt - 1).clamp(0, 20))
+        .min(MAX_RETRY_SEC)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, RgbImage};
+
+    use super::*;
+
+    #[test]
+    fn processes_images_by_actual_format() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(600, 300))
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+
+        let Ok(processed) = process_image(&png, 128) else {
+            panic!("png is not processed");
+        };
+        assert_eq!(processed.mime_type, "image/png");
+        assert_eq!((processed.width, processed.height), (600, 300));
+
+        let thumbnail = image::load_from_memory(&processed.thumbnail).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (128, 64));
+
+        assert!(matches!(
+            process_image(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>", 128),
+            Err(MediaError::Unsupported(_))
+        ));
+    }
+}
@@ -1,88 +1,138 @@
-use std::{str::FromStr, time::Duration};
-
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::jrpc_pool::JrpcPool;
+use crate::meta_schema::normalize_nft_meta;
+use crate::offchain::OffchainResolver;
 use crate::service::MetadataJrpcService;
 use anyhow::{bail, Result};
+use futures::{Future, StreamExt};
 use indexer_repo::{
-    meta::{MetadataModelService, NftAddressData, NftMeta, NftMetaAttribute},
+    meta::{
+        MetaViolation, MetadataModelService, NftAddressData, NftMeta, NftMetaAttribute,
+        NormalizedNftMeta,
+    },
     types::NftCollectionMeta,
 };
 use serde_json::Value;
 use sqlx::{types::chrono, PgPool};
 use ton_block::MsgAddressInt;
-use transaction_consumer::JrpcClient;
 
 const NFT_PER_ITERATION: i64 = 1_000;
 const COLLECTION_PER_ITERATION: i64 = 100;
 
 #[derive(Clone)]
 pub struct MetaReaderContext {
-    pub jrpc_client: JrpcClient,
+    pub jrpc_pool: Arc<JrpcPool>,
+    /// Off-chain metadata is resolved only if configured
+    pub offchain: Option<Arc<OffchainResolver>>,
     pub pool: PgPool,
-    pub jrpc_req_latency_millis: u64,
+    /// Addresses read at once, requests are rate limited by the jrpc pool
+    pub concurrency: usize,
     pub idle_after_loop: u64,
+    pub metrics: Arc<MetaReaderMetrics>,
+}
+
+/// Counters of the metadata reader, shared with the api.
+#[derive(Default)]
+pub struct MetaReaderMetrics {
+    processed: AtomicU64,
+    failed: AtomicU64,
+    queue_depth: AtomicU64,
+    in_flight: AtomicU64,
+    last_batch: Mutex<Option<(usize, Duration)>>,
+}
+
+pub struct MetaReaderStats {
+    pub processed: u64,
+    pub failed: u64,
+    /// Addresses of the current batch not read yet
+    pub queue_depth: u64,
+    pub in_flight: u64,
+    /// Addresses read per second in the last finished batch
+    pub last_batch_per_sec: Option<f64>,
+}
+
+impl MetaReaderMetrics {
+    pub fn stats(&self) -> MetaReaderStats {
+        let last_batch = *self.last_batch.lock().unwrap_or_else(|e| e.into_inner());
+
+        MetaReaderStats {
+            processed: self.processed.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            last_batch_per_sec: last_batch
+                .map(|(size, elapsed)| size as f64 / elapsed.as_secs_f64().max(f64::EPSILON)),
+        }
+    }
 }
 
 pub async fn run_meta_reader(context: MetaReaderContext) -> Result<()> {
     log::info!("Run metadata reader");
-    let meta_jrpc_service = MetadataJrpcService::new(context.jrpc_client.clone());
+    let meta_jrpc_service =
+        MetadataJrpcService::new(context.jrpc_pool.clone(), context.offchain.clone());
     let meta_model_service = MetadataModelService::new(context.pool.clone());
 
     loop {
         let nft_addresses = meta_model_service
             .get_nfts_for_meta_update(NFT_PER_ITERATION)
             .await?;
+        let nft_count = nft_addresses.len();
 
-        for address_data in nft_addresses.iter() {
-            if let Err(e) =
-                update_nft_meta(address_data, &meta_model_service, &meta_jrpc_service).await
-            {
-                log::error!("{:#?}", e);
-
-                let Ok(mut tx) = meta_model_service.start_transaction().await else {
-                    log::error!("Cant start transaction for saving metadata");
-                    tokio::time::sleep(Duration::from_millis(context.jrpc_req_latency_millis)).await;
-                    continue;
-                };
-
-                if let Err(e) = tx.add_to_proceeded(&address_data.nft, Some(true)).await {
-                    log::error!("Collection address: {}, error while adding to meta_handled_addresses table: {:#?}",
-                        address_data.nft,
-                        e
-                    );
+        read_concurrently(&context, nft_addresses, |address_data| {
+            let meta_model_service = meta_model_service.clone();
+            let meta_jrpc_service = meta_jrpc_service.clone();
+
+            async move {
+                let result =
+                    update_nft_meta(&address_data, &meta_model_service, &meta_jrpc_service).await;
+
+                if let Err(e) = &result {
+                    log::error!("{:#?}", e);
+                    mark_failed(&address_data.nft, &meta_model_service).await;
                 }
-            }
 
-            tokio::time::sleep(Duration::from_millis(context.jrpc_req_latency_millis)).await;
-        }
+                result.is_ok()
+            }
+        })
+        .await;
 
         let collection_addresses = meta_model_service
             .get_collections_for_meta_update(COLLECTION_PER_ITERATION)
             .await?;
+        let collection_count = collection_addresses.len();
 
-        for address in collection_addresses.iter() {
-            if let Err(e) =
-                update_collections_meta(address, &meta_model_service, &meta_jrpc_service).await
-            {
-                log::error!("{:#?}", e);
-
-                let Ok(mut tx) = meta_model_service.start_transaction().await else {
-                    log::error!("Cant start transaction for saving metadata");
-                    tokio::time::sleep(Duration::from_millis(context.jrpc_req_latency_millis)).await;
-                    continue;
-                };
-
-                if let Err(e) = tx.add_to_proceeded(address, Some(true)).await {
-                    log::error!("Collection address: {}, error while adding to meta_handled_addresses table: {:#?}",
-                        address,
-                        e
-                    );
+        read_concurrently(&context, collection_addresses, |address| {
+            let meta_model_service = meta_model_service.clone();
+            let meta_jrpc_service = meta_jrpc_service.clone();
+
+            async move {
+                let result =
+                    update_collections_meta(&address, &meta_model_service, &meta_jrpc_service)
+                        .await;
+
+                if let Err(e) = &result {
+                    log::error!("{:#?}", e);
+                    mark_failed(&address, &meta_model_service).await;
                 }
-            }
 
-            tokio::time::sleep(Duration::from_millis(context.jrpc_req_latency_millis)).await;
-        }
+                result.is_ok()
+            }
+        })
+        .await;
+
+        let normalized_count = match normalize_stored_meta(&meta_model_service).await {
+            Ok(count) => count,
+            Err(e) => {
+                log::error!("Error while normalizing stored metadata: {:#?}", e);
+                0
+            }
+        };
 
-        if nft_addresses.is_empty() && collection_addresses.is_empty() {
+        if nft_count == 0 && collection_count == 0 && normalized_count == 0 {
             log::info!("Finished updating metadata work. Idling");
             tokio::time::sleep(Duration::from_secs(context.idle_after_loop)).await;
 
@@ -91,6 +141,68 @@ pub async fn run_meta_reader(context: MetaReaderContext) -> Result<()> {
     }
 }
 
+/// Reads a batch with at most `concurrency` addresses in flight, `read`
+/// tells whether an address succeeded.
+async fn read_concurrently<T, F, Fut>(context: &MetaReaderContext, batch: Vec<T>, read: F)
+where
+    F: Fn(T) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    if batch.is_empty() {
+        return;
+    }
+
+    let metrics = &context.metrics;
+    let batch_size = batch.len();
+    let started = Instant::now();
+    metrics
+        .queue_depth
+        .store(batch_size as u64, Ordering::Relaxed);
+
+    futures::stream::iter(batch)
+        .map(|item| {
+            metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+            metrics.in_flight.fetch_add(1, Ordering::Relaxed);
+            read(item)
+        })
+        .buffer_unordered(context.concurrency.max(1))
+        .for_each(|success| async move {
+            metrics.in_flight.fetch_sub(1, Ordering::Relaxed);
+            metrics.processed.fetch_add(1, Ordering::Relaxed);
+            if !success {
+                metrics.failed.fetch_add(1, Ordering::Relaxed);
+            }
+        })
+        .await;
+
+    *metrics.last_batch.lock().unwrap_or_else(|e| e.into_inner()) =
+        Some((batch_size, started.elapsed()));
+}
+
+async fn mark_failed(address: &str, meta_model_service: &MetadataModelService) {
+    let Ok(mut tx) = meta_model_service.start_transaction().await else {
+        log::error!("Cant start transaction for saving metadata");
+        return;
+    };
+
+    if let Err(e) = tx.add_to_proceeded(address, Some(true)).await {
+        log::error!(
+            "Address: {}, error while adding to meta_handled_addresses table: {:#?}",
+            address,
+            e
+        );
+        return;
+    }
+
+    if let Err(e) = tx.commit().await {
+        log::error!(
+            "Address: {}, error while commiting transaction: {:#?}",
+            address,
+            e
+        );
+    }
+}
+
 pub async fn update_collections_meta(
     address: &str,
     meta_model_service: &MetadataModelService,
@@ -225,27 +337,26 @@ pub async fn update_nft_meta(
     let attr = meta
         .get("attributes")
         .and_then(|d| d.as_array())
-        .and_then(|d| d.is_empty().then_some(d))
         .map(|d| {
             d.iter()
                 .map(|e| NftMetaAttribute::new(e, address_data))
                 .collect::<Vec<_>>()
-        });
-
-    if let Some(attr) = attr {
-        if let Err(e) = tx.update_nft_attributes(&attr).await {
-            bail!(
-                "Nft address: {}, error while updating attributes: {:#?}",
-                &address_data.nft,
-                e
-            );
-        }
+        })
+        .unwrap_or_default();
+
+    if let Err(e) = tx.update_nft_attributes(&address_data.nft, &attr).await {
+        bail!(
+            "Nft address: {}, error while updating attributes: {:#?}",
+            &address_data.nft,
+            e
+        );
     }
 
+    let now = chrono::Utc::now().naive_utc();
     let nft_meta = NftMeta {
         address: &address_data.nft,
         meta: &meta,
-        updated: chrono::Utc::now().naive_utc(),
+        updated: now,
     };
 
     if let Err(e) = tx.update_nft_meta(&nft_meta).await {
@@ -256,6 +367,17 @@ pub async fn update_nft_meta(
         );
     };
 
+    if let Err(e) = tx
+        .update_normalized_meta(&address_data.nft, &normalize_nft_meta(&meta), now)
+        .await
+    {
+        bail!(
+            "Nft address: {}, error while updating normalized meta: {:#?}",
+            &address_data.nft,
+            e
+        );
+    };
+
     if let Err(e) = tx.add_to_proceeded(&address_data.nft, None).await {
         bail!(
             "Nft address: {}, error while adding to meta_handled_addresses table: {:#?}",
@@ -275,6 +397,58 @@ pub async fn update_nft_meta(
     Ok(())
 }
 
+/// Normalizes metadata stored before validation was introduced, each nft
+/// on its own so one that fails doesn't hold back the others. Returns the
+/// number of nfts stored, either normalized or with the failure
+async fn normalize_stored_meta(meta_model_service: &MetadataModelService) -> Result<usize> {
+    let metas = meta_model_service
+        .get_meta_for_normalization(NFT_PER_ITERATION)
+        .await?;
+
+    let now = chrono::Utc::now().naive_utc();
+    let mut stored = 0;
+
+    for (nft, meta) in &metas {
+        let normalized = normalize_nft_meta(meta);
+
+        if let Err(e) = save_normalized_meta(meta_model_service, nft, &normalized, now).await {
+            log::error!("Nft address: {nft}, error while saving normalized meta: {e:#?}");
+
+            // Recorded with the error alone, so it's not picked again
+            let failed = NormalizedNftMeta {
+                meta_type: None,
+                preview_url: None,
+                preview_mimetype: None,
+                external_url: None,
+                files: Vec::new(),
+                violations: vec![MetaViolation {
+                    path: String::new(),
+                    message: format!("Normalized fields can't be stored: {e:#}"),
+                }],
+            };
+            if let Err(e) = save_normalized_meta(meta_model_service, nft, &failed, now).await {
+                log::error!("Nft address: {nft}, error while saving normalization failure: {e:#?}");
+                continue;
+            }
+        }
+
+        stored += 1;
+    }
+
+    Ok(stored)
+}
+
+async fn save_normalized_meta(
+    meta_model_service: &MetadataModelService,
+    nft: &str,
+    meta: &NormalizedNftMeta,
+    now: chrono::NaiveDateTime,
+) -> Result<()> {
+    let mut tx = meta_model_service.start_transaction().await?;
+    tx.update_normalized_meta(nft, meta, now).await?;
+    tx.commit().await
+}
+
 fn extract_name_from_meta(meta: &Value) -> Option<&str> {
     meta.get("name").and_then(|d| d.as_str())
 }
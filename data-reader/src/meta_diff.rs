@@ -0,0 +1,125 @@
+use std::collections::BTreeSet;
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetaChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MetaChange {
+    /// Json path of the value, e.g. `attributes[2].value`, empty for the whole json
+    pub path: String,
+    pub kind: MetaChangeKind,
+    pub from: Option<Value>,
+    pub to: Option<Value>,
+}
+
+/// Changes between two metadata jsons, down to the values that differ.
+/// Arrays are compared by index, so an inserted element shows up as changes
+/// of every element after it.
+pub fn diff_meta(from: &Value, to: &Value) -> Vec<MetaChange> {
+    let mut changes = Vec::new();
+    diff_value("", from, to, &mut changes);
+
+    changes
+}
+
+fn diff_value(path: &str, from: &Value, to: &Value, changes: &mut Vec<MetaChange>) {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            let keys = from.keys().chain(to.keys()).collect::<BTreeSet<_>>();
+
+            for key in keys {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+
+                diff_entry(path, from.get(key), to.get(key), changes);
+            }
+        }
+        (Value::Array(from), Value::Array(to)) => {
+            for i in 0..from.len().max(to.len()) {
+                diff_entry(format!("{path}[{i}]"), from.get(i), to.get(i), changes);
+            }
+        }
+        (from, to) if from != to => changes.push(MetaChange {
+            path: path.to_string(),
+            kind: MetaChangeKind::Changed,
+            from: Some(from.clone()),
+            to: Some(to.clone()),
+        }),
+        _ => {}
+    }
+}
+
+fn diff_entry(
+    path: String,
+    from: Option<&Value>,
+    to: Option<&Value>,
+    changes: &mut Vec<MetaChange>,
+) {
+    match (from, to) {
+        (Some(from), Some(to)) => diff_value(&path, from, to, changes),
+        (Some(from), None) => changes.push(MetaChange {
+            path,
+            kind: MetaChangeKind::Removed,
+            from: Some(from.clone()),
+            to: None,
+        }),
+        (None, Some(to)) => changes.push(MetaChange {
+            path,
+            kind: MetaChangeKind::Added,
+            from: None,
+            to: Some(to.clone()),
+        }),
+        (None, None) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn diffs_a_reveal() {
+        let hidden = json!({
+            "name": "Venom #1",
+            "preview": { "source": "ipfs://bafy/hidden.png", "mimetype": "image/png" },
+            "attributes": [{ "trait_type": "Revealed", "value": false }],
+        });
+        let revealed = json!({
+            "name": "Venom #1",
+            "preview": { "source": "ipfs://bafy/1.png", "mimetype": "image/png" },
+            "attributes": [
+                { "trait_type": "Revealed", "value": true },
+                { "trait_type": "Eyes", "value": "Blue" },
+            ],
+            "external_url": "https://venom.network",
+        });
+
+        let changes = diff_meta(&hidden, &revealed);
+        let changes = changes
+            .iter()
+            .map(|c| (c.path.as_str(), c.kind))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            changes,
+            vec![
+                ("attributes[0].value", MetaChangeKind::Changed),
+                ("attributes[1]", MetaChangeKind::Added),
+                ("external_url", MetaChangeKind::Added),
+                ("preview.source", MetaChangeKind::Changed),
+            ]
+        );
+        assert!(diff_meta(&revealed, &revealed).is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+use indexer_repo::meta::{MetaViolation, NftMetaFile, NormalizedNftMeta};
+use serde_json::{Map, Value};
+
+/// Checks a metadata json against tip-4.2 and extracts its fields:
+///
+/// * `type` and `name` are required strings
+/// * `description` and `external_url` are strings
+/// * `preview` is an object with a `source` and a `mimetype` string
+/// * `files` is an array of objects like `preview`
+/// * `attributes` is an array of objects with a `trait_type` string
+///
+/// Other fields are allowed.
+pub fn normalize_nft_meta(meta: &Value) -> NormalizedNftMeta {
+    let mut normalized = NormalizedNftMeta::default();
+
+    let Some(meta) = meta.as_object() else {
+        normalized
+            .violations
+            .push(violation("", "must be an object"));
+        return normalized;
+    };
+
+    let violations = &mut normalized.violations;
+
+    normalized.meta_type = required_string(meta, "type", "", violations);
+    required_string(meta, "name", "", violations);
+    optional_string(meta, "description", "", violations);
+    normalized.external_url = optional_string(meta, "external_url", "", violations);
+
+    match meta.get("preview") {
+        None | Some(Value::Null) => {}
+        Some(Value::Object(preview)) => {
+            normalized.preview_url = required_string(preview, "source", "preview", violations);
+            normalized.preview_mimetype =
+                required_mimetype(preview, "mimetype", "preview", violations);
+        }
+        Some(_) => violations.push(violation("preview", "must be an object")),
+    }
+
+    match meta.get("files") {
+        None | Some(Value::Null) => {}
+        Some(Value::Array(files)) => {
+            for (i, file) in files.iter().enumerate() {
+                let path = format!("files[{i}]");
+
+                let Some(file) = file.as_object() else {
+                    violations.push(violation(&path, "must be an object"));
+                    continue;
+                };
+
+                let source = required_string(file, "source", &path, violations);
+                let mimetype = required_mimetype(file, "mimetype", &path, violations);
+
+                if let Some(source) = source {
+                    normalized.files.push(NftMetaFile { source, mimetype });
+                }
+            }
+        }
+        Some(_) => violations.push(violation("files", "must be an array")),
+    }
+
+    match meta.get("attributes") {
+        None | Some(Value::Null) => {}
+        Some(Value::Array(attributes)) => {
+            for (i, attribute) in attributes.iter().enumerate() {
+                let path = format!("attributes[{i}]");
+
+                match attribute.as_object() {
+                    Some(attribute) => {
+                        required_string(attribute, "trait_type", &path, violations);
+                    }
+                    None => violations.push(violation(&path, "must be an object")),
+                }
+            }
+        }
+        Some(_) => violations.push(violation("attributes", "must be an array")),
+    }
+
+    normalized
+}
+
+fn violation(path: &str, message: &str) -> MetaViolation {
+    MetaViolation {
+        path: path.to_string(),
+        message: message.to_string(),
+    }
+}
+
+fn field_path(parent: &str, field: &str) -> String {
+    if parent.is_empty() {
+        field.to_string()
+    } else {
+        format!("{parent}.{field}")
+    }
+}
+
+fn optional_string(
+    object: &Map<String, Value>,
+    field: &str,
+    parent: &str,
+    violations: &mut Vec<MetaViolation>,
+) -> Option<String> {
+    match object.get(field) {
+        None | Some(Value::Null) => None,
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(_) => {
+            violations.push(violation(&field_path(parent, field), "must be a string"));
+            None
+        }
+    }
+}
+
+fn required_string(
+    object: &Map<String, Value>,
+    field: &str,
+    parent: &str,
+    violations: &mut Vec<MetaViolation>,
+) -> Option<String> {
+    let value = optional_string(object, field, parent, violations);
+
+    if matches!(object.get(field), None | Some(Value::Null)) {
+        violations.push(violation(&field_path(parent, field), "is required"));
+    }
+
+    value
+}
+
+/// Mime types are kept as they are even if malformed
+fn required_mimetype(
+    object: &Map<String, Value>,
+    field: &str,
+    parent: &str,
+    violations: &mut Vec<MetaViolation>,
+) -> Option<String> {
+    let mimetype = required_string(object, field, parent, violations);
+
+    if let Some(mimetype) = &mimetype {
+        let valid = mimetype
+            .split_once('/')
+            .filter(|(kind, subtype)| !kind.is_empty() && !subtype.is_empty())
+            .is_some();
+
+        if !valid {
+            violations.push(violation(
+                &field_path(parent, field),
+                "must be a mime type like image/png",
+            ));
+        }
+    }
+
+    mimetype
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn normalizes_valid_meta() {
+        let meta = json!({
+            "type": "Basic NFT",
+            "name": "Venom #1",
+            "description": "First",
+            "preview": { "source": "ipfs://bafy/1.png", "mimetype": "image/png" },
+            "files": [{ "source": "ipfs://bafy/1.mp4", "mimetype": "video/mp4" }],
+            "external_url": "https://venom.network",
+            "attributes": [{ "trait_type": "Eyes", "value": "Blue" }],
+        });
+
+        assert_eq!(
+            normalize_nft_meta(&meta),
+            NormalizedNftMeta {
+                meta_type: Some("Basic NFT".into()),
+                preview_url: Some("ipfs://bafy/1.png".into()),
+                preview_mimetype: Some("image/png".into()),
+                external_url: Some("https://venom.network".into()),
+                files: vec![NftMetaFile {
+                    source: "ipfs://bafy/1.mp4".into(),
+                    mimetype: Some("video/mp4".into()),
+                }],
+                violations: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn lists_violations() {
+        let meta = json!({
+            "name": 1,
+            "preview": { "source": "https://a/1.png", "mimetype": "png" },
+            "files": [{ "mimetype": "image/png" }, "https://a/2.png"],
+            "attributes": {},
+        });
+
+        let normalized = normalize_nft_meta(&meta);
+        let paths = normalized
+            .violations
+            .iter()
+            .map(|v| (v.path.as_str(), v.message.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            paths,
+            vec![
+                ("type", "is required"),
+                ("name", "must be a string"),
+                ("preview.mimetype", "must be a mime type like image/png"),
+                ("files[0].source", "is required"),
+                ("files[1]", "must be an object"),
+                ("attributes", "must be an array"),
+            ]
+        );
+        assert_eq!(normalized.preview_url.as_deref(), Some("https://a/1.png"));
+        assert!(normalized.files.is_empty());
+    }
+}
@@ -0,0 +1,358 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{redirect, Client, Url};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::public_host::{is_public_url, public_client_builder};
+
+const DEFAULT_IPFS_GATEWAY: &str = "https://ipfs.io/ipfs/";
+const DEFAULT_ARWEAVE_GATEWAY: &str = "https://arweave.net/";
+const DEFAULT_MAX_SIZE_BYTES: usize = 1024 * 1024;
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+const MAX_REDIRECTS: usize = 3;
+
+/// Fields of the on-chain json that may point to the off-chain document,
+/// in the order they are tried
+const POINTER_FIELDS: [&str; 2] = ["external_url", "preview"];
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct OffchainMetaConfig {
+    /// Uri schemes followed, any of `https`, `http`, `ipfs` and `ar`.
+    /// `https`, `ipfs` and `ar` by default
+    pub schemes: Option<Vec<String>>,
+    /// Prefix of the cid path, `https://ipfs.io/ipfs/` by default
+    pub ipfs_gateway: Option<String>,
+    /// Prefix of the transaction id, `https://arweave.net/` by default
+    pub arweave_gateway: Option<String>,
+    pub max_size_bytes: Option<usize>,
+    pub timeout_ms: Option<u64>,
+}
+
+/// Follows uris of the on-chain metadata json to the off-chain one and
+/// merges them:
+///
+/// * an on-chain json that is a bare uri is replaced by the off-chain document
+/// * otherwise `external_url`, `preview.source` and `files[].source` are tried
+///   in order, the first one that serves a json object is merged
+/// * on-chain values win, off-chain ones only fill keys that are missing
+///   on-chain or hold null, an empty string, array or object
+/// * values are not merged deeper than the top level
+///
+/// Responses must be json or plain text and fit the size limit, media
+/// pointers are skipped by their content type without reading the body.
+/// Hosts are requested only when every address they resolve to is public,
+/// see [`public_client_builder`].
+pub struct OffchainResolver {
+    client: Client,
+    schemes: Vec<String>,
+    ipfs_gateway: String,
+    arweave_gateway: String,
+    max_size_bytes: usize,
+}
+
+impl OffchainResolver {
+    pub fn from_config(config: &OffchainMetaConfig) -> Result<Self> {
+        // Hosts are resolved to public addresses only, redirects included,
+        // and the connection is made to the checked addresses
+        let client = public_client_builder()
+            .timeout(Duration::from_millis(
+                config.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS),
+            ))
+            .redirect(redirect::Policy::custom(|attempt| {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    attempt.error("too many redirects")
+                } else if !is_public_url(attempt.url()) {
+                    attempt.error("redirect to a private host")
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .build()?;
+
+        Ok(Self {
+            client,
+            schemes: config
+                .schemes
+                .clone()
+                .unwrap_or_else(|| vec!["https".into(), "ipfs".into(), "ar".into()])
+                .into_iter()
+                .map(|s| s.to_lowercase())
+                .collect(),
+            ipfs_gateway: with_trailing_slash(
+                config
+                    .ipfs_gateway
+                    .as_deref()
+                    .unwrap_or(DEFAULT_IPFS_GATEWAY),
+            ),
+            arweave_gateway: with_trailing_slash(
+                config
+                    .arweave_gateway
+                    .as_deref()
+                    .unwrap_or(DEFAULT_ARWEAVE_GATEWAY),
+            ),
+            max_size_bytes: config.max_size_bytes.unwrap_or(DEFAULT_MAX_SIZE_BYTES),
+        })
+    }
+
+    /// Metadata of the raw `getJson` result, with the off-chain document
+    /// merged in. Failing pointers leave the on-chain json as it is.
+    pub async fn resolve(&self, onchain: &str) -> Result<Value> {
+        let onchain = match serde_json::from_str::<Value>(onchain) {
+            Ok(Value::String(uri)) if self.gateway_url(&uri).is_some() => {
+                return self.fetch_document(&uri).await
+            }
+            Ok(value) => value,
+            Err(e) => {
+                return match self.gateway_url(onchain.trim()) {
+                    Some(_) => self.fetch_document(onchain.trim()).await,
+                    None => Err(e.into()),
+                }
+            }
+        };
+
+        let Value::Object(onchain) = onchain else {
+            return Ok(onchain);
+        };
+
+        let uris = pointers(&onchain)
+            .filter(|uri| self.gateway_url(uri).is_some())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        for uri in uris {
+            match self.fetch_document(&uri).await {
+                Ok(Value::Object(offchain)) => return Ok(Value::Object(merge(onchain, offchain))),
+                Ok(_) => {}
+                Err(e) => log::debug!("Off-chain metadata {uri} is skipped: {e:#}"),
+            }
+        }
+
+        Ok(Value::Object(onchain))
+    }
+
+    /// Http url of a uri with a followed scheme
+    fn gateway_url(&self, uri: &str) -> Option<Url> {
+        let (scheme, rest) = uri.split_once("://")?;
+        let scheme = scheme.to_lowercase();
+
+        if !self.schemes.contains(&scheme) {
+            return None;
+        }
+
+        let url = match scheme.as_str() {
+            "ipfs" => Url::parse(&format!(
+                "{}{}",
+                self.ipfs_gateway,
+                rest.trim_start_matches("ipfs/")
+            )),
+            "ar" => Url::parse(&format!("{}{}", self.arweave_gateway, rest)),
+            "http" | "https" => Url::parse(uri),
+            _ => return None,
+        }
+        .ok()?;
+
+        is_public_url(&url).then_some(url)
+    }
+
+    async fn fetch_document(&self, uri: &str) -> Result<Value> {
+        match self
+            .fetch(uri, self.max_size_bytes, is_json_content_type)
+            .await?
+        {
+            Fetched::Body(body) => Ok(serde_json::from_slice(&body)?),
+            Fetched::Rejected(reason) => bail!(reason),
+        }
+    }
+
+    /// Body of a uri with a followed scheme. Responses of other content
+    /// types or larger than `max_size_bytes` are rejected without reading
+    /// them further, errors are worth a retry.
+    pub(crate) async fn fetch(
+        &self,
+        uri: &str,
+        max_size_bytes: usize,
+        accept: fn(&str) -> bool,
+    ) -> Result<Fetched> {
+        let Some(url) = self.gateway_url(uri) else {
+            return Ok(Fetched::Rejected(format!("Uri {uri} is not followed")));
+        };
+
+        let mut response = self.client.get(url).send().await?.error_for_status()?;
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_lowercase();
+        if !accept(&content_type) {
+            return Ok(Fetched::Rejected(format!(
+                "Content type {content_type:?} is not accepted"
+            )));
+        }
+
+        let too_large = || Fetched::Rejected(format!("Larger than {max_size_bytes} bytes"));
+
+        if response
+            .content_length()
+            .filter(|len| *len as usize > max_size_bytes)
+            .is_some()
+        {
+            return Ok(too_large());
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > max_size_bytes {
+                return Ok(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(Fetched::Body(body))
+    }
+}
+
+pub(crate) enum Fetched {
+    Body(Vec<u8>),
+    Rejected(String),
+}
+
+fn with_trailing_slash(prefix: &str) -> String {
+    format!("{}/", prefix.trim_end_matches('/'))
+}
+
+/// Gateways serve json from ipfs as plain text or without a type at all
+fn is_json_content_type(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+
+    mime.is_empty()
+        || mime == "application/json"
+        || mime == "text/plain"
+        || mime == "application/octet-stream"
+        || mime.ends_with("+json")
+}
+
+fn pointers(onchain: &Map<String, Value>) -> impl Iterator<Item = &str> {
+    let fields = POINTER_FIELDS.iter().filter_map(|field| {
+        let value = onchain.get(*field)?;
+        value
+            .as_str()
+            .or_else(|| value.get("source").and_then(Value::as_str))
+    });
+
+    let files = onchain
+        .get("files")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|file| file.get("source").and_then(Value::as_str));
+
+    fields.chain(files)
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        Value::Array(a) => a.is_empty(),
+        Value::Object(o) => o.is_empty(),
+        _ => false,
+    }
+}
+
+fn merge(mut onchain: Map<String, Value>, offchain: Map<String, Value>) -> Map<String, Value> {
+    for (key, value) in offchain {
+        match onchain.get(&key) {
+            Some(existing) if !is_empty(existing) => {}
+            _ => {
+                onchain.insert(key, value);
+            }
+        }
+    }
+
+    onchain
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn resolver() -> OffchainResolver {
+        OffchainResolver::from_config(&OffchainMetaConfig {
+            schemes: None,
+            ipfs_gateway: Some("https://gateway.test/ipfs".into()),
+            arweave_gateway: None,
+            max_size_bytes: None,
+            timeout_ms: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn maps_uris_to_gateways() {
+        let resolver = resolver();
+        let url = |uri: &str| resolver.gateway_url(uri).map(|u| u.to_string());
+
+        assert_eq!(
+            url("ipfs://bafy/1.json").as_deref(),
+            Some("https://gateway.test/ipfs/bafy/1.json")
+        );
+        assert_eq!(
+            url("ipfs://ipfs/bafy").as_deref(),
+            Some("https://gateway.test/ipfs/bafy")
+        );
+        assert_eq!(url("ar://tx").as_deref(), Some("https://arweave.net/tx"));
+        assert_eq!(url("http://example.com/1.json"), None);
+        assert_eq!(url("https://127.0.0.1/1.json"), None);
+        assert_eq!(url("https://[::1]/1.json"), None);
+        assert_eq!(url("https://192.168.0.1/1.json"), None);
+        assert_eq!(url("https://[::ffff:127.0.0.1]/1.json"), None);
+        assert_eq!(url("https://100.64.0.1/1.json"), None);
+        assert_eq!(url("data:application/json,{}"), None);
+    }
+
+    #[tokio::test]
+    async fn names_resolving_to_private_addresses_are_not_fetched() {
+        let result = resolver()
+            .fetch("https://localhost./1.json", 1024, |_| true)
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn onchain_values_win_over_offchain() {
+        let onchain = json!({
+            "name": "On-chain",
+            "description": "",
+            "attributes": [],
+            "external_url": "ipfs://bafy",
+        });
+        let offchain = json!({
+            "name": "Off-chain",
+            "description": "Off-chain description",
+            "attributes": [{ "trait_type": "Eyes", "value": "Blue" }],
+            "external_url": "https://example.com",
+        });
+
+        let (Value::Object(onchain), Value::Object(offchain)) = (onchain, offchain) else {
+            unreachable!()
+        };
+
+        assert_eq!(
+            Value::Object(merge(onchain, offchain)),
+            json!({
+                "name": "On-chain",
+                "description": "Off-chain description",
+                "attributes": [{ "trait_type": "Eyes", "value": "Blue" }],
+                "external_url": "ipfs://bafy",
+            })
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use bigdecimal::BigDecimal;
+use indexer_repo::price::{NftPriceModel, PairCandle, PriceHistoryRow};
+use nekoton_utils::TrustMe;
+use sqlx::types::chrono::NaiveDateTime;
+use tokio::sync::Mutex;
+use tokio::time::{Interval, MissedTickBehavior};
+
+use super::provider::PriceProviders;
+use super::router::PriceRoute;
+
+const HOUR_SEC: i64 = 3600;
+/// Longest range of hours asked from a provider at once
+const CANDLES_PER_REQUEST: i64 = 500;
+
+/// Completed hourly candles of dex pairs. Every candle is requested from the
+/// providers once, later lookups are served from the database.
+pub struct CandleCache {
+    model: NftPriceModel,
+    providers: PriceProviders,
+    /// Paces provider requests while backfilling
+    request_interval: Mutex<Interval>,
+}
+
+impl CandleCache {
+    pub fn new(model: NftPriceModel, providers: PriceProviders, request_interval_ms: u64) -> Self {
+        let mut request_interval =
+            tokio::time::interval(Duration::from_millis(request_interval_ms.max(1)));
+        request_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        Self {
+            model,
+            providers,
+            request_interval: Mutex::new(request_interval),
+        }
+    }
+
+    pub fn providers(&self) -> &PriceProviders {
+        &self.providers
+    }
+
+    /// Requests candles of the hours from `from` to `to` missing in the
+    /// database. Hours that have not ended yet are skipped.
+    pub async fn backfill(
+        &self,
+        pair: &str,
+        provider: Option<&str>,
+        from: i64,
+        to: i64,
+    ) -> Result<()> {
+        let last_complete_hour = hour_of(now_sec()) - HOUR_SEC;
+        let to = to.min(last_complete_hour);
+        if to < from {
+            return Ok(());
+        }
+
+        let missing = self
+            .model
+            .get_missing_candle_hours(pair, to_datetime(from), to_datetime(to))
+            .await?;
+
+        for (start, end) in missing_ranges(missing.iter().map(|ts| ts.timestamp())) {
+            self.request_interval.lock().await.tick().await;
+
+            let provided = self
+                .providers
+                .request_prices(provider, pair, start, end)
+                .await?;
+
+            let by_hour = provided
+                .prices
+                .into_iter()
+                .map(|p| (p.timestamp, p))
+                .collect::<HashMap<_, _>>();
+
+            let mut previous_close = self
+                .model
+                .get_candle_before(pair, to_datetime(start))
+                .await?
+                .map(|c| c.close);
+
+            let mut candles = Vec::new();
+            for hour in (start..=end).step_by(HOUR_SEC as usize) {
+                match by_hour.get(&hour) {
+                    Some(price) => {
+                        previous_close = Some(price.close.clone());
+                        candles.push(PairCandle {
+                            ts: to_datetime(hour),
+                            open: price.open.clone(),
+                            close: price.close.clone(),
+                            filled: false,
+                        });
+                    }
+                    // Hours before the first known candle stay missing
+                    None => {
+                        if let Some(close) = &previous_close {
+                            candles.push(PairCandle {
+                                ts: to_datetime(hour),
+                                open: close.clone(),
+                                close: close.clone(),
+                                filled: true,
+                            });
+                        }
+                    }
+                }
+            }
+
+            self.model
+                .save_candles(pair, &provided.provider, &candles)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Drops the stored candles of the pair from `from` to `to`, the next
+    /// backfill requests them again.
+    pub async fn invalidate(&self, pair: &str, from: i64, to: i64) -> Result<()> {
+        self.model
+            .delete_candles(pair, to_datetime(hour_of(from)), to_datetime(to))
+            .await
+    }
+
+    /// Usd values of the rows priced through the route at their closest
+    /// hours, `None` for rows missing a candle of any pair.
+    pub async fn usd_values(
+        &self,
+        route: &PriceRoute,
+        rows: &[&PriceHistoryRow],
+    ) -> Vec<Option<BigDecimal>> {
+        if let Some((from, to)) = get_price_time_bounds(rows) {
+            for (pair, provider) in route.legs() {
+                // Candles stored before are still used when the backfill fails
+                if let Err(e) = self.backfill(pair, provider, from, to).await {
+                    log::error!("Error while backfilling candles of pair {pair}: {e:?}");
+                }
+            }
+        }
+
+        self.stored_usd_values(route, rows).await
+    }
+
+    /// Usd values of the rows from the candles already stored, nothing is
+    /// requested from the providers or written.
+    pub async fn stored_usd_values(
+        &self,
+        route: &PriceRoute,
+        rows: &[&PriceHistoryRow],
+    ) -> Vec<Option<BigDecimal>> {
+        if rows.is_empty() {
+            return Vec::new();
+        }
+
+        let hours = rows
+            .iter()
+            .map(|e| get_closest_hour(e.created_at))
+            .collect::<Vec<_>>();
+
+        let mut route_closes = Vec::new();
+        for (pair, _) in route.legs() {
+            match self.get_closes(pair, &hours).await {
+                Ok(closes) => route_closes.push(closes),
+                Err(e) => {
+                    log::error!("Error while reading candles of pair {pair}: {e:?}");
+                    return vec![None; rows.len()];
+                }
+            }
+        }
+
+        rows.iter()
+            .zip(hours)
+            .map(|(row, hour)| {
+                let closes = route_closes
+                    .iter()
+                    .filter_map(|closes| closes.get(&hour))
+                    .collect::<Vec<_>>();
+
+                route.usd_value(&row.token_amount, &closes)
+            })
+            .collect()
+    }
+
+    /// Close prices of the stored candles among `hours`.
+    pub async fn get_closes(&self, pair: &str, hours: &[i64]) -> Result<HashMap<i64, BigDecimal>> {
+        let hours = hours.iter().map(|h| to_datetime(*h)).collect::<Vec<_>>();
+
+        Ok(self
+            .model
+            .get_candles(pair, &hours)
+            .await?
+            .into_iter()
+            .map(|c| (c.ts.timestamp(), c.close))
+            .collect())
+    }
+}
+
+fn now_sec() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .trust_me()
+        .as_secs() as i64
+}
+
+fn hour_of(time: i64) -> i64 {
+    time.div_euclid(HOUR_SEC) * HOUR_SEC
+}
+
+fn to_datetime(time: i64) -> NaiveDateTime {
+    NaiveDateTime::from_timestamp_opt(time, 0).unwrap_or_default()
+}
+
+fn get_closest_hour(time: i64) -> i64 {
+    let seconds = time % HOUR_SEC;
+    if seconds > HOUR_SEC / 2 {
+        time + HOUR_SEC - seconds
+    } else {
+        time - seconds
+    }
+}
+
+/// Hours around the rows, with an hour of margin on both sides
+fn get_price_time_bounds(rows: &[&PriceHistoryRow]) -> Option<(i64, i64)> {
+    let from = rows.iter().map(|e| e.created_at).min()?;
+    let to = rows.iter().map(|e| e.created_at).max()?;
+
+    Some((
+        get_closest_hour(from) - HOUR_SEC,
+        get_closest_hour(to) + HOUR_SEC,
+    ))
+}
+
+/// Sorted hours grouped into contiguous ranges of at most `CANDLES_PER_REQUEST` hours.
+fn missing_ranges(hours: impl Iterator<Item = i64>) -> Vec<(i64, i64)> {
+    let mut ranges: Vec<(i64, i64)> = Vec::new();
+
+    for hour in hours {
+        match ranges.last_mut() {
+            Some((start, end))
+                if *end + HOUR_SEC == hour && hour - *start < CANDLES_PER_REQUEST * HOUR_SEC =>
+            {
+                *end = hour;
+            }
+            _ => ranges.push((hour, hour)),
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_missing_hours() {
+        let hours = [0, 1, 2, 5, 6, 9].map(|h| h * HOUR_SEC);
+
+        assert_eq!(
+            missing_ranges(hours.into_iter()),
+            vec![
+                (0, 2 * HOUR_SEC),
+                (5 * HOUR_SEC, 6 * HOUR_SEC),
+                (9 * HOUR_SEC, 9 * HOUR_SEC)
+            ]
+        );
+
+        let long = (0..CANDLES_PER_REQUEST + 1).map(|h| h * HOUR_SEC);
+        assert_eq!(missing_ranges(long).len(), 2);
+    }
+}
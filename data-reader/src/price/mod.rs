@@ -1,4 +1,11 @@
-mod price_requester;
+mod candles;
+mod provider;
 mod reader;
+mod reprice;
+mod router;
 
+pub use candles::*;
+pub use provider::*;
 pub use reader::*;
+pub use reprice::*;
+pub use router::*;
@@ -0,0 +1,534 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+
+const HOUR_SEC: i64 = 3600;
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+/// Pools requested per page of a listing
+const LISTING_PAGE: usize = 100;
+/// Pools listed at most, the listing is ordered by liquidity
+const MAX_LISTED_PAIRS: usize = 1000;
+
+#[derive(Deserialize, Debug)]
+pub struct PriceInfo {
+    pub open: BigDecimal,
+    pub close: BigDecimal,
+    pub timestamp: i64,
+}
+
+/// DEX pair listed by a provider.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProvidedPair {
+    pub pair: String,
+    pub left_token: String,
+    pub right_token: String,
+    pub liquidity_usd: Option<f64>,
+}
+
+/// Source of hourly DEX pair candles.
+#[async_trait]
+pub trait PriceProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Candles of the pair from the one covering `from` up to `to`, unix seconds.
+    async fn request_prices(&self, pair: &str, from: i64, to: i64) -> Result<Vec<PriceInfo>>;
+
+    /// Pairs the provider has candles for, the most liquid first.
+    async fn request_pairs(&self) -> Result<Vec<ProvidedPair>> {
+        Ok(Vec::new())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceProviderKind {
+    Flatqube,
+    Web3world,
+    Static,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PriceProviderConfig {
+    pub kind: PriceProviderKind,
+    pub base_url: Option<String>,
+    pub timeout_ms: Option<u64>,
+    /// Fallbacks are tried from the lowest priority
+    #[serde(default)]
+    pub priority: i32,
+    /// Close price per pair of a `static` provider
+    #[serde(default)]
+    pub prices: HashMap<String, BigDecimal>,
+    /// Pairs listed by a `static` provider
+    #[serde(default)]
+    pub pairs: Vec<ProvidedPair>,
+}
+
+#[derive(Serialize)]
+struct ListingRequest {
+    limit: usize,
+    offset: usize,
+    ordering: &'static str,
+}
+
+/// Page of the pools listing of flatqube and web3.world
+#[derive(Deserialize)]
+struct ListingPage {
+    #[serde(alias = "pairs")]
+    pools: Vec<ListedPool>,
+}
+
+#[derive(Deserialize)]
+struct ListedPool {
+    meta: ListedPoolMeta,
+    /// Total value locked in usd, a decimal string
+    tvl: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListedPoolMeta {
+    #[serde(alias = "pairAddress")]
+    pool_address: String,
+    base_address: String,
+    counter_address: String,
+}
+
+impl From<ListedPool> for ProvidedPair {
+    fn from(pool: ListedPool) -> Self {
+        Self {
+            pair: pool.meta.pool_address,
+            left_token: pool.meta.base_address,
+            right_token: pool.meta.counter_address,
+            liquidity_usd: pool.tvl.and_then(|tvl| tvl.parse().ok()),
+        }
+    }
+}
+
+/// Pages through a pools listing ordered by liquidity.
+async fn request_listing(
+    client: &reqwest::Client,
+    url: &str,
+    timeout: Duration,
+) -> Result<Vec<ProvidedPair>> {
+    let mut pairs = Vec::new();
+
+    while pairs.len() < MAX_LISTED_PAIRS {
+        let page = client
+            .post(url)
+            .timeout(timeout)
+            .json(&ListingRequest {
+                limit: LISTING_PAGE,
+                offset: pairs.len(),
+                ordering: "tvldescending",
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ListingPage>()
+            .await
+            .map_err(|e| anyhow!(e))?;
+
+        let last = page.pools.len() < LISTING_PAGE;
+        pairs.extend(page.pools.into_iter().map(ProvidedPair::from));
+        if last {
+            break;
+        }
+    }
+
+    Ok(pairs)
+}
+
+pub struct FlatqubeProvider {
+    name: String,
+    client: reqwest::Client,
+    base_url: String,
+    timeout: Duration,
+}
+
+#[async_trait]
+impl PriceProvider for FlatqubeProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn request_prices(&self, pair: &str, from: i64, to: i64) -> Result<Vec<PriceInfo>> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct FlatqubePriceRequest<'a> {
+            from: i64,
+            to: i64,
+            timeframe: &'static str,
+            ohlcv_kind: &'static str,
+            pool_address: &'a str,
+        }
+
+        self.client
+            .post(format!("{}/v2/pools/ohlcv", self.base_url))
+            .timeout(self.timeout)
+            .json(&FlatqubePriceRequest {
+                from,
+                to,
+                timeframe: "H1",
+                ohlcv_kind: "Price",
+                pool_address: pair,
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<PriceInfo>>()
+            .await
+            .map_err(|e| anyhow!(e))
+    }
+
+    async fn request_pairs(&self) -> Result<Vec<ProvidedPair>> {
+        request_listing(
+            &self.client,
+            &format!("{}/v2/pools", self.base_url),
+            self.timeout,
+        )
+        .await
+    }
+}
+
+pub struct Web3WorldProvider {
+    name: String,
+    client: reqwest::Client,
+    base_url: String,
+    timeout: Duration,
+}
+
+#[async_trait]
+impl PriceProvider for Web3WorldProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Web3.world takes and returns milliseconds
+    async fn request_prices(&self, pair: &str, from: i64, to: i64) -> Result<Vec<PriceInfo>> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Web3WorldPriceRequest {
+            from: i64,
+            to: i64,
+            timeframe: &'static str,
+        }
+
+        self.client
+            .post(format!("{}/v1/pairs/address/{pair}/ohlcv", self.base_url))
+            .timeout(self.timeout)
+            .json(&Web3WorldPriceRequest {
+                from: from * 1000,
+                to: to * 1000,
+                timeframe: "H1",
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<PriceInfo>>()
+            .await
+            .map_err(|e| anyhow!(e))
+            .map(|v| {
+                v.into_iter()
+                    .map(|v| PriceInfo {
+                        timestamp: v.timestamp / 1000,
+                        ..v
+                    })
+                    .collect()
+            })
+    }
+
+    async fn request_pairs(&self) -> Result<Vec<ProvidedPair>> {
+        request_listing(
+            &self.client,
+            &format!("{}/v1/pairs", self.base_url),
+            self.timeout,
+        )
+        .await
+    }
+}
+
+/// Fixed prices for tests and setups without access to a DEX.
+pub struct StaticPriceProvider {
+    name: String,
+    prices: HashMap<String, BigDecimal>,
+    pairs: Vec<ProvidedPair>,
+}
+
+impl StaticPriceProvider {
+    pub fn new(name: &str, prices: HashMap<String, BigDecimal>) -> Self {
+        Self {
+            name: name.to_string(),
+            prices,
+            pairs: Vec::new(),
+        }
+    }
+
+    pub fn with_pairs(mut self, pairs: Vec<ProvidedPair>) -> Self {
+        self.pairs = pairs;
+        self
+    }
+}
+
+#[async_trait]
+impl PriceProvider for StaticPriceProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn request_prices(&self, pair: &str, from: i64, to: i64) -> Result<Vec<PriceInfo>> {
+        let price = self
+            .prices
+            .get(pair)
+            .ok_or_else(|| anyhow!("No static price for pair {pair}"))?;
+
+        let mut candles = Vec::new();
+        // The latest candle at or before `from` covers it
+        let mut timestamp = from.div_euclid(HOUR_SEC) * HOUR_SEC;
+        while timestamp <= to {
+            candles.push(PriceInfo {
+                open: price.clone(),
+                close: price.clone(),
+                timestamp,
+            });
+            timestamp += HOUR_SEC;
+        }
+
+        Ok(candles)
+    }
+
+    async fn request_pairs(&self) -> Result<Vec<ProvidedPair>> {
+        Ok(self.pairs.clone())
+    }
+}
+
+pub struct ProvidedPrices {
+    /// Name of the provider that returned the candles
+    pub provider: String,
+    pub prices: Vec<PriceInfo>,
+}
+
+/// Providers in fallback order.
+#[derive(Clone)]
+pub struct PriceProviders {
+    providers: Vec<Arc<dyn PriceProvider>>,
+}
+
+impl PriceProviders {
+    pub fn new(providers: Vec<Arc<dyn PriceProvider>>) -> Self {
+        Self { providers }
+    }
+
+    pub fn from_config(configs: &HashMap<String, PriceProviderConfig>) -> Result<Self> {
+        let client = reqwest::Client::new();
+
+        let mut configs = configs.iter().collect::<Vec<_>>();
+        configs.sort_by(|(a_name, a), (b_name, b)| {
+            a.priority.cmp(&b.priority).then(a_name.cmp(b_name))
+        });
+
+        let providers = configs
+            .into_iter()
+            .map(|(name, config)| {
+                let timeout =
+                    Duration::from_millis(config.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS));
+                let base_url = || {
+                    config
+                        .base_url
+                        .as_ref()
+                        .map(|url| url.trim_end_matches('/').to_string())
+                        .ok_or_else(|| anyhow!("Price provider {name} has no base url"))
+                };
+
+                let provider: Arc<dyn PriceProvider> = match config.kind {
+                    PriceProviderKind::Flatqube => Arc::new(FlatqubeProvider {
+                        name: name.clone(),
+                        client: client.clone(),
+                        base_url: base_url()?,
+                        timeout,
+                    }),
+                    PriceProviderKind::Web3world => Arc::new(Web3WorldProvider {
+                        name: name.clone(),
+                        client: client.clone(),
+                        base_url: base_url()?,
+                        timeout,
+                    }),
+                    PriceProviderKind::Static => Arc::new(
+                        StaticPriceProvider::new(name, config.prices.clone())
+                            .with_pairs(config.pairs.clone()),
+                    ),
+                };
+
+                Ok(provider)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self::new(providers))
+    }
+
+    /// Asks the `preferred` provider first, then the others in order, until
+    /// one returns candles.
+    pub async fn request_prices(
+        &self,
+        preferred: Option<&str>,
+        pair: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<ProvidedPrices> {
+        let chain = self
+            .providers
+            .iter()
+            .filter(|p| Some(p.name()) == preferred)
+            .chain(
+                self.providers
+                    .iter()
+                    .filter(|p| Some(p.name()) != preferred),
+            );
+
+        for provider in chain {
+            match provider.request_prices(pair, from, to).await {
+                Ok(prices) if !prices.is_empty() => {
+                    return Ok(ProvidedPrices {
+                        provider: provider.name().to_string(),
+                        prices,
+                    })
+                }
+                Ok(_) => log::warn!(
+                    "Price provider {} has no candles for {pair}",
+                    provider.name()
+                ),
+                Err(e) => log::warn!(
+                    "Price provider {} failed for {pair}: {e:?}",
+                    provider.name()
+                ),
+            }
+        }
+
+        Err(anyhow!("No price provider has candles for pair {pair}"))
+    }
+
+    /// Pairs listed by every provider in fallback order, along with the
+    /// provider name. Providers failing to list their pairs are skipped.
+    pub async fn request_pairs(&self) -> Vec<(String, Vec<ProvidedPair>)> {
+        let mut listed = Vec::new();
+        for provider in &self.providers {
+            match provider.request_pairs().await {
+                Ok(pairs) => listed.push((provider.name().to_string(), pairs)),
+                Err(e) => log::warn!(
+                    "Price provider {} failed to list pairs: {e:?}",
+                    provider.name()
+                ),
+            }
+        }
+
+        listed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn falls_back_in_order() {
+        let providers = PriceProviders::new(vec![
+            Arc::new(StaticPriceProvider::new("empty", HashMap::new())),
+            Arc::new(StaticPriceProvider::new(
+                "fixture",
+                HashMap::from([("0:pair".to_string(), BigDecimal::from(2))]),
+            )),
+        ]);
+
+        let prices = providers
+            .request_prices(Some("empty"), "0:pair", 3599, 7200)
+            .await
+            .unwrap();
+
+        assert_eq!(prices.provider, "fixture");
+        assert_eq!(
+            prices
+                .prices
+                .iter()
+                .map(|p| p.timestamp)
+                .collect::<Vec<_>>(),
+            vec![0, 3600, 7200]
+        );
+        assert!(providers
+            .request_prices(None, "0:other", 0, 3600)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn static_provider_covers_the_requested_time() {
+        let provider = StaticPriceProvider::new(
+            "fixture",
+            HashMap::from([("0:pair".to_string(), BigDecimal::from(2))]),
+        );
+
+        let prices = provider.request_prices("0:pair", 5000, 5000).await.unwrap();
+
+        assert_eq!(
+            prices.iter().map(|p| p.timestamp).collect::<Vec<_>>(),
+            vec![3600]
+        );
+        assert_eq!(prices[0].close, BigDecimal::from(2));
+    }
+
+    #[test]
+    fn parses_pools_listing() {
+        let page: ListingPage = serde_json::from_str(
+            r#"{"pairs": [{
+                "meta": {"pairAddress": "0:pair", "baseAddress": "0:left", "counterAddress": "0:right"},
+                "tvl": "12345.67"
+            }, {
+                "meta": {"pairAddress": "0:empty", "baseAddress": "0:left", "counterAddress": "0:other"},
+                "tvl": null
+            }]}"#,
+        )
+        .unwrap();
+
+        let pairs = page
+            .pools
+            .into_iter()
+            .map(ProvidedPair::from)
+            .collect::<Vec<_>>();
+
+        assert_eq!(pairs[0].pair, "0:pair");
+        assert_eq!(pairs[0].left_token, "0:left");
+        assert_eq!(pairs[0].right_token, "0:right");
+        assert_eq!(pairs[0].liquidity_usd, Some(12345.67));
+        assert_eq!(pairs[1].liquidity_usd, None);
+    }
+
+    #[tokio::test]
+    async fn lists_pairs_of_every_provider() {
+        let providers = PriceProviders::new(vec![
+            Arc::new(StaticPriceProvider::new("empty", HashMap::new())),
+            Arc::new(
+                StaticPriceProvider::new("fixture", HashMap::new()).with_pairs(vec![
+                    ProvidedPair {
+                        pair: "0:pair".to_string(),
+                        left_token: "0:left".to_string(),
+                        right_token: "0:right".to_string(),
+                        liquidity_usd: Some(100.0),
+                    },
+                ]),
+            ),
+        ]);
+
+        let listed = providers.request_pairs().await;
+
+        assert_eq!(
+            listed
+                .iter()
+                .map(|(name, pairs)| (name.as_str(), pairs.len()))
+                .collect::<Vec<_>>(),
+            vec![("empty", 0), ("fixture", 1)]
+        );
+    }
+}
@@ -1,4 +1,3 @@
-use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::UNIX_EPOCH;
 use std::{
@@ -6,64 +5,65 @@ use std::{
     time::Duration,
 };
 
-use bigdecimal::{num_bigint::BigInt, BigDecimal};
+use bigdecimal::BigDecimal;
+use futures::StreamExt;
 use indexer_repo::{
-    price::{NftPriceModel, RowWithoutUsdPrice},
+    price::{NewDexPair, NftPriceModel},
     types::BcName,
 };
 use nekoton_utils::TrustMe;
-use sqlx::types::chrono::NaiveDateTime;
 use sqlx::PgPool;
 use tokio::sync::RwLock;
+use tokio::time::MissedTickBehavior;
 
-use super::price_requester::{request_prices, PriceInfo};
+use super::candles::CandleCache;
+use super::provider::PriceProviders;
+use super::router::PriceRouter;
 
 const NFT_PER_ITERATION: i64 = 1000;
+/// Pairs requested at once while updating current prices
+const CURRENT_PRICE_REQUESTS: usize = 8;
+/// Pairs and their liquidity are listed again this often
+const DEX_PAIRS_UPDATE_SEC: u64 = 3600;
+
+/// Usd price of a token along with the time it was updated at
+#[derive(Clone, Debug)]
+pub struct CurrentPrice {
+    pub usd_price: BigDecimal,
+    pub updated_at: u64,
+}
 
 pub struct PriceReader {
     pub model: NftPriceModel,
-    pub http_client: reqwest::Client,
+    pub candles: CandleCache,
     pub bc: BcName,
     pub idle_after_loop: u64,
     pub price_update_frequency: u64,
-    pub current_prices: RwLock<HashMap<String, Option<BigDecimal>>>,
-    pub last_update: AtomicU64,
+    pub current_prices: RwLock<HashMap<String, CurrentPrice>>,
 }
 
 impl PriceReader {
     pub async fn new(
         pool: PgPool,
         bc: BcName,
+        providers: PriceProviders,
         idle_after_loop: u64,
         price_update_frequency_secs: u64,
+        price_request_interval_ms: u64,
     ) -> Arc<Self> {
         let model = NftPriceModel::new(pool.clone());
-        let http_client = reqwest::Client::new();
-
-        let tokens = model
-            .get_tokens_with_dex_pair(bc)
-            .await
-            .expect("Failed getting tokens from DB");
-
-        let current_prices = RwLock::new(tokens.into_iter().map(|t| (t, None)).collect());
-
-        let last_update = AtomicU64::new(
-            std::time::SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .trust_me()
-                .as_secs(),
-        );
+        let candles = CandleCache::new(model.clone(), providers, price_request_interval_ms);
 
         let reader = Arc::new(Self {
             model,
-            http_client,
+            candles,
             bc,
             idle_after_loop,
             price_update_frequency: price_update_frequency_secs,
-            current_prices,
-            last_update,
+            current_prices: RwLock::new(HashMap::new()),
         });
 
+        tokio::spawn(reader.clone().run_dex_pair_loader());
         tokio::spawn(reader.clone().run_current_price_updater());
 
         reader
@@ -77,55 +77,46 @@ impl PriceReader {
                 .await
                 .expect("Failed to get prices for update");
 
-            let iterator = prices.iter();
-
-            let Some((from, to)) = PriceReader::get_price_time_bounds(iterator.clone()) else {
-                tokio::time::sleep(Duration::from_secs(self.idle_after_loop)).await;
-                continue;
+            let router = match self.load_router().await {
+                Ok(router) => router,
+                Err(e) => {
+                    log::error!("Error while reading dex pairs: {e:?}");
+                    tokio::time::sleep(Duration::from_secs(self.idle_after_loop)).await;
+                    continue;
+                }
             };
 
-            let token_addresses = iterator
-                .clone()
+            let token_addresses = prices
+                .iter()
                 .map(|v| v.token_addr.as_str())
                 .collect::<HashSet<_>>();
 
             for token_addr in token_addresses {
-                let Ok(pool_info) = self.model.get_dex_pair_address(token_addr, self.bc).await else {
-                    log::error!("Error while reading dex pair by token address {token_addr}");
-                    continue;
-                };
-
-                let Ok(prices) =
-                    request_prices(&self.http_client, from, to, &pool_info.address, self.bc).await else {
-                    log::error!("Error while requesting prices to dex for address {}", &pool_info.address);
+                let Some(route) = router.route(token_addr) else {
+                    log::error!("No route to usd for token {token_addr}");
                     continue;
                 };
 
-                let price_dict = prices
+                let rows = prices
                     .iter()
-                    .map(|e| (e.timestamp, e))
-                    .collect::<HashMap<i64, &PriceInfo>>();
+                    .filter(|e| e.token_addr == token_addr)
+                    .collect::<Vec<_>>();
 
-                let multiplier =
-                    BigDecimal::from(BigInt::from(10).pow(pool_info.decimals.try_into().unwrap()));
+                let values = self.candles.usd_values(route, &rows).await;
+                let route_pairs = route.pairs();
 
-                for nft in iterator.clone().filter(|e| e.token_addr == token_addr) {
-                    let closest_hour = PriceReader::get_closest_hour(nft.created_at);
-                    let Some(hist_token_usd_price) = price_dict.get(&closest_hour) else {
-                        log::error!("Can't find price for token {token_addr} time: {closest_hour}");
+                for (nft, token_usd_price) in rows.into_iter().zip(values) {
+                    let Some(token_usd_price) = token_usd_price else {
+                        log::error!("Can't find price for token {token_addr} time: {}", nft.created_at);
 
                         continue;
                     };
 
-                    let token_usd_price = {
-                        if pool_info.is_l2r {
-                            &nft.token_amount * &hist_token_usd_price.close / &multiplier
-                        } else {
-                            &nft.token_amount / &hist_token_usd_price.close / &multiplier
-                        }
-                    };
-
-                    if let Err(e) = self.model.update_usd_price(&nft.id, &token_usd_price).await {
+                    if let Err(e) = self
+                        .model
+                        .update_usd_price(nft.id, &token_usd_price, &route_pairs)
+                        .await
+                    {
                         log::error!("Error while saving token {token_addr} usd price: {e:?}");
                     }
                 }
@@ -135,117 +126,180 @@ impl PriceReader {
         }
     }
 
+    /// Usd price of the token if it was updated within the update period of `timestamp`.
     pub async fn get_current_usd_price(&self, token: &str, timestamp: u64) -> Option<BigDecimal> {
-        let last_update = self.last_update.load(Ordering::Acquire);
+        let current_prices = self.current_prices.read().await;
+        let price = current_prices.get(token)?;
 
-        log::info!(
-            "Current prices (last update {}): {:?}",
-            NaiveDateTime::from_timestamp_opt(last_update as i64, 0).unwrap_or_default(),
-            self.current_prices.read().await
-        );
-
-        if last_update.abs_diff(timestamp) > self.price_update_frequency {
+        if price.updated_at.abs_diff(timestamp) > self.price_update_frequency {
+            log::debug!("Current price of token {token} is outdated");
             return None;
         }
 
+        Some(price.usd_price.clone())
+    }
+
+    /// Usd prices of tokens updated within the last update period, outdated tokens are skipped.
+    pub async fn get_current_usd_prices(&self) -> HashMap<String, BigDecimal> {
+        let now = unix_now();
+
         self.current_prices
             .read()
             .await
-            .get(token)
-            .unwrap_or(&None)
-            .clone()
+            .iter()
+            .filter(|(_, price)| price.updated_at.abs_diff(now) <= self.price_update_frequency)
+            .map(|(token, price)| (token.clone(), price.usd_price.clone()))
+            .collect()
     }
 
     async fn run_current_price_updater(self: Arc<Self>) {
-        loop {
-            let now = std::time::SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .trust_me()
-                .as_secs()
-                .saturating_sub(10);
-
-            {
-                let mut current_prices = self.current_prices.write().await;
-
-                for (token, usd_price) in current_prices.iter_mut() {
-                    let Ok(pool_info) = self.model.get_dex_pair_address(token, self.bc).await else {
-                        log::error!("Error while reading dex pair by token address {token}");
-
-                        *usd_price = None;
-                        continue;
-                    };
-
-                    let Ok(prices) =
-                        request_prices(&self.http_client, now as i64, now as i64, &pool_info.address, self.bc).await else {
-                        log::error!("Error while requesting prices to dex for address {}", &pool_info.address);
-
-                        *usd_price = None;
-                        continue;
-                    };
+        let mut interval = tokio::time::interval(Duration::from_secs(self.price_update_frequency));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
-                    log::info!("Current prices from dex (now = {}): {:#?}", now, prices);
-
-                    let price_dict = prices.iter().map(|e| (e.timestamp, e)).collect::<HashMap<
-                        i64,
-                        &PriceInfo,
-                    >>(
-                    );
-
-                    let multiplier = BigDecimal::from(
-                        BigInt::from(10).pow(pool_info.decimals.try_into().unwrap()),
-                    );
+        loop {
+            interval.tick().await;
 
-                    let Some(token_usd_price) = price_dict.get(&(now as i64)) else {
-                        log::error!("Can't find price for token {token} time: {now}");
+            let now = unix_now().saturating_sub(10);
 
-                        *usd_price = None;
-                        continue;
-                    };
+            // Routes are read every time to pick up tokens added to the dex tables
+            let router = match self.load_router().await {
+                Ok(router) => router,
+                Err(e) => {
+                    log::error!("Error while reading dex pairs: {e:?}");
+                    continue;
+                }
+            };
 
-                    let one = BigDecimal::from(1);
+            // Routes share pairs, every pair is requested once
+            let legs = router
+                .routes()
+                .flat_map(|(_, route)| route.legs())
+                .map(|(pair, provider)| (pair.to_string(), provider.map(str::to_string)))
+                .collect::<HashSet<_>>();
 
-                    let token_usd_price = {
-                        if pool_info.is_l2r {
-                            one * &token_usd_price.close / &multiplier
-                        } else {
-                            one / &token_usd_price.close / &multiplier
-                        }
-                    };
+            let reader = self.clone();
+            let closes = futures::stream::iter(legs)
+                .map(|(pair, provider)| {
+                    let reader = reader.clone();
+                    async move {
+                        let close = reader
+                            .request_current_close(&pair, provider.as_deref(), now as i64)
+                            .await;
+                        (pair, close)
+                    }
+                })
+                .buffer_unordered(CURRENT_PRICE_REQUESTS)
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .filter_map(|(pair, close)| Some((pair, close?)))
+                .collect::<HashMap<_, _>>();
+
+            let mut updated = Vec::new();
+            for (token, route) in router.routes() {
+                let route_closes = route
+                    .legs()
+                    .map(|(pair, _)| closes.get(pair))
+                    .collect::<Option<Vec<_>>>();
+
+                let Some(token_usd_price) = route_closes
+                    .and_then(|closes| route.usd_value(&BigDecimal::from(1), &closes)) else {
+                    log::error!("Can't find price for token {token} time: {now}");
+                    continue;
+                };
 
-                    *usd_price = Some(token_usd_price);
+                if let Err(e) = self.model.update_token_usd_price(token, &token_usd_price).await {
+                    log::error!("Error while saving token {token} current usd price: {e:?}");
                 }
-            }
 
-            self.last_update.store(now, Ordering::Release);
+                updated.push((token.clone(), token_usd_price));
+            }
 
-            tokio::time::sleep(Duration::from_secs(self.price_update_frequency)).await;
+            // Tokens that failed to update keep their price until it is outdated
+            let updated_at = unix_now();
+            let mut current_prices = self.current_prices.write().await;
+            current_prices.retain(|token, _| router.route(token).is_some());
+            for (token, usd_price) in updated {
+                current_prices.insert(
+                    token,
+                    CurrentPrice {
+                        usd_price,
+                        updated_at,
+                    },
+                );
+            }
         }
     }
 
-    fn get_closest_hour(time: i64) -> i64 {
-        let seconds = time % 3600;
-        if seconds > 1800 {
-            time + 3600 - seconds
-        } else {
-            time - seconds
+    /// Fills `dex_pairs` with the pairs listed by the providers, the router
+    /// reaches tokens without a usd pair through them.
+    async fn run_dex_pair_loader(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(DEX_PAIRS_UPDATE_SEC));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            for (provider, pairs) in self.candles.providers().request_pairs().await {
+                let pairs = pairs
+                    .into_iter()
+                    .map(|p| NewDexPair {
+                        pair: p.pair,
+                        left_token: p.left_token,
+                        right_token: p.right_token,
+                        liquidity_usd: p.liquidity_usd,
+                    })
+                    .collect::<Vec<_>>();
+
+                if let Err(e) = self.model.save_dex_pairs(self.bc, &provider, &pairs).await {
+                    log::error!("Error while saving dex pairs of {provider}: {e:?}");
+                }
+            }
         }
     }
 
-    fn get_price_time_bounds<'a, I>(iter: I) -> Option<(i64, i64)>
-    where
-        I: Iterator<Item = &'a RowWithoutUsdPrice> + Clone,
-    {
-        let Some(to) = iter.clone().max_by_key(|e| e.created_at) else {
-            return None
+    async fn request_current_close(
+        &self,
+        pair: &str,
+        provider: Option<&str>,
+        now: i64,
+    ) -> Option<BigDecimal> {
+        let provided = match self
+            .candles
+            .providers()
+            .request_prices(provider, pair, now, now)
+            .await
+        {
+            Ok(provided) => provided,
+            Err(e) => {
+                log::error!("Error while requesting prices to dex for address {pair}: {e:?}");
+                return None;
+            }
         };
 
-        // min_by_key returns None when iterator is empty. This case has been handled in the previous step.
-        let from = iter.min_by_key(|e| e.created_at).unwrap();
-        let hour_in_sec = 3600;
+        log::debug!(
+            "Current prices of {pair} from {} (now = {now}): {:?}",
+            provided.provider,
+            provided.prices
+        );
 
-        Some((
-            PriceReader::get_closest_hour(from.created_at) - hour_in_sec,
-            PriceReader::get_closest_hour(to.created_at) + hour_in_sec,
-        ))
+        // The candle covering now, providers stamp it with the hour or with `now` itself
+        provided
+            .prices
+            .into_iter()
+            .filter(|e| e.timestamp <= now)
+            .max_by_key(|e| e.timestamp)
+            .map(|e| e.close)
     }
+
+    async fn load_router(&self) -> anyhow::Result<PriceRouter> {
+        PriceRouter::load(&self.model, self.bc).await
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .trust_me()
+        .as_secs()
 }
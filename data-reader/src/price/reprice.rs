@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use bigdecimal::BigDecimal;
+use indexer_repo::price::{NftPriceModel, RepriceFilter};
+use indexer_repo::types::BcName;
+use sqlx::PgPool;
+
+use super::candles::CandleCache;
+use super::provider::PriceProviders;
+use super::router::PriceRouter;
+
+const ROWS_PER_BATCH: i64 = 1000;
+
+/// Usd price of a price history row before and after repricing.
+pub struct Repriced {
+    pub id: i64,
+    pub token: String,
+    pub old_usd_price: Option<BigDecimal>,
+    pub new_usd_price: BigDecimal,
+    pub route: Vec<String>,
+}
+
+/// Recomputes usd prices of stored price history, e.g. after a provider fix.
+pub struct Repricer {
+    model: NftPriceModel,
+    candles: CandleCache,
+    router: PriceRouter,
+}
+
+impl Repricer {
+    pub async fn new(
+        pool: PgPool,
+        bc: BcName,
+        providers: PriceProviders,
+        price_request_interval_ms: u64,
+    ) -> Result<Self> {
+        let model = NftPriceModel::new(pool);
+        let candles = CandleCache::new(model.clone(), providers, price_request_interval_ms);
+        let router = PriceRouter::load(&model, bc).await?;
+
+        Ok(Self {
+            model,
+            candles,
+            router,
+        })
+    }
+
+    /// Drops the stored candles of every pair the matching rows are priced
+    /// through, so they are requested from the providers again.
+    pub async fn refetch_candles(&self, filter: &RepriceFilter) -> Result<()> {
+        for bounds in self.model.get_price_history_bounds(filter).await? {
+            let Some(route) = self.router.route(&bounds.token) else {
+                continue;
+            };
+
+            for (pair, _) in route.legs() {
+                self.candles
+                    .invalidate(
+                        pair,
+                        bounds.from.timestamp() - 3600,
+                        bounds.to.timestamp() + 3600,
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reprices the next batch of matching rows after `after_id`. Returns
+    /// `None` once there are no rows left, otherwise the last id of the batch
+    /// and the rows that could be priced. A dry run is read-only: rows are
+    /// priced from the candles already stored and nothing is saved.
+    pub async fn reprice_batch(
+        &self,
+        filter: &RepriceFilter,
+        after_id: i64,
+        dry_run: bool,
+    ) -> Result<Option<(i64, Vec<Repriced>)>> {
+        let rows = self
+            .model
+            .get_price_history(filter, after_id, ROWS_PER_BATCH)
+            .await?;
+
+        let Some(last_id) = rows.last().map(|r| r.id) else {
+            return Ok(None);
+        };
+
+        let mut by_token = HashMap::<_, Vec<_>>::new();
+        for row in &rows {
+            by_token
+                .entry(row.token_addr.as_str())
+                .or_default()
+                .push(row);
+        }
+
+        let mut repriced = Vec::new();
+        for (token, rows) in by_token {
+            let Some(route) = self.router.route(token) else {
+                log::warn!("No route to usd for token {token}");
+                continue;
+            };
+
+            let route_pairs = route.pairs();
+            let values = if dry_run {
+                self.candles.stored_usd_values(route, &rows).await
+            } else {
+                self.candles.usd_values(route, &rows).await
+            };
+
+            for (row, value) in rows.into_iter().zip(values) {
+                let Some(new_usd_price) = value else {
+                    continue;
+                };
+
+                if !dry_run {
+                    self.model
+                        .update_usd_price(row.id, &new_usd_price, &route_pairs)
+                        .await?;
+                }
+
+                repriced.push(Repriced {
+                    id: row.id,
+                    token: token.to_string(),
+                    old_usd_price: row.usd_price.clone(),
+                    new_usd_price,
+                    route: route_pairs.clone(),
+                });
+            }
+        }
+
+        repriced.sort_by_key(|r| r.id);
+
+        Ok(Some((last_id, repriced)))
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use bigdecimal::{num_bigint::BigInt, BigDecimal};
+use indexer_repo::price::{DexPair, NftPriceModel, UsdPair};
+use indexer_repo::types::BcName;
+
+/// Longest chain of pairs before the usd pair
+const MAX_HOPS: usize = 3;
+
+/// Conversion of one token into another through a pair.
+#[derive(Clone, Debug)]
+pub struct Hop {
+    pub pair: String,
+    pub provider: Option<String>,
+    pub from_token: String,
+    pub to_token: String,
+    /// The token converted from is the left one of the pair
+    pub is_l2r: bool,
+    pub from_decimals: i32,
+    pub to_decimals: i32,
+}
+
+impl Hop {
+    /// Amount of `to_token` for `amount` of `from_token`, both in the smallest
+    /// units. `close` is the price of the left token in the right one.
+    fn convert(&self, amount: &BigDecimal, close: &BigDecimal) -> BigDecimal {
+        let scale = pow10(self.to_decimals) / pow10(self.from_decimals);
+
+        if self.is_l2r {
+            amount * close * scale
+        } else {
+            amount / close * scale
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UsdHop {
+    pub pair: String,
+    pub provider: Option<String>,
+    pub is_l2r: bool,
+    pub decimals: i32,
+}
+
+/// Pairs converting a token into usd, `hops` lead to a token of `usd`.
+#[derive(Clone, Debug)]
+pub struct PriceRoute {
+    pub hops: Vec<Hop>,
+    pub usd: UsdHop,
+    /// Lowest liquidity along the route, unknown liquidity counts as none
+    pub liquidity_usd: f64,
+}
+
+impl PriceRoute {
+    /// Pairs with their providers in conversion order, the usd pair last.
+    pub fn legs(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.hops
+            .iter()
+            .map(|h| (h.pair.as_str(), h.provider.as_deref()))
+            .chain(std::iter::once((
+                self.usd.pair.as_str(),
+                self.usd.provider.as_deref(),
+            )))
+    }
+
+    pub fn pairs(&self) -> Vec<String> {
+        self.legs().map(|(pair, _)| pair.to_string()).collect()
+    }
+
+    /// Usd value of `amount` of the token. `closes` go in the order of `pairs`.
+    pub fn usd_value(&self, amount: &BigDecimal, closes: &[&BigDecimal]) -> Option<BigDecimal> {
+        if closes.len() != self.hops.len() + 1 {
+            return None;
+        }
+
+        let amount = self
+            .hops
+            .iter()
+            .zip(closes)
+            .fold(amount.clone(), |amount, (hop, close)| {
+                hop.convert(&amount, close)
+            });
+
+        let close = closes[self.hops.len()];
+        let multiplier = pow10(self.usd.decimals);
+
+        Some(if self.usd.is_l2r {
+            &amount * close / &multiplier
+        } else {
+            &amount / close / &multiplier
+        })
+    }
+
+    fn visits(&self, token: &str) -> bool {
+        self.hops
+            .iter()
+            .any(|h| h.from_token == token || h.to_token == token)
+    }
+
+    fn is_better_than(&self, other: &PriceRoute) -> bool {
+        self.liquidity_usd > other.liquidity_usd
+            || (self.liquidity_usd == other.liquidity_usd && self.hops.len() < other.hops.len())
+    }
+}
+
+/// The most liquid route to usd of every token reachable through the pairs.
+/// Tokens with their own usd pair keep it.
+pub struct PriceRouter {
+    routes: HashMap<String, PriceRoute>,
+}
+
+impl PriceRouter {
+    pub fn new(pairs: &[DexPair], usd_pairs: &[UsdPair]) -> Self {
+        let mut routes = usd_pairs
+            .iter()
+            .map(|p| {
+                let route = PriceRoute {
+                    hops: Vec::new(),
+                    usd: UsdHop {
+                        pair: p.pair.clone(),
+                        provider: p.provider.clone(),
+                        is_l2r: p.is_l2r,
+                        decimals: p.decimals,
+                    },
+                    liquidity_usd: f64::INFINITY,
+                };
+                (p.token.clone(), route)
+            })
+            .collect::<HashMap<_, _>>();
+
+        let hops = pairs
+            .iter()
+            .flat_map(|p| {
+                let hop = |is_l2r: bool| Hop {
+                    pair: p.pair.clone(),
+                    provider: p.provider.clone(),
+                    from_token: if is_l2r {
+                        &p.left_token
+                    } else {
+                        &p.right_token
+                    }
+                    .clone(),
+                    to_token: if is_l2r {
+                        &p.right_token
+                    } else {
+                        &p.left_token
+                    }
+                    .clone(),
+                    is_l2r,
+                    from_decimals: if is_l2r {
+                        p.left_decimals
+                    } else {
+                        p.right_decimals
+                    },
+                    to_decimals: if is_l2r {
+                        p.right_decimals
+                    } else {
+                        p.left_decimals
+                    },
+                };
+                let liquidity = p.liquidity_usd.unwrap_or_default();
+                [(hop(true), liquidity), (hop(false), liquidity)]
+            })
+            .collect::<Vec<_>>();
+
+        // Every round extends the routes found so far by one hop
+        for _ in 0..MAX_HOPS {
+            let mut improved = Vec::new();
+
+            for (hop, liquidity) in &hops {
+                let Some(next) = routes.get(&hop.to_token) else {
+                    continue;
+                };
+                if next.hops.len() >= MAX_HOPS || next.visits(&hop.from_token) {
+                    continue;
+                }
+
+                let candidate = PriceRoute {
+                    hops: std::iter::once(hop.clone())
+                        .chain(next.hops.iter().cloned())
+                        .collect(),
+                    usd: next.usd.clone(),
+                    liquidity_usd: liquidity.min(next.liquidity_usd),
+                };
+
+                improved.push((hop.from_token.clone(), candidate));
+            }
+
+            let mut changed = false;
+            for (token, candidate) in improved {
+                let better = match routes.get(&token) {
+                    Some(current) => candidate.is_better_than(current),
+                    None => true,
+                };
+                if better {
+                    routes.insert(token, candidate);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        Self { routes }
+    }
+
+    /// Router over the pairs of the blockchain stored in the database.
+    pub async fn load(model: &NftPriceModel, bc: BcName) -> anyhow::Result<Self> {
+        let pairs = model.get_dex_pairs(bc).await?;
+        let usd_pairs = model.get_usd_pairs(bc).await?;
+
+        Ok(Self::new(&pairs, &usd_pairs))
+    }
+
+    pub fn route(&self, token: &str) -> Option<&PriceRoute> {
+        self.routes.get(token)
+    }
+
+    pub fn routes(&self) -> impl Iterator<Item = (&String, &PriceRoute)> {
+        self.routes.iter()
+    }
+}
+
+fn pow10(exp: i32) -> BigDecimal {
+    BigDecimal::from(BigInt::from(10).pow(exp.max(0) as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn pair(pair: &str, left: &str, right: &str, liquidity: f64) -> DexPair {
+        DexPair {
+            pair: pair.to_string(),
+            left_token: left.to_string(),
+            right_token: right.to_string(),
+            left_decimals: 9,
+            right_decimals: 9,
+            provider: None,
+            liquidity_usd: Some(liquidity),
+        }
+    }
+
+    #[test]
+    fn routes_through_the_most_liquid_pairs() {
+        let usd_pairs = [UsdPair {
+            token: "wvenom".to_string(),
+            pair: "wvenom_usdt".to_string(),
+            is_l2r: true,
+            decimals: 9,
+            provider: None,
+        }];
+        let pairs = [
+            pair("token_wvenom", "token", "wvenom", 100.0),
+            pair("token_qube", "token", "qube", 5000.0),
+            pair("wvenom_qube", "wvenom", "qube", 10000.0),
+        ];
+
+        let router = PriceRouter::new(&pairs, &usd_pairs);
+        let route = router.route("token").unwrap();
+
+        assert_eq!(route.pairs(), ["token_qube", "wvenom_qube", "wvenom_usdt"]);
+        assert_eq!(route.liquidity_usd, 5000.0);
+
+        // 1 token = 2 qube, 1 wvenom = 4 qube, 1 wvenom = 3 usd
+        let closes = ["2", "4", "3"].map(|c| BigDecimal::from_str(c).unwrap());
+        let value = route
+            .usd_value(
+                &BigDecimal::from(1_000_000_000),
+                &closes.iter().collect::<Vec<_>>(),
+            )
+            .unwrap();
+        assert_eq!(value, BigDecimal::from_str("1.5").unwrap());
+
+        assert!(router.route("unknown").is_none());
+    }
+}
@@ -0,0 +1,196 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::{ClientBuilder, Url};
+
+/// Client that only connects to public addresses. Hosts are resolved by
+/// [`PublicHostResolver`] on every new connection, so a name that starts
+/// resolving to a private address later is refused too. Proxies are not
+/// used, they would resolve the host themselves.
+pub fn public_client_builder() -> ClientBuilder {
+    reqwest::Client::builder()
+        .no_proxy()
+        .dns_resolver(Arc::new(PublicHostResolver))
+}
+
+/// Resolves hosts with the system resolver and fails unless every address
+/// of the host is public. The connection is made to the checked addresses.
+pub struct PublicHostResolver;
+
+impl Resolve for PublicHostResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = resolve_public(name.as_str()).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+async fn resolve_public(host: &str) -> Result<Vec<SocketAddr>> {
+    let addrs = tokio::net::lookup_host((host, 0))
+        .await?
+        .collect::<Vec<_>>();
+
+    if addrs.is_empty() {
+        bail!("Host {host} has no addresses");
+    }
+
+    if let Some(addr) = addrs.iter().find(|addr| !is_public_ip(addr.ip())) {
+        bail!("Host {host} resolves to a non-public address {}", addr.ip());
+    }
+
+    Ok(addrs)
+}
+
+/// Whether the host of `url` is an ip literal or a name that is public.
+/// Ip literals are not resolved by the client, so they are checked here.
+pub fn is_public_url(url: &Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+
+    match ip_literal(host) {
+        Some(ip) => is_public_ip(ip),
+        None => !host.eq_ignore_ascii_case("localhost"),
+    }
+}
+
+fn ip_literal(host: &str) -> Option<IpAddr> {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+/// Checks `url` the way a [`public_client_builder`] client would before
+/// connecting, for rejecting urls before they are stored
+pub async fn check_public_url(url: &Url) -> Result<()> {
+    if !is_public_url(url) {
+        bail!("Host of {url} is not public");
+    }
+
+    match url.host_str() {
+        Some(host) if ip_literal(host).is_none() => {
+            resolve_public(host).await?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+
+    let this_network = a == 0;
+    let shared = a == 100 && (b & 0xc0) == 64;
+    let benchmarking = a == 198 && (b & 0xfe) == 18;
+    let reserved = a >= 240;
+
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || this_network
+        || shared
+        || benchmarking
+        || reserved)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+
+    // ::ffff:a.b.c.d and 64:ff9b::a.b.c.d reach the ipv4 address
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(ip);
+    }
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [.., hi, lo] = segments;
+        return is_public_ipv4(Ipv4Addr::from(((hi as u32) << 16) | lo as u32));
+    }
+
+    let unique_local = (segments[0] & 0xfe00) == 0xfc00;
+    let link_local = (segments[0] & 0xffc0) == 0xfe80;
+    let documentation = segments[0] == 0x2001 && segments[1] == 0x0db8;
+    // deprecated ::a.b.c.d
+    let ipv4_compatible = segments[..6] == [0; 6];
+
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || unique_local
+        || link_local
+        || documentation
+        || ipv4_compatible)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_non_public_ips() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "100.127.255.255",
+            "0.0.0.0",
+            "224.0.0.1",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "::7f00:1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{ip}");
+        }
+
+        for ip in [
+            "1.1.1.1",
+            "100.128.0.1",
+            "2606:4700::1111",
+            "::ffff:1.1.1.1",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_resolved_addresses() {
+        let url = |s: &str| Url::parse(s).unwrap();
+
+        assert!(check_public_url(&url("http://localhost/hook"))
+            .await
+            .is_err());
+        assert!(check_public_url(&url("http://[::ffff:127.0.0.1]/"))
+            .await
+            .is_err());
+        assert!(check_public_url(&url("https://1.1.1.1/")).await.is_ok());
+
+        // a name is refused by what it resolves to, not by its spelling
+        assert!(check_public_url(&url("http://localhost./hook"))
+            .await
+            .is_err());
+        assert!(resolve_public("localhost").await.is_err());
+    }
+}
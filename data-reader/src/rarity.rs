@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use indexer_repo::rarity::RarityModel;
+use sqlx::PgPool;
+
+const COLLECTIONS_PER_ITERATION: i64 = 10;
+
+#[derive(Clone)]
+pub struct RarityUpdaterContext {
+    pub pool: PgPool,
+    pub idle_after_loop: u64,
+}
+
+/// Rescores nfts whose attributes or burn state changed. Marks accumulate
+/// while idle, so a mint wave is applied to the trait counts in one pass.
+pub async fn run_rarity_updater(context: RarityUpdaterContext) -> Result<()> {
+    log::info!("Run rarity updater");
+    let model = RarityModel::new(context.pool);
+
+    loop {
+        let dirty = match model.get_dirty_collections(COLLECTIONS_PER_ITERATION).await {
+            Ok(dirty) => dirty,
+            Err(e) => {
+                log::error!(
+                    "Error while reading collections for rarity update: {:#?}",
+                    e
+                );
+                tokio::time::sleep(Duration::from_secs(context.idle_after_loop)).await;
+                continue;
+            }
+        };
+
+        for collection in &dirty {
+            if let Err(e) = model.recompute_collection(collection).await {
+                log::error!(
+                    "Collection {}: error while computing rarity: {:#?}",
+                    collection.collection,
+                    e
+                );
+            }
+        }
+
+        if dirty.len() < COLLECTIONS_PER_ITERATION as usize {
+            tokio::time::sleep(Duration::from_secs(context.idle_after_loop)).await;
+        }
+    }
+}
@@ -1,32 +1,58 @@
 use anyhow::{anyhow, Result};
 use nekoton_abi::{FunctionBuilder, FunctionExt, UnpackFirst};
 use nekoton_utils::SimpleClock;
+use std::sync::Arc;
 use ton_block::{MsgAddrStd, MsgAddressInt};
-use transaction_consumer::JrpcClient;
+
+use crate::jrpc_pool::JrpcPool;
+use crate::offchain::OffchainResolver;
+
+/// Getters of a tip-3 root, the icon comes from its tip-4.2 json if the root has one.
+pub struct TokenRootMeta {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub icon: Option<String>,
+}
 
 #[derive(Clone)]
 pub struct MetadataJrpcService {
-    jrpc_client: JrpcClient,
+    jrpc_pool: Arc<JrpcPool>,
+    offchain: Option<Arc<OffchainResolver>>,
 }
 
 impl MetadataJrpcService {
-    pub fn new(jrpc_client: JrpcClient) -> Self {
-        Self { jrpc_client }
+    /// Off-chain metadata is not resolved without a resolver
+    pub fn new(jrpc_pool: Arc<JrpcPool>, offchain: Option<Arc<OffchainResolver>>) -> Self {
+        Self {
+            jrpc_pool,
+            offchain,
+        }
+    }
+
+    async fn parse_meta(&self, json: &str) -> Result<serde_json::Value> {
+        match &self.offchain {
+            Some(offchain) => offchain.resolve(json).await,
+            None => Ok(serde_json::from_str::<serde_json::Value>(json)?),
+        }
     }
 
     pub async fn get_nft_meta(&self, address: &MsgAddressInt) -> Result<serde_json::Value> {
         let contract = self
-            .jrpc_client
-            .get_contract_state(address)
+            .jrpc_pool
+            .request(|client| async move {
+                client
+                    .get_contract_state(address)
+                    .await
+                    .map_err(|e| anyhow!(e))
+            })
             .await?
             .ok_or_else(|| anyhow!("Contract state is none!"))?;
 
         let metadata =
             nekoton_contracts::tip4_2::MetadataContract(contract.as_context(&SimpleClock));
 
-        Ok(serde_json::from_str::<serde_json::Value>(
-            &metadata.get_json()?,
-        )?)
+        self.parse_meta(&metadata.get_json()?).await
     }
 
     fn owner() -> ton_abi::Function {
@@ -45,20 +71,83 @@ impl MetadataJrpcService {
             .build()
     }
 
+    /// Responsible getter of a tip-3 root
+    fn root_getter(name: &str, output: ton_abi::ParamType) -> ton_abi::Function {
+        FunctionBuilder::new(name)
+            .abi_version(ton_abi::contract::ABI_VERSION_2_2)
+            .default_headers()
+            .input("answerId", ton_abi::ParamType::Uint(32))
+            .output("value0", output)
+            .build()
+    }
+
+    pub async fn get_token_root_meta(&self, root: &MsgAddressInt) -> Result<TokenRootMeta> {
+        let contract = self
+            .jrpc_pool
+            .request(|client| async move {
+                client
+                    .get_contract_state(root)
+                    .await
+                    .map_err(|e| anyhow!(e))
+            })
+            .await?
+            .ok_or_else(|| anyhow!("Contract state is none!"))?;
+
+        let answer_id = [ton_abi::Token::new(
+            "answerId",
+            ton_abi::TokenValue::Uint(ton_abi::Uint::new(0, 32)),
+        )];
+
+        let run = |name: &str, output: ton_abi::ParamType| {
+            MetadataJrpcService::root_getter(name, output)
+                .run_local(&SimpleClock, contract.account.clone(), &answer_id)?
+                .tokens
+                .ok_or_else(|| anyhow!("Root {} returned no {}", root, name))
+        };
+
+        let name = run("name", ton_abi::ParamType::String)?.unpack_first::<String>()?;
+        let symbol = run("symbol", ton_abi::ParamType::String)?.unpack_first::<String>()?;
+        let decimals = run("decimals", ton_abi::ParamType::Uint(8))?.unpack_first::<u8>()?;
+
+        let icon = nekoton_contracts::tip4_2::MetadataContract(contract.as_context(&SimpleClock))
+            .get_json()
+            .ok()
+            .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+            .and_then(|meta| {
+                meta.get("preview")?
+                    .get("source")?
+                    .as_str()
+                    .map(str::to_string)
+            });
+
+        Ok(TokenRootMeta {
+            name,
+            symbol,
+            decimals,
+            icon,
+        })
+    }
+
     pub async fn get_collection_meta(
         &self,
         collection: MsgAddressInt,
     ) -> Result<(Option<String>, serde_json::Value)> {
+        let address = &collection;
         let contract = self
-            .jrpc_client
-            .get_contract_state(&collection)
+            .jrpc_pool
+            .request(|client| async move {
+                client
+                    .get_contract_state(address)
+                    .await
+                    .map_err(|e| anyhow!(e))
+            })
             .await?
             .ok_or_else(|| anyhow!("Contract state is none!"))?;
 
         let metadata =
             nekoton_contracts::tip4_2::MetadataContract(contract.as_context(&SimpleClock));
 
-        let meta = serde_json::from_str::<serde_json::Value>(&metadata.get_json()?)?;
+        let meta = self.parse_meta(&metadata.get_json()?).await?;
 
         let owner_contract =
             MetadataJrpcService::owner().run_local(&SimpleClock, contract.account.clone(), &[])?;
@@ -0,0 +1,27 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use indexer_repo::stats::CollectionStatsModel;
+use sqlx::PgPool;
+
+/// Usd prices of sales are filled in by the price reader within this window
+const SALES_LOOKBACK_SEC: i64 = 86_400;
+
+#[derive(Clone)]
+pub struct CollectionStatsContext {
+    pub pool: PgPool,
+    pub idle_after_loop: u64,
+}
+
+pub async fn run_collection_stats(context: CollectionStatsContext) -> Result<()> {
+    log::info!("Run collection stats aggregator");
+    let model = CollectionStatsModel::new(context.pool);
+
+    loop {
+        if let Err(e) = model.update_stats(SALES_LOOKBACK_SEC).await {
+            log::error!("Error while updating collection stats: {:#?}", e);
+        }
+
+        tokio::time::sleep(Duration::from_secs(context.idle_after_loop)).await;
+    }
+}
@@ -0,0 +1,85 @@
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use anyhow::{bail, Result};
+use indexer_repo::tokens::{TokenInfo, TokenModel};
+use sqlx::{types::chrono, PgPool};
+use ton_block::MsgAddressInt;
+
+use crate::jrpc_pool::JrpcPool;
+use crate::service::MetadataJrpcService;
+
+const TOKENS_PER_ITERATION: i64 = 100;
+/// Roots that failed to read are retried after a day
+const RETRY_FAILED_AFTER_SEC: i64 = 24 * 3600;
+
+#[derive(Clone)]
+pub struct TokenRegistryContext {
+    pub jrpc_pool: Arc<JrpcPool>,
+    pub pool: PgPool,
+    pub jrpc_req_latency_millis: u64,
+    pub idle_after_loop: u64,
+}
+
+/// Reads tip-3 root metadata of payment tokens seen in offers into the registry.
+pub async fn run_token_registry(context: TokenRegistryContext) -> Result<()> {
+    log::info!("Run token registry");
+    let jrpc_service = MetadataJrpcService::new(context.jrpc_pool.clone(), None);
+    let model = TokenModel::new(context.pool.clone());
+
+    loop {
+        let tokens = match model
+            .get_tokens_for_update(RETRY_FAILED_AFTER_SEC, TOKENS_PER_ITERATION)
+            .await
+        {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                log::error!("Error while reading tokens for registry update: {:#?}", e);
+                tokio::time::sleep(Duration::from_secs(context.idle_after_loop)).await;
+                continue;
+            }
+        };
+
+        for token in &tokens {
+            if let Err(e) = update_token(token, &model, &jrpc_service).await {
+                log::error!("Token {token}: error while reading root metadata: {:#?}", e);
+
+                let now = chrono::Utc::now().naive_utc();
+                if let Err(e) = model.mark_failed(token, now).await {
+                    log::error!("Token {token}: error while marking as failed: {:#?}", e);
+                }
+            }
+
+            tokio::time::sleep(Duration::from_millis(context.jrpc_req_latency_millis)).await;
+        }
+
+        if tokens.len() < TOKENS_PER_ITERATION as usize {
+            tokio::time::sleep(Duration::from_secs(context.idle_after_loop)).await;
+        }
+    }
+}
+
+async fn update_token(
+    address: &str,
+    model: &TokenModel,
+    jrpc_service: &MetadataJrpcService,
+) -> Result<()> {
+    let Ok(root) = MsgAddressInt::from_str(address) else {
+        bail!(
+            "Error while converting token address {} to MsgAddressInt",
+            address
+        );
+    };
+
+    let meta = jrpc_service.get_token_root_meta(&root).await?;
+
+    model
+        .save_token(&TokenInfo {
+            address: address.to_string(),
+            name: Some(meta.name),
+            symbol: Some(meta.symbol),
+            decimals: Some(meta.decimals as i32),
+            icon: meta.icon,
+            updated: chrono::Utc::now().naive_utc(),
+        })
+        .await
+}
@@ -0,0 +1,351 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Result};
+use futures::StreamExt;
+use hmac::{Hmac, Mac};
+use indexer_repo::types::WebhookDeliveryStatus;
+use indexer_repo::webhooks::{DeliveryAttempt, PendingDelivery, WebhookModel};
+use reqwest::{redirect, Url};
+use sha2::Sha256;
+use sqlx::PgPool;
+
+use crate::public_host::{check_public_url, is_public_url, public_client_builder};
+
+const DELIVERIES_PER_ITERATION: i64 = 100;
+const CONCURRENT_DELIVERIES: usize = 16;
+const MAX_ATTEMPTS: i32 = 8;
+const FIRST_RETRY_SEC: i64 = 30;
+const MAX_RETRY_SEC: i64 = 3_600;
+const REQUEST_TIMEOUT_SEC: u64 = 10;
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+#[derive(Clone)]
+pub struct WebhookSenderContext {
+    pub pool: PgPool,
+    pub idle_after_loop: u64,
+    pub auction_ending_window_sec: u64,
+}
+
+pub async fn run_webhook_sender(context: WebhookSenderContext) -> Result<()> {
+    log::info!("Run webhook sender");
+    let model = WebhookModel::new(context.pool);
+    // every endpoint gets its own timeout, a slow one only holds its slot
+    let client = public_client_builder()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SEC))
+        .redirect(redirect::Policy::none())
+        .build()?;
+
+    loop {
+        if let Err(e) = model
+            .enqueue_auction_ending(context.auction_ending_window_sec as i64)
+            .await
+        {
+            log::error!(
+                "Error while enqueueing auction ending notifications: {:#?}",
+                e
+            );
+        }
+
+        let deliveries = match model.get_due_deliveries(DELIVERIES_PER_ITERATION).await {
+            Ok(deliveries) => deliveries,
+            Err(e) => {
+                log::error!("Error while fetching webhook deliveries: {:#?}", e);
+                tokio::time::sleep(Duration::from_secs(context.idle_after_loop)).await;
+                continue;
+            }
+        };
+
+        futures::stream::iter(&deliveries)
+            .for_each_concurrent(CONCURRENT_DELIVERIES, |delivery| {
+                let (client, model) = (&client, &model);
+                async move {
+                    let attempt = deliver(client, delivery).await;
+
+                    if attempt.status == WebhookDeliveryStatus::Failed {
+                        log::warn!(
+                            "Giving up on webhook {} delivery {} after {} attempts",
+                            delivery.webhook_id,
+                            delivery.id,
+                            attempt.attempt
+                        );
+                    }
+
+                    if let Err(e) = model.record_delivery_attempt(&attempt).await {
+                        log::error!("Error while saving webhook delivery attempt: {:#?}", e);
+                    }
+                }
+            })
+            .await;
+
+        if deliveries.len() < DELIVERIES_PER_ITERATION as usize {
+            tokio::time::sleep(Duration::from_secs(context.idle_after_loop)).await;
+        }
+    }
+}
+
+async fn deliver(client: &reqwest::Client, delivery: &PendingDelivery) -> DeliveryAttempt {
+    let attempt = delivery.attempts + 1;
+    let started = Instant::now();
+
+    let result = send_webhook(
+        client,
+        &delivery.url,
+        &delivery.secret,
+        delivery.id,
+        &delivery.payload,
+    )
+    .await;
+
+    let (response_status, error) = match result {
+        Ok(status) if (200..300).contains(&status) => (Some(status as i32), None),
+        Ok(status) => (
+            Some(status as i32),
+            Some(format!("Unexpected status {status}")),
+        ),
+        Err(e) => (None, Some(e.to_string())),
+    };
+
+    let status = if error.is_none() {
+        WebhookDeliveryStatus::Delivered
+    } else if attempt >= MAX_ATTEMPTS {
+        WebhookDeliveryStatus::Failed
+    } else {
+        WebhookDeliveryStatus::Pending
+    };
+
+    DeliveryAttempt {
+        delivery_id: delivery.id,
+        attempt,
+        response_status,
+        error,
+        duration_ms: started.elapsed().as_millis() as i32,
+        status,
+        retry_after_sec: retry_after_sec(attempt),
+    }
+}
+
+fn retry_after_sec(attempt: i32) -> i64 {
+    FIRST_RETRY_SEC
+        .saturating_mul(1 << (attempt - 1).clamp(0, 16))
+        .min(MAX_RETRY_SEC)
+}
+
+/// Webhook urls must be http(s) and point to a public host. Deliveries check
+/// the host again, it may resolve to other addresses by then.
+pub async fn check_webhook_url(url: &str) -> Result<()> {
+    let url = Url::parse(url)?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!("Url must be http(s)");
+    }
+
+    check_public_url(&url).await
+}
+
+/// Signs `{timestamp}.{body}` with the webhook secret, so a receiver can check both
+/// the origin and the freshness of a request.
+pub fn sign_payload(secret: &str, timestamp: u64, body: &str) -> Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|e| anyhow!(e))?;
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+
+    Ok(format!(
+        "sha256={}",
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
+
+pub async fn send_webhook(
+    client: &reqwest::Client,
+    url: &str,
+    secret: &str,
+    delivery_id: i64,
+    payload: &serde_json::Value,
+) -> Result<u16> {
+    let url = Url::parse(url)?;
+    if !is_public_url(&url) {
+        bail!("Host of {url} is not public");
+    }
+
+    let body = serde_json::to_string(payload)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let signature = sign_payload(secret, timestamp, &body)?;
+
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, signature)
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(DELIVERY_HEADER, delivery_id)
+        .body(body)
+        .send()
+        .await?;
+
+    Ok(response.status().as_u16())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+
+    struct ReceivedRequest {
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    /// Receivers listen on loopback, the test client resolves this name to them
+    const RECEIVER_HOST: &str = "hooks.test";
+
+    fn test_client(addr: SocketAddr) -> reqwest::Client {
+        reqwest::Client::builder()
+            .resolve(RECEIVER_HOST, addr)
+            .build()
+            .unwrap()
+    }
+
+    /// Accepts a single request and answers it with `status`.
+    fn spawn_receiver(status: u16) -> (String, reqwest::Client, mpsc::Receiver<ReceivedRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let url = format!("http://{RECEIVER_HOST}:{}/hook", addr.port());
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut headers = HashMap::new();
+
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+                }
+            }
+
+            let length = headers
+                .get("content-length")
+                .map(|l| l.parse::<usize>().unwrap())
+                .unwrap_or_default();
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )
+            .unwrap();
+
+            tx.send(ReceivedRequest {
+                headers,
+                body: String::from_utf8(body).unwrap(),
+            })
+            .unwrap();
+        });
+
+        (url, test_client(addr), rx)
+    }
+
+    #[tokio::test]
+    async fn delivered_request_is_signed() {
+        let (url, client, rx) = spawn_receiver(200);
+        let payload = serde_json::json!({ "kind": "outbid", "nft": "0:01" });
+
+        let status = send_webhook(&client, &url, "secret", 42, &payload)
+            .await
+            .unwrap();
+        let request = rx.recv().unwrap();
+
+        assert_eq!(status, 200);
+        assert_eq!(request.headers["x-webhook-delivery"], "42");
+
+        let timestamp = request.headers["x-webhook-timestamp"].parse().unwrap();
+        let expected = sign_payload("secret", timestamp, &request.body).unwrap();
+        assert_eq!(request.headers["x-webhook-signature"], expected);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&request.body).unwrap(),
+            payload
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_delivery_is_retried() {
+        let (url, client, _rx) = spawn_receiver(503);
+        let delivery = PendingDelivery {
+            id: 1,
+            webhook_id: 1,
+            url,
+            secret: "secret".to_string(),
+            payload: serde_json::json!({}),
+            attempts: 2,
+        };
+
+        let attempt = deliver(&client, &delivery).await;
+
+        assert_eq!(attempt.attempt, 3);
+        assert_eq!(attempt.response_status, Some(503));
+        assert_eq!(attempt.status, WebhookDeliveryStatus::Pending);
+        assert_eq!(attempt.retry_after_sec, FIRST_RETRY_SEC * 4);
+    }
+
+    #[tokio::test]
+    async fn delivery_gives_up_after_max_attempts() {
+        let delivery = PendingDelivery {
+            id: 1,
+            webhook_id: 1,
+            url: format!("http://{RECEIVER_HOST}:1/unreachable"),
+            secret: "secret".to_string(),
+            payload: serde_json::json!({}),
+            attempts: MAX_ATTEMPTS - 1,
+        };
+        let client = test_client("127.0.0.1:1".parse().unwrap());
+
+        let attempt = deliver(&client, &delivery).await;
+
+        assert_eq!(attempt.response_status, None);
+        assert!(attempt.error.is_some());
+        assert_eq!(attempt.status, WebhookDeliveryStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn private_targets_are_refused() {
+        let client = public_client_builder().build().unwrap();
+        let payload = serde_json::json!({});
+
+        for url in [
+            "http://127.0.0.1/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "http://100.64.0.1/hook",
+            // resolved on delivery, not only when registered
+            "http://localhost./hook",
+        ] {
+            let error = send_webhook(&client, url, "secret", 1, &payload).await;
+            assert!(error.is_err(), "{url}");
+        }
+
+        assert!(check_webhook_url("ftp://example.com/hook").await.is_err());
+        assert!(check_webhook_url("http://10.0.0.1/hook").await.is_err());
+    }
+
+    #[test]
+    fn retry_backoff_is_capped() {
+        assert_eq!(retry_after_sec(1), FIRST_RETRY_SEC);
+        assert_eq!(retry_after_sec(2), FIRST_RETRY_SEC * 2);
+        assert_eq!(retry_after_sec(MAX_ATTEMPTS * 4), MAX_RETRY_SEC);
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::HashSet;
+
+use actix_web::HttpRequest;
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Keys of the clients allowed to manage server-side state, e.g. webhooks.
+/// Without keys these endpoints are closed.
+#[derive(Clone, Default)]
+pub struct ApiKeys(HashSet<String>);
+
+impl ApiKeys {
+    pub fn new(keys: impl IntoIterator<Item = String>) -> Self {
+        Self(keys.into_iter().filter(|k| !k.is_empty()).collect())
+    }
+
+    pub fn authorize(&self, req: &HttpRequest) -> bool {
+        req.headers()
+            .get(API_KEY_HEADER)
+            .and_then(|key| key.to_str().ok())
+            .is_some_and(|key| self.0.contains(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn authorizes_known_keys() {
+        let keys = ApiKeys::new(["key".to_string(), String::new()]);
+        let request = |key: Option<&str>| {
+            let mut request = TestRequest::default();
+            if let Some(key) = key {
+                request = request.insert_header((API_KEY_HEADER, key));
+            }
+            request.to_http_request()
+        };
+
+        assert!(keys.authorize(&request(Some("key"))));
+        assert!(!keys.authorize(&request(Some("other"))));
+        assert!(!keys.authorize(&request(Some(""))));
+        assert!(!keys.authorize(&request(None)));
+        assert!(!ApiKeys::default().authorize(&request(Some("key"))));
+    }
+}
@@ -3,7 +3,25 @@
 use actix_web::{get, web};
 use opg::*;
 
-use crate::api::metadata::RefreshMetadataParams;
+use crate::api::events::EventsPage;
+use crate::api::facets::{NftSearchPage, NftSearchParams};
+use crate::api::fiat::FiatRateResponse;
+use crate::api::media::MediaResponse;
+use crate::api::metadata::{
+    MetaDiffResponse, MetaReaderMetricsResponse, NftMetaVersionResponse, NormalizedNftMetaResponse,
+    RefreshMetadataParams,
+};
+use crate::api::nfts::{CollectionResponse, NftResponse, NftsPage, PriceHistoryPage};
+use crate::api::offers::{AuctionsPage, DirectBuysPage, DirectSellsPage};
+use crate::api::portfolio::PortfolioResponse;
+use crate::api::rarity::{NftRarityResponse, RankedNftsPage};
+use crate::api::search::SearchHitResponse;
+use crate::api::stats::CollectionStatsResponse;
+use crate::api::tokens::TokenResponse;
+use crate::api::webhooks::{
+    DeleteWebhookParams, RegisterWebhookParams, RegisterWebhookResponse, WebhookDeliveriesParams,
+    WebhookDeliveryResponse,
+};
 
 pub fn swagger(api_url: &str) -> Opg {
     describe_api! {
@@ -13,11 +31,29 @@ pub fn swagger(api_url: &str) -> Opg {
             description: "Provides manual management"
         },
         tags: {
-            metadata
+            metadata,
+            webhooks,
+            events,
+            nfts,
+            collections,
+            offers,
+            owners,
+            search,
+            tokens,
+            fiat,
+            media,
+            graphql
         },
         servers: {
             api_url
         },
+        security_schemes: {
+            (apiKey "ApiKey"): {
+                parameter_in: Header,
+                name: crate::api::auth::API_KEY_HEADER,
+                description: "One of the configured `API_KEYS`",
+            },
+        },
         paths: {
             ("metadata" / "refresh"): {
                 POST: {
@@ -26,6 +62,357 @@ pub fn swagger(api_url: &str) -> Opg {
                     body: RefreshMetadataParams,
                 }
             },
+            ("metadata" / "reader" / "metrics"): {
+                GET: {
+                    tags: { metadata },
+                    summary: "Throughput and queue depth of the metadata reader, state of every jrpc endpoint",
+                    200: MetaReaderMetricsResponse,
+                }
+            },
+            ("webhooks"): {
+                POST: {
+                    tags: { webhooks },
+                    summary: "Register a webhook for NFT and collection activity",
+                    security: { "ApiKey" },
+                    body: RegisterWebhookParams,
+                    200: RegisterWebhookResponse,
+                }
+            },
+            ("webhooks" / "delete"): {
+                POST: {
+                    tags: { webhooks },
+                    summary: "Delete a webhook",
+                    security: { "ApiKey" },
+                    body: DeleteWebhookParams,
+                }
+            },
+            ("webhooks" / "deliveries"): {
+                POST: {
+                    tags: { webhooks },
+                    summary: "Delivery log of a webhook",
+                    security: { "ApiKey" },
+                    body: WebhookDeliveriesParams,
+                    200: std::vec::Vec<WebhookDeliveryResponse>,
+                }
+            },
+            ("search"): {
+                GET: {
+                    tags: { search },
+                    summary: "NFTs, collections and users by name prefixes or address, exact address hits first",
+                    parameters: {
+                        (query q: String): {
+                            required: true,
+                        },
+                        (query kinds: String): {
+                            description: "Comma separated nft, collection or user, all by default",
+                        },
+                        (query limit: i64): {
+                            description: "Hits of every kind, 10 by default, at most 50",
+                        },
+                    },
+                    200: std::vec::Vec<SearchHitResponse>,
+                }
+            },
+            ("tokens"): {
+                GET: {
+                    tags: { tokens },
+                    summary: "Tip-3 root metadata of the payment tokens seen in offers",
+                    200: std::vec::Vec<TokenResponse>,
+                }
+            },
+            ("tokens" / { address: String }): {
+                GET: {
+                    tags: { tokens },
+                    summary: "Tip-3 root metadata of a payment token",
+                    200: TokenResponse,
+                }
+            },
+            ("fiat-rates"): {
+                GET: {
+                    tags: { fiat },
+                    summary: "The latest rate of every fiat currency, in units per one usd",
+                    200: std::vec::Vec<FiatRateResponse>,
+                }
+            },
+            ("media" / { address: String }): {
+                GET: {
+                    tags: { media },
+                    summary: "Copies and thumbnails of the preview of an nft, or the logo and wallpaper of a collection",
+                    200: std::vec::Vec<MediaResponse>,
+                }
+            },
+            ("events"): {
+                GET: {
+                    tags: { events },
+                    summary: "Marketplace events, newest first",
+                    parameters: {
+                        (query owner: String): {},
+                        (query nft: String): {},
+                        (query collection: String): {
+                            description: "Comma separated collections, at most 20",
+                        },
+                        (query event_kind: String): {
+                            description: "Comma separated event kinds",
+                        },
+                        (query verified: bool): {
+                            description: "Only events of verified collections",
+                        },
+                        (query with_count: bool): {
+                            description: "Return total_count on the first page",
+                        },
+                        (query cursor: String): {
+                            description: "next_cursor of the previous page",
+                        },
+                        (query limit: i64): {},
+                    },
+                    200: EventsPage,
+                }
+            },
+            ("events" / "stream"): {
+                GET: {
+                    tags: { events },
+                    summary: "Live stream of committed events (text/event-stream)",
+                    description: "Message id is the event position `<lt>:<id>`, reconnecting with Last-Event-ID resumes right after it. State changes of direct offers carry the accepted transition in `state_change`",
+                    parameters: {
+                        (query from_lt: i64): {
+                            description: "Replay events after this logical time before going live",
+                        },
+                        (query collection: String): {},
+                        (query nft: String): {},
+                        (query owner: String): {},
+                        (query event_type: String): {
+                            description: "Comma separated event types",
+                        },
+                    },
+                    200: String,
+                }
+            },
+            ("nfts" / { address: String }): {
+                GET: {
+                    tags: { nfts },
+                    summary: "NFT details",
+                    200: NftResponse,
+                }
+            },
+            ("nfts"): {
+                GET: {
+                    tags: { nfts },
+                    summary: "NFTs of an owner or a collection, ordered by address or from the rarest",
+                    parameters: {
+                        (query owner: String): {},
+                        (query collection: String): {},
+                        (query rarity: String): {
+                            description: "Order a collection from the rarest by statistical, information_content or trait_count",
+                        },
+                        (query min_rank: i32): {
+                            description: "Orders by statistical rarity unless rarity is given",
+                        },
+                        (query max_rank: i32): {},
+                        (query cursor: String): {
+                            description: "next_cursor of the previous page",
+                        },
+                        (query limit: i64): {},
+                    },
+                    200: NftsPage,
+                }
+            },
+            ("nfts" / { address: String } / "rarity"): {
+                GET: {
+                    tags: { nfts },
+                    summary: "Rarity score and rank of an NFT by every algorithm",
+                    200: std::vec::Vec<NftRarityResponse>,
+                }
+            },
+            ("nfts" / { address: String } / "metadata"): {
+                GET: {
+                    tags: { nfts },
+                    summary: "Tip-4.2 fields of the NFT metadata with its violations of the standard",
+                    200: NormalizedNftMetaResponse,
+                }
+            },
+            ("nfts" / { address: String } / "metadata" / "versions"): {
+                GET: {
+                    tags: { nfts },
+                    summary: "Every distinct metadata json of an NFT, newest first",
+                    200: std::vec::Vec<NftMetaVersionResponse>,
+                }
+            },
+            ("nfts" / { address: String } / "metadata" / "diff"): {
+                GET: {
+                    tags: { nfts },
+                    summary: "Changes of the NFT metadata between two versions",
+                    parameters: {
+                        (query from: i32): {
+                            required: true,
+                        },
+                        (query to: i32): {
+                            required: true,
+                        },
+                    },
+                    200: MetaDiffResponse,
+                }
+            },
+            ("price-history"): {
+                GET: {
+                    tags: { nfts },
+                    summary: "Deal prices of an NFT or a collection, newest first",
+                    parameters: {
+                        (query nft: String): {},
+                        (query collection: String): {},
+                        (query currency: String): {
+                            description: "Adds usd prices converted into the currency at the rate of their time",
+                        },
+                        (query cursor: String): {
+                            description: "next_cursor of the previous page",
+                        },
+                        (query limit: i64): {},
+                    },
+                    200: PriceHistoryPage,
+                }
+            },
+            ("collections" / { address: String }): {
+                GET: {
+                    tags: { collections },
+                    summary: "Collection details",
+                    200: CollectionResponse,
+                }
+            },
+            ("collections" / { address: String } / "stats"): {
+                GET: {
+                    tags: { collections },
+                    summary: "Hourly or daily collection stats for charts, oldest first",
+                    parameters: {
+                        (query period: String): {
+                            description: "hour or day",
+                            required: true,
+                        },
+                        (query from: i64): {
+                            description: "Unix time of the first bucket",
+                        },
+                        (query to: i64): {
+                            description: "Unix time after the last bucket",
+                        },
+                        (query limit: i64): {
+                            description: "Buckets, 168 by default and at most 1000",
+                        },
+                    },
+                    200: CollectionStatsResponse,
+                }
+            },
+            ("collections" / { address: String } / "nfts" / "search"): {
+                POST: {
+                    tags: { collections },
+                    summary: "NFTs of a collection filtered by traits, price, listing and owner, with trait facets",
+                    body: NftSearchParams,
+                    200: NftSearchPage,
+                }
+            },
+            ("collections" / { address: String } / "rarity"): {
+                GET: {
+                    tags: { collections },
+                    summary: "NFTs of a collection from the rarest",
+                    parameters: {
+                        (query algorithm: String): {
+                            description: "statistical (default), information_content or trait_count",
+                        },
+                        (query min_rank: i32): {},
+                        (query max_rank: i32): {},
+                        (query cursor: String): {
+                            description: "next_cursor of the previous page",
+                        },
+                        (query limit: i64): {},
+                    },
+                    200: RankedNftsPage,
+                }
+            },
+            ("auctions"): {
+                GET: {
+                    tags: { offers },
+                    summary: "Active auctions, latest first",
+                    parameters: {
+                        (query collection: String): {},
+                        (query nft: String): {},
+                        (query owner: String): {
+                            description: "NFT owner",
+                        },
+                        (query currency: String): {
+                            description: "Adds usd values converted into the currency, e.g. EUR",
+                        },
+                        (query cursor: String): {
+                            description: "next_cursor of the previous page",
+                        },
+                        (query limit: i64): {},
+                    },
+                    200: AuctionsPage,
+                }
+            },
+            ("listings"): {
+                GET: {
+                    tags: { offers },
+                    summary: "Active direct sells, latest first",
+                    parameters: {
+                        (query collection: String): {},
+                        (query nft: String): {},
+                        (query owner: String): {
+                            description: "Seller",
+                        },
+                        (query currency: String): {
+                            description: "Adds usd values converted into the currency, e.g. EUR",
+                        },
+                        (query cursor: String): {
+                            description: "next_cursor of the previous page",
+                        },
+                        (query limit: i64): {},
+                    },
+                    200: DirectSellsPage,
+                }
+            },
+            ("offers"): {
+                GET: {
+                    tags: { offers },
+                    summary: "Active direct buy offers, latest first",
+                    parameters: {
+                        (query collection: String): {},
+                        (query nft: String): {},
+                        (query owner: String): {
+                            description: "Buyer",
+                        },
+                        (query currency: String): {
+                            description: "Adds usd values converted into the currency, e.g. EUR",
+                        },
+                        (query cursor: String): {
+                            description: "next_cursor of the previous page",
+                        },
+                        (query limit: i64): {},
+                    },
+                    200: DirectBuysPage,
+                }
+            },
+            ("owners" / { address: String } / "portfolio"): {
+                GET: {
+                    tags: { owners },
+                    summary: "NFTs of an owner grouped by collection with valuations",
+                    parameters: {
+                        (query currency: String): {
+                            description: "Adds totals converted into the currency, e.g. EUR",
+                        },
+                    },
+                    200: PortfolioResponse,
+                }
+            },
+            ("graphql"): {
+                GET: {
+                    tags: { graphql },
+                    summary: "GraphiQL playground",
+                    200: String,
+                },
+                POST: {
+                    tags: { graphql },
+                    summary: "GraphQL endpoint over NFTs, collections and offers",
+                    description: "Takes a standard GraphQL request body, see the schema in the playground. Queries are limited in depth and complexity, lists return at most 100 entries",
+                    200: String,
+                }
+            },
         }
     }
 }
@@ -0,0 +1,578 @@
+//! Schemas of `nft_events.args`, as the indexer serializes the decoded contract events.
+
+use std::borrow::Cow;
+
+use indexer_repo::types::EventType;
+use opg::OpgModel;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct MarketOffer {
+    #[opg(string)]
+    collection: String,
+    #[opg(string)]
+    nft_owner: String,
+    #[opg(string)]
+    nft: String,
+    #[opg(string)]
+    offer: String,
+    #[opg(integer)]
+    price: Number,
+    #[opg(integer)]
+    auction_duration: Number,
+    deploy_nonce: u64,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct AuctionDetails {
+    #[opg(string)]
+    auction_subject: String,
+    #[opg(string)]
+    subject_owner: String,
+    #[opg(string)]
+    payment_token: String,
+    #[opg(string)]
+    wallet_for_bids: String,
+    start_time: u64,
+    duration: u64,
+    end_time: u64,
+    #[opg(integer)]
+    price: Number,
+    nonce: u64,
+    #[opg("Created, Active, Complete or Cancelled", string)]
+    status: String,
+    #[opg(string)]
+    collection: String,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct DirectBuyInfo {
+    #[opg(string)]
+    factory: String,
+    #[opg(string)]
+    creator: String,
+    #[opg(string)]
+    spent_token: String,
+    #[opg(string)]
+    nft: String,
+    _time_tx: u64,
+    #[opg(integer)]
+    _price: Number,
+    #[opg(string)]
+    spent_wallet: String,
+    status: u8,
+    start_time_buy: u64,
+    duration_time_buy: u64,
+    end_time_buy: u64,
+    #[opg(string)]
+    collection: String,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct DirectSellInfo {
+    #[opg(string)]
+    factory: String,
+    #[opg(string)]
+    creator: String,
+    #[opg(string)]
+    token: String,
+    #[opg(string)]
+    nft: String,
+    _time_tx: u64,
+    start: u64,
+    end: u64,
+    #[opg(integer)]
+    _price: Number,
+    #[opg(string)]
+    wallet: String,
+    status: u8,
+    #[opg(string)]
+    collection: String,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct MarketFee {
+    numerator: u32,
+    denominator: u32,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct CollectionFeeInfo {
+    #[opg(string)]
+    code_hash: String,
+    code_depth: u16,
+    numerator: u32,
+    denominator: u32,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct AuctionDeployedArgs {
+    #[opg(string)]
+    offer: String,
+    offer_info: MarketOffer,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct AuctionDeclinedArgs {
+    #[opg(string)]
+    nft_owner: String,
+    #[opg(string)]
+    nft: String,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+/// Created, active and cancelled auctions
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct AuctionArgs {
+    value0: AuctionDetails,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct AuctionBidPlacedArgs {
+    #[opg(string)]
+    buyer: String,
+    #[opg(integer)]
+    value: Number,
+    #[opg(integer)]
+    next_bid_value: Number,
+    value3: AuctionDetails,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+/// Declined bids and completed auctions
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct AuctionBidArgs {
+    #[opg(string)]
+    buyer: String,
+    #[opg(integer)]
+    value: Number,
+    value2: AuctionDetails,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct AuctionExpiredArgs {
+    #[opg(string)]
+    seller: String,
+    finished_at: i64,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct DirectBuyDeployedArgs {
+    #[opg(string)]
+    direct_buy: String,
+    #[opg(string)]
+    sender: String,
+    #[opg(string)]
+    token: String,
+    #[opg(string)]
+    nft: String,
+    nonce: u64,
+    #[opg(integer)]
+    amount: Number,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct DirectBuyDeclinedArgs {
+    #[opg(string)]
+    sender: String,
+    #[opg(string)]
+    token: String,
+    #[opg(integer)]
+    amount: Number,
+    #[opg(string)]
+    nft: String,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct DirectBuyStateChangedArgs {
+    from: u8,
+    to: u8,
+    value2: DirectBuyInfo,
+    #[opg(string)]
+    old_owner: String,
+    #[opg(string)]
+    new_owner: String,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct DirectBuyExpiredArgs {
+    from: u8,
+    to: u8,
+    #[opg(string)]
+    buyer: String,
+    expired_at: i64,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct DirectSellDeployedArgs {
+    #[opg(string)]
+    direct_sell: String,
+    #[opg(string)]
+    sender: String,
+    #[opg(string)]
+    payment_token: String,
+    #[opg(string)]
+    nft: String,
+    nonce: u64,
+    #[opg(integer)]
+    price: Number,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct DirectSellDeclinedArgs {
+    #[opg(string)]
+    sender: String,
+    #[opg(string)]
+    nft: String,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct DirectSellStateChangedArgs {
+    from: u8,
+    to: u8,
+    value2: DirectSellInfo,
+    #[opg(string)]
+    old_owner: String,
+    #[opg(string)]
+    new_owner: String,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct DirectSellExpiredArgs {
+    from: u8,
+    to: u8,
+    #[opg(string)]
+    seller: String,
+    expired_at: i64,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+/// Nft and collection ownership changes
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct OwnerChangedArgs {
+    #[opg(string)]
+    old_owner: String,
+    #[opg(string)]
+    new_owner: String,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct ManagerChangedArgs {
+    #[opg(string)]
+    old_manager: String,
+    #[opg(string)]
+    new_manager: String,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct NftCreatedArgs {
+    #[opg(string)]
+    id: String,
+    #[opg(string)]
+    nft: String,
+    #[opg(string)]
+    owner: String,
+    #[opg(string)]
+    manager: String,
+    #[opg(string)]
+    creator: String,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct NftBurnedArgs {
+    #[opg(string)]
+    id: String,
+    #[opg(string)]
+    nft: String,
+    #[opg(string)]
+    owner: String,
+    #[opg(string)]
+    manager: String,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct MarketFeeDefaultChangedArgs {
+    fee: MarketFee,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct MarketFeeChangedArgs {
+    #[opg(string)]
+    auction: String,
+    fee: MarketFee,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct AddCollectionRulesArgs {
+    #[opg(string)]
+    collection: String,
+    collection_fee_info: CollectionFeeInfo,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct RemoveCollectionRulesArgs {
+    #[opg(string)]
+    collection: String,
+    #[serde(flatten)]
+    #[opg(optional)]
+    extra: ExtraArgs,
+}
+
+/// Fields missing from a schema, e.g. added to a contract event later. They are kept
+/// next to the known fields rather than dropped, so typed args round-trip as stored.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct ExtraArgs(Map<String, Value>);
+
+// Serialized inline, the schema only notes that more fields may follow
+impl OpgModel for ExtraArgs {
+    fn get_schema(_: &mut opg::Components) -> opg::Model {
+        opg::Model {
+            description: Some(
+                "Fields not covered by the schema, inlined into the args".to_string(),
+            ),
+            data: opg::ModelData::Single(opg::ModelType {
+                nullable: false,
+                type_description: opg::ModelTypeDescription::Object(Default::default()),
+            }),
+        }
+    }
+
+    fn type_name() -> Option<Cow<'static, str>> {
+        Some(Cow::Borrowed("ExtraArgs"))
+    }
+}
+
+/// Args that don't match the schema of their event type are passed through as is
+#[derive(Serialize)]
+pub struct RawArgs(Value);
+
+// The derive can't mark a newtype as `any`
+impl OpgModel for RawArgs {
+    fn get_schema(_: &mut opg::Components) -> opg::Model {
+        opg::Model {
+            description: Some("Event args as stored".to_string()),
+            data: opg::ModelData::Single(opg::ModelType {
+                nullable: true,
+                type_description: opg::ModelTypeDescription::Object(Default::default()),
+            }),
+        }
+    }
+
+    fn type_name() -> Option<Cow<'static, str>> {
+        Some(Cow::Borrowed("RawArgs"))
+    }
+}
+
+/// `args` of an event, the schema is selected by `event_type`.
+#[derive(Serialize, OpgModel)]
+#[serde(untagged)]
+pub enum EventArgs {
+    AuctionDeployed(AuctionDeployedArgs),
+    AuctionDeclined(AuctionDeclinedArgs),
+    Auction(AuctionArgs),
+    AuctionBidPlaced(AuctionBidPlacedArgs),
+    AuctionBid(AuctionBidArgs),
+    AuctionExpired(AuctionExpiredArgs),
+    DirectBuyDeployed(DirectBuyDeployedArgs),
+    DirectBuyDeclined(DirectBuyDeclinedArgs),
+    DirectBuyStateChanged(DirectBuyStateChangedArgs),
+    DirectBuyExpired(DirectBuyExpiredArgs),
+    DirectSellDeployed(DirectSellDeployedArgs),
+    DirectSellDeclined(DirectSellDeclinedArgs),
+    DirectSellStateChanged(DirectSellStateChangedArgs),
+    DirectSellExpired(DirectSellExpiredArgs),
+    OwnerChanged(OwnerChangedArgs),
+    ManagerChanged(ManagerChangedArgs),
+    NftCreated(NftCreatedArgs),
+    NftBurned(NftBurnedArgs),
+    MarketFeeDefaultChanged(MarketFeeDefaultChangedArgs),
+    MarketFeeChanged(MarketFeeChangedArgs),
+    AddCollectionRules(AddCollectionRulesArgs),
+    RemoveCollectionRules(RemoveCollectionRulesArgs),
+    Raw(RawArgs),
+}
+
+impl EventArgs {
+    pub fn decode(event_type: EventType, args: Value) -> Self {
+        fn typed<T: serde::de::DeserializeOwned>(
+            args: &Value,
+            variant: fn(T) -> EventArgs,
+        ) -> Option<EventArgs> {
+            T::deserialize(args).ok().map(variant)
+        }
+
+        let decoded = match event_type {
+            EventType::AuctionDeployed => typed(&args, Self::AuctionDeployed),
+            EventType::AuctionDeclined => typed(&args, Self::AuctionDeclined),
+            EventType::AuctionCreated | EventType::AuctionActive | EventType::AuctionCancelled => {
+                typed(&args, Self::Auction)
+            }
+            EventType::AuctionBidPlaced => typed(&args, Self::AuctionBidPlaced),
+            EventType::AuctionBidDeclined | EventType::AuctionComplete => {
+                typed(&args, Self::AuctionBid)
+            }
+            EventType::AuctionExpired => typed(&args, Self::AuctionExpired),
+            EventType::DirectBuyDeployed => typed(&args, Self::DirectBuyDeployed),
+            EventType::DirectBuyDeclined => typed(&args, Self::DirectBuyDeclined),
+            EventType::DirectBuyStateChanged => typed(&args, Self::DirectBuyStateChanged),
+            EventType::DirectBuyExpired => typed(&args, Self::DirectBuyExpired),
+            EventType::DirectSellDeployed => typed(&args, Self::DirectSellDeployed),
+            EventType::DirectSellDeclined => typed(&args, Self::DirectSellDeclined),
+            EventType::DirectSellStateChanged => typed(&args, Self::DirectSellStateChanged),
+            EventType::DirectSellExpired => typed(&args, Self::DirectSellExpired),
+            EventType::NftOwnerChanged | EventType::OwnershipTransferred => {
+                typed(&args, Self::OwnerChanged)
+            }
+            EventType::NftManagerChanged => typed(&args, Self::ManagerChanged),
+            EventType::NftCreated => typed(&args, Self::NftCreated),
+            EventType::NftBurned => typed(&args, Self::NftBurned),
+            EventType::MarketFeeDefaultChanged => typed(&args, Self::MarketFeeDefaultChanged),
+            EventType::MarketFeeChanged => typed(&args, Self::MarketFeeChanged),
+            EventType::AddCollectionRules => typed(&args, Self::AddCollectionRules),
+            EventType::RemoveCollectionRules => typed(&args, Self::RemoveCollectionRules),
+        };
+
+        decoded.unwrap_or(Self::Raw(RawArgs(args)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn args_are_decoded_by_event_type() {
+        let args = json!({
+            "from": 2,
+            "to": 5,
+            "seller": "0:01",
+            "expired_at": 1696000000,
+        });
+
+        let decoded = EventArgs::decode(EventType::DirectSellExpired, args.clone());
+        assert!(matches!(decoded, EventArgs::DirectSellExpired(_)));
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), args);
+
+        let decoded = EventArgs::decode(EventType::NftCreated, args.clone());
+        assert!(matches!(decoded, EventArgs::Raw(_)));
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), args);
+    }
+
+    #[test]
+    fn unknown_fields_are_kept() {
+        let cases = [
+            (
+                EventType::NftOwnerChanged,
+                json!({
+                    "old_owner": "0:01",
+                    "new_owner": "0:02",
+                    "reason": "transfer",
+                }),
+            ),
+            (
+                EventType::MarketFeeChanged,
+                json!({
+                    "auction": "0:03",
+                    "fee": { "numerator": 1, "denominator": 100, "cap": 5 },
+                    "changed_by": "0:04",
+                }),
+            ),
+            (
+                EventType::AuctionExpired,
+                json!({
+                    "seller": "0:05",
+                    "finished_at": 1696000000,
+                    "bids": [],
+                }),
+            ),
+        ];
+
+        for (event_type, args) in cases {
+            let decoded = EventArgs::decode(event_type, args.clone());
+            assert!(!matches!(decoded, EventArgs::Raw(_)), "{event_type:?}");
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), args);
+        }
+    }
+}
@@ -0,0 +1,477 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+use actix_web::web::{Bytes, Query};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use indexer_repo::events::{
+    EventCursor, EventFilter, EventsModel, EventsQuery, LiveEvent, MarketEvent,
+};
+use indexer_repo::types::{EventCategory, EventKind, EventType};
+use opg::OpgModel;
+use serde::de::value::Error as ValueError;
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::api::event_args::EventArgs;
+use crate::api::pagination::{next_cursor, page_limit, parse_cursor};
+
+const CATCH_UP_PAGE: i64 = 500;
+const HEARTBEAT_SEC: u64 = 15;
+const RECENT_EVENTS: usize = 8192;
+const MAX_COLLECTIONS: usize = 20;
+
+#[derive(Serialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketEventKind {
+    Mint,
+    Transfer,
+    AuctionActive,
+    AuctionBidPlaced,
+    AuctionCanceled,
+    AuctionComplete,
+    AuctionExpired,
+    OfferActive,
+    OfferFilled,
+    OfferCanceled,
+    OfferExpired,
+    SellActive,
+    SellPurchased,
+    SellCanceled,
+    SellExpired,
+}
+
+impl From<EventKind> for MarketEventKind {
+    fn from(kind: EventKind) -> Self {
+        match kind {
+            EventKind::Mint => Self::Mint,
+            EventKind::Transfer => Self::Transfer,
+            EventKind::AuctionActive => Self::AuctionActive,
+            EventKind::AuctionBidPlaced => Self::AuctionBidPlaced,
+            EventKind::AuctionCanceled => Self::AuctionCanceled,
+            EventKind::AuctionComplete => Self::AuctionComplete,
+            EventKind::AuctionExpired => Self::AuctionExpired,
+            EventKind::OfferActive => Self::OfferActive,
+            EventKind::OfferFilled => Self::OfferFilled,
+            EventKind::OfferCanceled => Self::OfferCanceled,
+            EventKind::OfferExpired => Self::OfferExpired,
+            EventKind::SellActive => Self::SellActive,
+            EventKind::SellPurchased => Self::SellPurchased,
+            EventKind::SellCanceled => Self::SellCanceled,
+            EventKind::SellExpired => Self::SellExpired,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketEventType {
+    AuctionDeployed,
+    AuctionCreated,
+    AuctionActive,
+    AuctionDeclined,
+    AuctionBidPlaced,
+    AuctionBidDeclined,
+    AuctionCancelled,
+    AuctionComplete,
+    AuctionExpired,
+    DirectBuyDeployed,
+    DirectBuyDeclined,
+    DirectBuyStateChanged,
+    DirectBuyExpired,
+    DirectSellDeployed,
+    DirectSellDeclined,
+    DirectSellStateChanged,
+    DirectSellExpired,
+    NftOwnerChanged,
+    NftManagerChanged,
+    NftCreated,
+    NftBurned,
+    MarketFeeDefaultChanged,
+    MarketFeeChanged,
+    AddCollectionRules,
+    RemoveCollectionRules,
+    OwnershipTransferred,
+}
+
+impl From<EventType> for MarketEventType {
+    fn from(event_type: EventType) -> Self {
+        match event_type {
+            EventType::AuctionDeployed => Self::AuctionDeployed,
+            EventType::AuctionCreated => Self::AuctionCreated,
+            EventType::AuctionActive => Self::AuctionActive,
+            EventType::AuctionDeclined => Self::AuctionDeclined,
+            EventType::AuctionBidPlaced => Self::AuctionBidPlaced,
+            EventType::AuctionBidDeclined => Self::AuctionBidDeclined,
+            EventType::AuctionCancelled => Self::AuctionCancelled,
+            EventType::AuctionComplete => Self::AuctionComplete,
+            EventType::AuctionExpired => Self::AuctionExpired,
+            EventType::DirectBuyDeployed => Self::DirectBuyDeployed,
+            EventType::DirectBuyDeclined => Self::DirectBuyDeclined,
+            EventType::DirectBuyStateChanged => Self::DirectBuyStateChanged,
+            EventType::DirectBuyExpired => Self::DirectBuyExpired,
+            EventType::DirectSellDeployed => Self::DirectSellDeployed,
+            EventType::DirectSellDeclined => Self::DirectSellDeclined,
+            EventType::DirectSellStateChanged => Self::DirectSellStateChanged,
+            EventType::DirectSellExpired => Self::DirectSellExpired,
+            EventType::NftOwnerChanged => Self::NftOwnerChanged,
+            EventType::NftManagerChanged => Self::NftManagerChanged,
+            EventType::NftCreated => Self::NftCreated,
+            EventType::NftBurned => Self::NftBurned,
+            EventType::MarketFeeDefaultChanged => Self::MarketFeeDefaultChanged,
+            EventType::MarketFeeChanged => Self::MarketFeeChanged,
+            EventType::AddCollectionRules => Self::AddCollectionRules,
+            EventType::RemoveCollectionRules => Self::RemoveCollectionRules,
+            EventType::OwnershipTransferred => Self::OwnershipTransferred,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketEventCategory {
+    Auction,
+    DirectBuy,
+    DirectSell,
+    Nft,
+    Collection,
+    Common,
+}
+
+impl From<EventCategory> for MarketEventCategory {
+    fn from(category: EventCategory) -> Self {
+        match category {
+            EventCategory::Auction => Self::Auction,
+            EventCategory::DirectBuy => Self::DirectBuy,
+            EventCategory::DirectSell => Self::DirectSell,
+            EventCategory::Nft => Self::Nft,
+            EventCategory::Collection => Self::Collection,
+            EventCategory::Common => Self::Common,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EventsParams {
+    owner: Option<String>,
+    nft: Option<String>,
+    /// Comma separated collection addresses
+    collection: Option<String>,
+    /// Comma separated `event_kind` values
+    event_kind: Option<String>,
+    #[serde(default)]
+    verified: bool,
+    #[serde(default)]
+    with_count: bool,
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct EventResponse {
+    id: i64,
+    kind: MarketEventKind,
+    event_type: MarketEventType,
+    event_category: MarketEventCategory,
+    #[opg(string)]
+    address: String,
+    #[opg(optional, string)]
+    nft: Option<String>,
+    created_at: i64,
+    created_lt: i64,
+    args: EventArgs,
+}
+
+impl From<MarketEvent> for EventResponse {
+    fn from(event: MarketEvent) -> Self {
+        Self {
+            id: event.id,
+            kind: event.event_kind.into(),
+            event_type: event.event_type.into(),
+            event_category: event.event_category.into(),
+            address: event.address,
+            nft: event.nft,
+            created_at: event.created_at,
+            created_lt: event.created_lt,
+            args: EventArgs::decode(event.event_type, event.args),
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct EventsPage {
+    items: Vec<EventResponse>,
+    #[opg("Pass as `cursor` to get the next page", optional, string)]
+    next_cursor: Option<String>,
+    #[opg("Only on the first page when `with_count` is set", optional)]
+    total_count: Option<i64>,
+}
+
+/// Marketplace events, newest first
+#[get("/events")]
+pub async fn get_events(
+    params: Query<EventsParams>,
+    events_model: web::Data<EventsModel>,
+) -> HttpResponse {
+    let params = params.into_inner();
+
+    let collections = params
+        .collection
+        .as_deref()
+        .map(split_list)
+        .unwrap_or_default();
+    if collections.len() > MAX_COLLECTIONS {
+        return HttpResponse::BadRequest()
+            .body(format!("At most {MAX_COLLECTIONS} collections per query"));
+    }
+
+    let event_kinds = match params.event_kind.as_deref().map(parse_values) {
+        Some(Ok(kinds)) => kinds,
+        Some(Err(e)) => return HttpResponse::BadRequest().body(e.to_string()),
+        None => Vec::new(),
+    };
+
+    let Ok(before) = parse_cursor::<EventCursor>(params.cursor.as_deref()) else {
+        return HttpResponse::BadRequest().body("malformed cursor");
+    };
+
+    let query = EventsQuery {
+        owner: params.owner,
+        nft: params.nft,
+        collections: collections.into_iter().map(str::to_string).collect(),
+        event_kinds,
+        verified: params.verified,
+        // The count only holds for the whole result, so it is skipped on further pages
+        with_count: params.with_count && before.is_none(),
+        before,
+        limit: page_limit(params.limit),
+    };
+
+    match events_model.get_events(&query).await {
+        Ok(events) => HttpResponse::Ok().json(EventsPage {
+            next_cursor: next_cursor(&events, query.limit, |e| EventCursor::from(e)),
+            total_count: query
+                .with_count
+                .then(|| events.first().map_or(0, |e| e.total_rows)),
+            items: events.into_iter().map(Into::into).collect(),
+        }),
+        Err(err) => {
+            log::error!("get events error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EventStreamParams {
+    from_lt: Option<i64>,
+    collection: Option<String>,
+    nft: Option<String>,
+    owner: Option<String>,
+    /// Comma separated `event_type` values
+    event_type: Option<String>,
+}
+
+/// Server-Sent Events stream of committed events, state changes of direct
+/// offers carry the accepted transition. Each message id is the position of
+/// its event, `<lt>:<id>`, so a reconnecting `EventSource` resumes right after
+/// it via `Last-Event-ID`; `from_lt` starts the first connection after an lt.
+#[get("/events/stream")]
+pub async fn stream_events(
+    req: HttpRequest,
+    params: Query<EventStreamParams>,
+    events_model: web::Data<EventsModel>,
+    live_events: web::Data<broadcast::Sender<LiveEvent>>,
+) -> HttpResponse {
+    let params = params.into_inner();
+
+    let event_types = match params.event_type.as_deref().map(parse_values) {
+        Some(Ok(types)) => types,
+        Some(Err(e)) => return HttpResponse::BadRequest().body(e.to_string()),
+        None => Vec::new(),
+    };
+
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|id| id.to_str().ok())
+        .and_then(parse_event_id);
+
+    let subscription = Subscription {
+        // Subscribe before reading the backlog, so nothing committed in between is lost
+        rx: live_events.subscribe(),
+        model: events_model.get_ref().clone(),
+        filter: EventFilter {
+            collection: params.collection,
+            nft: params.nft,
+            owner: params.owner,
+            event_types,
+        },
+        catch_up_from: last_event_id.or(params.from_lt.map(after_lt)),
+        last_position: None,
+        recent: RecentEvents::default(),
+        pending: VecDeque::new(),
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(futures::stream::unfold(subscription, |s| s.next_message()))
+}
+
+fn split_list(value: &str) -> Vec<&str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// Comma separated snake_case enum values
+pub(crate) fn parse_values<T: DeserializeOwned>(value: &str) -> Result<Vec<T>, ValueError> {
+    split_list(value)
+        .into_iter()
+        .map(|t| T::deserialize(t.into_deserializer()))
+        .collect()
+}
+
+struct Subscription {
+    rx: broadcast::Receiver<LiveEvent>,
+    model: EventsModel,
+    filter: EventFilter,
+    /// Set while replaying events from the database
+    catch_up_from: Option<EventCursor>,
+    last_position: Option<EventCursor>,
+    recent: RecentEvents,
+    pending: VecDeque<LiveEvent>,
+}
+
+impl Subscription {
+    async fn next_message(mut self) -> Option<(Result<Bytes, actix_web::Error>, Self)> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some((Ok(format_message(&event)), self));
+            }
+
+            if let Some(after) = self.catch_up_from.take() {
+                let events = match self
+                    .model
+                    .get_events_since(&after, &self.filter, CATCH_UP_PAGE)
+                    .await
+                {
+                    Ok(events) => events,
+                    Err(e) => {
+                        log::error!("Error while reading events for stream: {e:#?}");
+                        return None;
+                    }
+                };
+
+                self.catch_up_from = match events.last() {
+                    Some(last) if events.len() as i64 == CATCH_UP_PAGE => Some(last.position()),
+                    _ => None,
+                };
+
+                for event in events {
+                    if self.recent.insert(event.id) {
+                        self.last_position = Some(event.position());
+                        self.pending.push_back(event);
+                    }
+                }
+
+                continue;
+            }
+
+            match tokio::time::timeout(Duration::from_secs(HEARTBEAT_SEC), self.rx.recv()).await {
+                Err(_) => return Some((Ok(Bytes::from_static(b": ping\n\n")), self)),
+                Ok(Ok(event)) => {
+                    if !event.matches(&self.filter) {
+                        continue;
+                    }
+
+                    if !self.recent.insert(event.id) {
+                        continue;
+                    }
+
+                    self.last_position = Some(event.position());
+                    return Some((Ok(format_message(&event)), self));
+                }
+                Ok(Err(RecvError::Lagged(skipped))) => {
+                    log::warn!("Event stream subscriber lagged by {skipped} messages");
+                    // Events are in the database, so a slow subscriber catches up from there
+                    self.catch_up_from = self.last_position.clone();
+                }
+                Ok(Err(RecvError::Closed)) => return None,
+            }
+        }
+    }
+}
+
+fn format_message(event: &LiveEvent) -> Bytes {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    let EventCursor { created_lt, id } = event.position();
+    Bytes::from(format!("id: {created_lt}:{id}\ndata: {data}\n\n"))
+}
+
+/// Position from a message id. A bare lt, as sent before ids carried the
+/// event id, resumes after every event of that lt.
+fn parse_event_id(value: &str) -> Option<EventCursor> {
+    match value.split_once(':') {
+        Some((lt, id)) => Some(EventCursor {
+            created_lt: lt.parse().ok()?,
+            id: id.parse().ok()?,
+        }),
+        None => value.parse().ok().map(after_lt),
+    }
+}
+
+fn after_lt(created_lt: i64) -> EventCursor {
+    EventCursor {
+        created_lt,
+        id: i64::MAX,
+    }
+}
+
+/// Ids of recently sent events. Replayed and live events overlap
+/// around the moment of subscription, this drops the second copy.
+#[derive(Default)]
+struct RecentEvents {
+    order: VecDeque<i64>,
+    ids: HashSet<i64>,
+}
+
+impl RecentEvents {
+    fn insert(&mut self, id: i64) -> bool {
+        if !self.ids.insert(id) {
+            return false;
+        }
+
+        self.order.push_back(id);
+        if self.order.len() > RECENT_EVENTS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_event_ids() {
+        assert_eq!(
+            parse_event_id("42:7"),
+            Some(EventCursor {
+                created_lt: 42,
+                id: 7
+            })
+        );
+        assert_eq!(parse_event_id("42"), Some(after_lt(42)));
+        assert_eq!(parse_event_id("42:"), None);
+        assert_eq!(parse_event_id("x"), None);
+    }
+}
@@ -0,0 +1,292 @@
+use std::str::FromStr;
+
+use actix_web::web::{Json, Path};
+use actix_web::{post, web, HttpResponse};
+use bigdecimal::BigDecimal;
+use indexer_repo::facets::{
+    FacetedSearchModel, ListingState, NftSearchFilter, TraitFacet, TraitFilter, TraitRange,
+};
+use opg::OpgModel;
+use serde::{Deserialize, Serialize};
+
+use crate::api::nfts::NftResponse;
+use crate::api::pagination::{next_cursor, page_limit};
+
+const MAX_TRAIT_FILTERS: usize = 20;
+
+#[derive(Deserialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum NftListingState {
+    Listed,
+    BuyNow,
+    Auction,
+    Unlisted,
+}
+
+impl From<NftListingState> for ListingState {
+    fn from(state: NftListingState) -> Self {
+        match state {
+            NftListingState::Listed => Self::Listed,
+            NftListingState::BuyNow => Self::BuyNow,
+            NftListingState::Auction => Self::Auction,
+            NftListingState::Unlisted => Self::Unlisted,
+        }
+    }
+}
+
+#[derive(Deserialize, OpgModel)]
+pub struct TraitValuesParam {
+    #[opg(string)]
+    trait_type: String,
+    #[opg("Any of the values matches")]
+    values: Vec<String>,
+}
+
+#[derive(Deserialize, OpgModel)]
+pub struct TraitRangeParam {
+    #[opg(string)]
+    trait_type: String,
+    #[opg(optional)]
+    min: Option<f64>,
+    #[opg(optional)]
+    max: Option<f64>,
+}
+
+#[derive(Deserialize, OpgModel)]
+pub struct NftSearchParams {
+    #[opg(optional, string)]
+    owner: Option<String>,
+    #[serde(default)]
+    #[opg(
+        "Every trait must match, at most 20 traits and ranges in total",
+        optional
+    )]
+    traits: Vec<TraitValuesParam>,
+    #[serde(default)]
+    #[opg(
+        "Bounds of numeric traits, inclusive, a trait with values must match both",
+        optional
+    )]
+    ranges: Vec<TraitRangeParam>,
+    #[opg(optional, string)]
+    min_price_usd: Option<String>,
+    #[opg(optional, string)]
+    max_price_usd: Option<String>,
+    #[opg(optional)]
+    listing: Option<NftListingState>,
+    #[opg("Facets are only returned for the first page", optional, string)]
+    cursor: Option<String>,
+    #[opg(optional)]
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct TraitValueCount {
+    #[opg(string)]
+    value: String,
+    nft_count: i64,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct TraitFacetResponse {
+    #[opg(string)]
+    trait_type: String,
+    #[opg("All values are numbers and can be filtered by range")]
+    numeric: bool,
+    #[opg(optional)]
+    min: Option<f64>,
+    #[opg(optional)]
+    max: Option<f64>,
+    values: Vec<TraitValueCount>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct NftSearchPage {
+    items: Vec<NftResponse>,
+    #[opg("Pass as `cursor` to get the next page", optional, string)]
+    next_cursor: Option<String>,
+    #[opg(
+        "Nft count per trait value, the filter on a trait is not applied to its own values",
+        optional
+    )]
+    facets: Option<Vec<TraitFacetResponse>>,
+}
+
+fn parse_price(
+    price: Option<&str>,
+) -> Result<Option<BigDecimal>, bigdecimal::ParseBigDecimalError> {
+    price.map(BigDecimal::from_str).transpose()
+}
+
+/// Facet rows come ordered by trait type
+fn group_facets(rows: Vec<TraitFacet>) -> Vec<TraitFacetResponse> {
+    let mut facets: Vec<TraitFacetResponse> = Vec::new();
+
+    for row in rows {
+        if facets.last().map(|f| &f.trait_type) != Some(&row.trait_type) {
+            facets.push(TraitFacetResponse {
+                trait_type: row.trait_type.clone(),
+                numeric: true,
+                min: None,
+                max: None,
+                values: Vec::new(),
+            });
+        }
+
+        let Some(facet) = facets.last_mut() else {
+            continue;
+        };
+
+        facet.numeric &= row.numeric;
+        if let Ok(value) = row.value.parse::<f64>() {
+            facet.min = Some(facet.min.map_or(value, |min| min.min(value)));
+            facet.max = Some(facet.max.map_or(value, |max| max.max(value)));
+        }
+
+        facet.values.push(TraitValueCount {
+            value: row.value,
+            nft_count: row.nft_count,
+        });
+    }
+
+    for facet in facets.iter_mut() {
+        if facet.numeric {
+            facet.values.sort_by(|a, b| {
+                let a = a.value.parse::<f64>().unwrap_or_default();
+                let b = b.value.parse::<f64>().unwrap_or_default();
+                a.total_cmp(&b)
+            });
+        } else {
+            facet.min = None;
+            facet.max = None;
+        }
+    }
+
+    facets
+}
+
+/// Nfts of a collection filtered by traits, price, listing and owner
+#[post("/collections/{address}/nfts/search")]
+pub async fn search_nfts(
+    collection: Path<String>,
+    params: Json<NftSearchParams>,
+    search_model: web::Data<FacetedSearchModel>,
+) -> HttpResponse {
+    let params = params.into_inner();
+
+    if params.traits.len() + params.ranges.len() > MAX_TRAIT_FILTERS {
+        return HttpResponse::BadRequest().body("too many trait filters");
+    }
+
+    let (Ok(min_price_usd), Ok(max_price_usd)) = (
+        parse_price(params.min_price_usd.as_deref()),
+        parse_price(params.max_price_usd.as_deref()),
+    ) else {
+        return HttpResponse::BadRequest().body("malformed price");
+    };
+
+    let filter = NftSearchFilter {
+        owner: params.owner,
+        traits: params
+            .traits
+            .into_iter()
+            .map(|t| TraitFilter {
+                trait_type: t.trait_type,
+                values: t.values,
+            })
+            .collect(),
+        ranges: params
+            .ranges
+            .into_iter()
+            .map(|r| TraitRange {
+                trait_type: r.trait_type,
+                min: r.min,
+                max: r.max,
+            })
+            .collect(),
+        min_price_usd,
+        max_price_usd,
+        listing: params.listing.map(Into::into),
+    };
+
+    let limit = page_limit(params.limit);
+
+    let nfts = match search_model
+        .search_nfts(&collection, &filter, params.cursor.as_deref(), limit)
+        .await
+    {
+        Ok(nfts) => nfts,
+        Err(err) => {
+            log::error!("search nfts error {err}");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let facets = if params.cursor.is_none() {
+        match search_model.get_trait_facets(&collection, &filter).await {
+            Ok(rows) => Some(group_facets(rows)),
+            Err(err) => {
+                log::error!("get trait facets error {err}");
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    } else {
+        None
+    };
+
+    HttpResponse::Ok().json(NftSearchPage {
+        next_cursor: next_cursor(&nfts, limit, |n| n.address.clone()),
+        items: nfts.into_iter().map(Into::into).collect(),
+        facets,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(trait_type: &str, value: &str, nft_count: i64, numeric: bool) -> TraitFacet {
+        TraitFacet {
+            trait_type: trait_type.to_string(),
+            value: value.to_string(),
+            nft_count,
+            numeric,
+        }
+    }
+
+    #[test]
+    fn groups_facets_by_trait_type() {
+        let facets = group_facets(vec![
+            row("Color", "red", 3, false),
+            row("Color", "1", 1, true),
+            row("Level", "12", 2, true),
+            row("Level", "5", 2, true),
+            row("Level", "-1.5", 1, true),
+        ]);
+
+        assert_eq!(facets.len(), 2);
+
+        // A single non numeric value makes the whole trait non numeric
+        let color = &facets[0];
+        assert_eq!(color.trait_type, "Color");
+        assert!(!color.numeric);
+        assert_eq!((color.min, color.max), (None, None));
+        let values = color
+            .values
+            .iter()
+            .map(|v| (v.value.as_str(), v.nft_count))
+            .collect::<Vec<_>>();
+        assert_eq!(values, [("red", 3), ("1", 1)]);
+
+        // Numeric values are ordered by number
+        let level = &facets[1];
+        assert!(level.numeric);
+        assert_eq!((level.min, level.max), (Some(-1.5), Some(12.0)));
+        let values = level
+            .values
+            .iter()
+            .map(|v| v.value.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["-1.5", "5", "12"]);
+    }
+}
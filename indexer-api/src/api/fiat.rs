@@ -0,0 +1,156 @@
+use actix_web::{get, web, HttpResponse};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use indexer_repo::fiat::{FiatRate, FiatRateModel};
+use opg::OpgModel;
+use serde::Serialize;
+
+const USD: &str = "USD";
+/// Fiat values are rounded to cents
+const FIAT_SCALE: i64 = 2;
+
+#[derive(Serialize, OpgModel)]
+pub struct FiatRateResponse {
+    #[opg(string)]
+    currency: String,
+    #[opg("Units of the currency per one usd", string)]
+    usd_rate: String,
+    updated: i64,
+}
+
+impl From<FiatRate> for FiatRateResponse {
+    fn from(rate: FiatRate) -> Self {
+        Self {
+            currency: rate.currency,
+            usd_rate: rate.usd_rate.to_string(),
+            updated: rate.ts.timestamp(),
+        }
+    }
+}
+
+/// Converts usd values into the requested currency at its latest rate.
+pub(crate) struct FiatConversion {
+    usd_rate: BigDecimal,
+}
+
+impl FiatConversion {
+    /// `None` without a currency. Currencies without a rate are a bad request.
+    pub(crate) async fn load(
+        fiat_model: &FiatRateModel,
+        currency: Option<&str>,
+    ) -> Result<Option<Self>, HttpResponse> {
+        let Some(currency) = currency.map(str::to_uppercase) else {
+            return Ok(None);
+        };
+
+        if currency == USD {
+            return Ok(Some(Self {
+                usd_rate: BigDecimal::from(1),
+            }));
+        }
+
+        match fiat_model.get_latest_rates().await {
+            Ok(rates) => rates
+                .into_iter()
+                .find(|r| r.currency == currency)
+                .map(|r| {
+                    Some(Self {
+                        usd_rate: r.usd_rate,
+                    })
+                })
+                .ok_or_else(|| HttpResponse::BadRequest().body("unknown currency")),
+            Err(err) => {
+                log::error!("get fiat rates error {err}");
+                Err(HttpResponse::InternalServerError().finish())
+            }
+        }
+    }
+
+    pub(crate) fn convert(&self, usd: Option<&BigDecimal>) -> Option<String> {
+        usd.map(|usd| format_fiat(&self.to_fiat(usd)))
+    }
+
+    pub(crate) fn to_fiat(&self, usd: &BigDecimal) -> BigDecimal {
+        usd * &self.usd_rate
+    }
+}
+
+/// Fiat value rounded to cents, the way every response shows it.
+pub(crate) fn format_fiat(value: &BigDecimal) -> String {
+    value.round(FIAT_SCALE).with_scale(FIAT_SCALE).to_string()
+}
+
+/// Rates of the currency in effect at `times`, for values priced in the past.
+/// Times before the first known rate get none.
+pub(crate) async fn load_rates_at(
+    fiat_model: &FiatRateModel,
+    currency: &str,
+    times: &[NaiveDateTime],
+) -> Result<Vec<Option<BigDecimal>>, HttpResponse> {
+    let currency = currency.to_uppercase();
+
+    if currency == USD {
+        return Ok(vec![Some(BigDecimal::from(1)); times.len()]);
+    }
+
+    let known = match fiat_model.get_latest_rates().await {
+        Ok(rates) => rates.iter().any(|r| r.currency == currency),
+        Err(err) => {
+            log::error!("get fiat rates error {err}");
+            return Err(HttpResponse::InternalServerError().finish());
+        }
+    };
+    if !known {
+        return Err(HttpResponse::BadRequest().body("unknown currency"));
+    }
+
+    fiat_model
+        .get_rates_at(&currency, times)
+        .await
+        .map_err(|err| {
+            log::error!("get fiat rates at times error {err}");
+            HttpResponse::InternalServerError().finish()
+        })
+}
+
+/// The latest rate of every currency
+#[get("/fiat-rates")]
+pub async fn get_fiat_rates(fiat_model: web::Data<FiatRateModel>) -> HttpResponse {
+    match fiat_model.get_latest_rates().await {
+        Ok(rates) => HttpResponse::Ok().json(
+            rates
+                .into_iter()
+                .map(FiatRateResponse::from)
+                .collect::<Vec<_>>(),
+        ),
+        Err(err) => {
+            log::error!("get fiat rates error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn converts_and_rounds_to_cents() {
+        let conversion = FiatConversion {
+            usd_rate: BigDecimal::from_str("0.9237").unwrap(),
+        };
+
+        assert_eq!(
+            conversion.convert(Some(&BigDecimal::from_str("12.5").unwrap())),
+            Some("11.55".to_string())
+        );
+        assert_eq!(
+            conversion.convert(Some(&BigDecimal::from(2))),
+            Some("1.85".to_string())
+        );
+        assert_eq!(conversion.convert(None), None);
+        assert_eq!(format_fiat(&BigDecimal::from(3)), "3.00");
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use async_graphql::dataloader::Loader;
+use async_graphql::{async_trait, Error};
+use indexer_repo::nft::{
+    CollectionDetails, NftAttribute, NftDetails, NftModel, PriceHistoryRecord,
+};
+use indexer_repo::offers::{
+    ActiveAuction, ActiveDirectBuy, ActiveDirectSell, AuctionBid, OffersModel,
+};
+
+/// Price history entries loaded per NFT, the `priceHistory` field can only narrow it down.
+pub const PRICE_HISTORY_PER_NFT: i64 = 100;
+
+fn by_key<T, K: Hash + Eq>(rows: Vec<T>, key: impl Fn(&T) -> K) -> HashMap<K, T> {
+    rows.into_iter().map(|row| (key(&row), row)).collect()
+}
+
+fn group_by_key<T, K: Hash + Eq>(rows: Vec<T>, key: impl Fn(&T) -> K) -> HashMap<K, Vec<T>> {
+    let mut groups: HashMap<K, Vec<T>> = HashMap::new();
+    for row in rows {
+        groups.entry(key(&row)).or_default().push(row);
+    }
+    groups
+}
+
+fn to_error(e: anyhow::Error) -> Error {
+    log::error!("graphql loader error {e:#?}");
+    Error::new("internal error")
+}
+
+pub struct NftLoader(pub NftModel);
+
+#[async_trait::async_trait]
+impl Loader<String> for NftLoader {
+    type Value = NftDetails;
+    type Error = Error;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let nfts = self.0.get_nfts_by_addresses(keys).await.map_err(to_error)?;
+        Ok(by_key(nfts, |n| n.address.clone()))
+    }
+}
+
+pub struct CollectionLoader(pub NftModel);
+
+#[async_trait::async_trait]
+impl Loader<String> for CollectionLoader {
+    type Value = CollectionDetails;
+    type Error = Error;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let collections = self
+            .0
+            .get_collections_by_addresses(keys)
+            .await
+            .map_err(to_error)?;
+        Ok(by_key(collections, |c| c.address.clone()))
+    }
+}
+
+/// Keyed by nft
+pub struct AttributesLoader(pub NftModel);
+
+#[async_trait::async_trait]
+impl Loader<String> for AttributesLoader {
+    type Value = Vec<NftAttribute>;
+    type Error = Error;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let attributes = self
+            .0
+            .get_attributes_by_nfts(keys)
+            .await
+            .map_err(to_error)?;
+        Ok(group_by_key(attributes, |a| a.nft.clone()))
+    }
+}
+
+/// Keyed by nft
+pub struct PriceHistoryLoader(pub NftModel);
+
+#[async_trait::async_trait]
+impl Loader<String> for PriceHistoryLoader {
+    type Value = Vec<PriceHistoryRecord>;
+    type Error = Error;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let history = self
+            .0
+            .get_price_history_by_nfts(keys, PRICE_HISTORY_PER_NFT)
+            .await
+            .map_err(to_error)?;
+        Ok(group_by_key(history, |h| h.nft.clone()))
+    }
+}
+
+/// Keyed by nft
+pub struct AuctionLoader(pub OffersModel);
+
+#[async_trait::async_trait]
+impl Loader<String> for AuctionLoader {
+    type Value = ActiveAuction;
+    type Error = Error;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let auctions = self
+            .0
+            .get_active_auctions_by_nfts(keys)
+            .await
+            .map_err(to_error)?;
+        Ok(by_key(auctions, |a| a.nft.clone()))
+    }
+}
+
+/// Keyed by nft
+pub struct ListingLoader(pub OffersModel);
+
+#[async_trait::async_trait]
+impl Loader<String> for ListingLoader {
+    type Value = Vec<ActiveDirectSell>;
+    type Error = Error;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let sells = self
+            .0
+            .get_active_direct_sells_by_nfts(keys)
+            .await
+            .map_err(to_error)?;
+        Ok(group_by_key(sells, |s| s.nft.clone()))
+    }
+}
+
+/// Keyed by nft
+pub struct BestOfferLoader(pub OffersModel);
+
+#[async_trait::async_trait]
+impl Loader<String> for BestOfferLoader {
+    type Value = ActiveDirectBuy;
+    type Error = Error;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let buys = self
+            .0
+            .get_best_direct_buys_by_nfts(keys)
+            .await
+            .map_err(to_error)?;
+        Ok(by_key(buys, |b| b.nft.clone()))
+    }
+}
+
+/// Keyed by auction
+pub struct BidsLoader(pub OffersModel);
+
+#[async_trait::async_trait]
+impl Loader<String> for BidsLoader {
+    type Value = Vec<AuctionBid>;
+    type Error = Error;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let bids = self.0.get_bids_by_auctions(keys).await.map_err(to_error)?;
+        Ok(group_by_key(bids, |b| b.auction.clone()))
+    }
+}
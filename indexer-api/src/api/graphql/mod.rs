@@ -0,0 +1,118 @@
+pub mod loaders;
+pub mod schema;
+
+use actix_web::{get, post, web, HttpResponse};
+use async_graphql::dataloader::DataLoader;
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{EmptyMutation, EmptySubscription, Schema};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use indexer_repo::nft::NftModel;
+use indexer_repo::offers::OffersModel;
+
+use self::loaders::{
+    AttributesLoader, AuctionLoader, BestOfferLoader, BidsLoader, CollectionLoader, ListingLoader,
+    NftLoader, PriceHistoryLoader,
+};
+use self::schema::Query;
+
+const MAX_QUERY_DEPTH: usize = 8;
+/// Every field costs one, list fields multiply the cost of their items by their size
+const MAX_QUERY_COMPLEXITY: usize = 5_000;
+
+pub type MarketplaceSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// Loaders are shared by all requests and keep no cache, so fields of concurrent
+/// queries are batched together into one sql query per loader.
+pub fn build_schema(nft_model: NftModel, offers_model: OffersModel) -> MarketplaceSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .data(DataLoader::new(NftLoader(nft_model.clone()), tokio::spawn))
+        .data(DataLoader::new(
+            CollectionLoader(nft_model.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            AttributesLoader(nft_model.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(PriceHistoryLoader(nft_model), tokio::spawn))
+        .data(DataLoader::new(
+            AuctionLoader(offers_model.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            ListingLoader(offers_model.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            BestOfferLoader(offers_model.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(BidsLoader(offers_model), tokio::spawn))
+        .finish()
+}
+
+#[post("/graphql")]
+pub async fn graphql(
+    schema: web::Data<MarketplaceSchema>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    schema.execute(request.into_inner()).await.into()
+}
+
+#[get("/graphql")]
+pub async fn graphiql() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    /// Rejected queries never reach the database
+    fn schema() -> MarketplaceSchema {
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        build_schema(NftModel::new(pool.clone()), OffersModel::new(pool))
+    }
+
+    fn error(response: async_graphql::Response) -> String {
+        response
+            .errors
+            .first()
+            .map(|e| e.message.clone())
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn rejects_too_complex_queries() {
+        let addresses = (0..100).map(|i| format!("\"0:{i}\"")).collect::<Vec<_>>();
+        let query = format!(
+            "{{ nfts(addresses: [{}]) {{ address priceHistory {{ price ts }} }} }}",
+            addresses.join(",")
+        );
+
+        let response = schema().execute(query.as_str()).await;
+        assert_eq!(error(response), "Query is too complex.");
+    }
+
+    #[tokio::test]
+    async fn caps_list_sizes() {
+        let response = schema()
+            .execute(r#"{ nft(address: "0:1") { priceHistory(last: 10000) { ts } } }"#)
+            .await;
+        assert_eq!(error(response), "Query is too complex.");
+
+        let addresses = (0..101).map(|i| format!("\"0:{i}\"")).collect::<Vec<_>>();
+        let query = format!(
+            "{{ nfts(addresses: [{}]) {{ address }} }}",
+            addresses.join(",")
+        );
+        let response = schema().execute(query.as_str()).await;
+        assert_eq!(error(response), "At most 100 addresses per query");
+    }
+}
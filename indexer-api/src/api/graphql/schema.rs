@@ -0,0 +1,404 @@
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{ComplexObject, Context, Enum, Json, Object, Result, SimpleObject};
+use indexer_repo::nft::{CollectionDetails, NftAttribute, NftDetails, PriceHistoryRecord};
+use indexer_repo::offers::{ActiveAuction, ActiveDirectBuy, ActiveDirectSell, AuctionBid};
+use indexer_repo::types::NftPriceSource;
+
+use crate::api::graphql::loaders::{
+    AttributesLoader, AuctionLoader, BestOfferLoader, BidsLoader, CollectionLoader, ListingLoader,
+    NftLoader, PriceHistoryLoader,
+};
+
+const MAX_NFTS_PER_QUERY: usize = 100;
+/// Kept in sync with the `maximum` validators of list sizes
+const MAX_LIST_LEN: usize = 100;
+/// Complexity of lists without a size argument
+const UNSIZED_LIST_LEN: usize = 10;
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    async fn nft(&self, ctx: &Context<'_>, address: String) -> Result<Option<Nft>> {
+        let loader = ctx.data_unchecked::<DataLoader<NftLoader>>();
+        Ok(loader.load_one(address).await?.map(Nft))
+    }
+
+    /// NFTs in the order of `addresses`, unknown addresses are skipped
+    #[graphql(complexity = "addresses.len().saturating_mul(child_complexity)")]
+    async fn nfts(&self, ctx: &Context<'_>, addresses: Vec<String>) -> Result<Vec<Nft>> {
+        if addresses.len() > MAX_NFTS_PER_QUERY {
+            return Err(format!("At most {MAX_NFTS_PER_QUERY} addresses per query").into());
+        }
+
+        let loader = ctx.data_unchecked::<DataLoader<NftLoader>>();
+        let mut nfts = loader.load_many(addresses.iter().cloned()).await?;
+
+        Ok(addresses
+            .iter()
+            .filter_map(|address| nfts.remove(address))
+            .map(Nft)
+            .collect())
+    }
+
+    async fn collection(&self, ctx: &Context<'_>, address: String) -> Result<Option<Collection>> {
+        let loader = ctx.data_unchecked::<DataLoader<CollectionLoader>>();
+        Ok(loader.load_one(address).await?.map(Into::into))
+    }
+}
+
+pub struct Nft(NftDetails);
+
+#[Object]
+impl Nft {
+    async fn address(&self) -> &str {
+        &self.0.address
+    }
+
+    async fn nft_id(&self) -> Option<&str> {
+        self.0.nft_id.as_deref()
+    }
+
+    async fn name(&self) -> Option<&str> {
+        self.0.name.as_deref()
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    async fn owner(&self) -> Option<&str> {
+        self.0.owner.as_deref()
+    }
+
+    async fn manager(&self) -> Option<&str> {
+        self.0.manager.as_deref()
+    }
+
+    async fn metadata(&self) -> Option<Json<&serde_json::Value>> {
+        self.0.meta.as_ref().map(Json)
+    }
+
+    async fn floor_price(&self) -> Option<String> {
+        self.0.floor_price.as_ref().map(ToString::to_string)
+    }
+
+    async fn floor_price_token(&self) -> Option<&str> {
+        self.0.floor_price_token.as_deref()
+    }
+
+    async fn floor_price_usd(&self) -> Option<String> {
+        self.0.floor_price_usd.as_ref().map(ToString::to_string)
+    }
+
+    async fn deal_price_usd(&self) -> Option<String> {
+        self.0.deal_price_usd.as_ref().map(ToString::to_string)
+    }
+
+    async fn updated(&self) -> i64 {
+        self.0.updated.timestamp()
+    }
+
+    async fn tx_lt(&self) -> i64 {
+        self.0.tx_lt
+    }
+
+    async fn collection(&self, ctx: &Context<'_>) -> Result<Option<Collection>> {
+        let Some(collection) = self.0.collection.clone() else {
+            return Ok(None);
+        };
+
+        let loader = ctx.data_unchecked::<DataLoader<CollectionLoader>>();
+        Ok(loader.load_one(collection).await?.map(Into::into))
+    }
+
+    #[graphql(complexity = "UNSIZED_LIST_LEN * child_complexity")]
+    async fn attributes(&self, ctx: &Context<'_>) -> Result<Vec<Attribute>> {
+        let loader = ctx.data_unchecked::<DataLoader<AttributesLoader>>();
+        Ok(loader
+            .load_one(self.0.address.clone())
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Active direct sells, latest first
+    #[graphql(complexity = "UNSIZED_LIST_LEN * child_complexity")]
+    async fn listings(&self, ctx: &Context<'_>) -> Result<Vec<Listing>> {
+        let loader = ctx.data_unchecked::<DataLoader<ListingLoader>>();
+        Ok(loader
+            .load_one(self.0.address.clone())
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Active direct buy with the highest usd price
+    async fn best_offer(&self, ctx: &Context<'_>) -> Result<Option<Offer>> {
+        let loader = ctx.data_unchecked::<DataLoader<BestOfferLoader>>();
+        Ok(loader
+            .load_one(self.0.address.clone())
+            .await?
+            .map(Into::into))
+    }
+
+    async fn auction(&self, ctx: &Context<'_>) -> Result<Option<Auction>> {
+        let loader = ctx.data_unchecked::<DataLoader<AuctionLoader>>();
+        Ok(loader
+            .load_one(self.0.address.clone())
+            .await?
+            .map(Into::into))
+    }
+
+    /// Newest first, at most 100 entries
+    #[graphql(complexity = "last.unwrap_or(MAX_LIST_LEN).saturating_mul(child_complexity)")]
+    async fn price_history(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(maximum = 100))] last: Option<usize>,
+    ) -> Result<Vec<PriceHistoryEntry>> {
+        let loader = ctx.data_unchecked::<DataLoader<PriceHistoryLoader>>();
+        let history = loader
+            .load_one(self.0.address.clone())
+            .await?
+            .unwrap_or_default();
+
+        Ok(history
+            .into_iter()
+            .take(last.unwrap_or(MAX_LIST_LEN))
+            .map(Into::into)
+            .collect())
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Collection {
+    address: String,
+    owner: Option<String>,
+    name: Option<String>,
+    description: Option<String>,
+    wallpaper: Option<String>,
+    logo: Option<String>,
+    social: Option<Json<serde_json::Value>>,
+    verified: bool,
+    owners_count: i64,
+    nft_count: i64,
+    floor_price_usd: Option<String>,
+    total_volume_usd: String,
+    created: i64,
+    updated: i64,
+    first_mint: i64,
+}
+
+impl From<CollectionDetails> for Collection {
+    fn from(collection: CollectionDetails) -> Self {
+        Self {
+            address: collection.address,
+            owner: collection.owner,
+            name: collection.name,
+            description: collection.description,
+            wallpaper: collection.wallpaper,
+            logo: collection.logo,
+            social: collection.social.map(Json),
+            verified: collection.verified,
+            owners_count: collection.owners_count,
+            nft_count: collection.nft_count,
+            floor_price_usd: collection.floor_price_usd.map(|p| p.to_string()),
+            total_volume_usd: collection.total_volume_usd.to_string(),
+            created: collection.created.timestamp(),
+            updated: collection.updated.timestamp(),
+            first_mint: collection.first_mint.timestamp(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Attribute {
+    trait_type: String,
+    value: Option<Json<serde_json::Value>>,
+}
+
+impl From<NftAttribute> for Attribute {
+    fn from(attribute: NftAttribute) -> Self {
+        Self {
+            trait_type: attribute.trait_type,
+            value: attribute.value.map(Json),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Listing {
+    address: String,
+    seller: String,
+    price_token: String,
+    price: String,
+    usd_price: Option<String>,
+    created: i64,
+    expired_at: i64,
+    tx_lt: i64,
+}
+
+impl From<ActiveDirectSell> for Listing {
+    fn from(sell: ActiveDirectSell) -> Self {
+        Self {
+            address: sell.address,
+            seller: sell.seller,
+            price_token: sell.price_token,
+            price: sell.price.to_string(),
+            usd_price: sell.usd_price.map(|p| p.to_string()),
+            created: sell.created.timestamp(),
+            expired_at: sell.expired_at.timestamp(),
+            tx_lt: sell.tx_lt,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Offer {
+    address: String,
+    buyer: String,
+    price_token: String,
+    price: String,
+    usd_price: Option<String>,
+    created: i64,
+    expired_at: i64,
+    tx_lt: i64,
+}
+
+impl From<ActiveDirectBuy> for Offer {
+    fn from(buy: ActiveDirectBuy) -> Self {
+        Self {
+            address: buy.address,
+            buyer: buy.buyer,
+            price_token: buy.price_token,
+            price: buy.price.to_string(),
+            usd_price: buy.usd_price.map(|p| p.to_string()),
+            created: buy.created.timestamp(),
+            expired_at: buy.expired_at.timestamp(),
+            tx_lt: buy.tx_lt,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Auction {
+    address: String,
+    nft_owner: String,
+    price_token: Option<String>,
+    start_price: Option<String>,
+    min_bid: Option<String>,
+    max_bid: Option<String>,
+    bids_count: i64,
+    created_at: Option<i64>,
+    finished_at: Option<i64>,
+    tx_lt: i64,
+}
+
+#[ComplexObject]
+impl Auction {
+    /// Not declined bids, newest first, at most 100 entries
+    #[graphql(complexity = "last.unwrap_or(MAX_LIST_LEN).saturating_mul(child_complexity)")]
+    async fn bids(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(maximum = 100))] last: Option<usize>,
+    ) -> Result<Vec<Bid>> {
+        let loader = ctx.data_unchecked::<DataLoader<BidsLoader>>();
+        Ok(loader
+            .load_one(self.address.clone())
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .take(last.unwrap_or(MAX_LIST_LEN))
+            .map(Into::into)
+            .collect())
+    }
+}
+
+impl From<ActiveAuction> for Auction {
+    fn from(auction: ActiveAuction) -> Self {
+        Self {
+            address: auction.address,
+            nft_owner: auction.nft_owner,
+            price_token: auction.price_token,
+            start_price: auction.start_price.map(|p| p.to_string()),
+            min_bid: auction.min_bid.map(|p| p.to_string()),
+            max_bid: auction.max_bid.map(|p| p.to_string()),
+            bids_count: auction.bids_count,
+            created_at: auction.created_at.map(|t| t.timestamp()),
+            finished_at: auction.finished_at.map(|t| t.timestamp()),
+            tx_lt: auction.tx_lt,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Bid {
+    buyer: String,
+    price_token: String,
+    price: String,
+    usd_price: Option<String>,
+    next_bid_value: String,
+    created_at: i64,
+    tx_lt: i64,
+}
+
+impl From<AuctionBid> for Bid {
+    fn from(bid: AuctionBid) -> Self {
+        Self {
+            buyer: bid.buyer,
+            price_token: bid.price_token,
+            price: bid.price.to_string(),
+            usd_price: bid.usd_price.map(|p| p.to_string()),
+            next_bid_value: bid.next_bid_value.to_string(),
+            created_at: bid.created_at.timestamp(),
+            tx_lt: bid.tx_lt,
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum PriceSource {
+    AuctionBid,
+    DirectBuy,
+    DirectSell,
+}
+
+impl From<NftPriceSource> for PriceSource {
+    fn from(source: NftPriceSource) -> Self {
+        match source {
+            NftPriceSource::AuctionBid => Self::AuctionBid,
+            NftPriceSource::DirectBuy => Self::DirectBuy,
+            NftPriceSource::DirectSell => Self::DirectSell,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct PriceHistoryEntry {
+    source: String,
+    source_type: PriceSource,
+    price_token: String,
+    price: String,
+    usd_price: Option<String>,
+    ts: i64,
+}
+
+impl From<PriceHistoryRecord> for PriceHistoryEntry {
+    fn from(record: PriceHistoryRecord) -> Self {
+        Self {
+            source: record.source,
+            source_type: record.source_type.into(),
+            price_token: record.price_token,
+            price: record.price.to_string(),
+            usd_price: record.usd_price.map(|p| p.to_string()),
+            ts: record.ts.timestamp(),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use actix_web::web::Path;
+use actix_web::{get, web, HttpResponse};
+use indexer_repo::media::{Media, MediaModel};
+use indexer_repo::types::{MediaKind, MediaStatus};
+use opg::OpgModel;
+use serde::Serialize;
+
+#[derive(Serialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKindResponse {
+    NftPreview,
+    CollectionLogo,
+    CollectionWallpaper,
+}
+
+impl From<MediaKind> for MediaKindResponse {
+    fn from(kind: MediaKind) -> Self {
+        match kind {
+            MediaKind::NftPreview => Self::NftPreview,
+            MediaKind::CollectionLogo => Self::CollectionLogo,
+            MediaKind::CollectionWallpaper => Self::CollectionWallpaper,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaStatusResponse {
+    Ready,
+    Failed,
+    Unsupported,
+}
+
+impl From<MediaStatus> for MediaStatusResponse {
+    fn from(status: MediaStatus) -> Self {
+        match status {
+            MediaStatus::Ready => Self::Ready,
+            MediaStatus::Failed => Self::Failed,
+            MediaStatus::Unsupported => Self::Unsupported,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct MediaResponse {
+    kind: MediaKindResponse,
+    #[opg("Uri in the metadata", string)]
+    source: String,
+    status: MediaStatusResponse,
+    #[opg(optional, string)]
+    mime_type: Option<String>,
+    #[opg(optional)]
+    size_bytes: Option<i64>,
+    #[opg("Sha-256 of the media, hex", optional, string)]
+    content_hash: Option<String>,
+    #[opg(optional)]
+    width: Option<i32>,
+    #[opg(optional)]
+    height: Option<i32>,
+    #[opg("Copy of the media", optional, string)]
+    url: Option<String>,
+    #[opg(optional, string)]
+    thumbnail_url: Option<String>,
+    #[opg(optional, string)]
+    error: Option<String>,
+    #[opg("Next retry of failed media, none once it's out of attempts", optional)]
+    next_attempt_at: Option<i64>,
+    updated: i64,
+}
+
+impl From<Media> for MediaResponse {
+    fn from(media: Media) -> Self {
+        Self {
+            kind: media.kind.into(),
+            source: media.source,
+            status: media.status.into(),
+            mime_type: media.mime_type,
+            size_bytes: media.size_bytes,
+            content_hash: media.content_hash,
+            width: media.width,
+            height: media.height,
+            url: media.url,
+            thumbnail_url: media.thumbnail_url,
+            error: media.error,
+            next_attempt_at: media.next_attempt_at.map(|t| t.timestamp()),
+            updated: media.updated.timestamp(),
+        }
+    }
+}
+
+/// Copies and thumbnails of the preview of an nft, or the logo and wallpaper of a collection
+#[get("/media/{address}")]
+pub async fn get_media(address: Path<String>, media_model: web::Data<MediaModel>) -> HttpResponse {
+    match media_model.get_media(&address).await {
+        Ok(media) => HttpResponse::Ok().json(
+            media
+                .into_iter()
+                .map(MediaResponse::from)
+                .collect::<Vec<_>>(),
+        ),
+        Err(err) => {
+            log::error!("get media error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -1,9 +1,17 @@
-use actix_web::web::Json;
-use actix_web::{post, web, HttpResponse};
-use data_reader::MetadataJrpcService;
-use indexer_repo::meta::{MetadataModelService, NftAddressData};
+use std::sync::Arc;
+
+use actix_web::web::{Json, Path, Query};
+use actix_web::{get, post, web, HttpResponse};
+use data_reader::{
+    BreakerState, JrpcEndpointStatus, JrpcPool, MetaChange, MetaChangeKind, MetaReaderMetrics,
+    MetadataJrpcService,
+};
+use indexer_repo::meta::{
+    MetaViolation, MetadataModelService, NftAddressData, NftMetaFile, NftMetaVersion,
+    NormalizedNftMetaRow,
+};
 use opg::OpgModel;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, OpgModel)]
 pub struct RefreshMetadataParams {
@@ -79,3 +87,294 @@ pub async fn refresh_metadata_by_nft(
         }
     }
 }
+
+#[derive(Serialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl From<BreakerState> for EndpointState {
+    fn from(state: BreakerState) -> Self {
+        match state {
+            BreakerState::Closed => Self::Closed,
+            BreakerState::Open => Self::Open,
+            BreakerState::HalfOpen => Self::HalfOpen,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct JrpcEndpointResponse {
+    #[opg("Host and port of the endpoint url", string)]
+    host: String,
+    #[opg("Open endpoints are out of rotation after repeated failures")]
+    state: EndpointState,
+    requests: u64,
+    failures: u64,
+}
+
+impl From<JrpcEndpointStatus> for JrpcEndpointResponse {
+    fn from(status: JrpcEndpointStatus) -> Self {
+        Self {
+            host: status.host,
+            state: status.state.into(),
+            requests: status.requests,
+            failures: status.failures,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct MetaReaderMetricsResponse {
+    #[opg("Addresses read since start, failed ones included")]
+    processed: u64,
+    failed: u64,
+    #[opg("Addresses of the current batch waiting to be read")]
+    queue_depth: u64,
+    in_flight: u64,
+    #[opg("Addresses read per second in the last finished batch", optional)]
+    last_batch_per_sec: Option<f64>,
+    endpoints: Vec<JrpcEndpointResponse>,
+}
+
+#[get("/metadata/reader/metrics")]
+pub async fn get_meta_reader_metrics(
+    metrics: web::Data<Arc<MetaReaderMetrics>>,
+    jrpc_pool: web::Data<Arc<JrpcPool>>,
+) -> HttpResponse {
+    let stats = metrics.stats();
+
+    HttpResponse::Ok().json(MetaReaderMetricsResponse {
+        processed: stats.processed,
+        failed: stats.failed,
+        queue_depth: stats.queue_depth,
+        in_flight: stats.in_flight,
+        last_batch_per_sec: stats.last_batch_per_sec,
+        endpoints: jrpc_pool
+            .status()
+            .into_iter()
+            .map(JrpcEndpointResponse::from)
+            .collect(),
+    })
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct NftMetaFileResponse {
+    #[opg(string)]
+    source: String,
+    #[opg(optional, string)]
+    mimetype: Option<String>,
+}
+
+impl From<NftMetaFile> for NftMetaFileResponse {
+    fn from(file: NftMetaFile) -> Self {
+        Self {
+            source: file.source,
+            mimetype: file.mimetype,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct MetaViolationResponse {
+    #[opg(
+        "Json path of the field, e.g. files[0].mimetype, empty for the whole json",
+        string
+    )]
+    path: String,
+    #[opg(string)]
+    message: String,
+}
+
+impl From<MetaViolation> for MetaViolationResponse {
+    fn from(violation: MetaViolation) -> Self {
+        Self {
+            path: violation.path,
+            message: violation.message,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct NormalizedNftMetaResponse {
+    #[opg(string)]
+    nft: String,
+    #[opg("Type of the nft, e.g. Basic NFT", optional, string)]
+    r#type: Option<String>,
+    #[opg(optional, string)]
+    preview_url: Option<String>,
+    #[opg(optional, string)]
+    preview_mimetype: Option<String>,
+    #[opg(optional, string)]
+    external_url: Option<String>,
+    files: Vec<NftMetaFileResponse>,
+    #[opg("Whether the metadata follows tip-4.2")]
+    valid: bool,
+    violations: Vec<MetaViolationResponse>,
+    normalized: i64,
+}
+
+impl From<NormalizedNftMetaRow> for NormalizedNftMetaResponse {
+    fn from(meta: NormalizedNftMetaRow) -> Self {
+        Self {
+            nft: meta.nft,
+            r#type: meta.meta_type,
+            preview_url: meta.preview_url,
+            preview_mimetype: meta.preview_mimetype,
+            external_url: meta.external_url,
+            files: meta.files.into_iter().map(Into::into).collect(),
+            valid: meta.violations.is_empty(),
+            violations: meta.violations.into_iter().map(Into::into).collect(),
+            normalized: meta.normalized.timestamp(),
+        }
+    }
+}
+
+/// Tip-4.2 fields of the nft metadata and the ways it breaks the standard
+#[get("/nfts/{address}/metadata")]
+pub async fn get_normalized_meta(
+    address: Path<String>,
+    meta_model_service: web::Data<MetadataModelService>,
+) -> HttpResponse {
+    match meta_model_service.get_normalized_meta(&address).await {
+        Ok(Some(meta)) => HttpResponse::Ok().json(NormalizedNftMetaResponse::from(meta)),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(err) => {
+            log::error!("get normalized meta error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct NftMetaVersionResponse {
+    version: i32,
+    #[opg(any)]
+    meta: serde_json::Value,
+    #[opg("Sha-256 of the json with sorted keys, hex", string)]
+    content_hash: String,
+    created: i64,
+}
+
+impl From<NftMetaVersion> for NftMetaVersionResponse {
+    fn from(version: NftMetaVersion) -> Self {
+        Self {
+            version: version.version,
+            meta: version.meta,
+            content_hash: version.content_hash,
+            created: version.created.timestamp(),
+        }
+    }
+}
+
+/// Every distinct metadata json of the nft, newest first
+#[get("/nfts/{address}/metadata/versions")]
+pub async fn get_meta_versions(
+    address: Path<String>,
+    meta_model_service: web::Data<MetadataModelService>,
+) -> HttpResponse {
+    match meta_model_service.get_meta_versions(&address).await {
+        Ok(versions) => HttpResponse::Ok().json(
+            versions
+                .into_iter()
+                .map(NftMetaVersionResponse::from)
+                .collect::<Vec<_>>(),
+        ),
+        Err(err) => {
+            log::error!("get meta versions error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MetaDiffParams {
+    from: i32,
+    to: i32,
+}
+
+#[derive(Serialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum MetaChangeKindResponse {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl From<MetaChangeKind> for MetaChangeKindResponse {
+    fn from(kind: MetaChangeKind) -> Self {
+        match kind {
+            MetaChangeKind::Added => Self::Added,
+            MetaChangeKind::Removed => Self::Removed,
+            MetaChangeKind::Changed => Self::Changed,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct MetaChangeResponse {
+    #[opg(
+        "Json path of the value, e.g. attributes[2].value, empty for the whole json",
+        string
+    )]
+    path: String,
+    kind: MetaChangeKindResponse,
+    #[opg("Value in the `from` version, none if added", optional, any)]
+    from: Option<serde_json::Value>,
+    #[opg("Value in the `to` version, none if removed", optional, any)]
+    to: Option<serde_json::Value>,
+}
+
+impl From<MetaChange> for MetaChangeResponse {
+    fn from(change: MetaChange) -> Self {
+        Self {
+            path: change.path,
+            kind: change.kind.into(),
+            from: change.from,
+            to: change.to,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct MetaDiffResponse {
+    from: NftMetaVersionResponse,
+    to: NftMetaVersionResponse,
+    changes: Vec<MetaChangeResponse>,
+}
+
+/// Changes of the nft metadata between two versions
+#[get("/nfts/{address}/metadata/diff")]
+pub async fn get_meta_diff(
+    address: Path<String>,
+    params: Query<MetaDiffParams>,
+    meta_model_service: web::Data<MetadataModelService>,
+) -> HttpResponse {
+    let versions = futures::try_join!(
+        meta_model_service.get_meta_version(&address, params.from),
+        meta_model_service.get_meta_version(&address, params.to),
+    );
+
+    match versions {
+        Ok((Some(from), Some(to))) => {
+            let changes = data_reader::diff_meta(&from.meta, &to.meta)
+                .into_iter()
+                .map(MetaChangeResponse::from)
+                .collect();
+
+            HttpResponse::Ok().json(MetaDiffResponse {
+                from: from.into(),
+                to: to.into(),
+                changes,
+            })
+        }
+        Ok(_) => HttpResponse::NotFound().finish(),
+        Err(err) => {
+            log::error!("get meta diff error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -1,2 +1,18 @@
+pub mod auth;
 pub mod docs;
+pub mod event_args;
+pub mod events;
+pub mod facets;
+pub mod fiat;
+pub mod graphql;
+pub mod media;
 pub mod metadata;
+pub mod nfts;
+pub mod offers;
+pub mod pagination;
+pub mod portfolio;
+pub mod rarity;
+pub mod search;
+pub mod stats;
+pub mod tokens;
+pub mod webhooks;
@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+
+use actix_web::web::{Path, Query};
+use actix_web::{get, web, HttpResponse};
+use bigdecimal::BigDecimal;
+use indexer_repo::fiat::FiatRateModel;
+use indexer_repo::nft::{
+    CollectionDetails, NftDetails, NftModel, PriceHistoryCursor, PriceHistoryRecord,
+};
+use indexer_repo::rarity::{RankFilter, RarityCursor, RarityModel};
+use indexer_repo::tokens::TokenModel;
+use indexer_repo::types::{AuctionStatus, DirectSellState, NftPriceSource, RarityAlgorithm};
+use opg::OpgModel;
+use serde::{Deserialize, Serialize};
+
+use crate::api::fiat::{format_fiat, load_rates_at};
+use crate::api::pagination::{next_cursor, page_limit, parse_cursor};
+use crate::api::tokens::TokenDecimals;
+
+#[derive(Serialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum NftAuctionStatus {
+    Created,
+    Active,
+    Completed,
+    Cancelled,
+    Expired,
+}
+
+impl From<AuctionStatus> for NftAuctionStatus {
+    fn from(status: AuctionStatus) -> Self {
+        match status {
+            AuctionStatus::Created => Self::Created,
+            AuctionStatus::Active => Self::Active,
+            AuctionStatus::Completed => Self::Completed,
+            AuctionStatus::Cancelled => Self::Cancelled,
+            AuctionStatus::Expired => Self::Expired,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum NftForsaleStatus {
+    Create,
+    AwaitNft,
+    Active,
+    Filled,
+    Cancelled,
+    Expired,
+}
+
+impl From<DirectSellState> for NftForsaleStatus {
+    fn from(state: DirectSellState) -> Self {
+        match state {
+            DirectSellState::Create => Self::Create,
+            DirectSellState::AwaitNft => Self::AwaitNft,
+            DirectSellState::Active => Self::Active,
+            DirectSellState::Filled => Self::Filled,
+            DirectSellState::Cancelled => Self::Cancelled,
+            DirectSellState::Expired => Self::Expired,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    AuctionBid,
+    DirectBuy,
+    DirectSell,
+}
+
+impl From<NftPriceSource> for PriceSource {
+    fn from(source: NftPriceSource) -> Self {
+        match source {
+            NftPriceSource::AuctionBid => Self::AuctionBid,
+            NftPriceSource::DirectBuy => Self::DirectBuy,
+            NftPriceSource::DirectSell => Self::DirectSell,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NftsParams {
+    owner: Option<String>,
+    collection: Option<String>,
+    /// Orders the collection from the rarest nft by the algorithm
+    rarity: Option<RarityAlgorithm>,
+    min_rank: Option<i32>,
+    max_rank: Option<i32>,
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct PriceHistoryParams {
+    nft: Option<String>,
+    collection: Option<String>,
+    currency: Option<String>,
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct NftResponse {
+    #[opg(string)]
+    address: String,
+    #[opg(optional, string)]
+    collection: Option<String>,
+    #[opg(optional, string)]
+    owner: Option<String>,
+    #[opg(optional, string)]
+    manager: Option<String>,
+    #[opg(optional, string)]
+    name: Option<String>,
+    #[opg(optional, string)]
+    description: Option<String>,
+    #[opg(optional, any)]
+    meta: Option<serde_json::Value>,
+    #[opg(optional, string)]
+    nft_id: Option<String>,
+    #[opg(optional, string)]
+    auction: Option<String>,
+    #[opg(optional)]
+    auction_status: Option<NftAuctionStatus>,
+    #[opg(optional, string)]
+    forsale: Option<String>,
+    #[opg(optional)]
+    forsale_status: Option<NftForsaleStatus>,
+    #[opg(optional, string)]
+    best_offer: Option<String>,
+    #[opg(optional, string)]
+    floor_price: Option<String>,
+    #[opg(optional, string)]
+    floor_price_token: Option<String>,
+    #[opg(optional, string)]
+    floor_price_usd: Option<String>,
+    #[opg(optional, string)]
+    deal_price_usd: Option<String>,
+    #[opg("Rank by the requested rarity algorithm, 1 is the rarest", optional)]
+    rarity_rank: Option<i32>,
+    #[opg("Copy of the preview, once it is stored", optional, string)]
+    preview_url: Option<String>,
+    #[opg(optional, string)]
+    preview_thumbnail_url: Option<String>,
+    updated: i64,
+    tx_lt: i64,
+}
+
+impl From<NftDetails> for NftResponse {
+    fn from(nft: NftDetails) -> Self {
+        Self {
+            address: nft.address,
+            collection: nft.collection,
+            owner: nft.owner,
+            manager: nft.manager,
+            name: nft.name,
+            description: nft.description,
+            meta: nft.meta,
+            nft_id: nft.nft_id,
+            auction: nft.auction,
+            auction_status: nft.auction_status.map(Into::into),
+            forsale: nft.forsale,
+            forsale_status: nft.forsale_status.map(Into::into),
+            best_offer: nft.best_offer,
+            floor_price: nft.floor_price.map(|p| p.to_string()),
+            floor_price_token: nft.floor_price_token,
+            floor_price_usd: nft.floor_price_usd.map(|p| p.to_string()),
+            deal_price_usd: nft.deal_price_usd.map(|p| p.to_string()),
+            rarity_rank: None,
+            preview_url: nft.preview_url,
+            preview_thumbnail_url: nft.preview_thumbnail_url,
+            updated: nft.updated.timestamp(),
+            tx_lt: nft.tx_lt,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct NftsPage {
+    items: Vec<NftResponse>,
+    #[opg("Pass as `cursor` to get the next page", optional, string)]
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct CollectionResponse {
+    #[opg(string)]
+    address: String,
+    #[opg(optional, string)]
+    owner: Option<String>,
+    #[opg(optional, string)]
+    name: Option<String>,
+    #[opg(optional, string)]
+    description: Option<String>,
+    #[opg(optional, string)]
+    wallpaper: Option<String>,
+    #[opg(optional, string)]
+    logo: Option<String>,
+    #[opg("Copy of the logo, once it is stored", optional, string)]
+    logo_url: Option<String>,
+    #[opg(optional, string)]
+    logo_thumbnail_url: Option<String>,
+    #[opg("Copy of the wallpaper, once it is stored", optional, string)]
+    wallpaper_url: Option<String>,
+    #[opg(optional, string)]
+    wallpaper_thumbnail_url: Option<String>,
+    #[opg(optional, any)]
+    social: Option<serde_json::Value>,
+    verified: bool,
+    owners_count: i64,
+    nft_count: i64,
+    #[opg(optional, string)]
+    floor_price_usd: Option<String>,
+    #[opg(string)]
+    total_volume_usd: String,
+    #[opg(optional, any)]
+    attributes: Option<serde_json::Value>,
+    created: i64,
+    updated: i64,
+    first_mint: i64,
+}
+
+impl From<CollectionDetails> for CollectionResponse {
+    fn from(collection: CollectionDetails) -> Self {
+        Self {
+            address: collection.address,
+            owner: collection.owner,
+            name: collection.name,
+            description: collection.description,
+            wallpaper: collection.wallpaper,
+            logo: collection.logo,
+            logo_url: collection.logo_url,
+            logo_thumbnail_url: collection.logo_thumbnail_url,
+            wallpaper_url: collection.wallpaper_url,
+            wallpaper_thumbnail_url: collection.wallpaper_thumbnail_url,
+            social: collection.social,
+            verified: collection.verified,
+            owners_count: collection.owners_count,
+            nft_count: collection.nft_count,
+            floor_price_usd: collection.floor_price_usd.map(|p| p.to_string()),
+            total_volume_usd: collection.total_volume_usd.to_string(),
+            attributes: collection.attributes,
+            created: collection.created.timestamp(),
+            updated: collection.updated.timestamp(),
+            first_mint: collection.first_mint.timestamp(),
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct PriceHistoryResponse {
+    #[opg(string)]
+    source: String,
+    source_type: PriceSource,
+    #[opg(string)]
+    nft: String,
+    #[opg(string)]
+    collection: String,
+    #[opg(string)]
+    price: String,
+    #[opg("In whole tokens, known for tokens in the registry", optional, string)]
+    price_formatted: Option<String>,
+    #[opg(string)]
+    price_token: String,
+    #[opg(optional, string)]
+    usd_price: Option<String>,
+    #[opg(
+        "Usd price in the requested currency at the rate of the deal completion, in cents",
+        optional,
+        string
+    )]
+    fiat_price: Option<String>,
+    ts: i64,
+}
+
+impl PriceHistoryResponse {
+    fn new(
+        record: PriceHistoryRecord,
+        decimals: &TokenDecimals,
+        fiat_rate: Option<&BigDecimal>,
+    ) -> Self {
+        Self {
+            fiat_price: record
+                .usd_price
+                .as_ref()
+                .zip(fiat_rate)
+                .map(|(usd, rate)| format_fiat(&(usd * rate))),
+            price_formatted: decimals.format(Some(&record.price_token), Some(&record.price)),
+            source: record.source,
+            source_type: record.source_type.into(),
+            nft: record.nft,
+            collection: record.collection,
+            price: record.price.to_string(),
+            price_token: record.price_token,
+            usd_price: record.usd_price.map(|p| p.to_string()),
+            ts: record.ts.timestamp(),
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct PriceHistoryPage {
+    items: Vec<PriceHistoryResponse>,
+    #[opg("Pass as `cursor` to get the next page", optional, string)]
+    next_cursor: Option<String>,
+}
+
+#[get("/nfts/{address}")]
+pub async fn get_nft(address: Path<String>, nft_model: web::Data<NftModel>) -> HttpResponse {
+    match nft_model.get_nft(&address).await {
+        Ok(Some(nft)) => HttpResponse::Ok().json(NftResponse::from(nft)),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(err) => {
+            log::error!("get nft error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/nfts")]
+pub async fn get_nfts(
+    params: Query<NftsParams>,
+    nft_model: web::Data<NftModel>,
+    rarity_model: web::Data<RarityModel>,
+) -> HttpResponse {
+    if params.owner.is_none() && params.collection.is_none() {
+        return HttpResponse::BadRequest().body("owner or collection is required");
+    }
+
+    let limit = page_limit(params.limit);
+
+    if params.rarity.is_some() || params.min_rank.is_some() || params.max_rank.is_some() {
+        return get_nfts_by_rarity(&params, limit, &nft_model, &rarity_model).await;
+    }
+
+    match nft_model
+        .get_nfts(
+            params.owner.as_deref(),
+            params.collection.as_deref(),
+            params.cursor.as_deref(),
+            limit,
+        )
+        .await
+    {
+        Ok(nfts) => HttpResponse::Ok().json(NftsPage {
+            next_cursor: next_cursor(&nfts, limit, |n| n.address.clone()),
+            items: nfts.into_iter().map(Into::into).collect(),
+        }),
+        Err(err) => {
+            log::error!("get nfts error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+async fn get_nfts_by_rarity(
+    params: &NftsParams,
+    limit: i64,
+    nft_model: &NftModel,
+    rarity_model: &RarityModel,
+) -> HttpResponse {
+    let Some(collection) = params.collection.as_deref() else {
+        return HttpResponse::BadRequest().body("rarity is ranked within a collection");
+    };
+    let Ok(after) = parse_cursor::<RarityCursor>(params.cursor.as_deref()) else {
+        return HttpResponse::BadRequest().body("malformed cursor");
+    };
+
+    let filter = RankFilter {
+        owner: params.owner.clone(),
+        min_rank: params.min_rank,
+        max_rank: params.max_rank,
+    };
+
+    let ranked = match rarity_model
+        .get_ranked_nfts(
+            collection,
+            params.rarity.unwrap_or(RarityAlgorithm::Statistical),
+            &filter,
+            after.as_ref(),
+            limit,
+        )
+        .await
+    {
+        Ok(ranked) => ranked,
+        Err(err) => {
+            log::error!("get ranked nfts error {err}");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let addresses = ranked.iter().map(|n| n.address.clone()).collect::<Vec<_>>();
+    let mut details = match nft_model.get_nfts_by_addresses(&addresses).await {
+        Ok(nfts) => nfts
+            .into_iter()
+            .map(|n| (n.address.clone(), n))
+            .collect::<HashMap<_, _>>(),
+        Err(err) => {
+            log::error!("get nfts by addresses error {err}");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    HttpResponse::Ok().json(NftsPage {
+        next_cursor: next_cursor(&ranked, limit, |n| RarityCursor::from(n)),
+        items: ranked
+            .iter()
+            .filter_map(|r| {
+                let mut nft = NftResponse::from(details.remove(&r.address)?);
+                nft.rarity_rank = Some(r.rank);
+                Some(nft)
+            })
+            .collect(),
+    })
+}
+
+#[get("/collections/{address}")]
+pub async fn get_collection(address: Path<String>, nft_model: web::Data<NftModel>) -> HttpResponse {
+    match nft_model.get_collection(&address).await {
+        Ok(Some(collection)) => HttpResponse::Ok().json(CollectionResponse::from(collection)),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(err) => {
+            log::error!("get collection error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/price-history")]
+pub async fn get_price_history(
+    params: Query<PriceHistoryParams>,
+    nft_model: web::Data<NftModel>,
+    token_model: web::Data<TokenModel>,
+    fiat_model: web::Data<FiatRateModel>,
+) -> HttpResponse {
+    if params.nft.is_none() && params.collection.is_none() {
+        return HttpResponse::BadRequest().body("nft or collection is required");
+    }
+
+    let cursor = match parse_cursor::<PriceHistoryCursor>(params.cursor.as_deref()) {
+        Ok(cursor) => cursor,
+        Err(_) => return HttpResponse::BadRequest().body("malformed cursor"),
+    };
+    let limit = page_limit(params.limit);
+
+    match nft_model
+        .get_price_history(
+            params.nft.as_deref(),
+            params.collection.as_deref(),
+            cursor.as_ref(),
+            limit,
+        )
+        .await
+    {
+        Ok(records) => {
+            let tokens = records.iter().map(|r| r.price_token.as_str());
+            let decimals = TokenDecimals::load(&token_model, tokens).await;
+
+            let fiat_rates = match &params.currency {
+                Some(currency) => {
+                    // Auction bids are converted at the rate of the auction completion
+                    let times = records.iter().map(|r| r.completed_at).collect::<Vec<_>>();
+                    match load_rates_at(&fiat_model, currency, &times).await {
+                        Ok(rates) => rates,
+                        Err(response) => return response,
+                    }
+                }
+                None => Vec::new(),
+            };
+
+            HttpResponse::Ok().json(PriceHistoryPage {
+                next_cursor: next_cursor(&records, limit, |r| PriceHistoryCursor::from(r)),
+                items: records
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, r)| {
+                        let fiat_rate = fiat_rates.get(i).and_then(Option::as_ref);
+                        PriceHistoryResponse::new(r, &decimals, fiat_rate)
+                    })
+                    .collect(),
+            })
+        }
+        Err(err) => {
+            log::error!("get price history error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
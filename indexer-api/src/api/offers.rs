@@ -0,0 +1,358 @@
+use actix_web::web::Query;
+use actix_web::{get, web, HttpResponse};
+use bigdecimal::BigDecimal;
+use indexer_repo::fiat::FiatRateModel;
+use indexer_repo::offers::{
+    ActiveAuction, ActiveDirectBuy, ActiveDirectSell, OfferCursor, OfferFilter, OffersModel,
+};
+use indexer_repo::tokens::TokenModel;
+use opg::OpgModel;
+use serde::{Deserialize, Serialize};
+
+use crate::api::fiat::FiatConversion;
+use crate::api::pagination::{next_cursor, page_limit, parse_cursor};
+use crate::api::tokens::TokenDecimals;
+
+#[derive(Deserialize)]
+pub struct ActiveOffersParams {
+    collection: Option<String>,
+    nft: Option<String>,
+    owner: Option<String>,
+    currency: Option<String>,
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
+impl ActiveOffersParams {
+    fn filter(&self) -> OfferFilter {
+        OfferFilter {
+            collection: self.collection.clone(),
+            nft: self.nft.clone(),
+            owner: self.owner.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct AuctionResponse {
+    #[opg(string)]
+    address: String,
+    #[opg(string)]
+    nft: String,
+    #[opg(string)]
+    collection: String,
+    #[opg(string)]
+    nft_owner: String,
+    #[opg(optional, string)]
+    price_token: Option<String>,
+    #[opg(optional, string)]
+    start_price: Option<String>,
+    #[opg("In whole tokens, known for tokens in the registry", optional, string)]
+    start_price_formatted: Option<String>,
+    #[opg(optional, string)]
+    start_usd_price: Option<String>,
+    #[opg("Usd value in the requested currency", optional, string)]
+    start_fiat_price: Option<String>,
+    #[opg(optional, string)]
+    min_bid: Option<String>,
+    #[opg(optional, string)]
+    min_bid_formatted: Option<String>,
+    #[opg(optional, string)]
+    min_usd_bid: Option<String>,
+    #[opg(optional, string)]
+    min_fiat_bid: Option<String>,
+    #[opg(optional, string)]
+    max_bid: Option<String>,
+    #[opg(optional, string)]
+    max_bid_formatted: Option<String>,
+    bids_count: i64,
+    #[opg(optional, string)]
+    last_bid_from: Option<String>,
+    #[opg(optional, string)]
+    last_bid_value: Option<String>,
+    #[opg(optional, string)]
+    last_bid_value_formatted: Option<String>,
+    #[opg(optional, string)]
+    last_bid_usd_value: Option<String>,
+    #[opg(optional, string)]
+    last_bid_fiat_value: Option<String>,
+    #[opg(optional)]
+    created_at: Option<i64>,
+    #[opg(optional)]
+    finished_at: Option<i64>,
+    tx_lt: i64,
+}
+
+impl AuctionResponse {
+    fn new(
+        auction: ActiveAuction,
+        decimals: &TokenDecimals,
+        fiat: Option<&FiatConversion>,
+    ) -> Self {
+        let token = auction.price_token.as_deref();
+        let to_fiat = |usd: Option<&BigDecimal>| fiat.and_then(|f| f.convert(usd));
+
+        Self {
+            start_fiat_price: to_fiat(auction.start_usd_price.as_ref()),
+            min_fiat_bid: to_fiat(auction.min_usd_bid.as_ref()),
+            last_bid_fiat_value: to_fiat(auction.last_bid_usd_value.as_ref()),
+            start_price_formatted: decimals.format(token, auction.start_price.as_ref()),
+            min_bid_formatted: decimals.format(token, auction.min_bid.as_ref()),
+            max_bid_formatted: decimals.format(token, auction.max_bid.as_ref()),
+            last_bid_value_formatted: decimals.format(token, auction.last_bid_value.as_ref()),
+            address: auction.address,
+            nft: auction.nft,
+            collection: auction.collection,
+            nft_owner: auction.nft_owner,
+            price_token: auction.price_token,
+            start_price: auction.start_price.map(|p| p.to_string()),
+            start_usd_price: auction.start_usd_price.map(|p| p.to_string()),
+            min_bid: auction.min_bid.map(|p| p.to_string()),
+            min_usd_bid: auction.min_usd_bid.map(|p| p.to_string()),
+            max_bid: auction.max_bid.map(|p| p.to_string()),
+            bids_count: auction.bids_count,
+            last_bid_from: auction.last_bid_from,
+            last_bid_value: auction.last_bid_value.map(|p| p.to_string()),
+            last_bid_usd_value: auction.last_bid_usd_value.map(|p| p.to_string()),
+            created_at: auction.created_at.map(|t| t.timestamp()),
+            finished_at: auction.finished_at.map(|t| t.timestamp()),
+            tx_lt: auction.tx_lt,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct AuctionsPage {
+    items: Vec<AuctionResponse>,
+    #[opg("Pass as `cursor` to get the next page", optional, string)]
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct DirectSellResponse {
+    #[opg(string)]
+    address: String,
+    #[opg(string)]
+    nft: String,
+    #[opg(optional, string)]
+    collection: Option<String>,
+    #[opg(string)]
+    seller: String,
+    #[opg(string)]
+    price_token: String,
+    #[opg(string)]
+    price: String,
+    #[opg("In whole tokens, known for tokens in the registry", optional, string)]
+    price_formatted: Option<String>,
+    #[opg(optional, string)]
+    usd_price: Option<String>,
+    #[opg("Usd value in the requested currency", optional, string)]
+    fiat_price: Option<String>,
+    created: i64,
+    expired_at: i64,
+    tx_lt: i64,
+}
+
+impl DirectSellResponse {
+    fn new(
+        sell: ActiveDirectSell,
+        decimals: &TokenDecimals,
+        fiat: Option<&FiatConversion>,
+    ) -> Self {
+        Self {
+            fiat_price: fiat.and_then(|f| f.convert(sell.usd_price.as_ref())),
+            price_formatted: decimals.format(Some(&sell.price_token), Some(&sell.price)),
+            address: sell.address,
+            nft: sell.nft,
+            collection: sell.collection,
+            seller: sell.seller,
+            price_token: sell.price_token,
+            price: sell.price.to_string(),
+            usd_price: sell.usd_price.map(|p| p.to_string()),
+            created: sell.created.timestamp(),
+            expired_at: sell.expired_at.timestamp(),
+            tx_lt: sell.tx_lt,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct DirectSellsPage {
+    items: Vec<DirectSellResponse>,
+    #[opg("Pass as `cursor` to get the next page", optional, string)]
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct DirectBuyResponse {
+    #[opg(string)]
+    address: String,
+    #[opg(string)]
+    nft: String,
+    #[opg(optional, string)]
+    collection: Option<String>,
+    #[opg(string)]
+    buyer: String,
+    #[opg(string)]
+    price_token: String,
+    #[opg(string)]
+    price: String,
+    #[opg("In whole tokens, known for tokens in the registry", optional, string)]
+    price_formatted: Option<String>,
+    #[opg(optional, string)]
+    usd_price: Option<String>,
+    #[opg("Usd value in the requested currency", optional, string)]
+    fiat_price: Option<String>,
+    created: i64,
+    expired_at: i64,
+    tx_lt: i64,
+}
+
+impl DirectBuyResponse {
+    fn new(buy: ActiveDirectBuy, decimals: &TokenDecimals, fiat: Option<&FiatConversion>) -> Self {
+        Self {
+            fiat_price: fiat.and_then(|f| f.convert(buy.usd_price.as_ref())),
+            price_formatted: decimals.format(Some(&buy.price_token), Some(&buy.price)),
+            address: buy.address,
+            nft: buy.nft,
+            collection: buy.collection,
+            buyer: buy.buyer,
+            price_token: buy.price_token,
+            price: buy.price.to_string(),
+            usd_price: buy.usd_price.map(|p| p.to_string()),
+            created: buy.created.timestamp(),
+            expired_at: buy.expired_at.timestamp(),
+            tx_lt: buy.tx_lt,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct DirectBuysPage {
+    items: Vec<DirectBuyResponse>,
+    #[opg("Pass as `cursor` to get the next page", optional, string)]
+    next_cursor: Option<String>,
+}
+
+#[get("/auctions")]
+pub async fn get_active_auctions(
+    params: Query<ActiveOffersParams>,
+    offers_model: web::Data<OffersModel>,
+    token_model: web::Data<TokenModel>,
+    fiat_model: web::Data<FiatRateModel>,
+) -> HttpResponse {
+    let fiat = match FiatConversion::load(&fiat_model, params.currency.as_deref()).await {
+        Ok(fiat) => fiat,
+        Err(response) => return response,
+    };
+    let cursor = match parse_cursor::<OfferCursor>(params.cursor.as_deref()) {
+        Ok(cursor) => cursor,
+        Err(_) => return HttpResponse::BadRequest().body("malformed cursor"),
+    };
+    let limit = page_limit(params.limit);
+
+    match offers_model
+        .get_active_auctions(&params.filter(), cursor.as_ref(), limit)
+        .await
+    {
+        Ok(auctions) => {
+            let tokens = auctions.iter().filter_map(|a| a.price_token.as_deref());
+            let decimals = TokenDecimals::load(&token_model, tokens).await;
+
+            HttpResponse::Ok().json(AuctionsPage {
+                next_cursor: next_cursor(&auctions, limit, |a| {
+                    OfferCursor::new(a.tx_lt, &a.address)
+                }),
+                items: auctions
+                    .into_iter()
+                    .map(|a| AuctionResponse::new(a, &decimals, fiat.as_ref()))
+                    .collect(),
+            })
+        }
+        Err(err) => {
+            log::error!("get active auctions error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/listings")]
+pub async fn get_active_listings(
+    params: Query<ActiveOffersParams>,
+    offers_model: web::Data<OffersModel>,
+    token_model: web::Data<TokenModel>,
+    fiat_model: web::Data<FiatRateModel>,
+) -> HttpResponse {
+    let fiat = match FiatConversion::load(&fiat_model, params.currency.as_deref()).await {
+        Ok(fiat) => fiat,
+        Err(response) => return response,
+    };
+    let cursor = match parse_cursor::<OfferCursor>(params.cursor.as_deref()) {
+        Ok(cursor) => cursor,
+        Err(_) => return HttpResponse::BadRequest().body("malformed cursor"),
+    };
+    let limit = page_limit(params.limit);
+
+    match offers_model
+        .get_active_direct_sells(&params.filter(), cursor.as_ref(), limit)
+        .await
+    {
+        Ok(sells) => {
+            let tokens = sells.iter().map(|s| s.price_token.as_str());
+            let decimals = TokenDecimals::load(&token_model, tokens).await;
+
+            HttpResponse::Ok().json(DirectSellsPage {
+                next_cursor: next_cursor(&sells, limit, |s| OfferCursor::new(s.tx_lt, &s.address)),
+                items: sells
+                    .into_iter()
+                    .map(|s| DirectSellResponse::new(s, &decimals, fiat.as_ref()))
+                    .collect(),
+            })
+        }
+        Err(err) => {
+            log::error!("get active listings error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/offers")]
+pub async fn get_active_offers(
+    params: Query<ActiveOffersParams>,
+    offers_model: web::Data<OffersModel>,
+    token_model: web::Data<TokenModel>,
+    fiat_model: web::Data<FiatRateModel>,
+) -> HttpResponse {
+    let fiat = match FiatConversion::load(&fiat_model, params.currency.as_deref()).await {
+        Ok(fiat) => fiat,
+        Err(response) => return response,
+    };
+    let cursor = match parse_cursor::<OfferCursor>(params.cursor.as_deref()) {
+        Ok(cursor) => cursor,
+        Err(_) => return HttpResponse::BadRequest().body("malformed cursor"),
+    };
+    let limit = page_limit(params.limit);
+
+    match offers_model
+        .get_active_direct_buys(&params.filter(), cursor.as_ref(), limit)
+        .await
+    {
+        Ok(buys) => {
+            let tokens = buys.iter().map(|b| b.price_token.as_str());
+            let decimals = TokenDecimals::load(&token_model, tokens).await;
+
+            HttpResponse::Ok().json(DirectBuysPage {
+                next_cursor: next_cursor(&buys, limit, |b| OfferCursor::new(b.tx_lt, &b.address)),
+                items: buys
+                    .into_iter()
+                    .map(|b| DirectBuyResponse::new(b, &decimals, fiat.as_ref()))
+                    .collect(),
+            })
+        }
+        Err(err) => {
+            log::error!("get active offers error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use std::str::FromStr;
+
+const DEFAULT_PAGE_LIMIT: i64 = 50;
+const MAX_PAGE_LIMIT: i64 = 200;
+
+pub fn page_limit(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT)
+}
+
+pub fn parse_cursor<T: FromStr>(cursor: Option<&str>) -> Result<Option<T>, T::Err> {
+    cursor.map(str::parse).transpose()
+}
+
+/// The next cursor is only returned when the page is full.
+pub fn next_cursor<T, C: ToString>(
+    items: &[T],
+    limit: i64,
+    cursor: impl Fn(&T) -> C,
+) -> Option<String> {
+    match items.last() {
+        Some(last) if items.len() as i64 == limit => Some(cursor(last).to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use actix_web::web::{Path, Query};
+use actix_web::{get, web, HttpResponse};
+use bigdecimal::BigDecimal;
+use data_reader::PriceReader;
+use indexer_repo::fiat::FiatRateModel;
+use indexer_repo::nft::{NftModel, PortfolioNft};
+use opg::OpgModel;
+use serde::{Deserialize, Serialize};
+
+use crate::api::fiat::{format_fiat, FiatConversion};
+
+/// Wallets above it get a truncated portfolio
+const MAX_PORTFOLIO_NFTS: i64 = 5000;
+const USD_SCALE: i64 = 2;
+
+#[derive(Deserialize)]
+pub struct PortfolioParams {
+    currency: Option<String>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct PortfolioNftResponse {
+    #[opg(string)]
+    address: String,
+    #[opg(optional, string)]
+    name: Option<String>,
+    #[opg(optional, string)]
+    last_sale_price: Option<String>,
+    #[opg(optional, string)]
+    last_sale_token: Option<String>,
+    #[opg("Usd price at the time of the sale", optional, string)]
+    last_sale_usd: Option<String>,
+    #[opg(optional)]
+    last_sale_at: Option<i64>,
+    #[opg(
+        "Last sale at the current token price, the collection floor for never sold NFTs",
+        optional,
+        string
+    )]
+    estimated_usd: Option<String>,
+    #[opg(optional)]
+    acquired_at: Option<i64>,
+    #[opg(optional, string)]
+    acquisition_price: Option<String>,
+    #[opg(optional, string)]
+    acquisition_token: Option<String>,
+    #[opg(optional, string)]
+    acquisition_usd: Option<String>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct PortfolioCollection {
+    #[opg(string)]
+    address: String,
+    #[opg(optional, string)]
+    name: Option<String>,
+    #[opg(optional, string)]
+    floor_price_usd: Option<String>,
+    nft_count: usize,
+    #[opg(string)]
+    estimated_usd: String,
+    #[opg(string)]
+    acquisition_usd: String,
+    #[opg("Estimated usd in the requested currency", optional, string)]
+    estimated_fiat: Option<String>,
+    #[opg(optional, string)]
+    acquisition_fiat: Option<String>,
+    nfts: Vec<PortfolioNftResponse>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct PortfolioResponse {
+    #[opg(string)]
+    owner: String,
+    nft_count: usize,
+    #[opg(string)]
+    estimated_usd: String,
+    #[opg(string)]
+    acquisition_usd: String,
+    #[opg("Estimated usd in the requested currency", optional, string)]
+    estimated_fiat: Option<String>,
+    #[opg(optional, string)]
+    acquisition_fiat: Option<String>,
+    #[opg("Only the first 5000 NFTs are listed")]
+    truncated: bool,
+    collections: Vec<PortfolioCollection>,
+}
+
+#[derive(Default)]
+struct Totals {
+    nft_count: usize,
+    estimated_usd: BigDecimal,
+    acquisition_usd: BigDecimal,
+}
+
+impl Totals {
+    fn add(&mut self, estimated_usd: Option<&BigDecimal>, acquisition_usd: Option<&BigDecimal>) {
+        self.nft_count += 1;
+        if let Some(usd) = estimated_usd {
+            self.estimated_usd += usd;
+        }
+        if let Some(usd) = acquisition_usd {
+            self.acquisition_usd += usd;
+        }
+    }
+}
+
+fn usd(value: &BigDecimal) -> String {
+    value.round(USD_SCALE).with_scale(USD_SCALE).to_string()
+}
+
+fn fiat(usd_total: &BigDecimal, fiat: Option<&FiatConversion>) -> Option<String> {
+    fiat.map(|f| format_fiat(&f.to_fiat(usd_total)))
+}
+
+fn estimate_usd(
+    nft: &PortfolioNft,
+    current_prices: &HashMap<String, BigDecimal>,
+) -> Option<BigDecimal> {
+    let current_price = nft
+        .last_sale_token
+        .as_ref()
+        .and_then(|token| current_prices.get(token));
+
+    match (&nft.last_sale_price, current_price) {
+        (Some(price), Some(token_usd)) => Some(price * token_usd),
+        _ => nft
+            .last_sale_usd
+            .clone()
+            .or_else(|| nft.collection_floor_usd.clone()),
+    }
+}
+
+#[get("/owners/{address}/portfolio")]
+pub async fn get_portfolio(
+    owner: Path<String>,
+    params: Query<PortfolioParams>,
+    nft_model: web::Data<NftModel>,
+    fiat_model: web::Data<FiatRateModel>,
+    price_reader: web::Data<Arc<PriceReader>>,
+) -> HttpResponse {
+    let owner = owner.into_inner();
+
+    let fiat_conversion = match FiatConversion::load(&fiat_model, params.currency.as_deref()).await
+    {
+        Ok(fiat) => fiat,
+        Err(response) => return response,
+    };
+
+    let mut nfts = match nft_model
+        .get_portfolio(&owner, MAX_PORTFOLIO_NFTS + 1)
+        .await
+    {
+        Ok(nfts) => nfts,
+        Err(err) => {
+            log::error!("get portfolio error {err}");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let truncated = nfts.len() as i64 > MAX_PORTFOLIO_NFTS;
+    nfts.truncate(MAX_PORTFOLIO_NFTS as usize);
+
+    let current_prices = price_reader.get_current_usd_prices().await;
+
+    HttpResponse::Ok().json(build_portfolio(
+        owner,
+        nfts,
+        &current_prices,
+        fiat_conversion.as_ref(),
+        truncated,
+    ))
+}
+
+/// Groups NFTs ordered by collection and sums their usd values
+fn build_portfolio(
+    owner: String,
+    nfts: Vec<PortfolioNft>,
+    current_prices: &HashMap<String, BigDecimal>,
+    fiat_conversion: Option<&FiatConversion>,
+    truncated: bool,
+) -> PortfolioResponse {
+    let mut totals = Totals::default();
+    let mut collections: Vec<(PortfolioCollection, Totals)> = Vec::new();
+
+    // Rows come ordered by collection
+    for nft in nfts {
+        let estimated_usd = estimate_usd(&nft, current_prices);
+
+        if collections.last().map(|(c, _)| &c.address) != Some(&nft.collection) {
+            collections.push((
+                PortfolioCollection {
+                    address: nft.collection.clone(),
+                    name: nft.collection_name.clone(),
+                    floor_price_usd: nft.collection_floor_usd.as_ref().map(usd),
+                    nft_count: 0,
+                    estimated_usd: String::new(),
+                    acquisition_usd: String::new(),
+                    estimated_fiat: None,
+                    acquisition_fiat: None,
+                    nfts: Vec::new(),
+                },
+                Totals::default(),
+            ));
+        }
+
+        let Some((collection, collection_totals)) = collections.last_mut() else {
+            continue;
+        };
+
+        totals.add(estimated_usd.as_ref(), nft.acquisition_usd.as_ref());
+        collection_totals.add(estimated_usd.as_ref(), nft.acquisition_usd.as_ref());
+
+        collection.nfts.push(PortfolioNftResponse {
+            address: nft.address,
+            name: nft.name,
+            last_sale_price: nft.last_sale_price.map(|p| p.to_string()),
+            last_sale_token: nft.last_sale_token,
+            last_sale_usd: nft.last_sale_usd.as_ref().map(usd),
+            last_sale_at: nft.last_sale_ts.map(|ts| ts.timestamp()),
+            estimated_usd: estimated_usd.as_ref().map(usd),
+            acquired_at: nft.acquired_at,
+            acquisition_price: nft.acquisition_price.map(|p| p.to_string()),
+            acquisition_token: nft.acquisition_token,
+            acquisition_usd: nft.acquisition_usd.as_ref().map(usd),
+        });
+    }
+
+    PortfolioResponse {
+        owner,
+        nft_count: totals.nft_count,
+        estimated_usd: usd(&totals.estimated_usd),
+        acquisition_usd: usd(&totals.acquisition_usd),
+        estimated_fiat: fiat(&totals.estimated_usd, fiat_conversion),
+        acquisition_fiat: fiat(&totals.acquisition_usd, fiat_conversion),
+        truncated,
+        collections: collections
+            .into_iter()
+            .map(|(mut collection, collection_totals)| {
+                collection.nft_count = collection_totals.nft_count;
+                collection.estimated_usd = usd(&collection_totals.estimated_usd);
+                collection.acquisition_usd = usd(&collection_totals.acquisition_usd);
+                collection.estimated_fiat = fiat(&collection_totals.estimated_usd, fiat_conversion);
+                collection.acquisition_fiat =
+                    fiat(&collection_totals.acquisition_usd, fiat_conversion);
+                collection
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn nft(address: &str, collection: &str) -> PortfolioNft {
+        PortfolioNft {
+            address: address.to_string(),
+            collection: collection.to_string(),
+            name: None,
+            collection_name: None,
+            collection_floor_usd: None,
+            last_sale_price: None,
+            last_sale_token: None,
+            last_sale_usd: None,
+            last_sale_ts: None,
+            acquired_at: None,
+            acquisition_price: None,
+            acquisition_token: None,
+            acquisition_usd: None,
+        }
+    }
+
+    fn dec(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn estimates_at_current_token_price_then_sale_usd_then_floor() {
+        let current_prices = HashMap::from([("0:t".to_string(), dec("2.5"))]);
+
+        let mut sold = nft("0:n1", "0:c");
+        sold.last_sale_price = Some(dec("4"));
+        sold.last_sale_token = Some("0:t".to_string());
+        sold.last_sale_usd = Some(dec("1"));
+        sold.collection_floor_usd = Some(dec("3"));
+        assert_eq!(estimate_usd(&sold, &current_prices), Some(dec("10")));
+
+        sold.last_sale_token = Some("0:unknown".to_string());
+        assert_eq!(estimate_usd(&sold, &current_prices), Some(dec("1")));
+
+        let mut never_sold = nft("0:n2", "0:c");
+        never_sold.collection_floor_usd = Some(dec("3"));
+        assert_eq!(estimate_usd(&never_sold, &current_prices), Some(dec("3")));
+
+        assert_eq!(estimate_usd(&nft("0:n3", "0:c"), &current_prices), None);
+    }
+
+    #[test]
+    fn groups_by_collection_and_sums_totals() {
+        let mut a1 = nft("0:a1", "0:ca");
+        a1.last_sale_usd = Some(dec("1.004"));
+        a1.acquisition_usd = Some(dec("0.5"));
+        let mut a2 = nft("0:a2", "0:ca");
+        a2.collection_floor_usd = Some(dec("2"));
+        let mut b1 = nft("0:b1", "0:cb");
+        b1.last_sale_usd = Some(dec("10"));
+        b1.acquisition_usd = Some(dec("7.125"));
+
+        let portfolio = build_portfolio(
+            "0:o".to_string(),
+            vec![a1, a2, b1],
+            &HashMap::new(),
+            None,
+            false,
+        );
+
+        assert_eq!(portfolio.nft_count, 3);
+        assert_eq!(portfolio.estimated_usd, "13.00");
+        assert_eq!(portfolio.acquisition_usd, "7.63");
+        assert_eq!(portfolio.estimated_fiat, None);
+
+        let collections = portfolio
+            .collections
+            .iter()
+            .map(|c| {
+                (
+                    c.address.as_str(),
+                    c.nft_count,
+                    c.estimated_usd.as_str(),
+                    c.acquisition_usd.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            collections,
+            [("0:ca", 2, "3.00", "0.50"), ("0:cb", 1, "10.00", "7.13")]
+        );
+        assert_eq!(
+            portfolio.collections[0].nfts[0].estimated_usd.as_deref(),
+            Some("1.00")
+        );
+    }
+}
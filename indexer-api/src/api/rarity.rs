@@ -0,0 +1,143 @@
+use actix_web::web::{Path, Query};
+use actix_web::{get, web, HttpResponse};
+use indexer_repo::rarity::{NftRarity, RankFilter, RankedNft, RarityCursor, RarityModel};
+use indexer_repo::types::RarityAlgorithm;
+use opg::OpgModel;
+use serde::{Deserialize, Serialize};
+
+use crate::api::pagination::{next_cursor, page_limit, parse_cursor};
+
+#[derive(Serialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum RarityAlgorithmName {
+    Statistical,
+    InformationContent,
+    TraitCount,
+}
+
+impl From<RarityAlgorithm> for RarityAlgorithmName {
+    fn from(algorithm: RarityAlgorithm) -> Self {
+        match algorithm {
+            RarityAlgorithm::Statistical => Self::Statistical,
+            RarityAlgorithm::InformationContent => Self::InformationContent,
+            RarityAlgorithm::TraitCount => Self::TraitCount,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RankedNftsParams {
+    algorithm: Option<RarityAlgorithm>,
+    min_rank: Option<i32>,
+    max_rank: Option<i32>,
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct RankedNftResponse {
+    #[opg(string)]
+    address: String,
+    #[opg(optional, string)]
+    name: Option<String>,
+    #[opg(optional, string)]
+    owner: Option<String>,
+    score: f64,
+    #[opg("1 is the rarest")]
+    rank: i32,
+}
+
+impl From<RankedNft> for RankedNftResponse {
+    fn from(nft: RankedNft) -> Self {
+        Self {
+            address: nft.address,
+            name: nft.name,
+            owner: nft.owner,
+            score: nft.score,
+            rank: nft.rank,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct RankedNftsPage {
+    items: Vec<RankedNftResponse>,
+    #[opg("Pass as `cursor` to get the next page", optional, string)]
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct NftRarityResponse {
+    algorithm: RarityAlgorithmName,
+    score: f64,
+    rank: i32,
+}
+
+impl From<NftRarity> for NftRarityResponse {
+    fn from(rarity: NftRarity) -> Self {
+        Self {
+            algorithm: rarity.algorithm.into(),
+            score: rarity.score,
+            rank: rarity.rank,
+        }
+    }
+}
+
+/// Nfts of a collection ordered by rarity rank
+#[get("/collections/{address}/rarity")]
+pub async fn get_ranked_nfts(
+    address: Path<String>,
+    params: Query<RankedNftsParams>,
+    rarity_model: web::Data<RarityModel>,
+) -> HttpResponse {
+    let Ok(after) = parse_cursor::<RarityCursor>(params.cursor.as_deref()) else {
+        return HttpResponse::BadRequest().body("malformed cursor");
+    };
+
+    let limit = page_limit(params.limit);
+    let filter = RankFilter {
+        owner: None,
+        min_rank: params.min_rank,
+        max_rank: params.max_rank,
+    };
+
+    match rarity_model
+        .get_ranked_nfts(
+            &address,
+            params.algorithm.unwrap_or(RarityAlgorithm::Statistical),
+            &filter,
+            after.as_ref(),
+            limit,
+        )
+        .await
+    {
+        Ok(nfts) => HttpResponse::Ok().json(RankedNftsPage {
+            next_cursor: next_cursor(&nfts, limit, |n| RarityCursor::from(n)),
+            items: nfts.into_iter().map(Into::into).collect(),
+        }),
+        Err(err) => {
+            log::error!("get ranked nfts error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Rarity of an nft by every algorithm
+#[get("/nfts/{address}/rarity")]
+pub async fn get_nft_rarity(
+    address: Path<String>,
+    rarity_model: web::Data<RarityModel>,
+) -> HttpResponse {
+    match rarity_model.get_nft_rarity(&address).await {
+        Ok(rarity) => HttpResponse::Ok().json(
+            rarity
+                .into_iter()
+                .map(NftRarityResponse::from)
+                .collect::<Vec<_>>(),
+        ),
+        Err(err) => {
+            log::error!("get nft rarity error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use actix_web::web::Query;
+use actix_web::{get, web, HttpResponse};
+use indexer_repo::search::{SearchHit, SearchKind, SearchModel};
+use opg::OpgModel;
+use serde::{Deserialize, Serialize};
+
+use crate::api::events::parse_values;
+
+const DEFAULT_SEARCH_LIMIT: i64 = 10;
+const MAX_SEARCH_LIMIT: i64 = 50;
+
+#[derive(Deserialize, Serialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchHitKind {
+    Nft,
+    Collection,
+    User,
+}
+
+impl From<SearchHitKind> for SearchKind {
+    fn from(kind: SearchHitKind) -> Self {
+        match kind {
+            SearchHitKind::Nft => Self::Nft,
+            SearchHitKind::Collection => Self::Collection,
+            SearchHitKind::User => Self::User,
+        }
+    }
+}
+
+impl From<SearchKind> for SearchHitKind {
+    fn from(kind: SearchKind) -> Self {
+        match kind {
+            SearchKind::Nft => Self::Nft,
+            SearchKind::Collection => Self::Collection,
+            SearchKind::User => Self::User,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    q: String,
+    kinds: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct SearchHitResponse {
+    kind: SearchHitKind,
+    #[opg(string)]
+    address: String,
+    #[opg(optional, string)]
+    name: Option<String>,
+    #[opg("Collection of an NFT", optional, string)]
+    collection: Option<String>,
+    #[opg("The query is the address of the hit")]
+    exact: bool,
+    rank: f32,
+}
+
+impl From<SearchHit> for SearchHitResponse {
+    fn from(hit: SearchHit) -> Self {
+        Self {
+            kind: hit.kind.into(),
+            address: hit.address,
+            name: hit.name,
+            collection: hit.collection,
+            exact: hit.exact,
+            rank: hit.rank,
+        }
+    }
+}
+
+/// Nfts, collections and users by name prefixes or address
+#[get("/search")]
+pub async fn search(
+    params: Query<SearchParams>,
+    search_model: web::Data<SearchModel>,
+) -> HttpResponse {
+    let kinds = match params.kinds.as_deref().map(parse_values::<SearchHitKind>) {
+        None => SearchKind::ALL.to_vec(),
+        Some(Ok(kinds)) => kinds.into_iter().map(Into::into).collect(),
+        Some(Err(_)) => return HttpResponse::BadRequest().body("unknown search kind"),
+    };
+
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
+
+    match search_model.search(&params.q, &kinds, limit).await {
+        Ok(hits) => HttpResponse::Ok().json(
+            hits.into_iter()
+                .map(SearchHitResponse::from)
+                .collect::<Vec<_>>(),
+        ),
+        Err(err) => {
+            log::error!("search error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
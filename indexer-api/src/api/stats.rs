@@ -0,0 +1,100 @@
+use actix_web::web::{Path, Query};
+use actix_web::{get, web, HttpResponse};
+use chrono::NaiveDateTime;
+use indexer_repo::stats::{CollectionStatsBucket, CollectionStatsModel};
+use indexer_repo::types::StatsPeriod;
+use opg::OpgModel;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_BUCKETS: i64 = 168;
+const MAX_BUCKETS: i64 = 1000;
+
+#[derive(Deserialize)]
+pub struct CollectionStatsParams {
+    period: StatsPeriod,
+    from: Option<i64>,
+    to: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct StatsBucketResponse {
+    #[opg("Bucket start")]
+    ts: i64,
+    #[opg(optional, string)]
+    floor_price: Option<String>,
+    #[opg(optional, string)]
+    floor_price_token: Option<String>,
+    #[opg(optional, string)]
+    floor_price_usd: Option<String>,
+    #[opg(string)]
+    volume_usd: String,
+    #[opg("Volume per payment token", any)]
+    volume_token: serde_json::Value,
+    sales_count: i32,
+    unique_buyers: i32,
+    unique_sellers: i32,
+    #[opg(optional)]
+    owners_count: Option<i32>,
+    #[opg(optional)]
+    listed_count: Option<i32>,
+}
+
+impl From<CollectionStatsBucket> for StatsBucketResponse {
+    fn from(bucket: CollectionStatsBucket) -> Self {
+        Self {
+            ts: bucket.bucket.timestamp(),
+            floor_price: bucket.floor_price.map(|p| p.to_string()),
+            floor_price_token: bucket.floor_price_token,
+            floor_price_usd: bucket.floor_price_usd.map(|p| p.to_string()),
+            volume_usd: bucket.volume_usd.to_string(),
+            volume_token: bucket.volume_token,
+            sales_count: bucket.sales_count,
+            unique_buyers: bucket.unique_buyers,
+            unique_sellers: bucket.unique_sellers,
+            owners_count: bucket.owners_count,
+            listed_count: bucket.listed_count,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct CollectionStatsResponse {
+    #[opg(string)]
+    collection: String,
+    buckets: Vec<StatsBucketResponse>,
+}
+
+/// Hourly or daily stats of a collection, oldest first
+#[get("/collections/{address}/stats")]
+pub async fn get_collection_stats(
+    address: Path<String>,
+    params: Query<CollectionStatsParams>,
+    stats_model: web::Data<CollectionStatsModel>,
+) -> HttpResponse {
+    let collection = address.into_inner();
+    let from = params
+        .from
+        .and_then(|ts| NaiveDateTime::from_timestamp_opt(ts, 0));
+    let to = params
+        .to
+        .and_then(|ts| NaiveDateTime::from_timestamp_opt(ts, 0));
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_BUCKETS)
+        .clamp(1, MAX_BUCKETS);
+
+    match stats_model
+        .get_stats(&collection, params.period, from, to, limit)
+        .await
+    {
+        Ok(buckets) => HttpResponse::Ok().json(CollectionStatsResponse {
+            collection,
+            buckets: buckets.into_iter().map(Into::into).collect(),
+        }),
+        Err(err) => {
+            log::error!("get collection stats error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
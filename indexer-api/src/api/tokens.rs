@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use actix_web::web::Path;
+use actix_web::{get, web, HttpResponse};
+use bigdecimal::BigDecimal;
+use indexer_repo::tokens::{TokenInfo, TokenModel};
+use opg::OpgModel;
+use serde::Serialize;
+
+#[derive(Serialize, OpgModel)]
+pub struct TokenResponse {
+    #[opg(string)]
+    address: String,
+    #[opg(optional, string)]
+    name: Option<String>,
+    #[opg(optional, string)]
+    symbol: Option<String>,
+    #[opg(optional)]
+    decimals: Option<i32>,
+    #[opg(optional, string)]
+    icon: Option<String>,
+    updated: i64,
+}
+
+impl From<TokenInfo> for TokenResponse {
+    fn from(token: TokenInfo) -> Self {
+        Self {
+            address: token.address,
+            name: token.name,
+            symbol: token.symbol,
+            decimals: token.decimals,
+            icon: token.icon,
+            updated: token.updated.timestamp(),
+        }
+    }
+}
+
+/// Decimals of the tokens in the registry, formats raw amounts for display.
+pub(crate) struct TokenDecimals(HashMap<String, i32>);
+
+impl TokenDecimals {
+    /// Tokens missing in the registry are left out, their amounts are not
+    /// formatted. Formatting is skipped as a whole if the registry can't be read.
+    pub(crate) async fn load<'a>(
+        token_model: &TokenModel,
+        tokens: impl Iterator<Item = &'a str>,
+    ) -> Self {
+        let mut addresses = tokens.map(str::to_string).collect::<Vec<_>>();
+        addresses.sort();
+        addresses.dedup();
+
+        match token_model.get_tokens(Some(&addresses)).await {
+            Ok(tokens) => Self(
+                tokens
+                    .into_iter()
+                    .filter_map(|t| Some((t.address, t.decimals?)))
+                    .collect(),
+            ),
+            Err(err) => {
+                log::error!("get token decimals error {err}");
+                Self(HashMap::new())
+            }
+        }
+    }
+
+    pub(crate) fn format(
+        &self,
+        token: Option<&str>,
+        amount: Option<&BigDecimal>,
+    ) -> Option<String> {
+        let decimals = self.0.get(token?)?;
+
+        Some(format_amount(amount?, *decimals))
+    }
+}
+
+/// Raw integer amount in whole tokens, e.g. `1500000000` with 9 decimals is `1.5`.
+fn format_amount(amount: &BigDecimal, decimals: i32) -> String {
+    let (digits, scale) = amount.as_bigint_and_exponent();
+
+    BigDecimal::new(digits, scale + decimals as i64)
+        .normalized()
+        .to_string()
+}
+
+/// Tip-3 root metadata of the payment tokens seen in offers
+#[get("/tokens")]
+pub async fn get_tokens(token_model: web::Data<TokenModel>) -> HttpResponse {
+    match token_model.get_tokens(None).await {
+        Ok(tokens) => HttpResponse::Ok().json(
+            tokens
+                .into_iter()
+                .map(TokenResponse::from)
+                .collect::<Vec<_>>(),
+        ),
+        Err(err) => {
+            log::error!("get tokens error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/tokens/{address}")]
+pub async fn get_token(address: Path<String>, token_model: web::Data<TokenModel>) -> HttpResponse {
+    match token_model
+        .get_tokens(Some(&[address.into_inner()]))
+        .await
+        .map(|tokens| tokens.into_iter().next())
+    {
+        Ok(Some(token)) => HttpResponse::Ok().json(TokenResponse::from(token)),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(err) => {
+            log::error!("get token error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn formats_amounts_by_decimals() {
+        let amount = |s: &str| BigDecimal::from_str(s).unwrap();
+
+        assert_eq!(format_amount(&amount("1500000000"), 9), "1.5");
+        assert_eq!(format_amount(&amount("1"), 6), "0.000001");
+        assert_eq!(format_amount(&amount("2000000"), 6), "2");
+        assert_eq!(format_amount(&amount("0"), 18), "0");
+    }
+}
@@ -0,0 +1,230 @@
+use actix_web::web::Json;
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use data_reader::check_webhook_url;
+use indexer_repo::types::{WebhookDeliveryStatus, WebhookNotificationKind};
+use indexer_repo::webhooks::{NewWebhook, WebhookModel};
+use opg::OpgModel;
+use serde::{Deserialize, Serialize};
+
+use crate::api::auth::ApiKeys;
+
+const MIN_SECRET_LEN: usize = 16;
+const DEFAULT_DELIVERIES_LIMIT: i64 = 100;
+const MAX_DELIVERIES_LIMIT: i64 = 1_000;
+
+#[derive(Deserialize, Serialize, OpgModel, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookKind {
+    Outbid,
+    ListingFilled,
+    OfferReceived,
+    AuctionEnding,
+}
+
+impl From<WebhookKind> for WebhookNotificationKind {
+    fn from(kind: WebhookKind) -> Self {
+        match kind {
+            WebhookKind::Outbid => Self::Outbid,
+            WebhookKind::ListingFilled => Self::ListingFilled,
+            WebhookKind::OfferReceived => Self::OfferReceived,
+            WebhookKind::AuctionEnding => Self::AuctionEnding,
+        }
+    }
+}
+
+impl From<WebhookNotificationKind> for WebhookKind {
+    fn from(kind: WebhookNotificationKind) -> Self {
+        match kind {
+            WebhookNotificationKind::Outbid => Self::Outbid,
+            WebhookNotificationKind::ListingFilled => Self::ListingFilled,
+            WebhookNotificationKind::OfferReceived => Self::OfferReceived,
+            WebhookNotificationKind::AuctionEnding => Self::AuctionEnding,
+        }
+    }
+}
+
+#[derive(Serialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl From<WebhookDeliveryStatus> for DeliveryStatus {
+    fn from(status: WebhookDeliveryStatus) -> Self {
+        match status {
+            WebhookDeliveryStatus::Pending => Self::Pending,
+            WebhookDeliveryStatus::Delivered => Self::Delivered,
+            WebhookDeliveryStatus::Failed => Self::Failed,
+        }
+    }
+}
+
+#[derive(Deserialize, OpgModel)]
+pub struct RegisterWebhookParams {
+    #[opg(string)]
+    url: String,
+    #[opg("Used to sign deliveries, at least 16 chars", string)]
+    secret: String,
+    #[opg(optional, string)]
+    owner: Option<String>,
+    #[opg(optional, string)]
+    nft: Option<String>,
+    #[opg(optional, string)]
+    collection: Option<String>,
+    #[opg("Empty list subscribes to every kind", optional)]
+    kinds: Option<Vec<WebhookKind>>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct RegisterWebhookResponse {
+    id: i64,
+    #[opg("Manages the webhook, it is not shown again", string)]
+    management_token: String,
+}
+
+#[derive(Deserialize, OpgModel)]
+pub struct DeleteWebhookParams {
+    id: i64,
+    #[opg(string)]
+    management_token: String,
+}
+
+#[derive(Deserialize, OpgModel)]
+pub struct WebhookDeliveriesParams {
+    id: i64,
+    #[opg(string)]
+    management_token: String,
+    #[opg(optional)]
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, OpgModel)]
+pub struct WebhookDeliveryResponse {
+    id: i64,
+    kind: WebhookKind,
+    status: DeliveryStatus,
+    attempts: i32,
+    #[opg(optional)]
+    last_response_status: Option<i32>,
+    #[opg(optional, string)]
+    last_error: Option<String>,
+    created_at: i64,
+    #[opg(optional)]
+    delivered_at: Option<i64>,
+}
+
+#[post("/webhooks/")]
+pub async fn register_webhook(
+    req: HttpRequest,
+    params: Json<RegisterWebhookParams>,
+    api_keys: web::Data<ApiKeys>,
+    webhook_model: web::Data<WebhookModel>,
+) -> HttpResponse {
+    if !api_keys.authorize(&req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let params = params.into_inner();
+
+    if let Err(err) = check_webhook_url(&params.url).await {
+        return HttpResponse::BadRequest().body(err.to_string());
+    }
+
+    if params.secret.len() < MIN_SECRET_LEN {
+        return HttpResponse::BadRequest()
+            .body(format!("secret must be at least {MIN_SECRET_LEN} chars"));
+    }
+
+    let webhook = NewWebhook {
+        url: params.url,
+        secret: params.secret,
+        owner: params.owner,
+        nft: params.nft,
+        collection: params.collection,
+        kinds: params
+            .kinds
+            .unwrap_or_default()
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+    };
+
+    match webhook_model.create_webhook(&webhook).await {
+        Ok(created) => HttpResponse::Ok().json(RegisterWebhookResponse {
+            id: created.id,
+            management_token: created.management_token,
+        }),
+        Err(err) => {
+            log::error!("register webhook error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/webhooks/delete/")]
+pub async fn delete_webhook(
+    req: HttpRequest,
+    params: Json<DeleteWebhookParams>,
+    api_keys: web::Data<ApiKeys>,
+    webhook_model: web::Data<WebhookModel>,
+) -> HttpResponse {
+    if !api_keys.authorize(&req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    match webhook_model
+        .delete_webhook(params.id, &params.management_token)
+        .await
+    {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(err) => {
+            log::error!("delete webhook error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/webhooks/deliveries/")]
+pub async fn get_webhook_deliveries(
+    req: HttpRequest,
+    params: Json<WebhookDeliveriesParams>,
+    api_keys: web::Data<ApiKeys>,
+    webhook_model: web::Data<WebhookModel>,
+) -> HttpResponse {
+    if !api_keys.authorize(&req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_DELIVERIES_LIMIT)
+        .clamp(1, MAX_DELIVERIES_LIMIT);
+
+    match webhook_model
+        .get_deliveries(params.id, &params.management_token, limit)
+        .await
+    {
+        Ok(deliveries) => HttpResponse::Ok().json(
+            deliveries
+                .into_iter()
+                .map(|d| WebhookDeliveryResponse {
+                    id: d.id,
+                    kind: d.kind.into(),
+                    status: d.status.into(),
+                    attempts: d.attempts,
+                    last_response_status: d.last_response_status,
+                    last_error: d.last_error,
+                    created_at: d.created_at.timestamp(),
+                    delivered_at: d.delivered_at.map(|t| t.timestamp()),
+                })
+                .collect::<Vec<_>>(),
+        ),
+        Err(err) => {
+            log::error!("get webhook deliveries error {err}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -1,4 +1,5 @@
 mod api;
 mod server;
 
+pub use api::auth::ApiKeys;
 pub use server::*;
@@ -2,15 +2,49 @@ use actix_cors::Cors;
 use actix_web::middleware::Logger;
 use actix_web::web::Data;
 use actix_web::{get, App, HttpResponse, HttpServer};
-use data_reader::{MetaReaderContext, MetadataJrpcService};
+use data_reader::{MetaReaderContext, MetadataJrpcService, PriceReader};
+use indexer_repo::events::{EventsModel, LiveEvent};
+use indexer_repo::facets::FacetedSearchModel;
+use indexer_repo::fiat::FiatRateModel;
+use indexer_repo::media::MediaModel;
 use indexer_repo::meta::MetadataModelService;
+use indexer_repo::nft::NftModel;
+use indexer_repo::offers::OffersModel;
+use indexer_repo::rarity::RarityModel;
+use indexer_repo::search::SearchModel;
+use indexer_repo::stats::CollectionStatsModel;
+use indexer_repo::tokens::TokenModel;
+use indexer_repo::webhooks::WebhookModel;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::broadcast;
 
 use crate::api;
+use crate::api::auth::ApiKeys;
 use crate::api::docs::v1::{swagger_json, swagger_yaml};
 
-pub async fn run_api(address: &SocketAddr, context: MetaReaderContext) -> std::io::Result<()> {
-    let meta_jrpc_service = MetadataJrpcService::new(context.jrpc_client);
+pub async fn run_api(
+    address: &SocketAddr,
+    context: MetaReaderContext,
+    live_events: broadcast::Sender<LiveEvent>,
+    price_reader: Arc<PriceReader>,
+    api_keys: ApiKeys,
+) -> std::io::Result<()> {
+    let meta_jrpc_service = MetadataJrpcService::new(context.jrpc_pool.clone(), context.offchain);
+    let jrpc_pool = context.jrpc_pool;
+    let meta_reader_metrics = context.metrics;
+    let webhook_model = WebhookModel::new(context.pool.clone());
+    let events_model = EventsModel::new(context.pool.clone());
+    let nft_model = NftModel::new(context.pool.clone());
+    let offers_model = OffersModel::new(context.pool.clone());
+    let stats_model = CollectionStatsModel::new(context.pool.clone());
+    let rarity_model = RarityModel::new(context.pool.clone());
+    let faceted_search_model = FacetedSearchModel::new(context.pool.clone());
+    let search_model = SearchModel::new(context.pool.clone());
+    let token_model = TokenModel::new(context.pool.clone());
+    let fiat_model = FiatRateModel::new(context.pool.clone());
+    let media_model = MediaModel::new(context.pool.clone());
+    let graphql_schema = api::graphql::build_schema(nft_model.clone(), offers_model.clone());
     let meta_model_service = MetadataModelService::new(context.pool);
     let address_str = address.to_string();
 
@@ -20,11 +54,56 @@ pub async fn run_api(address: &SocketAddr, context: MetaReaderContext) -> std::i
             .wrap(Logger::default())
             .wrap(cors)
             .service(api::metadata::refresh_metadata_by_nft)
+            .service(api::metadata::get_meta_reader_metrics)
+            .service(api::webhooks::register_webhook)
+            .service(api::webhooks::delete_webhook)
+            .service(api::webhooks::get_webhook_deliveries)
+            .service(api::events::stream_events)
+            .service(api::events::get_events)
+            .service(api::nfts::get_nft)
+            .service(api::nfts::get_nfts)
+            .service(api::nfts::get_collection)
+            .service(api::stats::get_collection_stats)
+            .service(api::rarity::get_ranked_nfts)
+            .service(api::rarity::get_nft_rarity)
+            .service(api::metadata::get_normalized_meta)
+            .service(api::metadata::get_meta_versions)
+            .service(api::metadata::get_meta_diff)
+            .service(api::facets::search_nfts)
+            .service(api::search::search)
+            .service(api::tokens::get_tokens)
+            .service(api::tokens::get_token)
+            .service(api::fiat::get_fiat_rates)
+            .service(api::media::get_media)
+            .service(api::nfts::get_price_history)
+            .service(api::offers::get_active_auctions)
+            .service(api::offers::get_active_listings)
+            .service(api::offers::get_active_offers)
+            .service(api::portfolio::get_portfolio)
+            .service(api::graphql::graphql)
+            .service(api::graphql::graphiql)
             .service(swagger_yaml)
             .service(swagger_json)
             .service(health)
             .app_data(Data::new(meta_jrpc_service.clone()))
             .app_data(Data::new(meta_model_service.clone()))
+            .app_data(Data::new(jrpc_pool.clone()))
+            .app_data(Data::new(meta_reader_metrics.clone()))
+            .app_data(Data::new(api_keys.clone()))
+            .app_data(Data::new(webhook_model.clone()))
+            .app_data(Data::new(events_model.clone()))
+            .app_data(Data::new(nft_model.clone()))
+            .app_data(Data::new(offers_model.clone()))
+            .app_data(Data::new(stats_model.clone()))
+            .app_data(Data::new(rarity_model.clone()))
+            .app_data(Data::new(faceted_search_model.clone()))
+            .app_data(Data::new(search_model.clone()))
+            .app_data(Data::new(token_model.clone()))
+            .app_data(Data::new(fiat_model.clone()))
+            .app_data(Data::new(media_model.clone()))
+            .app_data(Data::new(graphql_schema.clone()))
+            .app_data(Data::new(live_events.clone()))
+            .app_data(Data::new(price_reader.clone()))
             .app_data(Data::new(address_str.clone()))
     })
     .bind(address)?
@@ -0,0 +1,25 @@
+create table nft_direct_sell_state_history
+(
+    address    t_address         not null,
+    state_from direct_sell_state not null,
+    state_to   direct_sell_state not null,
+    tx_lt      bigint            not null,
+    created_at timestamp         not null,
+    constraint nft_direct_sell_state_history_pk primary key (address, tx_lt, state_from, state_to)
+);
+
+create index nft_direct_sell_state_history_state_to_created_at_index
+    on nft_direct_sell_state_history (state_to, created_at);
+
+create table nft_direct_buy_state_history
+(
+    address    t_address        not null,
+    state_from direct_buy_state not null,
+    state_to   direct_buy_state not null,
+    tx_lt      bigint           not null,
+    created_at timestamp        not null,
+    constraint nft_direct_buy_state_history_pk primary key (address, tx_lt, state_from, state_to)
+);
+
+create index nft_direct_buy_state_history_state_to_created_at_index
+    on nft_direct_buy_state_history (state_to, created_at);
@@ -0,0 +1,40 @@
+create table offer_state_anomalies
+(
+    id                     bigint generated always as identity
+        constraint offer_state_anomalies_pk primary key,
+    address                t_address      not null,
+    offer_type             event_category not null,
+    auction_status_from    auction_status,
+    auction_status_to      auction_status,
+    direct_sell_state_from direct_sell_state,
+    direct_sell_state_to   direct_sell_state,
+    direct_buy_state_from  direct_buy_state,
+    direct_buy_state_to    direct_buy_state,
+    current_tx_lt          bigint         not null,
+    incoming_tx_lt         bigint         not null,
+    context                jsonb          not null,
+    created_at             timestamp      not null default now(),
+    constraint offer_state_anomalies_states_check check (
+        case offer_type
+            when 'auction' then auction_status_from is not null and auction_status_to is not null
+            when 'direct_sell' then direct_sell_state_from is not null and direct_sell_state_to is not null
+            when 'direct_buy' then direct_buy_state_from is not null and direct_buy_state_to is not null
+            else false
+        end
+    )
+);
+
+create unique index offer_state_anomalies_auction_uindex
+    on offer_state_anomalies (address, incoming_tx_lt, auction_status_to)
+    where offer_type = 'auction';
+
+create unique index offer_state_anomalies_direct_sell_uindex
+    on offer_state_anomalies (address, incoming_tx_lt, direct_sell_state_to)
+    where offer_type = 'direct_sell';
+
+create unique index offer_state_anomalies_direct_buy_uindex
+    on offer_state_anomalies (address, incoming_tx_lt, direct_buy_state_to)
+    where offer_type = 'direct_buy';
+
+create index offer_state_anomalies_created_at_index
+    on offer_state_anomalies (created_at desc);
@@ -0,0 +1,55 @@
+create type webhook_notification_kind as enum (
+    'outbid',
+    'listing_filled',
+    'offer_received',
+    'auction_ending'
+);
+
+create type webhook_delivery_status as enum (
+    'pending',
+    'delivered',
+    'failed'
+);
+
+create table webhooks
+(
+    id         bigint generated always as identity primary key,
+    url        text                        not null,
+    secret     text                        not null,
+    owner      t_address,
+    nft        t_address,
+    collection t_address,
+    kinds      webhook_notification_kind[] not null default '{}',
+    created_at timestamp                   not null default now()
+);
+
+create index webhooks_owner_index on webhooks (owner);
+
+create table webhook_deliveries
+(
+    id              bigint generated always as identity primary key,
+    webhook_id      bigint                    not null references webhooks (id) on delete cascade,
+    kind            webhook_notification_kind not null,
+    dedup_key       text                      not null,
+    payload         jsonb                     not null,
+    status          webhook_delivery_status   not null default 'pending',
+    attempts        int                       not null default 0,
+    next_attempt_at timestamp                 not null default now(),
+    delivered_at    timestamp,
+    created_at      timestamp                 not null default now(),
+    unique (webhook_id, dedup_key)
+);
+
+create index webhook_deliveries_pending_index on webhook_deliveries (next_attempt_at)
+    where status = 'pending';
+
+create table webhook_delivery_attempts
+(
+    delivery_id     bigint    not null references webhook_deliveries (id) on delete cascade,
+    attempt         int       not null,
+    response_status int,
+    error           text,
+    duration_ms     int       not null,
+    created_at      timestamp not null default now(),
+    primary key (delivery_id, attempt)
+);
@@ -0,0 +1 @@
+create index if not exists nft_events_created_lt_index on nft_events (created_lt);
@@ -0,0 +1,106 @@
+drop function get_events(t_address, event_kind[], t_address, t_address[], integer, integer, boolean, boolean);
+
+create or replace function get_events(
+    p_owner t_address,
+    p_event_kind event_kind[],
+    p_nft t_address,
+    p_collections t_address[],
+    p_limit int,
+    p_offset int,
+    p_with_count bool,
+    p_verified bool,
+    p_before_lt bigint default null,
+    p_before_id bigint default null
+)
+returns table
+(
+    computed_event_kind event_kind,
+    id                  bigint,
+    event_address       t_address,
+    nft                 t_address,
+    created_at          bigint,
+    created_lt          bigint,
+    event_type          event_type,
+    event_cat           event_category,
+    args                jsonb,
+    f                   int,
+    t                   int,
+    total_rows          bigint,
+    new_owner           text,
+    old_owner           text
+)
+language plpgsql
+as
+$$
+begin
+    -- planner hints are local to the calling transaction, so pooled connections keep their defaults
+    if p_owner is not null then
+        perform set_config('enable_bitmapscan', 'on', true);
+        perform set_config('enable_indexscan', 'off', true);
+        perform set_config('enable_sort', 'on', true);
+    elsif p_event_kind is not null and p_nft is null and p_collections is null then
+        perform set_config('enable_indexscan', 'on', true);
+        perform set_config('enable_sort', 'off', true);
+    else
+        perform set_config('enable_bitmapscan', 'on', true);
+        perform set_config('enable_indexscan', 'on', true);
+        perform set_config('enable_sort', 'on', true);
+    end if;
+
+    return query
+    with events_whitelist as (
+        select of.address
+        from deployed_offers of
+        union
+        select address
+        from roots
+    )
+    select
+        ne.computed_event_kind as computed_event_kind,
+        ne.id,
+        ne.address as event_address,
+        ne.nft,
+        ne.created_at,
+        ne.created_lt,
+        ne.event_type,
+        ne.event_cat,
+        ne.args,
+        (ne.args ->> 'from')::int as f,
+        (ne.args ->> 'to')::int as t,
+        case
+            when p_with_count then count(1) over ()
+            else 0
+        end as total_rows,
+        case ne.computed_event_kind
+            when 'sell_purchased'::event_kind then ne.args ->> 'new_owner'
+        end as new_owner,
+        case ne.computed_event_kind
+            when 'offer_filled'::event_kind then ne.args ->> 'old_owner'
+        end as old_owner
+    from
+        nft_events ne
+    join nft_collection nc on nc.address = ne.collection and (nc.verified = true or not p_verified)
+
+    where
+        (p_owner in (
+            ne.args -> 'value0' ->> 'subject_owner',
+            ne.args -> 'value2' ->> 'subject_owner',
+            ne.args -> 'value2' ->> 'creator',
+            ne.args ->> 'buyer',
+            ne.args ->> 'seller',
+            ne.args ->> 'old_owner',
+            ne.args ->> 'new_owner'
+        ) or p_owner is null)
+    and (
+        (ne.computed_event_kind in ('mint'::event_kind, 'transfer'::event_kind) or
+        exists(select 1 from events_whitelist ew where ew.address = ne.address))
+    )
+    and (ne.nft = p_nft or p_nft is null)
+    and (ne.collection = any (p_collections) or p_collections = '{}')
+    and ne.computed_event_kind is not null
+    and (ne.computed_event_kind = any (p_event_kind) or (p_event_kind) = '{}')
+    and (p_before_lt is null or (ne.created_lt, ne.id) < (p_before_lt, p_before_id))
+    order by ne.created_lt desc, ne.id desc
+    limit p_limit offset p_offset;
+end;
+$$ volatile;
@@ -0,0 +1,30 @@
+create type stats_period as enum ('hour', 'day');
+
+create table collection_stats
+(
+    collection        t_address    not null,
+    period            stats_period not null,
+    bucket            timestamp    not null,
+    floor_price       numeric(40, 0),
+    floor_price_token t_address,
+    floor_price_usd   numeric,
+    volume_usd        numeric      not null default 0,
+    volume_token      jsonb        not null default '{}',
+    sales_count       int          not null default 0,
+    unique_buyers     int          not null default 0,
+    unique_sellers    int          not null default 0,
+    owners_count      int,
+    listed_count      int,
+    updated           timestamp    not null default now(),
+    primary key (collection, period, bucket)
+);
+
+-- sales are re-aggregated from this point on every run
+create table collection_stats_progress
+(
+    id         int primary key default 1 check (id = 1),
+    sales_from timestamp not null
+);
+
+insert into collection_stats_progress (sales_from)
+values (to_timestamp(0) at time zone 'utc');
@@ -0,0 +1,60 @@
+create type rarity_algorithm as enum ('statistical', 'information_content', 'trait_count');
+
+-- trait values of a collection, a null value counts nfts without the trait
+create table collection_traits
+(
+    collection t_address        not null,
+    trait_type varchar(200)     not null,
+    value      text,
+    nft_count  int              not null,
+    frequency  double precision not null
+);
+
+create unique index collection_traits_uindex
+    on collection_traits (collection, trait_type, coalesce(value, ''), (value is null));
+
+create table nft_rarity
+(
+    nft        t_address        not null,
+    collection t_address        not null,
+    algorithm  rarity_algorithm not null,
+    score      double precision not null,
+    rank       int              not null,
+    primary key (nft, algorithm)
+);
+
+create index nft_rarity_collection_rank_index
+    on nft_rarity (collection, algorithm, rank, nft);
+
+create table rarity_dirty_collections
+(
+    collection t_address primary key,
+    marked_at  timestamp not null
+);
+
+create or replace function mark_rarity_dirty() returns trigger as
+$$
+begin
+    insert into rarity_dirty_collections (collection, marked_at)
+    values (coalesce(new.collection, old.collection), clock_timestamp())
+    on conflict (collection) do update set marked_at = excluded.marked_at;
+
+    return null;
+end;
+$$ language plpgsql;
+
+create trigger nft_attributes_rarity
+    after insert or delete on nft_attributes
+    for each row
+execute procedure mark_rarity_dirty();
+
+create trigger nft_rarity_supply
+    after insert or update of burned on nft
+    for each row
+execute procedure mark_rarity_dirty();
+
+-- collections indexed before rarity existed
+insert into rarity_dirty_collections (collection, marked_at)
+select distinct collection, now()
+from nft_attributes
+on conflict do nothing;
@@ -0,0 +1,63 @@
+-- trait filter types every not burned nft of a collection fails to match;
+-- values of a type are or-ed, types are and-ed, ranges apply to numeric values
+create or replace function nft_trait_misses(
+    p_collection  varchar,
+    p_types       varchar[],
+    p_values      text[],
+    p_range_types varchar[],
+    p_mins        double precision[],
+    p_maxes       double precision[]
+)
+    returns table
+            (
+                nft    t_address,
+                missed varchar[]
+            )
+as
+$$
+with value_filters as (
+    select *
+    from unnest(p_types, p_values) as f(trait_type, value)
+),
+range_filters as (
+    select *
+    from unnest(p_range_types, p_mins, p_maxes) as r(trait_type, min_value, max_value)
+),
+filter_types as (
+    select trait_type from value_filters
+    union
+    select trait_type from range_filters
+),
+attrs as (
+    select a.nft, a.trait_type, a.value #>> '{}' as value
+    from nft_attributes a
+    where a.collection = p_collection
+      and a.trait_type in (select trait_type from filter_types)
+),
+matched as (
+    select a.nft, a.trait_type
+    from attrs a
+        join value_filters f on f.trait_type = a.trait_type and f.value = a.value
+    union
+    select a.nft, a.trait_type
+    from attrs a
+        join range_filters r on r.trait_type = a.trait_type
+    where a.value ~ '^-?[0-9]+(\.[0-9]+)?$'
+      and a.value::double precision >= coalesce(r.min_value, '-infinity')
+      and a.value::double precision <= coalesce(r.max_value, 'infinity')
+),
+matched_types as (
+    select nft, array_agg(trait_type) as trait_types
+    from matched
+    group by nft
+)
+select n.address,
+       array(select trait_type
+             from filter_types
+             except
+             select unnest(coalesce(m.trait_types, '{}')))::varchar[]
+from nft n
+    left join matched_types m on m.nft = n.address
+where n.collection = p_collection
+  and not n.burned
+$$ language sql stable;
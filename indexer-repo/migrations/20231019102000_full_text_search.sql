@@ -0,0 +1,16 @@
+-- 'simple' config: names are not english prose and must match by prefix unstemmed
+create or replace function name_search_vector(name text, description text) returns tsvector as
+$$
+select setweight(to_tsvector('simple', coalesce(name, '')), 'A') ||
+       setweight(to_tsvector('simple', coalesce(description, '')), 'B')
+$$ language sql immutable;
+
+create index nft_search_idx
+    on nft using gin (name_search_vector(name, description))
+    where not burned;
+
+create index nft_collection_search_idx
+    on nft_collection using gin (name_search_vector(name, description));
+
+create index users_search_idx
+    on users using gin (name_search_vector(username, null));
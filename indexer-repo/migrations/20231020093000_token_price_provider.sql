@@ -0,0 +1,6 @@
+-- price provider tried first for the token, the others are fallbacks
+alter table token_to_dex
+    add column provider varchar(64);
+
+update token_to_dex
+set provider = case source when 'venom' then 'web3world' else 'flatqube' end;
@@ -0,0 +1,11 @@
+-- completed hourly candles of dex pairs, hours without trades repeat the last close
+create table dex_pair_candles
+(
+    pair     t_address   not null,
+    ts       timestamp   not null,
+    open     numeric     not null,
+    close    numeric     not null,
+    provider varchar(64) not null,
+    filled   boolean     not null default false,
+    primary key (pair, ts)
+);
@@ -0,0 +1,20 @@
+-- pairs between tokens, used to reach a token of token_to_dex when a token
+-- has no usd pair itself; liquidity is maintained along with token_to_dex
+create table dex_pairs
+(
+    pair           t_address   primary key,
+    source         bc_name     not null,
+    left_token     t_address   not null,
+    right_token    t_address   not null,
+    left_decimals  int         not null,
+    right_decimals int         not null,
+    provider       varchar(64),
+    liquidity_usd  numeric
+);
+
+create index dex_pairs_source_index
+    on dex_pairs (source);
+
+-- pairs the usd price was computed through, the usd pair of token_to_dex last
+alter table nft_price_history
+    add column usd_price_route t_address[];
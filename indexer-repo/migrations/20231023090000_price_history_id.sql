@@ -0,0 +1,3 @@
+-- a source can produce several prices, rows are updated by their own key
+alter table nft_price_history
+    add column id bigserial primary key;
@@ -0,0 +1,11 @@
+-- tip-3 root metadata of payment tokens, failed roots are retried later
+create table tokens
+(
+    address  t_address primary key,
+    name     text,
+    symbol   text,
+    decimals int,
+    icon     text,
+    failed   boolean   not null default false,
+    updated  timestamp not null
+);
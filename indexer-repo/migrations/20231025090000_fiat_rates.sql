@@ -0,0 +1,9 @@
+-- hourly fiat rates, units of the currency per one usd
+create table fiat_rates
+(
+    currency varchar(8)  not null,
+    ts       timestamp   not null,
+    usd_rate numeric     not null,
+    provider varchar(64) not null,
+    primary key (currency, ts)
+);
@@ -0,0 +1,35 @@
+create type media_kind as enum (
+    'nft_preview',
+    'collection_logo',
+    'collection_wallpaper'
+);
+
+create type media_status as enum (
+    'ready',
+    'failed',
+    'unsupported'
+);
+
+-- copies and thumbnails of the media referenced by nft and collection metadata
+create table media
+(
+    address         t_address    not null,
+    kind            media_kind   not null,
+    source          t_uri        not null,
+    status          media_status not null,
+    mime_type       varchar(64),
+    size_bytes      bigint,
+    content_hash    varchar(64),
+    width           int,
+    height          int,
+    url             t_uri,
+    thumbnail_url   t_uri,
+    error           text,
+    attempts        int          not null default 0,
+    next_attempt_at timestamp,
+    updated         timestamp    not null,
+    primary key (address, kind)
+);
+
+create index media_failed_index on media (next_attempt_at)
+    where status = 'failed';
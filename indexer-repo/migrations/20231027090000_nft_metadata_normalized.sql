@@ -0,0 +1,20 @@
+-- tip-4.2 fields of the metadata json, null until it's normalized
+alter table nft_metadata
+    add column meta_type        text,
+    add column preview_url      text,
+    add column preview_mimetype varchar(255),
+    add column external_url     text,
+    add column violations       jsonb,
+    add column normalized       timestamp;
+
+create index nft_metadata_not_normalized_index on nft_metadata (nft)
+    where normalized is null;
+
+create table nft_metadata_files
+(
+    nft      t_address not null references nft_metadata (nft) on delete cascade,
+    position int       not null,
+    source   text      not null,
+    mimetype varchar(255),
+    primary key (nft, position)
+);
@@ -0,0 +1,15 @@
+-- every distinct metadata json of an nft, appended as it changes
+create table nft_metadata_history
+(
+    nft          t_address   not null references nft (address) on delete cascade,
+    version      int         not null,
+    meta         jsonb       not null,
+    -- sha-256 of the jsonb text, keys sorted
+    content_hash varchar(64) not null,
+    created      timestamp   not null,
+    primary key (nft, version)
+);
+
+insert into nft_metadata_history (nft, version, meta, content_hash, created)
+select nft, 1, meta, encode(sha256(convert_to(meta::text, 'utf8')), 'hex'), updated
+from nft_metadata;
@@ -0,0 +1,13 @@
+-- webhooks were managed with their signing secret, which the receiving
+-- endpoint knows as well. existing webhooks get the hash of a random token
+-- nobody knows, so they keep delivering but can't be managed through the api
+-- anymore. owners register them again to get a management token, operators
+-- remove the old rows.
+alter table webhooks
+    add column management_token_hash bytea;
+
+update webhooks
+set management_token_hash = sha256(convert_to(gen_random_uuid()::text, 'utf8'));
+
+alter table webhooks
+    alter column management_token_hash set not null;
@@ -0,0 +1,20 @@
+-- ts of an auction deal is the auction start, completed_at is when the deal was made
+alter table nft_price_history
+    add column completed_at timestamp;
+
+update nft_price_history ph
+set completed_at = to_timestamp(ne.created_at) at time zone 'utc'
+from nft_events ne
+where ph.source_type = 'auctionBid'
+  and ne.event_type = 'auction_complete'
+  and ne.address = ph.source;
+
+update nft_price_history
+set completed_at = ts
+where completed_at is null;
+
+alter table nft_price_history
+    alter column completed_at set not null;
+
+create index nft_price_history_nft_completed_at_index
+    on nft_price_history (nft, completed_at);
@@ -0,0 +1,9 @@
+-- sales inserted after this id are aggregated on the next run whatever their time is
+alter table collection_stats_progress
+    add column last_price_history_id bigint not null default 0;
+
+create index nft_price_history_completed_at_index
+    on nft_price_history (completed_at);
+
+create index nft_price_history_collection_completed_at_index
+    on nft_price_history (collection, completed_at);
@@ -0,0 +1,90 @@
+-- trait values an nft was last scored with, traits it lacks are not stored
+create table nft_rarity_traits
+(
+    nft        t_address    not null,
+    collection t_address    not null,
+    trait_type varchar(200) not null,
+    value      text         not null,
+    primary key (nft, trait_type)
+);
+
+create index nft_rarity_traits_value_index
+    on nft_rarity_traits (collection, trait_type, value);
+
+-- what the raw scores of a collection are turned into scores with
+create table collection_rarity
+(
+    collection  t_address primary key,
+    supply      int              not null,
+    trait_types int              not null,
+    entropy     double precision not null
+);
+
+-- raw scores don't depend on the supply, so a mint only rescores the nfts sharing its traits:
+-- the sum of ln(nft_count) of the trait values for statistical and information content,
+-- the number of nfts with as many traits for trait count
+delete from nft_rarity;
+delete from collection_traits;
+
+alter table nft_rarity
+    rename column score to raw_score;
+
+alter table nft_rarity
+    add column trait_count int not null default 0;
+
+create or replace function rarity_score(algorithm rarity_algorithm, raw_score double precision,
+                                        supply int, trait_types int, entropy double precision)
+    returns double precision as
+$$
+select case algorithm
+           when 'statistical' then raw_score - trait_types * ln(supply)
+           when 'information_content'
+               then coalesce((trait_types * ln(supply) - raw_score) / ln(2) / nullif(entropy, 0), 0)
+           else raw_score / supply
+           end
+$$ language sql immutable;
+
+create table rarity_dirty_nfts
+(
+    nft        t_address primary key,
+    collection t_address not null,
+    marked_at  timestamp not null
+);
+
+create index rarity_dirty_nfts_collection_index
+    on rarity_dirty_nfts (collection, marked_at);
+
+create or replace function mark_rarity_dirty() returns trigger as
+$$
+begin
+    if tg_table_name = 'nft' then
+        insert into rarity_dirty_nfts (nft, collection, marked_at)
+        values (new.address, new.collection, clock_timestamp())
+        on conflict (nft) do update set marked_at = excluded.marked_at;
+    else
+        insert into rarity_dirty_nfts (nft, collection, marked_at)
+        values (coalesce(new.nft, old.nft), coalesce(new.collection, old.collection), clock_timestamp())
+        on conflict (nft) do update set marked_at = excluded.marked_at;
+    end if;
+
+    return null;
+end;
+$$ language plpgsql;
+
+-- every collection with attributes is scored again from scratch
+insert into rarity_dirty_nfts (nft, collection, marked_at)
+select address, collection, now()
+from nft
+where collection in (select distinct collection from nft_attributes)
+on conflict do nothing;
+
+drop table rarity_dirty_collections;
+
+-- trait values of not burned nfts, one per trait
+create or replace view nft_trait_values as
+select a.nft, a.collection, a.trait_type, min(a.value #>> '{}') as value
+from nft_attributes a
+    join nft n on n.address = a.nft
+where not n.burned
+group by a.nft, a.collection, a.trait_type
+having min(a.value #>> '{}') is not null;
@@ -0,0 +1,107 @@
+drop function if exists nft_trait_misses(varchar, varchar[], text[], varchar[], double precision[], double precision[]);
+
+create index ix_nft_attributes_collection_trait_value
+    on nft_attributes (collection, trait_type, (value #>> '{}'));
+
+-- not burned nfts of a collection matching the owner, price and listing filters and
+-- missing at most p_max_missed trait filter types. Values of a type are or-ed, ranges
+-- of a type are and-ed with them and with each other, types are and-ed.
+create or replace function nft_search(
+    p_collection    varchar,
+    p_types         varchar[],
+    p_values        text[],
+    p_range_types   varchar[],
+    p_mins          double precision[],
+    p_maxes         double precision[],
+    p_max_missed    int,
+    p_owner         varchar,
+    p_min_price_usd numeric,
+    p_max_price_usd numeric,
+    p_listing       text
+)
+    returns table
+            (
+                nft    t_address,
+                missed varchar[]
+            )
+as
+$$
+with value_filters as (
+    select *
+    from unnest(p_types, p_values) as f(trait_type, value)
+),
+range_filters as (
+    select *
+    from unnest(p_range_types, p_mins, p_maxes) as r(trait_type, min_value, max_value)
+),
+filter_types as (
+    select trait_type from value_filters
+    union
+    select trait_type from range_filters
+),
+-- only the attributes of the filtered traits are read, by value when a type has no range
+attrs as (
+    select a.nft, a.trait_type, a.value #>> '{}' as value
+    from (select distinct trait_type, value from value_filters) f
+        join nft_attributes a on a.collection = p_collection
+            and a.trait_type = f.trait_type
+            and a.value #>> '{}' = f.value
+    where f.trait_type not in (select trait_type from range_filters)
+    union
+    select a.nft, a.trait_type, a.value #>> '{}'
+    from (select distinct trait_type from range_filters) r
+        join nft_attributes a on a.collection = p_collection and a.trait_type = r.trait_type
+),
+matched as (
+    select a.nft, array_agg(distinct a.trait_type) as trait_types
+    from (
+        select *, case when value ~ '^-?[0-9]+(\.[0-9]+)?$' then value::double precision end as number
+        from attrs
+    ) a
+    where (a.trait_type not in (select trait_type from value_filters)
+        or (a.trait_type, a.value) in (select trait_type, value from value_filters))
+      and not exists (
+        select 1
+        from range_filters r
+        where r.trait_type = a.trait_type
+          and (a.number is null
+            or a.number < coalesce(r.min_value, '-infinity')
+            or a.number > coalesce(r.max_value, 'infinity'))
+      )
+    group by a.nft
+),
+candidates as (
+    select m.nft, m.trait_types
+    from matched m
+    where cardinality(m.trait_types) >= (select count(*) from filter_types) - p_max_missed
+    union all
+    -- nfts matching no filter only count when they may miss every filter type
+    select n.address, '{}'
+    from nft n
+    where (select count(*) from filter_types) <= p_max_missed
+      and n.collection = p_collection
+      and not n.burned
+      and n.address not in (select m.nft from matched m)
+)
+select c.nft,
+       array(select trait_type
+             from filter_types
+             except
+             select unnest(c.trait_types))::varchar[]
+from candidates c
+    join nft_details d on d.address = c.nft
+where d.collection = p_collection
+  and not d.burned
+  and (p_owner is null or d.owner = p_owner)
+  and (p_min_price_usd is null or d.floor_price_usd >= p_min_price_usd)
+  and (p_max_price_usd is null or d.floor_price_usd <= p_max_price_usd)
+  and case p_listing
+        when 'listed' then d."forsale_status: _" = 'active'
+            or d."auction_status: _" = 'active'
+        when 'buy_now' then d."forsale_status: _" = 'active'
+        when 'auction' then d."auction_status: _" = 'active'
+        when 'unlisted' then d."forsale_status: _" is distinct from 'active'
+            and d."auction_status: _" is distinct from 'active'
+        else true
+      end
+$$ language sql stable;
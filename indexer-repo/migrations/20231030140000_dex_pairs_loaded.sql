@@ -0,0 +1,5 @@
+-- pairs are loaded from the price providers, decimals come from the token
+-- registry and stay unknown until it has read the token
+alter table dex_pairs
+    alter column left_decimals drop not null,
+    alter column right_decimals drop not null;
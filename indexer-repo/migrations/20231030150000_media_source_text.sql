@@ -0,0 +1,3 @@
+-- metadata uris are not limited in length, data uris included
+alter table media
+    alter column source type text;
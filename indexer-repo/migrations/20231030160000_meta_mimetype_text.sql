@@ -0,0 +1,6 @@
+-- mimetypes are copied from the metadata as they are, without a length limit
+alter table nft_metadata
+    alter column preview_mimetype type text;
+
+alter table nft_metadata_files
+    alter column mimetype type text;
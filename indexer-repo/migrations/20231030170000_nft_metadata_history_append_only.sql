@@ -0,0 +1,23 @@
+-- versions outlive nothing they describe: an nft with history can't be deleted,
+-- and versions are never changed or removed once written
+alter table nft_metadata_history
+    drop constraint nft_metadata_history_nft_fkey,
+    add constraint nft_metadata_history_nft_fkey
+        foreign key (nft) references nft (address) on delete restrict;
+
+create or replace function nft_metadata_history_append_only() returns trigger as
+$$
+begin
+    raise exception 'nft_metadata_history is append-only, % is not allowed', tg_op;
+end;
+$$ language plpgsql;
+
+create trigger nft_metadata_history_append_only
+    before update or delete on nft_metadata_history
+    for each row
+execute function nft_metadata_history_append_only();
+
+create trigger nft_metadata_history_no_truncate
+    before truncate on nft_metadata_history
+    for each statement
+execute function nft_metadata_history_append_only();
@@ -1,12 +1,33 @@
 use anyhow::{anyhow, Result};
+use serde_json::json;
 use sqlx::{Postgres, Transaction};
 
+use super::state_anomalies::validate_auction_status;
+
 use crate::types::{decoded::AuctionActive, AuctionStatus};
 
 pub async fn save_auc_active(
     tx: &mut Transaction<'_, Postgres>,
     data: &[AuctionActive],
 ) -> Result<()> {
+    let items = data
+        .iter()
+        .map(|a| {
+            let context = json!({
+                "price_token": a.price_token,
+                "start_price": a.start_price.to_string(),
+                "finished_at": a.finished_at.timestamp(),
+            });
+            (a.address.as_str(), a.tx_lt, context)
+        })
+        .collect::<Vec<_>>();
+    let accepted = validate_auction_status(tx, &items, AuctionStatus::Active).await?;
+    let data = data
+        .iter()
+        .zip(accepted)
+        .filter_map(|(a, ok)| ok.then_some(a))
+        .collect::<Vec<_>>();
+
     let addresses = data.iter().map(|a| a.address.as_str()).collect::<Vec<_>>();
     let wallets = data
         .iter()
@@ -1,6 +1,9 @@
 use anyhow::{anyhow, Result};
+use serde_json::json;
 use sqlx::{Postgres, Transaction};
 
+use super::state_anomalies::validate_auction_status;
+
 use crate::types::{
     decoded::{AuctionCancelled, AuctionComplete},
     AuctionStatus,
@@ -10,25 +13,43 @@ pub async fn save_auc_complete(
     tx: &mut Transaction<'_, Postgres>,
     data: &[AuctionComplete],
 ) -> Result<()> {
+    let items = data
+        .iter()
+        .map(|e| {
+            let context = json!({ "max_bid": e.max_bid.to_string() });
+            (e.address.as_str(), e.tx_lt, context)
+        })
+        .collect::<Vec<_>>();
+    let accepted = validate_auction_status(tx, &items, AuctionStatus::Completed).await?;
+    let data = data
+        .iter()
+        .zip(accepted)
+        .filter_map(|(e, ok)| ok.then_some(e))
+        .collect::<Vec<_>>();
+
     let addresses = data.iter().map(|e| e.address.as_str()).collect::<Vec<_>>();
     let max_bids = data.iter().map(|e| e.max_bid.clone()).collect::<Vec<_>>();
+    let tx_lts = data.iter().map(|e| e.tx_lt).collect::<Vec<_>>();
 
     sqlx::query!(
         r#"
         update nft_auction set
             max_bid = data.max_bid,
-            status = data.status
+            status = data.status,
+            tx_lt = data.tx_lt
         from
         (
             select 
                 unnest($1::varchar[]) as address,
                 unnest($2::numeric[]) as max_bid,
-                $3::auction_status as status
+                unnest($3::bigint[]) as tx_lt,
+                $4::auction_status as status
         ) as data
         where nft_auction.address = data.address
     "#,
         addresses as _,
         max_bids as _,
+        tx_lts as _,
         AuctionStatus::Completed as _,
     )
     .execute(tx)
@@ -41,21 +62,36 @@ pub async fn save_auc_cancelled(
     tx: &mut Transaction<'_, Postgres>,
     data: &[AuctionCancelled],
 ) -> Result<()> {
+    let items = data
+        .iter()
+        .map(|e| (e.address.as_str(), e.tx_lt, json!({})))
+        .collect::<Vec<_>>();
+    let accepted = validate_auction_status(tx, &items, AuctionStatus::Cancelled).await?;
+    let data = data
+        .iter()
+        .zip(accepted)
+        .filter_map(|(e, ok)| ok.then_some(e))
+        .collect::<Vec<_>>();
+
     let addresses = data.iter().map(|e| e.address.as_str()).collect::<Vec<_>>();
+    let tx_lts = data.iter().map(|e| e.tx_lt).collect::<Vec<_>>();
 
     sqlx::query!(
         r#"
         update nft_auction set
-            status = data.status
+            status = data.status,
+            tx_lt = data.tx_lt
         from
         (
             select 
                 unnest($1::varchar[]) as address,
-                $2::auction_status as status
+                unnest($2::bigint[]) as tx_lt,
+                $3::auction_status as status
         ) as data
         where nft_auction.address = data.address
     "#,
         addresses as _,
+        tx_lts as _,
         AuctionStatus::Cancelled as _,
     )
     .execute(tx)
@@ -1,5 +1,11 @@
-use crate::types::decoded::DirectBuy;
+use super::state_anomalies::{
+    get_direct_buy_states, save_offer_state_anomalies, RejectedStateChanges,
+};
+use crate::state_machine::{check_transition, TransitionCheck};
+use crate::types::decoded::{DirectBuy, DirectBuyStateTransition};
+use crate::types::{DirectBuyState, OfferStateAnomaly, OfferStateTransition};
 use anyhow::{anyhow, Result};
+use serde_json::json;
 use sqlx::{Postgres, Transaction};
 use std::collections::HashMap;
 
@@ -80,14 +86,57 @@ pub async fn save_direct_buy(tx: &mut Transaction<'_, Postgres>, dbs: &[DirectBu
 pub async fn update_direct_buy_state(
     tx: &mut Transaction<'_, Postgres>,
     dbs: &mut [DirectBuy],
-) -> Result<()> {
-    dbs.sort_by(|a, b| b.tx_lt.cmp(&a.tx_lt));
+) -> Result<RejectedStateChanges<DirectBuyState>> {
+    // One transaction can emit several state changes (e.g. Create -> AwaitTokens -> Active).
+    // The sort is stable, so they keep their emission order and the last one wins.
+    dbs.sort_by_key(|db| db.tx_lt);
     let mut last_state_change = HashMap::with_capacity(dbs.len());
 
+    let offers = dbs.iter().map(|db| db.address.as_str()).collect::<Vec<_>>();
+    let mut current = get_direct_buy_states(tx, &offers).await?;
+    let mut anomalies = Vec::new();
+    let mut rejected = RejectedStateChanges::new();
+
     for db in dbs.iter() {
-        if !last_state_change.contains_key(&db.address) {
-            last_state_change.insert(&db.address, db);
+        if let Some((state, current_lt)) = current.get(&db.address) {
+            match check_transition(state, *current_lt, &db.state, db.tx_lt) {
+                TransitionCheck::Valid => {}
+                TransitionCheck::Stale => continue,
+                TransitionCheck::Invalid => {
+                    anomalies.push(OfferStateAnomaly {
+                        address: db.address.clone(),
+                        transition: OfferStateTransition::DirectBuy(
+                            state.clone(),
+                            db.state.clone(),
+                        ),
+                        current_tx_lt: *current_lt,
+                        incoming_tx_lt: db.tx_lt,
+                        context: json!({
+                            "nft": db.nft,
+                            "collection": db.collection,
+                            "price_token": db.price_token,
+                            "price": db.price.to_string(),
+                            "buyer": db.buyer,
+                            "expired_at": db.expired_at.timestamp(),
+                            "updated": db.updated.timestamp(),
+                        }),
+                    });
+                    rejected.insert((db.address.clone(), db.tx_lt, db.state.clone()));
+                    continue;
+                }
+            }
         }
+
+        current.insert(db.address.clone(), (db.state.clone(), db.tx_lt));
+        last_state_change.insert(&db.address, db);
+    }
+
+    if !anomalies.is_empty() {
+        save_offer_state_anomalies(tx, &anomalies).await?;
+    }
+
+    if last_state_change.is_empty() {
+        return Ok(rejected);
     }
 
     let mut addresses = Vec::with_capacity(dbs.len());
@@ -165,6 +214,175 @@ pub async fn update_direct_buy_state(
     )
     .execute(tx)
     .await
+    .map_err(|e| anyhow!(e))?;
+
+    Ok(rejected)
+}
+
+pub async fn save_direct_buy_state_transitions(
+    tx: &mut Transaction<'_, Postgres>,
+    transitions: &[DirectBuyStateTransition],
+    rejected: &RejectedStateChanges<DirectBuyState>,
+) -> Result<()> {
+    let transitions = transitions
+        .iter()
+        .filter(|t| !rejected.contains(&(t.address.clone(), t.tx_lt, t.to.clone())))
+        .collect::<Vec<_>>();
+    if transitions.is_empty() {
+        return Ok(());
+    }
+
+    let addresses = transitions
+        .iter()
+        .map(|t| t.address.as_str())
+        .collect::<Vec<_>>();
+    let states_from = transitions
+        .iter()
+        .map(|t| t.from.clone())
+        .collect::<Vec<_>>();
+    let states_to = transitions.iter().map(|t| t.to.clone()).collect::<Vec<_>>();
+    let tx_lts = transitions.iter().map(|t| t.tx_lt).collect::<Vec<_>>();
+    let created_at = transitions.iter().map(|t| t.created_at).collect::<Vec<_>>();
+
+    sqlx::query!(
+        r#"
+            insert into nft_direct_buy_state_history (
+                address,
+                state_from,
+                state_to,
+                tx_lt,
+                created_at
+            )
+            select
+                unnest($1::varchar[]),
+                unnest($2::direct_buy_state[]),
+                unnest($3::direct_buy_state[]),
+                unnest($4::bigint[]),
+                unnest($5::timestamp[])
+            on conflict do nothing
+        "#,
+        addresses as _,
+        states_from as _,
+        states_to as _,
+        tx_lts as _,
+        created_at as _,
+    )
+    .execute(tx)
+    .await
     .map_err(|e| anyhow!(e))
     .map(|_| ())
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use sqlx::types::BigDecimal;
+
+    use super::*;
+    use crate::test_db;
+
+    const ADDRESS: &str = "0:db";
+
+    fn direct_buy(state: DirectBuyState, tx_lt: i64) -> DirectBuy {
+        let ts = NaiveDateTime::from_timestamp_opt(1_700_000_000 + tx_lt, 0).unwrap();
+
+        DirectBuy {
+            address: ADDRESS.to_string(),
+            root: "0:r".to_string(),
+            nft: "0:n".to_string(),
+            collection: None,
+            price_token: "0:t".to_string(),
+            price: BigDecimal::from(1),
+            buyer: "0:o".to_string(),
+            finished_at: None,
+            expired_at: NaiveDateTime::from_timestamp_opt(0, 0).unwrap(),
+            state,
+            created: ts,
+            updated: ts,
+            tx_lt,
+        }
+    }
+
+    fn transition(
+        from: DirectBuyState,
+        to: DirectBuyState,
+        tx_lt: i64,
+    ) -> DirectBuyStateTransition {
+        DirectBuyStateTransition {
+            address: ADDRESS.to_string(),
+            from,
+            to,
+            tx_lt,
+            created_at: NaiveDateTime::from_timestamp_opt(1_700_000_000 + tx_lt, 0).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn keeps_rejected_changes_out_of_history() {
+        use DirectBuyState::*;
+
+        let mut tx = test_db::begin().await;
+
+        save_direct_buy(&mut tx, &[direct_buy(Create, 10)])
+            .await
+            .unwrap();
+
+        // A valid change, one the state machine rejects and a stale one
+        let mut changes = vec![
+            direct_buy(AwaitTokens, 20),
+            direct_buy(Filled, 30),
+            direct_buy(Cancelled, 5),
+        ];
+        let rejected = update_direct_buy_state(&mut tx, &mut changes)
+            .await
+            .unwrap();
+        assert_eq!(
+            rejected,
+            RejectedStateChanges::from([(ADDRESS.to_string(), 30, Filled)])
+        );
+
+        let transitions = [
+            transition(Create, AwaitTokens, 20),
+            transition(AwaitTokens, Filled, 30),
+        ];
+        save_direct_buy_state_transitions(&mut tx, &transitions, &rejected)
+            .await
+            .unwrap();
+
+        let state: (DirectBuyState, i64) =
+            sqlx::query_as("select state, tx_lt from nft_direct_buy where address = $1")
+                .bind(ADDRESS)
+                .fetch_one(&mut tx)
+                .await
+                .unwrap();
+        assert_eq!(state, (AwaitTokens, 20));
+
+        let history: Vec<(DirectBuyState, DirectBuyState, i64)> = sqlx::query_as(
+            r#"
+                select state_from, state_to, tx_lt
+                from nft_direct_buy_state_history
+                where address = $1
+                order by tx_lt
+            "#,
+        )
+        .bind(ADDRESS)
+        .fetch_all(&mut tx)
+        .await
+        .unwrap();
+        assert_eq!(history, vec![(Create, AwaitTokens, 20)]);
+
+        let anomalies: Vec<(DirectBuyState, DirectBuyState, i64)> = sqlx::query_as(
+            r#"
+                select direct_buy_state_from, direct_buy_state_to, incoming_tx_lt
+                from offer_state_anomalies
+                where address = $1
+            "#,
+        )
+        .bind(ADDRESS)
+        .fetch_all(&mut tx)
+        .await
+        .unwrap();
+        assert_eq!(anomalies, vec![(AwaitTokens, Filled, 30)]);
+    }
+}
@@ -1,5 +1,11 @@
-use crate::types::decoded::DirectSell;
+use super::state_anomalies::{
+    get_direct_sell_states, save_offer_state_anomalies, RejectedStateChanges,
+};
+use crate::state_machine::{check_transition, TransitionCheck};
+use crate::types::decoded::{DirectSell, DirectSellStateTransition};
+use crate::types::{DirectSellState, OfferStateAnomaly, OfferStateTransition};
 use anyhow::{anyhow, Result};
+use serde_json::json;
 use sqlx::{Postgres, Transaction};
 use std::collections::HashMap;
 
@@ -83,14 +89,57 @@ pub async fn save_direct_sell(
 pub async fn update_direct_sell_state(
     tx: &mut Transaction<'_, Postgres>,
     dss: &mut [DirectSell],
-) -> Result<()> {
-    dss.sort_by(|a, b| b.tx_lt.cmp(&a.tx_lt));
+) -> Result<RejectedStateChanges<DirectSellState>> {
+    // One transaction can emit several state changes (e.g. Create -> AwaitNft -> Active).
+    // The sort is stable, so they keep their emission order and the last one wins.
+    dss.sort_by_key(|ds| ds.tx_lt);
     let mut last_state_change = HashMap::with_capacity(dss.len());
 
+    let offers = dss.iter().map(|ds| ds.address.as_str()).collect::<Vec<_>>();
+    let mut current = get_direct_sell_states(tx, &offers).await?;
+    let mut anomalies = Vec::new();
+    let mut rejected = RejectedStateChanges::new();
+
     for ds in dss.iter() {
-        if !last_state_change.contains_key(&ds.address) {
-            last_state_change.insert(&ds.address, ds);
+        if let Some((state, current_lt)) = current.get(&ds.address) {
+            match check_transition(state, *current_lt, &ds.state, ds.tx_lt) {
+                TransitionCheck::Valid => {}
+                TransitionCheck::Stale => continue,
+                TransitionCheck::Invalid => {
+                    anomalies.push(OfferStateAnomaly {
+                        address: ds.address.clone(),
+                        transition: OfferStateTransition::DirectSell(
+                            state.clone(),
+                            ds.state.clone(),
+                        ),
+                        current_tx_lt: *current_lt,
+                        incoming_tx_lt: ds.tx_lt,
+                        context: json!({
+                            "nft": ds.nft,
+                            "collection": ds.collection,
+                            "price_token": ds.price_token,
+                            "price": ds.price.to_string(),
+                            "seller": ds.seller,
+                            "expired_at": ds.expired_at.timestamp(),
+                            "updated": ds.updated.timestamp(),
+                        }),
+                    });
+                    rejected.insert((ds.address.clone(), ds.tx_lt, ds.state.clone()));
+                    continue;
+                }
+            }
         }
+
+        current.insert(ds.address.clone(), (ds.state.clone(), ds.tx_lt));
+        last_state_change.insert(&ds.address, ds);
+    }
+
+    if !anomalies.is_empty() {
+        save_offer_state_anomalies(tx, &anomalies).await?;
+    }
+
+    if last_state_change.is_empty() {
+        return Ok(rejected);
     }
 
     let mut addresses = Vec::with_capacity(dss.len());
@@ -168,6 +217,175 @@ pub async fn update_direct_sell_state(
     )
     .execute(tx)
     .await
+    .map_err(|e| anyhow!(e))?;
+
+    Ok(rejected)
+}
+
+pub async fn save_direct_sell_state_transitions(
+    tx: &mut Transaction<'_, Postgres>,
+    transitions: &[DirectSellStateTransition],
+    rejected: &RejectedStateChanges<DirectSellState>,
+) -> Result<()> {
+    let transitions = transitions
+        .iter()
+        .filter(|t| !rejected.contains(&(t.address.clone(), t.tx_lt, t.to.clone())))
+        .collect::<Vec<_>>();
+    if transitions.is_empty() {
+        return Ok(());
+    }
+
+    let addresses = transitions
+        .iter()
+        .map(|t| t.address.as_str())
+        .collect::<Vec<_>>();
+    let states_from = transitions
+        .iter()
+        .map(|t| t.from.clone())
+        .collect::<Vec<_>>();
+    let states_to = transitions.iter().map(|t| t.to.clone()).collect::<Vec<_>>();
+    let tx_lts = transitions.iter().map(|t| t.tx_lt).collect::<Vec<_>>();
+    let created_at = transitions.iter().map(|t| t.created_at).collect::<Vec<_>>();
+
+    sqlx::query!(
+        r#"
+            insert into nft_direct_sell_state_history (
+                address,
+                state_from,
+                state_to,
+                tx_lt,
+                created_at
+            )
+            select
+                unnest($1::varchar[]),
+                unnest($2::direct_sell_state[]),
+                unnest($3::direct_sell_state[]),
+                unnest($4::bigint[]),
+                unnest($5::timestamp[])
+            on conflict do nothing
+        "#,
+        addresses as _,
+        states_from as _,
+        states_to as _,
+        tx_lts as _,
+        created_at as _,
+    )
+    .execute(tx)
+    .await
     .map_err(|e| anyhow!(e))
     .map(|_| ())
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use sqlx::types::BigDecimal;
+
+    use super::*;
+    use crate::test_db;
+
+    const ADDRESS: &str = "0:d5";
+
+    fn direct_sell(state: DirectSellState, tx_lt: i64) -> DirectSell {
+        let ts = NaiveDateTime::from_timestamp_opt(1_700_000_000 + tx_lt, 0).unwrap();
+
+        DirectSell {
+            address: ADDRESS.to_string(),
+            root: "0:r".to_string(),
+            nft: "0:n".to_string(),
+            collection: None,
+            price_token: "0:t".to_string(),
+            price: BigDecimal::from(1),
+            seller: "0:o".to_string(),
+            finished_at: None,
+            expired_at: NaiveDateTime::from_timestamp_opt(0, 0).unwrap(),
+            state,
+            created: ts,
+            updated: ts,
+            tx_lt,
+        }
+    }
+
+    fn transition(
+        from: DirectSellState,
+        to: DirectSellState,
+        tx_lt: i64,
+    ) -> DirectSellStateTransition {
+        DirectSellStateTransition {
+            address: ADDRESS.to_string(),
+            from,
+            to,
+            tx_lt,
+            created_at: NaiveDateTime::from_timestamp_opt(1_700_000_000 + tx_lt, 0).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn keeps_rejected_changes_out_of_history() {
+        use DirectSellState::*;
+
+        let mut tx = test_db::begin().await;
+
+        save_direct_sell(&mut tx, &[direct_sell(Create, 10)])
+            .await
+            .unwrap();
+
+        // A valid change, one the state machine rejects and a stale one
+        let mut changes = vec![
+            direct_sell(AwaitNft, 20),
+            direct_sell(Filled, 30),
+            direct_sell(Cancelled, 5),
+        ];
+        let rejected = update_direct_sell_state(&mut tx, &mut changes)
+            .await
+            .unwrap();
+        assert_eq!(
+            rejected,
+            RejectedStateChanges::from([(ADDRESS.to_string(), 30, Filled)])
+        );
+
+        let transitions = [
+            transition(Create, AwaitNft, 20),
+            transition(AwaitNft, Filled, 30),
+        ];
+        save_direct_sell_state_transitions(&mut tx, &transitions, &rejected)
+            .await
+            .unwrap();
+
+        let state: (DirectSellState, i64) =
+            sqlx::query_as("select state, tx_lt from nft_direct_sell where address = $1")
+                .bind(ADDRESS)
+                .fetch_one(&mut tx)
+                .await
+                .unwrap();
+        assert_eq!(state, (AwaitNft, 20));
+
+        let history: Vec<(DirectSellState, DirectSellState, i64)> = sqlx::query_as(
+            r#"
+                select state_from, state_to, tx_lt
+                from nft_direct_sell_state_history
+                where address = $1
+                order by tx_lt
+            "#,
+        )
+        .bind(ADDRESS)
+        .fetch_all(&mut tx)
+        .await
+        .unwrap();
+        assert_eq!(history, vec![(Create, AwaitNft, 20)]);
+
+        let anomalies: Vec<(DirectSellState, DirectSellState, i64)> = sqlx::query_as(
+            r#"
+                select direct_sell_state_from, direct_sell_state_to, incoming_tx_lt
+                from offer_state_anomalies
+                where address = $1
+            "#,
+        )
+        .bind(ADDRESS)
+        .fetch_all(&mut tx)
+        .await
+        .unwrap();
+        assert_eq!(anomalies, vec![(AwaitNft, Filled, 30)]);
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use anyhow::{anyhow, Result};
 use sqlx::{Postgres, Transaction};
 
@@ -6,7 +8,7 @@ use crate::types::decoded::{EventRecord, OfferDeployed};
 pub async fn save_raw_event(
     tx: &mut Transaction<'_, Postgres>,
     events: &[EventRecord],
-) -> Result<()> {
+) -> Result<HashMap<String, i64>> {
     let categories = events.iter().map(|e| e.event_category).collect::<Vec<_>>();
     let types = events.iter().map(|e| e.event_type).collect::<Vec<_>>();
     let addresses = events
@@ -53,6 +55,7 @@ pub async fn save_raw_event(
                 unnest($8::jsonb[]),
                 unnest($9::text[])
             on conflict(message_hash) do nothing
+            returning message_hash as "message_hash!", id
         "#,
         categories as _,
         types as _,
@@ -64,10 +67,10 @@ pub async fn save_raw_event(
         args as _,
         hashes as _,
     )
-    .execute(tx)
+    .fetch_all(tx)
     .await
+    .map(|rows| rows.into_iter().map(|r| (r.message_hash, r.id)).collect())
     .map_err(|e| anyhow!(e))
-    .map(|_| ())
 }
 
 pub async fn save_deployed_offers(
@@ -13,6 +13,8 @@ mod nft_created;
 mod nft_manager_changed;
 mod nft_owner_changed;
 mod prices;
+mod state_anomalies;
+mod webhooks;
 
 pub use auc_active::save_auc_active;
 pub use auc_bid_save::save_auc_bid;
@@ -23,8 +25,10 @@ pub use auc_update_prices::update_auc_maxmin;
 pub use collection::save_collections;
 pub use collection_fee::update_collection_fee;
 pub use direct_buy::save_direct_buy;
+pub use direct_buy::save_direct_buy_state_transitions;
 pub use direct_buy::update_direct_buy_state;
 pub use direct_sell::save_direct_sell;
+pub use direct_sell::save_direct_sell_state_transitions;
 pub use direct_sell::update_direct_sell_state;
 pub use events::save_deployed_offers;
 pub use events::save_raw_event;
@@ -33,3 +37,6 @@ pub use nft_created::save_nft_created;
 pub use nft_manager_changed::save_nft_manager_changed;
 pub use nft_owner_changed::save_nft_owner_changed;
 pub use prices::save_price_history;
+pub use state_anomalies::save_offer_state_anomalies;
+pub use state_anomalies::RejectedStateChanges;
+pub use webhooks::enqueue_webhook_notifications;
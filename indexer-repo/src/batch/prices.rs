@@ -10,6 +10,7 @@ pub async fn save_price_history(
     let sources = data.iter().map(|e| e.source.as_str()).collect::<Vec<_>>();
     let source_types = data.iter().map(|e| e.source_type).collect::<Vec<_>>();
     let created_at = data.iter().map(|e| e.created_at).collect::<Vec<_>>();
+    let completed_at = data.iter().map(|e| e.completed_at).collect::<Vec<_>>();
     let prices = data.iter().map(|e| e.price.clone()).collect::<Vec<_>>();
     let price_tokens = data
         .iter()
@@ -32,7 +33,8 @@ pub async fn save_price_history(
                 price_token, 
                 nft,
                 usd_price,
-                collection
+                collection,
+                completed_at
             )
             select
                 unnest($1::varchar[]),
@@ -42,7 +44,8 @@ pub async fn save_price_history(
                 unnest($5::varchar[]),
                 unnest($6::varchar[]),
                 unnest($7::numeric[]),
-                unnest($8::varchar[])
+                unnest($8::varchar[]),
+                unnest($9::timestamp[])
         "#,
         sources as _,
         source_types as _,
@@ -52,6 +55,7 @@ pub async fn save_price_history(
         nft as _,
         usd_prices as _,
         collections as _,
+        completed_at as _,
     )
     .execute(tx)
     .await
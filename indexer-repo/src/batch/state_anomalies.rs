@@ -0,0 +1,228 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use sqlx::{Postgres, Transaction};
+
+use crate::state_machine::{check_transition, TransitionCheck};
+use crate::types::{
+    AuctionStatus, DirectBuyState, DirectSellState, OfferStateAnomaly, OfferStateTransition,
+};
+
+/// Address, lt and target state of the state changes rejected as anomalies.
+/// They are kept out of the state history and the live events.
+pub type RejectedStateChanges<S> = HashSet<(String, i64, S)>;
+
+pub async fn save_offer_state_anomalies(
+    tx: &mut Transaction<'_, Postgres>,
+    anomalies: &[OfferStateAnomaly],
+) -> Result<()> {
+    for anomaly in anomalies {
+        log::warn!(
+            "Invalid {:?} state transition for {} (lt {} -> {})",
+            anomaly.transition,
+            anomaly.address,
+            anomaly.current_tx_lt,
+            anomaly.incoming_tx_lt
+        );
+    }
+
+    let addresses = anomalies
+        .iter()
+        .map(|a| a.address.as_str())
+        .collect::<Vec<_>>();
+    let offer_types = anomalies
+        .iter()
+        .map(|a| a.transition.offer_type())
+        .collect::<Vec<_>>();
+    let (auction_statuses_from, auction_statuses_to): (Vec<_>, Vec<_>) = anomalies
+        .iter()
+        .map(|a| match &a.transition {
+            OfferStateTransition::Auction(from, to) => (Some(from.clone()), Some(to.clone())),
+            _ => (None, None),
+        })
+        .unzip();
+    let (direct_sell_states_from, direct_sell_states_to): (Vec<_>, Vec<_>) = anomalies
+        .iter()
+        .map(|a| match &a.transition {
+            OfferStateTransition::DirectSell(from, to) => (Some(from.clone()), Some(to.clone())),
+            _ => (None, None),
+        })
+        .unzip();
+    let (direct_buy_states_from, direct_buy_states_to): (Vec<_>, Vec<_>) = anomalies
+        .iter()
+        .map(|a| match &a.transition {
+            OfferStateTransition::DirectBuy(from, to) => (Some(from.clone()), Some(to.clone())),
+            _ => (None, None),
+        })
+        .unzip();
+    let current_tx_lts = anomalies
+        .iter()
+        .map(|a| a.current_tx_lt)
+        .collect::<Vec<_>>();
+    let incoming_tx_lts = anomalies
+        .iter()
+        .map(|a| a.incoming_tx_lt)
+        .collect::<Vec<_>>();
+    let contexts = anomalies
+        .iter()
+        .map(|a| a.context.clone())
+        .collect::<Vec<_>>();
+
+    sqlx::query!(
+        r#"
+            insert into offer_state_anomalies (
+                address,
+                offer_type,
+                auction_status_from,
+                auction_status_to,
+                direct_sell_state_from,
+                direct_sell_state_to,
+                direct_buy_state_from,
+                direct_buy_state_to,
+                current_tx_lt,
+                incoming_tx_lt,
+                context
+            )
+            select
+                unnest($1::varchar[]),
+                unnest($2::event_category[]),
+                unnest($3::auction_status[]),
+                unnest($4::auction_status[]),
+                unnest($5::direct_sell_state[]),
+                unnest($6::direct_sell_state[]),
+                unnest($7::direct_buy_state[]),
+                unnest($8::direct_buy_state[]),
+                unnest($9::bigint[]),
+                unnest($10::bigint[]),
+                unnest($11::jsonb[])
+            on conflict do nothing
+        "#,
+        addresses as _,
+        offer_types as _,
+        auction_statuses_from as _,
+        auction_statuses_to as _,
+        direct_sell_states_from as _,
+        direct_sell_states_to as _,
+        direct_buy_states_from as _,
+        direct_buy_states_to as _,
+        current_tx_lts as _,
+        incoming_tx_lts as _,
+        contexts as _,
+    )
+    .execute(tx)
+    .await
+    .map_err(|e| anyhow!(e))
+    .map(|_| ())
+}
+
+pub(super) async fn get_direct_sell_states(
+    tx: &mut Transaction<'_, Postgres>,
+    addresses: &[&str],
+) -> Result<HashMap<String, (DirectSellState, i64)>> {
+    sqlx::query!(
+        r#"
+            select
+                address as "address!",
+                state as "state: DirectSellState",
+                tx_lt
+            from nft_direct_sell
+            where address = any($1::varchar[])
+        "#,
+        addresses as _,
+    )
+    .fetch_all(tx)
+    .await
+    .map(|rows| {
+        rows.into_iter()
+            .map(|r| (r.address, (r.state, r.tx_lt)))
+            .collect()
+    })
+    .map_err(|e| anyhow!(e))
+}
+
+pub(super) async fn get_direct_buy_states(
+    tx: &mut Transaction<'_, Postgres>,
+    addresses: &[&str],
+) -> Result<HashMap<String, (DirectBuyState, i64)>> {
+    sqlx::query!(
+        r#"
+            select
+                address as "address!",
+                state as "state: DirectBuyState",
+                tx_lt
+            from nft_direct_buy
+            where address = any($1::varchar[])
+        "#,
+        addresses as _,
+    )
+    .fetch_all(tx)
+    .await
+    .map(|rows| {
+        rows.into_iter()
+            .map(|r| (r.address, (r.state, r.tx_lt)))
+            .collect()
+    })
+    .map_err(|e| anyhow!(e))
+}
+
+/// Checks that every auction may move to `to` and reports the ones that may not.
+/// Returns a flag per input item telling whether the change should be applied.
+pub(super) async fn validate_auction_status(
+    tx: &mut Transaction<'_, Postgres>,
+    items: &[(&str, i64, serde_json::Value)],
+    to: AuctionStatus,
+) -> Result<Vec<bool>> {
+    let addresses = items.iter().map(|(a, _, _)| *a).collect::<Vec<_>>();
+
+    let mut current = sqlx::query!(
+        r#"
+            select
+                address as "address!",
+                status as "status: AuctionStatus",
+                tx_lt
+            from nft_auction
+            where address = any($1::varchar[])
+        "#,
+        addresses as _,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| anyhow!(e))?
+    .into_iter()
+    .map(|r| (r.address, (r.status, r.tx_lt)))
+    .collect::<HashMap<_, _>>();
+
+    let mut accepted = Vec::with_capacity(items.len());
+    let mut anomalies = Vec::new();
+
+    for (address, tx_lt, context) in items {
+        let Some((status, current_lt)) = current.get(*address) else {
+            accepted.push(true);
+            continue;
+        };
+
+        match check_transition(status, *current_lt, &to, *tx_lt) {
+            TransitionCheck::Valid => {
+                accepted.push(true);
+                current.insert(address.to_string(), (to.clone(), *tx_lt));
+            }
+            TransitionCheck::Stale => accepted.push(false),
+            TransitionCheck::Invalid => {
+                accepted.push(false);
+                anomalies.push(OfferStateAnomaly {
+                    address: address.to_string(),
+                    transition: OfferStateTransition::Auction(status.clone(), to.clone()),
+                    current_tx_lt: *current_lt,
+                    incoming_tx_lt: *tx_lt,
+                    context: context.clone(),
+                });
+            }
+        }
+    }
+
+    if !anomalies.is_empty() {
+        save_offer_state_anomalies(tx, &anomalies).await?;
+    }
+
+    Ok(accepted)
+}
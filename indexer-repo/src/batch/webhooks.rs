@@ -0,0 +1,105 @@
+use anyhow::{anyhow, Result};
+use sqlx::{Postgres, Transaction};
+
+/// Matches freshly saved events against registered webhooks and queues deliveries.
+/// Runs in the same transaction as the events, so a delivery exists only if they were committed.
+pub async fn enqueue_webhook_notifications(
+    tx: &mut Transaction<'_, Postgres>,
+    message_hashes: &[&str],
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+            with events as (
+                select *
+                from nft_events
+                where message_hash = any($1::text[])
+            ),
+            notifications as (
+                select
+                    e.id,
+                    e.message_hash,
+                    e.address,
+                    e.nft,
+                    e.collection,
+                    e.created_lt,
+                    e.created_at,
+                    e.args,
+                    'outbid'::webhook_notification_kind as kind,
+                    prev.buyer::text as recipient
+                from events e
+                join lateral (
+                    select b.buyer
+                    from nft_auction_bid b
+                    where b.auction = e.address
+                      and not b.declined
+                      and b.tx_lt < e.created_lt
+                    order by b.tx_lt desc
+                    limit 1
+                ) prev on prev.buyer <> e.args ->> 'buyer'
+                where e.computed_event_kind = 'auction_bid_placed'
+
+                union all
+
+                select
+                    e.id,
+                    e.message_hash,
+                    e.address,
+                    e.nft,
+                    e.collection,
+                    e.created_lt,
+                    e.created_at,
+                    e.args,
+                    'listing_filled'::webhook_notification_kind,
+                    e.args -> 'value2' ->> 'creator'
+                from events e
+                where e.computed_event_kind = 'sell_purchased'
+
+                union all
+
+                select
+                    e.id,
+                    e.message_hash,
+                    e.address,
+                    e.nft,
+                    e.collection,
+                    e.created_lt,
+                    e.created_at,
+                    e.args,
+                    'offer_received'::webhook_notification_kind,
+                    n.owner::text
+                from events e
+                join nft n on n.address = e.nft
+                where e.computed_event_kind = 'offer_active'
+            )
+            insert into webhook_deliveries (webhook_id, kind, dedup_key, payload)
+            select
+                w.id,
+                n.kind,
+                n.message_hash || ':' || n.kind || ':' || n.recipient,
+                jsonb_build_object(
+                    'kind', n.kind,
+                    'recipient', n.recipient,
+                    'event_id', n.id,
+                    'address', n.address,
+                    'nft', n.nft,
+                    'collection', n.collection,
+                    'created_lt', n.created_lt,
+                    'created_at', n.created_at,
+                    'args', n.args
+                )
+            from notifications n
+            join webhooks w on
+                (w.owner is null or w.owner = n.recipient)
+                and (w.nft is null or w.nft = n.nft)
+                and (w.collection is null or w.collection = n.collection)
+                and (w.kinds = '{}' or n.kind = any(w.kinds))
+            where n.recipient is not null
+            on conflict (webhook_id, dedup_key) do nothing
+        "#,
+        message_hashes as _,
+    )
+    .execute(tx)
+    .await
+    .map_err(|e| anyhow!(e))
+    .map(|_| ())
+}
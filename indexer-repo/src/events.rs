@@ -0,0 +1,372 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use sqlx::{PgExecutor, PgPool};
+
+use crate::types::{
+    decoded::EventRecord, DirectBuyState, DirectSellState, EventCategory, EventKind, EventType,
+};
+
+/// Event args that name an account taking part in the event.
+/// Kept in sync with the owner filter of `get_events_since`.
+const PARTICIPANT_ARGS: &[&[&str]] = &[
+    &["seller"],
+    &["buyer"],
+    &["creator"],
+    &["old_owner"],
+    &["new_owner"],
+    &["value0", "subject_owner"],
+    &["value2", "subject_owner"],
+    &["value2", "creator"],
+];
+
+/// A committed event pushed to live subscribers.
+#[derive(Clone, Debug, Serialize)]
+pub struct LiveEvent {
+    pub id: i64,
+    #[serde(flatten)]
+    pub event: EventRecord,
+    /// Offer state change made by the event, absent when it was rejected as an anomaly
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_change: Option<OfferStateChange>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "offer_type", rename_all = "snake_case")]
+pub enum OfferStateChange {
+    DirectSell {
+        from: DirectSellState,
+        to: DirectSellState,
+    },
+    DirectBuy {
+        from: DirectBuyState,
+        to: DirectBuyState,
+    },
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct EventFilter {
+    pub collection: Option<String>,
+    pub nft: Option<String>,
+    pub owner: Option<String>,
+    pub event_types: Vec<EventType>,
+}
+
+impl LiveEvent {
+    pub fn position(&self) -> EventCursor {
+        EventCursor {
+            created_lt: self.event.created_lt,
+            id: self.id,
+        }
+    }
+
+    pub fn matches(&self, filter: &EventFilter) -> bool {
+        let e = &self.event;
+
+        if !filter.event_types.is_empty() && !filter.event_types.contains(&e.event_type) {
+            return false;
+        }
+
+        if filter.nft.is_some() && filter.nft != e.nft {
+            return false;
+        }
+
+        if filter.collection.is_some() && filter.collection != e.collection {
+            return false;
+        }
+
+        match &filter.owner {
+            None => true,
+            Some(owner) => PARTICIPANT_ARGS.iter().any(|path| {
+                path.iter()
+                    .try_fold(&e.raw_data, |value, key| value.get(key))
+                    .and_then(|value| value.as_str())
+                    == Some(owner.as_str())
+            }),
+        }
+    }
+}
+
+/// Parameters of the `get_events_keyset` sql function.
+#[derive(Default, Clone, Debug)]
+pub struct EventsQuery {
+    pub owner: Option<String>,
+    pub nft: Option<String>,
+    pub collections: Vec<String>,
+    /// Empty means every kind
+    pub event_kinds: Vec<EventKind>,
+    pub verified: bool,
+    pub before: Option<EventCursor>,
+    pub limit: i64,
+    pub with_count: bool,
+}
+
+pub struct MarketEvent {
+    pub id: i64,
+    pub event_kind: EventKind,
+    pub event_type: EventType,
+    pub event_category: EventCategory,
+    pub address: String,
+    pub nft: Option<String>,
+    pub created_at: i64,
+    pub created_lt: i64,
+    pub args: serde_json::Value,
+    /// Zero unless the count was requested
+    pub total_rows: i64,
+}
+
+/// Position of an event in the `(created_lt, id)` order of the events.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EventCursor {
+    pub created_lt: i64,
+    pub id: i64,
+}
+
+impl From<&MarketEvent> for EventCursor {
+    fn from(event: &MarketEvent) -> Self {
+        Self {
+            created_lt: event.created_lt,
+            id: event.id,
+        }
+    }
+}
+
+impl std::fmt::Display for EventCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.created_lt, self.id)
+    }
+}
+
+impl std::str::FromStr for EventCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (created_lt, id) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Malformed cursor"))?;
+
+        Ok(Self {
+            created_lt: created_lt.parse()?,
+            id: id.parse()?,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct EventsModel {
+    pool: PgPool,
+}
+
+impl EventsModel {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Events after `after`, oldest first, with the accepted offer state changes
+    pub async fn get_events_since(
+        &self,
+        after: &EventCursor,
+        filter: &EventFilter,
+        limit: i64,
+    ) -> Result<Vec<LiveEvent>> {
+        get_events_since(&self.pool, after, filter, limit).await
+    }
+
+    /// Marketplace events through the `get_events_keyset` sql function, newest first.
+    pub async fn get_events(&self, query: &EventsQuery) -> Result<Vec<MarketEvent>> {
+        sqlx::query_as!(
+            MarketEvent,
+            r#"
+                select
+                    id as "id!",
+                    computed_event_kind as "event_kind!: EventKind",
+                    event_type as "event_type!: EventType",
+                    event_cat as "event_category!: EventCategory",
+                    event_address as "address!",
+                    nft::text,
+                    created_at as "created_at!",
+                    created_lt as "created_lt!",
+                    coalesce(args, 'null'::jsonb) as "args!",
+                    total_rows as "total_rows!"
+                from get_events_keyset(
+                    $1::varchar,
+                    $2::event_kind[],
+                    $3::varchar,
+                    $4::varchar[],
+                    $5::int,
+                    $6::bool,
+                    $7::bool,
+                    $8::bigint,
+                    $9::bigint
+                )
+            "#,
+            query.owner as _,
+            query.event_kinds as _,
+            query.nft as _,
+            query.collections as _,
+            query.limit as i32,
+            query.with_count,
+            query.verified,
+            query.before.as_ref().map(|c| c.created_lt),
+            query.before.as_ref().map(|c| c.id),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+}
+
+async fn get_events_since(
+    executor: impl PgExecutor<'_>,
+    after: &EventCursor,
+    filter: &EventFilter,
+    limit: i64,
+) -> Result<Vec<LiveEvent>> {
+    let rows = sqlx::query!(
+        r#"
+            select
+                e.id,
+                e.event_cat as "event_category: EventCategory",
+                e.event_type as "event_type: EventType",
+                e.address as "address!",
+                e.created_lt,
+                e.created_at,
+                coalesce(e.message_hash, '') as "message_hash!",
+                e.nft::text,
+                e.collection::text,
+                coalesce(e.args, 'null'::jsonb) as "raw_data!",
+                ds.state_from as "direct_sell_from?: DirectSellState",
+                ds.state_to as "direct_sell_to?: DirectSellState",
+                db.state_from as "direct_buy_from?: DirectBuyState",
+                db.state_to as "direct_buy_to?: DirectBuyState"
+            from nft_events e
+            left join nft_direct_sell_state_history ds
+                on e.event_type = 'direct_sell_state_changed'
+                and ds.address = e.address and ds.tx_lt = e.created_lt
+            left join nft_direct_buy_state_history db
+                on e.event_type = 'direct_buy_state_changed'
+                and db.address = e.address and db.tx_lt = e.created_lt
+            where (e.created_lt, e.id) > ($1, $2)
+              and ($3::varchar is null or e.collection = $3)
+              and ($4::varchar is null or e.nft = $4)
+              and (cardinality($5::event_type[]) = 0 or e.event_type = any($5))
+              and ($6::varchar is null or $6 in (
+                  e.args ->> 'seller',
+                  e.args ->> 'buyer',
+                  e.args ->> 'creator',
+                  e.args ->> 'old_owner',
+                  e.args ->> 'new_owner',
+                  e.args -> 'value0' ->> 'subject_owner',
+                  e.args -> 'value2' ->> 'subject_owner',
+                  e.args -> 'value2' ->> 'creator'
+              ))
+            order by e.created_lt, e.id
+            limit $7
+        "#,
+        after.created_lt,
+        after.id,
+        filter.collection as _,
+        filter.nft as _,
+        filter.event_types as _,
+        filter.owner as _,
+        limit,
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| anyhow!(e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            let state_change = match (
+                r.direct_sell_from,
+                r.direct_sell_to,
+                r.direct_buy_from,
+                r.direct_buy_to,
+            ) {
+                (Some(from), Some(to), _, _) => Some(OfferStateChange::DirectSell { from, to }),
+                (_, _, Some(from), Some(to)) => Some(OfferStateChange::DirectBuy { from, to }),
+                _ => None,
+            };
+
+            LiveEvent {
+                id: r.id,
+                event: EventRecord {
+                    event_category: r.event_category,
+                    event_type: r.event_type,
+                    address: r.address,
+                    created_lt: r.created_lt,
+                    created_at: r.created_at,
+                    message_hash: r.message_hash,
+                    nft: r.nft,
+                    collection: r.collection,
+                    raw_data: r.raw_data,
+                },
+                state_change,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    const LT: i64 = 8_000_000_000_000_000;
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn resumes_by_lt_and_id_with_accepted_state_changes() {
+        let mut tx = test_db::begin().await;
+
+        // Two events of one transaction share the lt, only the first state change was accepted
+        sqlx::query(
+            r#"
+                insert into nft_events (event_cat, event_type, address, created_lt, created_at, message_hash)
+                values
+                    ('direct_sell', 'direct_sell_state_changed', '0:ee1', $1, 1, 'test:ee1'),
+                    ('direct_sell', 'direct_sell_state_changed', '0:ee2', $1, 1, 'test:ee2')
+            "#,
+        )
+        .bind(LT)
+        .execute(&mut tx)
+        .await
+        .unwrap();
+
+        sqlx::query(
+            r#"
+                insert into nft_direct_sell_state_history (address, state_from, state_to, tx_lt, created_at)
+                values ('0:ee1', 'create', 'active', $1, to_timestamp(1))
+            "#,
+        )
+        .bind(LT)
+        .execute(&mut tx)
+        .await
+        .unwrap();
+
+        let start = EventCursor {
+            created_lt: LT - 1,
+            id: i64::MAX,
+        };
+        let events = get_events_since(&mut tx, &start, &EventFilter::default(), 10)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event.address, "0:ee1");
+        assert_eq!(
+            events[0].state_change,
+            Some(OfferStateChange::DirectSell {
+                from: DirectSellState::Create,
+                to: DirectSellState::Active,
+            })
+        );
+        assert_eq!(events[1].state_change, None);
+
+        // Resuming after the first event of the lt doesn't skip the second
+        let events = get_events_since(&mut tx, &events[0].position(), &EventFilter::default(), 10)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event.address, "0:ee2");
+    }
+}
@@ -0,0 +1,510 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::batch::save_raw_event;
+use crate::events::LiveEvent;
+use crate::types::{
+    decoded::EventRecord, AuctionStatus, DirectBuyState, DirectSellState, EventCategory, EventType,
+};
+
+#[derive(Clone)]
+pub struct OfferExpiryModel {
+    pool: PgPool,
+}
+
+/// Latest indexed event, the clock offers are expired by.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainPosition {
+    pub time: NaiveDateTime,
+    pub lt: i64,
+}
+
+#[derive(Default, Debug)]
+pub struct ExpiredOffers {
+    pub auctions: usize,
+    pub direct_sells: usize,
+    pub direct_buys: usize,
+    /// Events of the offers expired by this call, for live subscribers
+    pub events: Vec<LiveEvent>,
+}
+
+impl ExpiredOffers {
+    pub fn total(&self) -> usize {
+        self.auctions + self.direct_sells + self.direct_buys
+    }
+}
+
+impl OfferExpiryModel {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Time and lt of the latest indexed event. Offers are expired relative to
+    /// it rather than wall clock, so a lagging indexer never expires an offer
+    /// before it has seen the transactions that could have closed it.
+    pub async fn get_chain_position(&self) -> Result<Option<ChainPosition>> {
+        sqlx::query!(
+            r#"
+                select max(created_at) as time, max(created_lt) as lt
+                from nft_events
+            "#
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map(|r| {
+            Some(ChainPosition {
+                time: NaiveDateTime::from_timestamp_opt(r.time?, 0)?,
+                lt: r.lt?,
+            })
+        })
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn expire_offers(
+        &self,
+        position: &ChainPosition,
+        limit: i64,
+    ) -> Result<ExpiredOffers> {
+        let mut tx = self.pool.begin().await?;
+        let expired = expire_offers(&mut tx, position, limit).await?;
+        tx.commit().await?;
+
+        Ok(expired)
+    }
+}
+
+async fn expire_offers(
+    tx: &mut Transaction<'_, Postgres>,
+    position: &ChainPosition,
+    limit: i64,
+) -> Result<ExpiredOffers> {
+    let auctions = expire_auctions(tx, position, limit).await?;
+    let direct_sells = expire_direct_sells(tx, position, limit).await?;
+    let direct_buys = expire_direct_buys(tx, position, limit).await?;
+
+    let mut expired = ExpiredOffers {
+        auctions: auctions.len(),
+        direct_sells: direct_sells.len(),
+        direct_buys: direct_buys.len(),
+        events: Vec::new(),
+    };
+
+    let events = [auctions, direct_sells, direct_buys].concat();
+    if !events.is_empty() {
+        let mut ids = save_raw_event(tx, &events).await?;
+
+        // Events of offers expired before are already stored and were streamed then
+        expired.events = events
+            .into_iter()
+            .filter_map(|event| {
+                Some(LiveEvent {
+                    id: ids.remove(&event.message_hash)?,
+                    event,
+                    state_change: None,
+                })
+            })
+            .collect();
+        expired.events.sort_by_key(LiveEvent::position);
+    }
+
+    Ok(expired)
+}
+
+// Synthetic events have no message, so the hash is derived from the offer address.
+// Re-expiring an offer (e.g. after replaying an older state change) doesn't duplicate it.
+// They take the lt of the latest indexed event rather than the offer's own, so they
+// land after every position an events cursor or a stream has already passed.
+fn synthetic_message_hash(address: &str) -> String {
+    format!("expired:{address}")
+}
+
+async fn expire_auctions(
+    tx: &mut Transaction<'_, Postgres>,
+    position: &ChainPosition,
+    limit: i64,
+) -> Result<Vec<EventRecord>> {
+    struct Row {
+        address: String,
+        nft: String,
+        collection: String,
+        nft_owner: String,
+        finished_at: NaiveDateTime,
+    }
+
+    // Auctions with bids are waiting for settlement and will be completed on-chain
+    let rows = sqlx::query_as!(
+        Row,
+        r#"
+            update nft_auction set
+                status = $1
+            where address in (
+                select address
+                from nft_auction
+                where status = $2
+                  and max_bid is null
+                  and finished_at < $3
+                limit $4
+            )
+            returning
+                address as "address!",
+                nft as "nft!",
+                collection as "collection!",
+                nft_owner as "nft_owner!",
+                finished_at as "finished_at!"
+        "#,
+        AuctionStatus::Expired as _,
+        AuctionStatus::Active as _,
+        position.time,
+        limit,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| anyhow!(e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| EventRecord {
+            event_category: EventCategory::Auction,
+            event_type: EventType::AuctionExpired,
+
+            message_hash: synthetic_message_hash(&r.address),
+            created_lt: position.lt,
+            created_at: r.finished_at.timestamp(),
+            nft: Some(r.nft),
+            collection: Some(r.collection),
+            raw_data: json!({
+                "seller": r.nft_owner,
+                "finished_at": r.finished_at.timestamp(),
+            }),
+            address: r.address,
+        })
+        .collect())
+}
+
+async fn expire_direct_sells(
+    tx: &mut Transaction<'_, Postgres>,
+    position: &ChainPosition,
+    limit: i64,
+) -> Result<Vec<EventRecord>> {
+    struct Row {
+        address: String,
+        nft: String,
+        collection: Option<String>,
+        seller: String,
+        expired_at: NaiveDateTime,
+    }
+
+    // Zero expiration time means the listing never expires
+    // The history gets the transition with the lt and time of the synthetic event
+    let rows = sqlx::query_as!(
+        Row,
+        r#"
+            with expired as (
+                update nft_direct_sell set
+                    state = $1,
+                    updated = expired_at
+                where address in (
+                    select address
+                    from nft_direct_sell
+                    where state = $2
+                      and expired_at > to_timestamp(0)
+                      and expired_at < $3
+                    limit $4
+                )
+                returning address, nft, collection, seller, expired_at
+            ), history as (
+                insert into nft_direct_sell_state_history (address, state_from, state_to, tx_lt, created_at)
+                select address, $2, $1, $5, expired_at
+                from expired
+                on conflict do nothing
+            )
+            select
+                address as "address!",
+                nft as "nft!",
+                collection,
+                seller as "seller!",
+                expired_at as "expired_at!"
+            from expired
+        "#,
+        DirectSellState::Expired as _,
+        DirectSellState::Active as _,
+        position.time,
+        limit,
+        position.lt,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| anyhow!(e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| EventRecord {
+            event_category: EventCategory::DirectSell,
+            event_type: EventType::DirectSellExpired,
+
+            message_hash: synthetic_message_hash(&r.address),
+            created_lt: position.lt,
+            created_at: r.expired_at.timestamp(),
+            nft: Some(r.nft),
+            collection: r.collection,
+            raw_data: json!({
+                "from": DirectSellState::Active as u8,
+                "to": DirectSellState::Expired as u8,
+                "seller": r.seller,
+                "expired_at": r.expired_at.timestamp(),
+            }),
+            address: r.address,
+        })
+        .collect())
+}
+
+async fn expire_direct_buys(
+    tx: &mut Transaction<'_, Postgres>,
+    position: &ChainPosition,
+    limit: i64,
+) -> Result<Vec<EventRecord>> {
+    struct Row {
+        address: String,
+        nft: String,
+        collection: Option<String>,
+        buyer: String,
+        expired_at: NaiveDateTime,
+    }
+
+    // Zero expiration time means the offer never expires
+    // The history gets the transition with the lt and time of the synthetic event
+    let rows = sqlx::query_as!(
+        Row,
+        r#"
+            with expired as (
+                update nft_direct_buy set
+                    state = $1,
+                    updated = expired_at
+                where address in (
+                    select address
+                    from nft_direct_buy
+                    where state = $2
+                      and expired_at > to_timestamp(0)
+                      and expired_at < $3
+                    limit $4
+                )
+                returning address, nft, collection, buyer, expired_at
+            ), history as (
+                insert into nft_direct_buy_state_history (address, state_from, state_to, tx_lt, created_at)
+                select address, $2, $1, $5, expired_at
+                from expired
+                on conflict do nothing
+            )
+            select
+                address as "address!",
+                nft as "nft!",
+                collection,
+                buyer as "buyer!",
+                expired_at as "expired_at!"
+            from expired
+        "#,
+        DirectBuyState::Expired as _,
+        DirectBuyState::Active as _,
+        position.time,
+        limit,
+        position.lt,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| anyhow!(e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| EventRecord {
+            event_category: EventCategory::DirectBuy,
+            event_type: EventType::DirectBuyExpired,
+
+            message_hash: synthetic_message_hash(&r.address),
+            created_lt: position.lt,
+            created_at: r.expired_at.timestamp(),
+            nft: Some(r.nft),
+            collection: r.collection,
+            raw_data: json!({
+                "from": DirectBuyState::Active as u8,
+                "to": DirectBuyState::Expired as u8,
+                "buyer": r.buyer,
+                "expired_at": r.expired_at.timestamp(),
+            }),
+            address: r.address,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    const LATEST_LT: i64 = 9_000_000_000_000_000;
+    // Early enough that offers already in the database don't expire along
+    const NOW: i64 = 7200;
+    const PAST: i64 = NOW - 3600;
+
+    async fn seed(tx: &mut Transaction<'_, Postgres>) {
+        let past = NaiveDateTime::from_timestamp_opt(PAST, 0).unwrap();
+
+        sqlx::query(
+            r#"
+                insert into nft_events (event_cat, event_type, address, created_lt, created_at, message_hash)
+                values ('auction', 'auction_active', '0:e0', $1, $2, 'test:latest')
+            "#,
+        )
+        .bind(LATEST_LT)
+        .bind(NOW)
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+        sqlx::query(
+            r#"
+                insert into nft_auction (address, nft, status, finished_at, max_bid, tx_lt, root, collection, nft_owner)
+                values
+                    ('0:e1', '0:f1', 'active', $1, null, 1, '0:r', '0:c', '0:o'),
+                    ('0:e2', '0:f2', 'active', $1, 10, 1, '0:r', '0:c', '0:o')
+            "#,
+        )
+        .bind(past)
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+        let offers = [
+            ("nft_direct_sell", "seller", "0:e3", "0:e4"),
+            ("nft_direct_buy", "buyer", "0:e5", "0:e6"),
+        ];
+
+        // The second offer of each kind never expires
+        for (table, owner, expiring, endless) in offers {
+            sqlx::query(&format!(
+                r#"
+                    insert into {table} (address, nft, price_token, price, {owner}, expired_at, state, created, updated, tx_lt, root)
+                    values
+                        ($1, '0:f3', '0:t', 1, '0:o', $3, 'active', $3, $3, 1, '0:r'),
+                        ($2, '0:f4', '0:t', 1, '0:o', to_timestamp(0), 'active', $3, $3, 1, '0:r')
+                "#
+            ))
+            .bind(expiring)
+            .bind(endless)
+            .bind(past)
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn expires_offers_after_the_latest_event() {
+        let mut tx = test_db::begin().await;
+        seed(&mut tx).await;
+
+        let position = ChainPosition {
+            time: NaiveDateTime::from_timestamp_opt(NOW, 0).unwrap(),
+            lt: LATEST_LT,
+        };
+
+        let expired = expire_offers(&mut tx, &position, 100).await.unwrap();
+        assert_eq!(
+            (expired.auctions, expired.direct_sells, expired.direct_buys),
+            (1, 1, 1)
+        );
+        assert_eq!(expired.events.len(), 3);
+        assert!(expired
+            .events
+            .windows(2)
+            .all(|w| w[0].position() < w[1].position()));
+
+        let events: Vec<(String, i64, bool)> = sqlx::query_as(
+            r#"
+                select address::text, created_lt, id > (select id from nft_events where message_hash = 'test:latest')
+                from nft_events
+                where message_hash like 'expired:0:e_'
+                order by address
+            "#,
+        )
+        .fetch_all(&mut tx)
+        .await
+        .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                ("0:e1".to_string(), LATEST_LT, true),
+                ("0:e3".to_string(), LATEST_LT, true),
+                ("0:e5".to_string(), LATEST_LT, true),
+            ]
+        );
+
+        // Auctions with bids and offers without expiration stay active
+        let active: Vec<(String,)> = sqlx::query_as(
+            r#"
+                select address::text from nft_auction where address in ('0:e1', '0:e2') and status = 'active'
+                union all
+                select address::text from nft_direct_sell where address in ('0:e3', '0:e4') and state = 'active'
+                union all
+                select address::text from nft_direct_buy where address in ('0:e5', '0:e6') and state = 'active'
+            "#,
+        )
+        .fetch_all(&mut tx)
+        .await
+        .unwrap();
+        assert_eq!(
+            active,
+            vec![
+                ("0:e2".to_string(),),
+                ("0:e4".to_string(),),
+                ("0:e6".to_string(),)
+            ]
+        );
+
+        // The history of the swept offers moves from active to expired with the synthetic event
+        let history: Vec<(String, String, String, i64, i64)> = sqlx::query_as(
+            r#"
+                select address::text, state_from::text, state_to::text, tx_lt, extract(epoch from created_at)::bigint
+                from nft_direct_sell_state_history
+                where address in ('0:e3', '0:e4')
+                union all
+                select address::text, state_from::text, state_to::text, tx_lt, extract(epoch from created_at)::bigint
+                from nft_direct_buy_state_history
+                where address in ('0:e5', '0:e6')
+            "#,
+        )
+        .fetch_all(&mut tx)
+        .await
+        .unwrap();
+        let transition = |address: &str| {
+            (
+                address.to_string(),
+                "active".to_string(),
+                "expired".to_string(),
+                LATEST_LT,
+                PAST,
+            )
+        };
+        assert_eq!(history, vec![transition("0:e3"), transition("0:e5")]);
+        let synthetic: Vec<(String, i64)> = sqlx::query_as(
+            r#"
+                select address::text, created_at
+                from nft_events
+                where message_hash in ('expired:0:e3', 'expired:0:e5')
+                order by address
+            "#,
+        )
+        .fetch_all(&mut tx)
+        .await
+        .unwrap();
+        assert_eq!(
+            synthetic,
+            vec![("0:e3".to_string(), PAST), ("0:e5".to_string(), PAST)]
+        );
+
+        let again = expire_offers(&mut tx, &position, 100).await.unwrap();
+        assert_eq!(again.total(), 0);
+    }
+}
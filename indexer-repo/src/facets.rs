@@ -0,0 +1,299 @@
+use anyhow::{anyhow, Result};
+use sqlx::{types::BigDecimal, PgExecutor, PgPool};
+
+use crate::nft::{get_nfts_by_addresses, NftDetails};
+
+#[derive(Clone)]
+pub struct FacetedSearchModel {
+    pool: PgPool,
+}
+
+/// Nfts having any of `values` of the trait.
+pub struct TraitFilter {
+    pub trait_type: String,
+    pub values: Vec<String>,
+}
+
+/// Nfts with a numeric value of the trait within the bounds, inclusive.
+pub struct TraitRange {
+    pub trait_type: String,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ListingState {
+    /// On direct sale or auction
+    Listed,
+    BuyNow,
+    Auction,
+    Unlisted,
+}
+
+impl ListingState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ListingState::Listed => "listed",
+            ListingState::BuyNow => "buy_now",
+            ListingState::Auction => "auction",
+            ListingState::Unlisted => "unlisted",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct NftSearchFilter {
+    pub owner: Option<String>,
+    pub traits: Vec<TraitFilter>,
+    pub ranges: Vec<TraitRange>,
+    pub min_price_usd: Option<BigDecimal>,
+    pub max_price_usd: Option<BigDecimal>,
+    pub listing: Option<ListingState>,
+}
+
+/// Trait filters flattened into the parallel arrays `nft_search` takes.
+struct TraitArgs {
+    types: Vec<String>,
+    values: Vec<String>,
+    range_types: Vec<String>,
+    mins: Vec<Option<f64>>,
+    maxes: Vec<Option<f64>>,
+}
+
+impl From<&NftSearchFilter> for TraitArgs {
+    fn from(filter: &NftSearchFilter) -> Self {
+        let (types, values) = filter
+            .traits
+            .iter()
+            .flat_map(|t| t.values.iter().map(|v| (t.trait_type.clone(), v.clone())))
+            .unzip();
+
+        Self {
+            types,
+            values,
+            range_types: filter.ranges.iter().map(|r| r.trait_type.clone()).collect(),
+            mins: filter.ranges.iter().map(|r| r.min).collect(),
+            maxes: filter.ranges.iter().map(|r| r.max).collect(),
+        }
+    }
+}
+
+/// Count of nfts with a trait value. Counts of a trait ignore the filter on
+/// that trait itself, so they tell how the result grows when a value is added.
+pub struct TraitFacet {
+    pub trait_type: String,
+    pub value: String,
+    pub nft_count: i64,
+    pub numeric: bool,
+}
+
+impl FacetedSearchModel {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Not burned nfts of the collection matching the filter, ordered by address.
+    pub async fn search_nfts(
+        &self,
+        collection: &str,
+        filter: &NftSearchFilter,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<NftDetails>> {
+        let addresses = search_nfts(&self.pool, collection, filter, after, limit).await?;
+
+        let mut nfts = get_nfts_by_addresses(&self.pool, &addresses).await?;
+        nfts.sort_by(|a, b| a.address.cmp(&b.address));
+
+        Ok(nfts)
+    }
+
+    /// Facets of the nfts `search_nfts` returns, most common values first.
+    pub async fn get_trait_facets(
+        &self,
+        collection: &str,
+        filter: &NftSearchFilter,
+    ) -> Result<Vec<TraitFacet>> {
+        get_trait_facets(&self.pool, collection, filter).await
+    }
+}
+
+async fn search_nfts(
+    executor: impl PgExecutor<'_>,
+    collection: &str,
+    filter: &NftSearchFilter,
+    after: Option<&str>,
+    limit: i64,
+) -> Result<Vec<String>> {
+    let traits = TraitArgs::from(filter);
+
+    sqlx::query_scalar!(
+        r#"
+            select nft as "nft!"
+            from nft_search($1, $2, $3, $4, $5, $6, 0, $7, $8, $9, $10)
+            where ($11::varchar is null or nft > $11)
+            order by nft
+            limit $12
+        "#,
+        collection,
+        &traits.types as _,
+        &traits.values,
+        &traits.range_types as _,
+        &traits.mins as _,
+        &traits.maxes as _,
+        filter.owner,
+        filter.min_price_usd,
+        filter.max_price_usd,
+        filter.listing.map(|l| l.as_str()),
+        after,
+        limit
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+}
+
+async fn get_trait_facets(
+    executor: impl PgExecutor<'_>,
+    collection: &str,
+    filter: &NftSearchFilter,
+) -> Result<Vec<TraitFacet>> {
+    let traits = TraitArgs::from(filter);
+
+    sqlx::query_as!(
+        TraitFacet,
+        r#"
+            select
+                a.trait_type as "trait_type!",
+                a.value #>> '{}' as "value!",
+                count(distinct a.nft) as "nft_count!",
+                (a.value #>> '{}') ~ '^-?[0-9]+(\.[0-9]+)?$' as "numeric!"
+            from nft_search($1, $2, $3, $4, $5, $6, 1, $7, $8, $9, $10) c
+                join nft_attributes a on a.nft = c.nft
+            where a.value #>> '{}' is not null
+              and (cardinality(c.missed) = 0 or c.missed = array[a.trait_type])
+            group by a.trait_type, a.value #>> '{}'
+            order by a.trait_type, count(distinct a.nft) desc, a.value #>> '{}'
+        "#,
+        collection,
+        &traits.types as _,
+        &traits.values,
+        &traits.range_types as _,
+        &traits.mins as _,
+        &traits.maxes as _,
+        filter.owner,
+        filter.min_price_usd,
+        filter.max_price_usd,
+        filter.listing.map(|l| l.as_str())
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{Postgres, Transaction};
+
+    use super::*;
+    use crate::test_db;
+
+    const COLLECTION: &str = "0:c37";
+
+    fn values(trait_type: &str, values: &[&str]) -> TraitFilter {
+        TraitFilter {
+            trait_type: trait_type.to_string(),
+            values: values.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    fn range(trait_type: &str, min: Option<f64>, max: Option<f64>) -> TraitRange {
+        TraitRange {
+            trait_type: trait_type.to_string(),
+            min,
+            max,
+        }
+    }
+
+    async fn search(tx: &mut Transaction<'_, Postgres>, filter: NftSearchFilter) -> Vec<String> {
+        search_nfts(&mut *tx, COLLECTION, &filter, None, 10)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn trait_filters_and_facets() {
+        let mut tx = test_db::begin().await;
+
+        sqlx::query(
+            r#"
+                insert into nft (address, collection, owner, manager, burned, updated, owner_update_lt, manager_update_lt, id)
+                select a, $1, '0:o37', '0:o37', false, now(), 1, 1, 1
+                from unnest(array['0:n37a', '0:n37b', '0:n37c', '0:n37d']) a
+            "#,
+        )
+        .bind(COLLECTION)
+        .execute(&mut tx)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"
+                insert into nft_attributes (nft, collection, raw, trait_type, value)
+                select nft, $1, '{}', trait_type, value::jsonb
+                from (values
+                    ('0:n37a', 'Level', '5'), ('0:n37a', 'Color', '"red"'),
+                    ('0:n37b', 'Level', '12'), ('0:n37b', 'Color', '"red"'),
+                    ('0:n37c', 'Level', '5'), ('0:n37c', 'Color', '"blue"'),
+                    ('0:n37d', 'Color', '"red"')
+                ) v (nft, trait_type, value)
+            "#,
+        )
+        .bind(COLLECTION)
+        .execute(&mut tx)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            search(&mut tx, NftSearchFilter::default()).await,
+            ["0:n37a", "0:n37b", "0:n37c", "0:n37d"]
+        );
+
+        // Values and a range of the same trait must both match
+        let filter = NftSearchFilter {
+            traits: vec![values("Level", &["5", "12"])],
+            ranges: vec![range("Level", Some(10.0), None)],
+            ..Default::default()
+        };
+        assert_eq!(search(&mut tx, filter).await, ["0:n37b"]);
+
+        let filter = NftSearchFilter {
+            traits: vec![values("Color", &["red"])],
+            ranges: vec![range("Level", None, Some(6.0))],
+            ..Default::default()
+        };
+        assert_eq!(search(&mut tx, filter).await, ["0:n37a"]);
+
+        let filter = NftSearchFilter {
+            traits: vec![values("Color", &["red"])],
+            ..Default::default()
+        };
+        let facets = get_trait_facets(&mut tx, COLLECTION, &filter)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|f| (f.trait_type, f.value, f.nft_count, f.numeric))
+            .collect::<Vec<_>>();
+        let facet =
+            |t: &str, v: &str, n: i64, numeric: bool| (t.to_string(), v.to_string(), n, numeric);
+        assert_eq!(
+            facets,
+            [
+                facet("Color", "red", 3, false),
+                facet("Color", "blue", 1, false),
+                facet("Level", "12", 1, true),
+                facet("Level", "5", 1, true),
+            ]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use sqlx::{types::BigDecimal, PgExecutor, PgPool};
+
+#[derive(Clone)]
+pub struct FiatRateModel {
+    pool: PgPool,
+}
+
+/// Units of the currency per one usd.
+pub struct FiatRate {
+    pub currency: String,
+    pub ts: NaiveDateTime,
+    pub usd_rate: BigDecimal,
+}
+
+impl FiatRateModel {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Rates of the hour `ts` are replaced.
+    pub async fn save_rates(
+        &self,
+        provider: &str,
+        ts: NaiveDateTime,
+        rates: &[(String, BigDecimal)],
+    ) -> Result<()> {
+        save_rates(&self.pool, provider, ts, rates).await
+    }
+
+    /// The latest rate of every currency.
+    pub async fn get_latest_rates(&self) -> Result<Vec<FiatRate>> {
+        sqlx::query_as!(
+            FiatRate,
+            r#"
+                select distinct on (currency) currency, ts, usd_rate
+                from fiat_rates
+                order by currency, ts desc
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    /// The rate in effect at every time of `times`, in the same order. Times
+    /// before the first known rate get none.
+    pub async fn get_rates_at(
+        &self,
+        currency: &str,
+        times: &[NaiveDateTime],
+    ) -> Result<Vec<Option<BigDecimal>>> {
+        get_rates_at(&self.pool, currency, times).await
+    }
+}
+
+async fn save_rates(
+    executor: impl PgExecutor<'_>,
+    provider: &str,
+    ts: NaiveDateTime,
+    rates: &[(String, BigDecimal)],
+) -> Result<()> {
+    let currencies = rates.iter().map(|(c, _)| c.clone()).collect::<Vec<_>>();
+    let usd_rates = rates.iter().map(|(_, r)| r.clone()).collect::<Vec<_>>();
+
+    sqlx::query!(
+        r#"
+            insert into fiat_rates (currency, ts, usd_rate, provider)
+            select r.currency, $1, r.usd_rate, $2
+            from unnest($3::varchar[], $4::numeric[]) as r(currency, usd_rate)
+            on conflict (currency, ts) do update set
+                usd_rate = excluded.usd_rate,
+                provider = excluded.provider
+        "#,
+        ts,
+        provider,
+        &currencies,
+        &usd_rates
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+    .map(|_| ())
+}
+
+async fn get_rates_at(
+    executor: impl PgExecutor<'_>,
+    currency: &str,
+    times: &[NaiveDateTime],
+) -> Result<Vec<Option<BigDecimal>>> {
+    sqlx::query_scalar!(
+        r#"
+            select r.usd_rate
+            from unnest($2::timestamp[]) with ordinality as t(ts, n)
+            left join lateral (
+                select usd_rate
+                from fiat_rates
+                where currency = $1 and ts <= t.ts
+                order by ts desc
+                limit 1
+            ) r on true
+            order by t.n
+        "#,
+        currency,
+        times
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    fn at(ts: i64) -> NaiveDateTime {
+        NaiveDateTime::from_timestamp_opt(ts, 0).unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn rates_at_follow_the_order_of_times() {
+        let mut tx = test_db::begin().await;
+
+        for (ts, rate) in [(3600, 2), (7200, 3)] {
+            save_rates(
+                &mut tx,
+                "test",
+                at(ts),
+                &[("XTS".to_string(), BigDecimal::from(rate))],
+            )
+            .await
+            .unwrap();
+        }
+
+        let rates = get_rates_at(&mut tx, "XTS", &[at(9000), at(0), at(3600), at(5000)])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            rates,
+            vec![
+                Some(BigDecimal::from(3)),
+                None,
+                Some(BigDecimal::from(2)),
+                Some(BigDecimal::from(2)),
+            ]
+        );
+    }
+}
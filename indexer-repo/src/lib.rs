@@ -1,6 +1,22 @@
 pub mod batch;
 pub mod collection;
+pub mod events;
+pub mod expiry;
+pub mod facets;
+pub mod fiat;
+pub mod media;
 pub mod meta;
+pub mod nft;
+pub mod offers;
 pub mod price;
+pub mod rarity;
+pub mod search;
+pub mod state_machine;
+pub mod stats;
+pub mod tokens;
 pub mod types;
 pub mod utils;
+pub mod webhooks;
+
+#[cfg(test)]
+mod test_db;
@@ -0,0 +1,249 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use sqlx::{PgExecutor, PgPool};
+
+use crate::types::{MediaKind, MediaStatus};
+
+#[derive(Clone)]
+pub struct MediaModel {
+    pool: PgPool,
+}
+
+/// Media uri of an nft or collection that has no up to date copy.
+pub struct MediaSource {
+    pub address: String,
+    pub kind: MediaKind,
+    pub source: String,
+    /// Failed attempts of the same source
+    pub attempts: i32,
+}
+
+pub struct Media {
+    pub address: String,
+    pub kind: MediaKind,
+    pub source: String,
+    pub status: MediaStatus,
+    pub mime_type: Option<String>,
+    pub size_bytes: Option<i64>,
+    pub content_hash: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub url: Option<String>,
+    pub thumbnail_url: Option<String>,
+    pub error: Option<String>,
+    pub attempts: i32,
+    pub next_attempt_at: Option<NaiveDateTime>,
+    pub updated: NaiveDateTime,
+}
+
+impl MediaModel {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Nft previews, collection logos and wallpapers never processed,
+    /// changed since or failed and due for a retry, the longest waiting first.
+    pub async fn get_media_for_update(&self, limit: i64) -> Result<Vec<MediaSource>> {
+        get_media_for_update(&self.pool, limit).await
+    }
+
+    pub async fn save_media(&self, media: &Media) -> Result<()> {
+        save_media(&self.pool, media).await
+    }
+
+    /// Media of an nft or a collection
+    pub async fn get_media(&self, address: &str) -> Result<Vec<Media>> {
+        sqlx::query_as!(
+            Media,
+            r#"
+                select
+                    address as "address!",
+                    kind as "kind: MediaKind",
+                    source as "source!",
+                    status as "status: MediaStatus",
+                    mime_type,
+                    size_bytes,
+                    content_hash,
+                    width,
+                    height,
+                    url,
+                    thumbnail_url,
+                    error,
+                    attempts,
+                    next_attempt_at,
+                    updated
+                from media
+                where address = $1
+                order by kind
+            "#,
+            address as _
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+}
+
+async fn get_media_for_update(
+    executor: impl PgExecutor<'_>,
+    limit: i64,
+) -> Result<Vec<MediaSource>> {
+    sqlx::query_as!(
+        MediaSource,
+        r#"
+            with sources as (
+                select nft as address, 'nft_preview'::media_kind as kind, meta -> 'preview' ->> 'source' as source
+                from nft_metadata
+                union all
+                select address, 'collection_logo'::media_kind, logo
+                from nft_collection
+                union all
+                select address, 'collection_wallpaper'::media_kind, wallpaper
+                from nft_collection
+            )
+            select
+                s.address as "address!",
+                s.kind as "kind!: MediaKind",
+                s.source as "source!",
+                case when m.source = s.source then m.attempts else 0 end as "attempts!"
+            from sources s
+            left join media m on m.address = s.address and m.kind = s.kind
+            where coalesce(s.source, '') <> ''
+              and (m.address is null
+                or m.source <> s.source
+                or (m.status = 'failed' and m.next_attempt_at <= now()))
+            order by m.updated nulls first, s.address, s.kind
+            limit $1
+        "#,
+        limit
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+}
+
+async fn save_media(executor: impl PgExecutor<'_>, media: &Media) -> Result<()> {
+    sqlx::query!(
+        r#"
+            insert into media (
+                address, kind, source, status, mime_type, size_bytes, content_hash,
+                width, height, url, thumbnail_url, error, attempts, next_attempt_at, updated
+            )
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+            on conflict (address, kind) do update set
+                source = excluded.source,
+                status = excluded.status,
+                mime_type = excluded.mime_type,
+                size_bytes = excluded.size_bytes,
+                content_hash = excluded.content_hash,
+                width = excluded.width,
+                height = excluded.height,
+                url = excluded.url,
+                thumbnail_url = excluded.thumbnail_url,
+                error = excluded.error,
+                attempts = excluded.attempts,
+                next_attempt_at = excluded.next_attempt_at,
+                updated = excluded.updated
+        "#,
+        media.address as _,
+        media.kind as _,
+        media.source as _,
+        media.status as _,
+        media.mime_type,
+        media.size_bytes,
+        media.content_hash,
+        media.width,
+        media.height,
+        media.url as _,
+        media.thumbnail_url as _,
+        media.error,
+        media.attempts,
+        media.next_attempt_at,
+        media.updated
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+    .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::test_db;
+
+    fn failed(source: &MediaSource, source_uri: &str) -> Media {
+        let now = chrono::Utc::now().naive_utc();
+
+        Media {
+            address: source.address.clone(),
+            kind: source.kind,
+            source: source_uri.to_string(),
+            status: MediaStatus::Failed,
+            mime_type: None,
+            size_bytes: None,
+            content_hash: None,
+            width: None,
+            height: None,
+            url: None,
+            thumbnail_url: None,
+            error: Some("timeout".to_string()),
+            attempts: 1,
+            next_attempt_at: Some(now + Duration::hours(1)),
+            updated: now,
+        }
+    }
+
+    async fn pending(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Vec<MediaSource> {
+        get_media_for_update(&mut *tx, 10_000)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|m| m.address.starts_with("0:media"))
+            .collect()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn failed_media_waits_for_its_retry() {
+        let mut tx = test_db::begin().await;
+
+        let long_uri = format!("https://example.com/{}", "a".repeat(5000));
+        sqlx::query(
+            "insert into nft_collection (address, created, updated, first_mint, logo)
+             values ('0:mediab', now(), now(), now(), 'https://example.com/b.png'),
+                    ('0:mediaa', now(), now(), now(), 'https://example.com/a.png')",
+        )
+        .execute(&mut tx)
+        .await
+        .unwrap();
+
+        let sources = pending(&mut tx).await;
+        assert_eq!(
+            sources
+                .iter()
+                .map(|m| m.address.as_str())
+                .collect::<Vec<_>>(),
+            vec!["0:mediaa", "0:mediab"]
+        );
+
+        save_media(&mut tx, &failed(&sources[0], &sources[0].source))
+            .await
+            .unwrap();
+        // Sources of any length are stored, a source changed since is picked again
+        save_media(&mut tx, &failed(&sources[1], &long_uri))
+            .await
+            .unwrap();
+
+        let sources = pending(&mut tx).await;
+        assert_eq!(
+            sources
+                .iter()
+                .map(|m| m.address.as_str())
+                .collect::<Vec<_>>(),
+            vec!["0:mediab"]
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use anyhow::{anyhow, Result};
 use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
 use sqlx::{PgPool, Postgres, Transaction};
 
 use crate::types::NftCollectionMeta;
@@ -22,6 +24,52 @@ pub struct NftMeta<'a> {
     pub updated: NaiveDateTime,
 }
 
+/// Tip-4.2 fields of a metadata json along with the ways it breaks the standard.
+/// Fields are extracted even if the json is not valid.
+#[derive(Debug, Default, PartialEq)]
+pub struct NormalizedNftMeta {
+    pub meta_type: Option<String>,
+    pub preview_url: Option<String>,
+    pub preview_mimetype: Option<String>,
+    pub external_url: Option<String>,
+    pub files: Vec<NftMetaFile>,
+    pub violations: Vec<MetaViolation>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct NftMetaFile {
+    pub source: String,
+    pub mimetype: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetaViolation {
+    /// Json path of the field, e.g. `files[0].mimetype`
+    pub path: String,
+    pub message: String,
+}
+
+/// Normalized metadata of an nft as stored
+pub struct NormalizedNftMetaRow {
+    pub nft: String,
+    pub meta_type: Option<String>,
+    pub preview_url: Option<String>,
+    pub preview_mimetype: Option<String>,
+    pub external_url: Option<String>,
+    pub files: Vec<NftMetaFile>,
+    pub violations: Vec<MetaViolation>,
+    pub normalized: NaiveDateTime,
+}
+
+/// Metadata json of an nft as it was at some point
+pub struct NftMetaVersion {
+    pub version: i32,
+    pub meta: serde_json::Value,
+    /// Sha-256 of the json with sorted keys, hex
+    pub content_hash: String,
+    pub created: NaiveDateTime,
+}
+
 pub struct NftMetaAttribute<'a> {
     pub nft: &'a str,
     pub collection: &'a str,
@@ -141,6 +189,112 @@ impl MetadataModelService {
         .map_err(|e| anyhow!(e))
     }
 
+    /// Metadata stored before it was normalized
+    pub async fn get_meta_for_normalization(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<(String, serde_json::Value)>> {
+        sqlx::query!(
+            r#"
+                select nft as "nft!", meta
+                from nft_metadata
+                where normalized is null
+                limit $1
+            "#,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map(|rows| rows.into_iter().map(|r| (r.nft, r.meta)).collect())
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn get_normalized_meta(&self, nft: &str) -> Result<Option<NormalizedNftMetaRow>> {
+        let Some(row) = sqlx::query!(
+            r#"
+                select
+                    nft as "nft!",
+                    meta_type,
+                    preview_url,
+                    preview_mimetype,
+                    external_url,
+                    violations as "violations!: Json<Vec<MetaViolation>>",
+                    normalized as "normalized!"
+                from nft_metadata
+                where nft = $1 and normalized is not null
+            "#,
+            nft as _
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?
+        else {
+            return Ok(None);
+        };
+
+        let files = sqlx::query_as!(
+            NftMetaFile,
+            r#"
+                select source, mimetype
+                from nft_metadata_files
+                where nft = $1
+                order by position
+            "#,
+            nft as _
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        Ok(Some(NormalizedNftMetaRow {
+            nft: row.nft,
+            meta_type: row.meta_type,
+            preview_url: row.preview_url,
+            preview_mimetype: row.preview_mimetype,
+            external_url: row.external_url,
+            files,
+            violations: row.violations.0,
+            normalized: row.normalized,
+        }))
+    }
+
+    /// Versions of the nft metadata, newest first
+    pub async fn get_meta_versions(&self, nft: &str) -> Result<Vec<NftMetaVersion>> {
+        sqlx::query_as!(
+            NftMetaVersion,
+            r#"
+                select version, meta, content_hash, created
+                from nft_metadata_history
+                where nft = $1
+                order by version desc
+            "#,
+            nft as _
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn get_meta_version(
+        &self,
+        nft: &str,
+        version: i32,
+    ) -> Result<Option<NftMetaVersion>> {
+        sqlx::query_as!(
+            NftMetaVersion,
+            r#"
+                select version, meta, content_hash, created
+                from nft_metadata_history
+                where nft = $1 and version = $2
+            "#,
+            nft as _,
+            version
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
     pub async fn start_transaction(&self) -> Result<MetadataModelTransaction> {
         let tx = self.pool.begin().await?;
 
@@ -203,7 +357,47 @@ impl<'a> MetadataModelTransaction<'a> {
         .map_err(|e| anyhow!(e))
     }
 
-    pub async fn update_nft_attributes(&mut self, attr: &[NftMetaAttribute<'a>]) -> Result<()> {
+    /// Replaces the attributes of an nft. Unchanged attributes are left as is,
+    /// so a metadata refresh alone doesn't trigger a rarity recount.
+    pub async fn update_nft_attributes(
+        &mut self,
+        nft: &str,
+        attr: &[NftMetaAttribute<'a>],
+    ) -> Result<()> {
+        let mut stored = sqlx::query_scalar!(
+            r#"
+                select raw
+                from nft_attributes
+                where nft = $1
+            "#,
+            nft
+        )
+        .fetch_all(&mut self.tx)
+        .await
+        .map_err(|e| anyhow!(e))?
+        .iter()
+        .map(|raw| raw.to_string())
+        .collect::<Vec<_>>();
+
+        let mut received = attr.iter().map(|a| a.raw.to_string()).collect::<Vec<_>>();
+
+        stored.sort();
+        received.sort();
+        if stored == received {
+            return Ok(());
+        }
+
+        sqlx::query!(
+            r#"
+                delete from nft_attributes
+                where nft = $1
+            "#,
+            nft
+        )
+        .execute(&mut self.tx)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
         for nft_attribute in attr {
             sqlx::query!(
                 r#"
@@ -237,6 +431,108 @@ impl<'a> MetadataModelTransaction<'a> {
         )
         .execute(&mut self.tx)
         .await
+        .map_err(|e| anyhow!(e))?;
+
+        // writers of one nft take turns here, so each one sees the version
+        // appended by the previous one and no version is lost
+        sqlx::query!(
+            r#"
+                select nft as "nft!"
+                from nft_metadata
+                where nft = $1
+                for update
+            "#,
+            &meta.address as _,
+        )
+        .fetch_one(&mut self.tx)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        // a new version only if the json differs from the latest one
+        sqlx::query!(
+            r#"
+                with latest as (
+                    select version, content_hash
+                    from nft_metadata_history
+                    where nft = $1
+                    order by version desc
+                    limit 1
+                ), hashed as (
+                    select encode(sha256(convert_to($2::jsonb::text, 'utf8')), 'hex') as content_hash
+                )
+                insert into nft_metadata_history (nft, version, meta, content_hash, created)
+                select $1, coalesce((select version from latest), 0) + 1, $2, hashed.content_hash, $3
+                from hashed
+                where hashed.content_hash is distinct from (select content_hash from latest)
+            "#,
+            &meta.address as _,
+            &meta.meta,
+            &meta.updated
+        )
+        .execute(&mut self.tx)
+        .await
+        .map(|_| ())
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn update_normalized_meta(
+        &mut self,
+        nft: &str,
+        meta: &NormalizedNftMeta,
+        normalized: NaiveDateTime,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+                update nft_metadata
+                set meta_type = $2,
+                    preview_url = $3,
+                    preview_mimetype = $4,
+                    external_url = $5,
+                    violations = $6,
+                    normalized = $7
+                where nft = $1
+            "#,
+            nft as _,
+            meta.meta_type,
+            meta.preview_url,
+            meta.preview_mimetype,
+            meta.external_url,
+            Json(&meta.violations) as _,
+            normalized
+        )
+        .execute(&mut self.tx)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        sqlx::query!(
+            r#"
+                delete from nft_metadata_files
+                where nft = $1
+            "#,
+            nft as _
+        )
+        .execute(&mut self.tx)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        let (sources, mimetypes): (Vec<_>, Vec<_>) = meta
+            .files
+            .iter()
+            .map(|f| (f.source.clone(), f.mimetype.clone()))
+            .unzip();
+
+        sqlx::query!(
+            r#"
+                insert into nft_metadata_files (nft, position, source, mimetype)
+                select $1, f.position - 1, f.source, f.mimetype
+                from unnest($2::text[], $3::varchar[]) with ordinality as f(source, mimetype, position)
+            "#,
+            nft as _,
+            &sources,
+            &mimetypes as _
+        )
+        .execute(&mut self.tx)
+        .await
         .map(|_| ())
         .map_err(|e| anyhow!(e))
     }
@@ -303,3 +599,66 @@ impl<'a> MetadataModelTransaction<'a> {
         self.tx.commit().await.map_err(|e| anyhow!(e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    async fn versions(tx: &mut Transaction<'_, Postgres>, nft: &str) -> Vec<i32> {
+        sqlx::query_scalar(
+            "select version from nft_metadata_history where nft = $1 order by version",
+        )
+        .bind(nft)
+        .fetch_all(&mut *tx)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn history_is_append_only() {
+        let first = serde_json::json!({"name": "first"});
+        let second = serde_json::json!({"name": "second"});
+        let mut tx = test_db::begin().await;
+
+        sqlx::query(
+            r#"
+                insert into nft (address, collection, owner, manager, burned, updated, owner_update_lt, manager_update_lt, id)
+                values ('0:n50', '0:c50', '0:o50', '0:o50', false, now(), 1, 1, 1)
+            "#,
+        )
+        .execute(&mut tx)
+        .await
+        .unwrap();
+
+        let mut meta_tx = MetadataModelTransaction { tx };
+        for (meta, secs) in [(&first, 1), (&second, 2), (&second, 3)] {
+            let meta = NftMeta {
+                address: "0:n50",
+                meta,
+                updated: NaiveDateTime::from_timestamp_opt(1_696_000_000 + secs, 0).unwrap(),
+            };
+            meta_tx.update_nft_meta(&meta).await.unwrap();
+        }
+        let mut tx = meta_tx.tx;
+        assert_eq!(versions(&mut tx, "0:n50").await, [1, 2]);
+
+        for statement in [
+            "update nft_metadata_history set meta = '{}' where nft = '0:n50'",
+            "delete from nft_metadata_history where nft = '0:n50'",
+            "delete from nft where address = '0:n50'",
+        ] {
+            sqlx::query("savepoint history")
+                .execute(&mut tx)
+                .await
+                .unwrap();
+            assert!(sqlx::query(statement).execute(&mut tx).await.is_err());
+            sqlx::query("rollback to savepoint history")
+                .execute(&mut tx)
+                .await
+                .unwrap();
+        }
+        assert_eq!(versions(&mut tx, "0:n50").await, [1, 2]);
+    }
+}
@@ -0,0 +1,591 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use sqlx::{types::BigDecimal, PgExecutor, PgPool};
+
+use crate::types::{AuctionStatus, DirectSellState, NftPriceSource};
+
+#[derive(Clone)]
+pub struct NftModel {
+    pool: PgPool,
+}
+
+#[derive(Clone)]
+pub struct NftDetails {
+    pub address: String,
+    pub collection: Option<String>,
+    pub owner: Option<String>,
+    pub manager: Option<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub updated: NaiveDateTime,
+    pub tx_lt: i64,
+    pub meta: Option<serde_json::Value>,
+    pub auction: Option<String>,
+    pub auction_status: Option<AuctionStatus>,
+    pub forsale: Option<String>,
+    pub forsale_status: Option<DirectSellState>,
+    pub best_offer: Option<String>,
+    pub floor_price_usd: Option<BigDecimal>,
+    pub deal_price_usd: Option<BigDecimal>,
+    pub floor_price: Option<BigDecimal>,
+    pub floor_price_token: Option<String>,
+    pub nft_id: Option<String>,
+    /// Copy of the preview and its thumbnail, once the media processor has stored them
+    pub preview_url: Option<String>,
+    pub preview_thumbnail_url: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct CollectionDetails {
+    pub address: String,
+    pub owner: Option<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub created: NaiveDateTime,
+    pub updated: NaiveDateTime,
+    pub wallpaper: Option<String>,
+    pub logo: Option<String>,
+    pub social: Option<serde_json::Value>,
+    pub verified: bool,
+    pub owners_count: i64,
+    pub nft_count: i64,
+    pub floor_price_usd: Option<BigDecimal>,
+    pub total_volume_usd: BigDecimal,
+    pub attributes: Option<serde_json::Value>,
+    pub first_mint: NaiveDateTime,
+    /// Copies of the logo and the wallpaper with their thumbnails, once stored
+    pub logo_url: Option<String>,
+    pub logo_thumbnail_url: Option<String>,
+    pub wallpaper_url: Option<String>,
+    pub wallpaper_thumbnail_url: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct PriceHistoryRecord {
+    pub id: i64,
+    pub source: String,
+    pub source_type: NftPriceSource,
+    pub ts: NaiveDateTime,
+    /// When the deal completed, the auction end for auction bids
+    pub completed_at: NaiveDateTime,
+    pub price: BigDecimal,
+    pub price_token: String,
+    pub usd_price: Option<BigDecimal>,
+    pub nft: String,
+    pub collection: String,
+}
+
+#[derive(Clone)]
+pub struct NftAttribute {
+    pub nft: String,
+    pub trait_type: String,
+    pub value: Option<serde_json::Value>,
+}
+
+/// An NFT held by an owner with the prices needed to value it.
+pub struct PortfolioNft {
+    pub address: String,
+    pub collection: String,
+    pub name: Option<String>,
+    pub collection_name: Option<String>,
+    pub collection_floor_usd: Option<BigDecimal>,
+    pub last_sale_price: Option<BigDecimal>,
+    pub last_sale_token: Option<String>,
+    pub last_sale_usd: Option<BigDecimal>,
+    pub last_sale_ts: Option<NaiveDateTime>,
+    /// Time of the mint or transfer that gave the NFT to its owner
+    pub acquired_at: Option<i64>,
+    pub acquisition_price: Option<BigDecimal>,
+    pub acquisition_token: Option<String>,
+    pub acquisition_usd: Option<BigDecimal>,
+}
+
+/// Position after the last returned price history record, newest first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PriceHistoryCursor {
+    pub ts: NaiveDateTime,
+    pub source: String,
+    /// Breaks ties between records of the same source at the same time
+    pub id: i64,
+}
+
+impl From<&PriceHistoryRecord> for PriceHistoryCursor {
+    fn from(record: &PriceHistoryRecord) -> Self {
+        Self {
+            ts: record.ts,
+            source: record.source.clone(),
+            id: record.id,
+        }
+    }
+}
+
+impl std::fmt::Display for PriceHistoryCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let micros = self.ts.timestamp() * 1_000_000 + self.ts.timestamp_subsec_micros() as i64;
+        write!(f, "{}:{}:{}", micros, self.id, self.source)
+    }
+}
+
+impl std::str::FromStr for PriceHistoryCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(3, ':');
+        let (Some(micros), Some(id), Some(source)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(anyhow!("Malformed cursor"));
+        };
+        let micros = micros.parse::<i64>()?;
+        let id = id.parse::<i64>()?;
+        let ts = NaiveDateTime::from_timestamp_opt(
+            micros.div_euclid(1_000_000),
+            micros.rem_euclid(1_000_000) as u32 * 1_000,
+        )
+        .ok_or_else(|| anyhow!("Malformed cursor"))?;
+
+        Ok(Self {
+            ts,
+            source: source.to_string(),
+            id,
+        })
+    }
+}
+
+impl NftModel {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn get_nft(&self, address: &str) -> Result<Option<NftDetails>> {
+        let nfts = self.get_nfts_by_addresses(&[address.to_string()]).await?;
+        Ok(nfts.into_iter().next())
+    }
+
+    /// Not burned NFTs ordered by address, starting after `after` exclusive.
+    pub async fn get_nfts(
+        &self,
+        owner: Option<&str>,
+        collection: Option<&str>,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<NftDetails>> {
+        get_nfts(&self.pool, owner, collection, after, limit).await
+    }
+
+    pub async fn get_nfts_by_addresses(&self, addresses: &[String]) -> Result<Vec<NftDetails>> {
+        get_nfts_by_addresses(&self.pool, addresses).await
+    }
+
+    pub async fn get_collections_by_addresses(
+        &self,
+        addresses: &[String],
+    ) -> Result<Vec<CollectionDetails>> {
+        sqlx::query_as!(
+            CollectionDetails,
+            r#"
+                select
+                    nft_collection_details.address as "address!",
+                    owner,
+                    name,
+                    description,
+                    created as "created!",
+                    nft_collection_details.updated as "updated!",
+                    wallpaper,
+                    logo,
+                    social,
+                    coalesce(verified, false) as "verified!",
+                    coalesce(owners_count, 0) as "owners_count!",
+                    coalesce(nft_count, 0) as "nft_count!",
+                    floor_price_usd,
+                    coalesce(total_volume_usd, 0) as "total_volume_usd!",
+                    attributes::jsonb as attributes,
+                    first_mint as "first_mint!",
+                    logo_media.url as "logo_url?",
+                    logo_media.thumbnail_url as "logo_thumbnail_url?",
+                    wallpaper_media.url as "wallpaper_url?",
+                    wallpaper_media.thumbnail_url as "wallpaper_thumbnail_url?"
+                from nft_collection_details
+                    left join media logo_media on logo_media.address = nft_collection_details.address
+                        and logo_media.kind = 'collection_logo'
+                        and logo_media.status = 'ready'
+                    left join media wallpaper_media on wallpaper_media.address = nft_collection_details.address
+                        and wallpaper_media.kind = 'collection_wallpaper'
+                        and wallpaper_media.status = 'ready'
+                where nft_collection_details.address = any($1::varchar[])
+            "#,
+            addresses as _
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn get_attributes_by_nfts(&self, nfts: &[String]) -> Result<Vec<NftAttribute>> {
+        sqlx::query_as!(
+            NftAttribute,
+            r#"
+                select nft, trait_type, value
+                from nft_attributes
+                where nft = any($1::varchar[])
+                order by nft, trait_type
+            "#,
+            nfts as _
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    /// Latest `per_nft` price history records of every given NFT, newest first.
+    pub async fn get_price_history_by_nfts(
+        &self,
+        nfts: &[String],
+        per_nft: i64,
+    ) -> Result<Vec<PriceHistoryRecord>> {
+        sqlx::query_as!(
+            PriceHistoryRecord,
+            r#"
+                select
+                    id as "id!",
+                    source as "source!",
+                    source_type as "source_type!: NftPriceSource",
+                    ts as "ts!",
+                    completed_at as "completed_at!",
+                    price as "price!",
+                    price_token as "price_token!",
+                    usd_price,
+                    nft as "nft!",
+                    collection as "collection!"
+                from (
+                    select
+                        h.*,
+                        row_number() over (partition by h.nft order by h.ts desc, h.source desc, h.id desc) as rn
+                    from nft_price_history h
+                    where h.nft = any($1::varchar[])
+                ) h
+                where rn <= $2
+                order by nft, ts desc, source desc, id desc
+            "#,
+            nfts as _,
+            per_nft
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn get_collection(&self, address: &str) -> Result<Option<CollectionDetails>> {
+        let collections = self
+            .get_collections_by_addresses(&[address.to_string()])
+            .await?;
+        Ok(collections.into_iter().next())
+    }
+
+    /// Price history of an NFT or a whole collection, newest first.
+    pub async fn get_price_history(
+        &self,
+        nft: Option<&str>,
+        collection: Option<&str>,
+        after: Option<&PriceHistoryCursor>,
+        limit: i64,
+    ) -> Result<Vec<PriceHistoryRecord>> {
+        sqlx::query_as!(
+            PriceHistoryRecord,
+            r#"
+                select
+                    id,
+                    source as "source!",
+                    source_type as "source_type: NftPriceSource",
+                    ts,
+                    completed_at,
+                    price,
+                    price_token as "price_token!",
+                    usd_price,
+                    nft as "nft!",
+                    collection as "collection!"
+                from nft_price_history
+                where ($1::varchar is null or nft = $1)
+                  and ($2::varchar is null or collection = $2)
+                  and ($3::timestamp is null or (ts, source, id) < ($3, $4::varchar, $5::bigint))
+                order by ts desc, source desc, id desc
+                limit $6
+            "#,
+            nft,
+            collection,
+            after.map(|c| c.ts),
+            after.map(|c| c.source.as_str()),
+            after.map(|c| c.id),
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    /// NFTs of an owner grouped by collection. The acquisition price is the last deal
+    /// between the previous ownership change and the one that gave the NFT to the owner.
+    pub async fn get_portfolio(&self, owner: &str, limit: i64) -> Result<Vec<PortfolioNft>> {
+        get_portfolio(&self.pool, owner, limit).await
+    }
+}
+
+async fn get_portfolio(
+    executor: impl PgExecutor<'_>,
+    owner: &str,
+    limit: i64,
+) -> Result<Vec<PortfolioNft>> {
+    sqlx::query_as!(
+        PortfolioNft,
+        r#"
+            select
+                n.address as "address!",
+                n.collection as "collection!",
+                n.name::text,
+                c.name as "collection_name?",
+                c.floor_price_usd as "collection_floor_usd?",
+                ls.price as "last_sale_price?",
+                ls.price_token as "last_sale_token?",
+                ls.usd_price as "last_sale_usd?",
+                ls.ts as "last_sale_ts?",
+                acq.created_at as "acquired_at?",
+                paid.price as "acquisition_price?",
+                paid.price_token as "acquisition_token?",
+                paid.usd_price as "acquisition_usd?"
+            from nft n
+            left join nft_collection_details c on c.address = n.collection
+            left join lateral (
+                select ph.price, ph.price_token, ph.usd_price, ph.completed_at as ts
+                from nft_price_history ph
+                where ph.nft = n.address
+                order by ph.completed_at desc
+                limit 1
+            ) ls on true
+            left join lateral (
+                select ne.created_at, ne.created_lt
+                from nft_events ne
+                where ne.nft = n.address
+                  and ((ne.event_type = 'nft_owner_changed' and ne.args ->> 'new_owner' = n.owner)
+                    or (ne.event_type = 'nft_created' and ne.args ->> 'owner' = n.owner))
+                order by ne.created_lt desc
+                limit 1
+            ) acq on true
+            left join lateral (
+                select ne.created_at
+                from nft_events ne
+                where ne.nft = n.address
+                  and ne.event_type in ('nft_owner_changed', 'nft_created')
+                  and ne.created_lt < acq.created_lt
+                order by ne.created_lt desc
+                limit 1
+            ) prev on true
+            left join lateral (
+                select ph.price, ph.price_token, ph.usd_price
+                from nft_price_history ph
+                where ph.nft = n.address
+                  and ph.completed_at <= to_timestamp(acq.created_at) at time zone 'utc'
+                  and (prev.created_at is null
+                    or ph.completed_at >= to_timestamp(prev.created_at) at time zone 'utc')
+                order by ph.completed_at desc
+                limit 1
+            ) paid on true
+            where n.owner = $1 and not n.burned
+            order by n.collection, n.address
+            limit $2
+        "#,
+        owner,
+        limit
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+}
+
+pub(crate) async fn get_nfts_by_addresses(
+    executor: impl PgExecutor<'_>,
+    addresses: &[String],
+) -> Result<Vec<NftDetails>> {
+    sqlx::query_as!(
+        NftDetails,
+        r#"
+            select
+                nft_details.address as "address!",
+                collection,
+                owner,
+                manager,
+                name,
+                description,
+                nft_details.updated as "updated!",
+                tx_lt as "tx_lt!",
+                meta,
+                auction,
+                "auction_status: _" as "auction_status: AuctionStatus",
+                forsale,
+                "forsale_status: _" as "forsale_status: DirectSellState",
+                best_offer,
+                floor_price_usd,
+                deal_price_usd,
+                floor_price,
+                floor_price_token,
+                nft_id,
+                preview.url as "preview_url?",
+                preview.thumbnail_url as "preview_thumbnail_url?"
+            from nft_details
+                left join media preview on preview.address = nft_details.address
+                    and preview.kind = 'nft_preview'
+                    and preview.status = 'ready'
+            where nft_details.address = any($1::varchar[])
+        "#,
+        addresses as _
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+}
+
+async fn get_nfts(
+    executor: impl PgExecutor<'_>,
+    owner: Option<&str>,
+    collection: Option<&str>,
+    after: Option<&str>,
+    limit: i64,
+) -> Result<Vec<NftDetails>> {
+    sqlx::query_as!(
+        NftDetails,
+        r#"
+            select
+                nft_details.address as "address!",
+                collection,
+                owner,
+                manager,
+                name,
+                description,
+                nft_details.updated as "updated!",
+                tx_lt as "tx_lt!",
+                meta,
+                auction,
+                "auction_status: _" as "auction_status: AuctionStatus",
+                forsale,
+                "forsale_status: _" as "forsale_status: DirectSellState",
+                best_offer,
+                floor_price_usd,
+                deal_price_usd,
+                floor_price,
+                floor_price_token,
+                nft_id,
+                preview.url as "preview_url?",
+                preview.thumbnail_url as "preview_thumbnail_url?"
+            from nft_details
+                left join media preview on preview.address = nft_details.address
+                    and preview.kind = 'nft_preview'
+                    and preview.status = 'ready'
+            where not burned
+              and ($1::varchar is null or owner = $1)
+              and ($2::varchar is null or collection = $2)
+              and ($3::varchar is null or nft_details.address > $3)
+            order by nft_details.address
+            limit $4
+        "#,
+        owner,
+        collection,
+        after,
+        limit
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_db;
+
+    #[test]
+    fn price_history_cursor_round_trip() {
+        let cursor = PriceHistoryCursor {
+            ts: NaiveDateTime::from_timestamp_opt(1_696_000_000, 123_456_000).unwrap(),
+            source: "0:abc".to_string(),
+            id: 42,
+        };
+
+        let encoded = cursor.to_string();
+        assert_eq!(encoded, "1696000000123456:42:0:abc");
+        assert_eq!(encoded.parse::<PriceHistoryCursor>().unwrap(), cursor);
+        assert!("garbage".parse::<PriceHistoryCursor>().is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn lists_nfts_without_burned_ones() {
+        let mut tx = test_db::begin().await;
+
+        sqlx::query(
+            r#"
+                insert into nft (address, collection, owner, manager, burned, updated, owner_update_lt, manager_update_lt, id)
+                values
+                    ('0:n31a', '0:c31', '0:o31', '0:o31', false, now(), 1, 1, 1),
+                    ('0:n31b', '0:c31', '0:o31', '0:o31', true, now(), 1, 1, 2),
+                    ('0:n31c', '0:c31', '0:o31', '0:o31', false, now(), 1, 1, 3)
+            "#,
+        )
+        .execute(&mut tx)
+        .await
+        .unwrap();
+
+        let first = get_nfts(&mut tx, Some("0:o31"), Some("0:c31"), None, 1)
+            .await
+            .unwrap();
+        assert_eq!(first[0].address, "0:n31a");
+
+        let rest = get_nfts(&mut tx, Some("0:o31"), Some("0:c31"), Some("0:n31a"), 10)
+            .await
+            .unwrap();
+        let rest = rest.iter().map(|n| n.address.as_str()).collect::<Vec<_>>();
+        assert_eq!(rest, ["0:n31c"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn portfolio_matches_acquisition_by_deal_completion() {
+        let mut tx = test_db::begin().await;
+
+        sqlx::query(
+            r#"
+                insert into nft (address, collection, owner, manager, burned, updated, owner_update_lt, manager_update_lt, id)
+                values ('0:n34', '0:c34', '0:b34', '0:b34', false, now(), 3, 3, 1)
+            "#,
+        )
+        .execute(&mut tx)
+        .await
+        .unwrap();
+        // The auction starts before the NFT is moved into it and completes when it leaves
+        sqlx::query(
+            r#"
+                insert into nft_events (event_cat, event_type, address, nft, created_lt, created_at, args, message_hash)
+                values
+                    ('nft', 'nft_created', '0:n34', '0:n34', 1, 100, '{"owner": "0:a34"}', 'test:n34:1'),
+                    ('nft', 'nft_owner_changed', '0:n34', '0:n34', 2, 180, '{"new_owner": "0:x34"}', 'test:n34:2'),
+                    ('nft', 'nft_owner_changed', '0:n34', '0:n34', 3, 300, '{"new_owner": "0:b34"}', 'test:n34:3')
+            "#,
+        )
+        .execute(&mut tx)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"
+                insert into nft_price_history (source, source_type, ts, completed_at, price, price_token, nft, collection)
+                values ('0:x34', 'auctionBid', to_timestamp(150) at time zone 'utc', to_timestamp(300) at time zone 'utc', 10, '0:t34', '0:n34', '0:c34')
+            "#,
+        )
+        .execute(&mut tx)
+        .await
+        .unwrap();
+
+        let portfolio = get_portfolio(&mut tx, "0:b34", 10).await.unwrap();
+        assert_eq!(portfolio.len(), 1);
+        let nft = &portfolio[0];
+        assert_eq!(nft.acquired_at, Some(300));
+        assert_eq!(nft.acquisition_price, Some(BigDecimal::from(10)));
+        assert_eq!(nft.last_sale_ts, NaiveDateTime::from_timestamp_opt(300, 0));
+    }
+}
@@ -0,0 +1,477 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use sqlx::{types::BigDecimal, PgExecutor, PgPool};
+
+#[derive(Clone)]
+pub struct OffersModel {
+    pool: PgPool,
+}
+
+#[derive(Default)]
+pub struct OfferFilter {
+    pub collection: Option<String>,
+    pub nft: Option<String>,
+    /// Auction nft owner, direct sell seller or direct buy buyer
+    pub owner: Option<String>,
+    /// Offers of any of these NFTs, of every NFT when empty
+    pub nfts: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct ActiveAuction {
+    pub address: String,
+    pub nft: String,
+    pub collection: String,
+    pub nft_owner: String,
+    pub price_token: Option<String>,
+    pub start_price: Option<BigDecimal>,
+    pub min_bid: Option<BigDecimal>,
+    pub max_bid: Option<BigDecimal>,
+    pub start_usd_price: Option<BigDecimal>,
+    pub min_usd_bid: Option<BigDecimal>,
+    pub bids_count: i64,
+    pub last_bid_from: Option<String>,
+    pub last_bid_value: Option<BigDecimal>,
+    pub last_bid_usd_value: Option<BigDecimal>,
+    pub created_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+    pub tx_lt: i64,
+}
+
+#[derive(Clone)]
+pub struct ActiveDirectSell {
+    pub address: String,
+    pub nft: String,
+    pub collection: Option<String>,
+    pub seller: String,
+    pub price_token: String,
+    pub price: BigDecimal,
+    pub usd_price: Option<BigDecimal>,
+    pub created: NaiveDateTime,
+    pub expired_at: NaiveDateTime,
+    pub tx_lt: i64,
+}
+
+#[derive(Clone)]
+pub struct ActiveDirectBuy {
+    pub address: String,
+    pub nft: String,
+    pub collection: Option<String>,
+    pub buyer: String,
+    pub price_token: String,
+    pub price: BigDecimal,
+    pub usd_price: Option<BigDecimal>,
+    pub created: NaiveDateTime,
+    pub expired_at: NaiveDateTime,
+    pub tx_lt: i64,
+}
+
+#[derive(Clone)]
+pub struct AuctionBid {
+    pub auction: String,
+    pub buyer: String,
+    pub price_token: String,
+    pub price: BigDecimal,
+    pub usd_price: Option<BigDecimal>,
+    pub next_bid_value: BigDecimal,
+    pub created_at: NaiveDateTime,
+    pub tx_lt: i64,
+}
+
+/// Position after the last returned offer, latest activation first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OfferCursor {
+    pub tx_lt: i64,
+    pub address: String,
+}
+
+impl OfferCursor {
+    pub fn new(tx_lt: i64, address: &str) -> Self {
+        Self {
+            tx_lt,
+            address: address.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for OfferCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.tx_lt, self.address)
+    }
+}
+
+impl std::str::FromStr for OfferCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (tx_lt, address) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Malformed cursor"))?;
+
+        Ok(Self::new(tx_lt.parse()?, address))
+    }
+}
+
+impl OffersModel {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn get_active_auctions(
+        &self,
+        filter: &OfferFilter,
+        after: Option<&OfferCursor>,
+        limit: i64,
+    ) -> Result<Vec<ActiveAuction>> {
+        active_auctions(&self.pool, filter, after, Some(limit)).await
+    }
+
+    pub async fn get_active_direct_sells(
+        &self,
+        filter: &OfferFilter,
+        after: Option<&OfferCursor>,
+        limit: i64,
+    ) -> Result<Vec<ActiveDirectSell>> {
+        active_direct_sells(&self.pool, filter, after, Some(limit)).await
+    }
+
+    pub async fn get_active_direct_buys(
+        &self,
+        filter: &OfferFilter,
+        after: Option<&OfferCursor>,
+        limit: i64,
+    ) -> Result<Vec<ActiveDirectBuy>> {
+        active_direct_buys(&self.pool, filter, after, Some(limit)).await
+    }
+
+    /// Active auctions of every given NFT, latest activation first.
+    pub async fn get_active_auctions_by_nfts(&self, nfts: &[String]) -> Result<Vec<ActiveAuction>> {
+        let filter = OfferFilter {
+            nfts: nfts.to_vec(),
+            ..Default::default()
+        };
+        active_auctions(&self.pool, &filter, None, None).await
+    }
+
+    /// Active direct sells of every given NFT, latest activation first.
+    pub async fn get_active_direct_sells_by_nfts(
+        &self,
+        nfts: &[String],
+    ) -> Result<Vec<ActiveDirectSell>> {
+        let filter = OfferFilter {
+            nfts: nfts.to_vec(),
+            ..Default::default()
+        };
+        active_direct_sells(&self.pool, &filter, None, None).await
+    }
+
+    /// The highest active direct buy of every given NFT by usd price.
+    pub async fn get_best_direct_buys_by_nfts(
+        &self,
+        nfts: &[String],
+    ) -> Result<Vec<ActiveDirectBuy>> {
+        sqlx::query_as!(
+            ActiveDirectBuy,
+            r#"
+                select distinct on (b.nft)
+                    b.address as "address!",
+                    b.nft as "nft!",
+                    b.collection,
+                    b.buyer as "buyer!",
+                    b.price_token as "price_token!",
+                    b.price,
+                    b.price * p.usd_price as usd_price,
+                    b.created,
+                    b.expired_at,
+                    b.tx_lt
+                from nft_direct_buy b
+                    join offers_whitelist ow on ow.address = b.address
+                    left join token_usd_prices p on p.token = b.price_token
+                where b.state = 'active'
+                  and (b.expired_at = to_timestamp(0) or b.expired_at > now())
+                  and b.nft = any($1::varchar[])
+                order by b.nft, b.price * p.usd_price desc nulls last, b.tx_lt desc
+            "#,
+            nfts as _
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    /// Not declined bids of every given auction, newest first.
+    pub async fn get_bids_by_auctions(&self, auctions: &[String]) -> Result<Vec<AuctionBid>> {
+        sqlx::query_as!(
+            AuctionBid,
+            r#"
+                select
+                    b.auction,
+                    b.buyer,
+                    b.price_token,
+                    b.price,
+                    b.price * p.usd_price as usd_price,
+                    b.next_bid_value,
+                    b.created_at,
+                    b.tx_lt
+                from nft_auction_bid b
+                    left join token_usd_prices p on p.token = b.price_token
+                where b.auction = any($1::varchar[])
+                  and not b.declined
+                order by b.auction, b.created_at desc
+            "#,
+            auctions as _
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+}
+
+async fn active_auctions(
+    executor: impl PgExecutor<'_>,
+    filter: &OfferFilter,
+    after: Option<&OfferCursor>,
+    limit: Option<i64>,
+) -> Result<Vec<ActiveAuction>> {
+    sqlx::query_as!(
+        ActiveAuction,
+        r#"
+            select
+                address as "address!",
+                nft as "nft!",
+                collection as "collection!",
+                nft_owner as "nft_owner!",
+                price_token,
+                start_price,
+                min_bid,
+                max_bid,
+                start_usd_price,
+                min_usd_bid,
+                coalesce(bids_count, 0) as "bids_count!",
+                last_bid_from,
+                last_bid_value,
+                last_bid_usd_value,
+                created_at,
+                finished_at,
+                tx_lt as "tx_lt!"
+            from nft_auction_search
+            where "status: _" = 'active'
+              and ($1::varchar is null or collection = $1)
+              and ($2::varchar is null or nft = $2)
+              and (cardinality($7::varchar[]) = 0 or nft = any($7))
+              and ($3::varchar is null or nft_owner = $3)
+              and ($4::bigint is null or (tx_lt, address) < ($4, $5::varchar))
+            order by tx_lt desc, address desc
+            limit $6
+        "#,
+        filter.collection as _,
+        filter.nft as _,
+        filter.owner as _,
+        after.map(|c| c.tx_lt),
+        after.map(|c| c.address.as_str()),
+        limit,
+        filter.nfts as _,
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+}
+
+async fn active_direct_sells(
+    executor: impl PgExecutor<'_>,
+    filter: &OfferFilter,
+    after: Option<&OfferCursor>,
+    limit: Option<i64>,
+) -> Result<Vec<ActiveDirectSell>> {
+    sqlx::query_as!(
+        ActiveDirectSell,
+        r#"
+            select
+                s.address as "address!",
+                s.nft as "nft!",
+                s.collection,
+                s.seller as "seller!",
+                s.price_token as "price_token!",
+                s.price,
+                s.price * p.usd_price as usd_price,
+                s.created,
+                s.expired_at,
+                s.tx_lt
+            from nft_direct_sell s
+                join offers_whitelist ow on ow.address = s.address
+                left join token_usd_prices p on p.token = s.price_token
+            where s.state = 'active'
+              and (s.expired_at = to_timestamp(0) or s.expired_at > now())
+              and ($1::varchar is null or s.collection = $1)
+              and ($2::varchar is null or s.nft = $2)
+              and (cardinality($7::varchar[]) = 0 or s.nft = any($7))
+              and ($3::varchar is null or s.seller = $3)
+              and ($4::bigint is null or (s.tx_lt, s.address) < ($4, $5::varchar))
+            order by s.tx_lt desc, s.address desc
+            limit $6
+        "#,
+        filter.collection as _,
+        filter.nft as _,
+        filter.owner as _,
+        after.map(|c| c.tx_lt),
+        after.map(|c| c.address.as_str()),
+        limit,
+        filter.nfts as _,
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+}
+
+async fn active_direct_buys(
+    executor: impl PgExecutor<'_>,
+    filter: &OfferFilter,
+    after: Option<&OfferCursor>,
+    limit: Option<i64>,
+) -> Result<Vec<ActiveDirectBuy>> {
+    sqlx::query_as!(
+        ActiveDirectBuy,
+        r#"
+            select
+                b.address as "address!",
+                b.nft as "nft!",
+                b.collection,
+                b.buyer as "buyer!",
+                b.price_token as "price_token!",
+                b.price,
+                b.price * p.usd_price as usd_price,
+                b.created,
+                b.expired_at,
+                b.tx_lt
+            from nft_direct_buy b
+                join offers_whitelist ow on ow.address = b.address
+                left join token_usd_prices p on p.token = b.price_token
+            where b.state = 'active'
+              and (b.expired_at = to_timestamp(0) or b.expired_at > now())
+              and ($1::varchar is null or b.collection = $1)
+              and ($2::varchar is null or b.nft = $2)
+              and (cardinality($7::varchar[]) = 0 or b.nft = any($7))
+              and ($3::varchar is null or b.buyer = $3)
+              and ($4::bigint is null or (b.tx_lt, b.address) < ($4, $5::varchar))
+            order by b.tx_lt desc, b.address desc
+            limit $6
+        "#,
+        filter.collection as _,
+        filter.nft as _,
+        filter.owner as _,
+        after.map(|c| c.tx_lt),
+        after.map(|c| c.address.as_str()),
+        limit,
+        filter.nfts as _,
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{Postgres, Transaction};
+
+    use super::*;
+    use crate::test_db;
+
+    const COLLECTION: &str = "0:c31";
+
+    /// Two listed offers sharing an lt, one of an unknown root, one expired and one cancelled
+    async fn seed(tx: &mut Transaction<'_, Postgres>, table: &str, owner: &str) {
+        sqlx::query(
+            r#"
+                insert into roots (address, code) values ('0:r31', 'sell'), ('0:r32', 'buy')
+                on conflict do nothing
+            "#,
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+        sqlx::query(
+            r#"
+                insert into deployed_offers (address, root, created)
+                select a, '0:r31', now()::timestamp
+                from unnest(array['0:o1', '0:o2', '0:o4', '0:o5']) a
+                on conflict do nothing
+            "#,
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+        sqlx::query(&format!(
+            r#"
+                insert into {table} (address, nft, collection, price_token, price, {owner}, expired_at, state, created, updated, tx_lt, root)
+                values
+                    ('0:o1', '0:n1', $1, '0:t', 1, '0:s', to_timestamp(0), 'active', now(), now(), 10, '0:r31'),
+                    ('0:o2', '0:n2', $1, '0:t', 1, '0:s', now() + interval '1 day', 'active', now(), now(), 10, '0:r31'),
+                    ('0:o3', '0:n3', $1, '0:t', 1, '0:s', to_timestamp(0), 'active', now(), now(), 11, '0:r33'),
+                    ('0:o4', '0:n4', $1, '0:t', 1, '0:s', now() - interval '1 day', 'active', now(), now(), 12, '0:r31'),
+                    ('0:o5', '0:n5', $1, '0:t', 1, '0:s', to_timestamp(0), 'cancelled', now(), now(), 13, '0:r31')
+            "#
+        ))
+        .bind(COLLECTION)
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+    }
+
+    fn filter() -> OfferFilter {
+        OfferFilter {
+            collection: Some(COLLECTION.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn lists_active_direct_sells_page_by_page() {
+        let mut tx = test_db::begin().await;
+        seed(&mut tx, "nft_direct_sell", "seller").await;
+
+        let first = active_direct_sells(&mut tx, &filter(), None, Some(1))
+            .await
+            .unwrap();
+        assert_eq!(first[0].address, "0:o2");
+
+        let cursor = OfferCursor::new(first[0].tx_lt, &first[0].address);
+        let rest = active_direct_sells(&mut tx, &filter(), Some(&cursor), None)
+            .await
+            .unwrap();
+        let rest = rest.iter().map(|s| s.address.as_str()).collect::<Vec<_>>();
+        assert_eq!(rest, ["0:o1"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn lists_every_direct_sell_of_the_given_nfts() {
+        let mut tx = test_db::begin().await;
+        seed(&mut tx, "nft_direct_sell", "seller").await;
+
+        let filter = OfferFilter {
+            nfts: vec!["0:n1".to_string(), "0:n3".to_string(), "0:n2".to_string()],
+            ..Default::default()
+        };
+        let sells = active_direct_sells(&mut tx, &filter, None, None)
+            .await
+            .unwrap();
+        let sells = sells.iter().map(|s| s.address.as_str()).collect::<Vec<_>>();
+        assert_eq!(sells, ["0:o2", "0:o1"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn lists_direct_buys_like_direct_sells() {
+        let mut tx = test_db::begin().await;
+        seed(&mut tx, "nft_direct_buy", "buyer").await;
+
+        let buys = active_direct_buys(&mut tx, &filter(), None, None)
+            .await
+            .unwrap();
+        let buys = buys.iter().map(|b| b.address.as_str()).collect::<Vec<_>>();
+        assert_eq!(buys, ["0:o2", "0:o1"]);
+    }
+}
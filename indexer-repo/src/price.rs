@@ -1,6 +1,6 @@
 use anyhow::{anyhow, Result};
 use chrono::{Local, NaiveDateTime};
-use sqlx::{types::BigDecimal, PgPool};
+use sqlx::{types::BigDecimal, PgExecutor, PgPool};
 
 use crate::types::BcName;
 
@@ -9,17 +9,93 @@ pub struct NftPriceModel {
     pool: PgPool,
 }
 
-pub struct RowWithoutUsdPrice {
-    pub id: String,
+pub struct PriceHistoryRow {
+    pub id: i64,
     pub token_addr: String,
     pub token_amount: BigDecimal,
     pub created_at: i64,
+    pub usd_price: Option<BigDecimal>,
+}
+
+/// Price history rows to recompute usd prices of, unset fields match any row.
+#[derive(Default)]
+pub struct RepriceFilter {
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub token: Option<String>,
+    pub collection: Option<String>,
+}
+
+/// Time span of the price history rows of a token.
+pub struct TokenTimeBounds {
+    pub token: String,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+}
+
+pub struct PairCandle {
+    /// Opening hour
+    pub ts: NaiveDateTime,
+    pub open: BigDecimal,
+    pub close: BigDecimal,
+    /// No trades this hour, the previous close is repeated
+    pub filled: bool,
+}
+
+/// Pair between two tokens, one hop of a price route.
+pub struct DexPair {
+    pub pair: String,
+    pub left_token: String,
+    pub right_token: String,
+    pub left_decimals: i32,
+    pub right_decimals: i32,
+    pub provider: Option<String>,
+    pub liquidity_usd: Option<f64>,
+}
+
+/// Pair listed by a price provider, decimals are taken from the token registry.
+pub struct NewDexPair {
+    pub pair: String,
+    pub left_token: String,
+    pub right_token: String,
+    pub liquidity_usd: Option<f64>,
+}
+
+/// Pair pricing the token in usd directly.
+pub struct UsdPair {
+    pub token: String,
+    pub pair: String,
+    pub is_l2r: bool,
+    pub decimals: i32,
+    pub provider: Option<String>,
 }
 
 pub struct DexPoolInfo {
     pub address: String,
     pub is_l2r: bool,
     pub decimals: i32,
+    /// Price provider to ask first
+    pub provider: Option<String>,
+}
+
+struct PriceRow {
+    id: i64,
+    token_addr: String,
+    token_amount: BigDecimal,
+    created_at: NaiveDateTime,
+    usd_price: Option<BigDecimal>,
+}
+
+impl From<PriceRow> for PriceHistoryRow {
+    fn from(value: PriceRow) -> Self {
+        Self {
+            id: value.id,
+            token_addr: value.token_addr,
+            token_amount: value.token_amount,
+            created_at: value.created_at.timestamp(),
+            usd_price: value.usd_price,
+        }
+    }
 }
 
 impl NftPriceModel {
@@ -27,38 +103,19 @@ impl NftPriceModel {
         Self { pool }
     }
 
-    pub async fn get_offers_without_price_usd(
-        &self,
-        limit: i64,
-    ) -> Result<Vec<RowWithoutUsdPrice>> {
-        struct Row {
-            id: String,
-            token_addr: String,
-            token_amount: BigDecimal,
-            created_at: NaiveDateTime,
-        }
-        impl From<Row> for RowWithoutUsdPrice {
-            fn from(value: Row) -> Self {
-                Self {
-                    token_addr: value.token_addr,
-                    id: value.id,
-                    token_amount: value.token_amount,
-                    created_at: value.created_at.timestamp(),
-                }
-            }
-        }
-
+    pub async fn get_offers_without_price_usd(&self, limit: i64) -> Result<Vec<PriceHistoryRow>> {
         let now = Local::now().naive_local();
         let zero_time = NaiveDateTime::from_timestamp_opt(0, 0).unwrap();
 
         sqlx::query_as!(
-            Row,
+            PriceRow,
             r#"
                 select
-                    source as id,
+                    id,
                     price_token as "token_addr!",
                     price as "token_amount!",
-                    ts as "created_at!"
+                    ts as "created_at!",
+                    usd_price
                 from nft_price_history
                 where usd_price is null
                 and ts <= $1
@@ -75,14 +132,96 @@ impl NftPriceModel {
         .map_err(|e| anyhow!(e))
     }
 
-    pub async fn update_usd_price(&self, id: &str, price: &BigDecimal) -> Result<()> {
+    /// Rows matching the filter ordered by id, starting after `after_id`.
+    pub async fn get_price_history(
+        &self,
+        filter: &RepriceFilter,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<PriceHistoryRow>> {
+        let zero_time = NaiveDateTime::from_timestamp_opt(0, 0).unwrap();
+
+        sqlx::query_as!(
+            PriceRow,
+            r#"
+                select
+                    id,
+                    price_token as "token_addr!",
+                    price as "token_amount!",
+                    ts as "created_at!",
+                    usd_price
+                from nft_price_history
+                where id > $1
+                and ts != $2
+                and ($3::timestamp is null or ts >= $3)
+                and ($4::timestamp is null or ts < $4)
+                and ($5::varchar is null or price_token = $5)
+                and ($6::varchar is null or collection = $6)
+                order by id
+                limit $7
+            "#,
+            after_id,
+            zero_time,
+            filter.from,
+            filter.to,
+            filter.token,
+            filter.collection,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map(|v| v.into_iter().map(|r| r.into()).collect())
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn get_price_history_bounds(
+        &self,
+        filter: &RepriceFilter,
+    ) -> Result<Vec<TokenTimeBounds>> {
+        let zero_time = NaiveDateTime::from_timestamp_opt(0, 0).unwrap();
+
+        sqlx::query_as!(
+            TokenTimeBounds,
+            r#"
+                select
+                    price_token as "token!",
+                    min(ts) as "from!",
+                    max(ts) as "to!"
+                from nft_price_history
+                where ts != $1
+                and ($2::timestamp is null or ts >= $2)
+                and ($3::timestamp is null or ts < $3)
+                and ($4::varchar is null or price_token = $4)
+                and ($5::varchar is null or collection = $5)
+                group by price_token
+            "#,
+            zero_time,
+            filter.from,
+            filter.to,
+            filter.token,
+            filter.collection
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    /// `route` lists the pairs the price was computed through.
+    pub async fn update_usd_price(
+        &self,
+        id: i64,
+        price: &BigDecimal,
+        route: &[String],
+    ) -> Result<()> {
         sqlx::query!(
             r#"
                 update nft_price_history
-                set usd_price = $1
-                where source = $2
+                set usd_price = $1,
+                    usd_price_route = $2::varchar[]
+                where id = $3
             "#,
             price,
+            route,
             id
         )
         .execute(&self.pool)
@@ -91,6 +230,158 @@ impl NftPriceModel {
         .map(|_| ())
     }
 
+    /// Hours from `from` to `to` inclusive without a stored candle of the pair.
+    pub async fn get_missing_candle_hours(
+        &self,
+        pair: &str,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<NaiveDateTime>> {
+        sqlx::query_scalar!(
+            r#"
+                select h.ts as "ts!"
+                from generate_series(
+                    date_trunc('hour', $2::timestamp),
+                    date_trunc('hour', $3::timestamp),
+                    interval '1 hour'
+                ) h(ts)
+                where not exists (
+                    select 1
+                    from dex_pair_candles c
+                    where c.pair = $1 and c.ts = h.ts
+                )
+                order by h.ts
+            "#,
+            pair,
+            from,
+            to
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    /// The latest stored candle of the pair opened before `ts`.
+    pub async fn get_candle_before(
+        &self,
+        pair: &str,
+        ts: NaiveDateTime,
+    ) -> Result<Option<PairCandle>> {
+        sqlx::query_as!(
+            PairCandle,
+            r#"
+                select ts, open, close, filled
+                from dex_pair_candles
+                where pair = $1 and ts < $2
+                order by ts desc
+                limit 1
+            "#,
+            pair,
+            ts
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn get_candles(
+        &self,
+        pair: &str,
+        hours: &[NaiveDateTime],
+    ) -> Result<Vec<PairCandle>> {
+        sqlx::query_as!(
+            PairCandle,
+            r#"
+                select ts, open, close, filled
+                from dex_pair_candles
+                where pair = $1 and ts = any($2::timestamp[])
+            "#,
+            pair,
+            hours
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    /// Drops the candles of the pair from `from` to `to` inclusive, so they are requested again.
+    pub async fn delete_candles(
+        &self,
+        pair: &str,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+                delete from dex_pair_candles
+                where pair = $1 and ts between $2 and $3
+            "#,
+            pair,
+            from,
+            to
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+        .map(|_| ())
+    }
+
+    pub async fn save_candles(
+        &self,
+        pair: &str,
+        provider: &str,
+        candles: &[PairCandle],
+    ) -> Result<()> {
+        let ts = candles.iter().map(|c| c.ts).collect::<Vec<_>>();
+        let open = candles.iter().map(|c| c.open.clone()).collect::<Vec<_>>();
+        let close = candles.iter().map(|c| c.close.clone()).collect::<Vec<_>>();
+        let filled = candles.iter().map(|c| c.filled).collect::<Vec<_>>();
+
+        sqlx::query!(
+            r#"
+                insert into dex_pair_candles (pair, ts, open, close, provider, filled)
+                select $1, c.ts, c.open, c.close, $2, c.filled
+                from unnest($3::timestamp[], $4::numeric[], $5::numeric[], $6::bool[])
+                    as c(ts, open, close, filled)
+                on conflict (pair, ts) do update set
+                    open = excluded.open,
+                    close = excluded.close,
+                    provider = excluded.provider,
+                    filled = excluded.filled
+                where dex_pair_candles.filled
+            "#,
+            pair as _,
+            provider,
+            &ts,
+            &open,
+            &close,
+            &filled
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+        .map(|_| ())
+    }
+
+    /// Usd price of the smallest unit of the token, as read by the views.
+    pub async fn update_token_usd_price(&self, token: &str, usd_price: &BigDecimal) -> Result<()> {
+        sqlx::query!(
+            r#"
+                insert into token_usd_prices (token, usd_price, ts)
+                values ($1, $2, now())
+                on conflict (token) do update set
+                    usd_price = excluded.usd_price,
+                    ts = excluded.ts
+            "#,
+            token as _,
+            usd_price
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+        .map(|_| ())
+    }
+
     pub async fn get_dex_pair_address(&self, token_addr: &str, bc: BcName) -> Result<DexPoolInfo> {
         match bc {
             BcName::Everscale => self.get_pair_address(token_addr, BcName::Everscale).await,
@@ -98,6 +389,61 @@ impl NftPriceModel {
         }
     }
 
+    pub async fn get_dex_pairs(&self, source: BcName) -> Result<Vec<DexPair>> {
+        sqlx::query_as!(
+            DexPair,
+            r#"
+                select
+                    pair as "pair!",
+                    left_token as "left_token!",
+                    right_token as "right_token!",
+                    left_decimals as "left_decimals!",
+                    right_decimals as "right_decimals!",
+                    provider,
+                    liquidity_usd::float8 as liquidity_usd
+                from dex_pairs
+                where source = $1
+                  and left_decimals is not null
+                  and right_decimals is not null
+            "#,
+            source as _
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    /// Upserts the pairs listed by `provider`. A pair stays with the provider
+    /// that listed it first, others don't overwrite its liquidity.
+    pub async fn save_dex_pairs(
+        &self,
+        source: BcName,
+        provider: &str,
+        pairs: &[NewDexPair],
+    ) -> Result<()> {
+        save_dex_pairs(&self.pool, source, provider, pairs).await
+    }
+
+    pub async fn get_usd_pairs(&self, source: BcName) -> Result<Vec<UsdPair>> {
+        sqlx::query_as!(
+            UsdPair,
+            r#"
+                select
+                    token as "token!",
+                    pair,
+                    is_l2r,
+                    decimals,
+                    provider
+                from token_to_dex
+                where source = $1
+            "#,
+            source as _
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
     pub async fn get_tokens_with_dex_pair(&self, source: BcName) -> Result<Vec<String>> {
         sqlx::query_scalar!(
             r#"
@@ -119,7 +465,8 @@ impl NftPriceModel {
                 select 
                     pair as address,
                     is_l2r,
-                    decimals
+                    decimals,
+                    provider
                 from token_to_dex
                 where token = $1 and source = $2
             "#,
@@ -131,3 +478,132 @@ impl NftPriceModel {
         .map_err(|e| anyhow!(e))
     }
 }
+
+async fn save_dex_pairs(
+    executor: impl PgExecutor<'_>,
+    source: BcName,
+    provider: &str,
+    pairs: &[NewDexPair],
+) -> Result<()> {
+    let addresses = pairs.iter().map(|p| p.pair.clone()).collect::<Vec<_>>();
+    let left_tokens = pairs
+        .iter()
+        .map(|p| p.left_token.clone())
+        .collect::<Vec<_>>();
+    let right_tokens = pairs
+        .iter()
+        .map(|p| p.right_token.clone())
+        .collect::<Vec<_>>();
+    let liquidity = pairs.iter().map(|p| p.liquidity_usd).collect::<Vec<_>>();
+
+    sqlx::query!(
+        r#"
+            insert into dex_pairs (pair, source, left_token, right_token,
+                                   left_decimals, right_decimals, provider, liquidity_usd)
+            select p.pair, $1, p.left_token, p.right_token,
+                   l.decimals, r.decimals, $2, p.liquidity::numeric
+            from unnest($3::varchar[], $4::varchar[], $5::varchar[], $6::float8[])
+                as p(pair, left_token, right_token, liquidity)
+                left join tokens l on l.address = p.left_token
+                left join tokens r on r.address = p.right_token
+            on conflict (pair) do update
+                set left_decimals  = coalesce(excluded.left_decimals, dex_pairs.left_decimals),
+                    right_decimals = coalesce(excluded.right_decimals, dex_pairs.right_decimals),
+                    provider       = coalesce(dex_pairs.provider, excluded.provider),
+                    liquidity_usd  = case
+                                         when dex_pairs.provider is null
+                                             or dex_pairs.provider = excluded.provider
+                                             then excluded.liquidity_usd
+                                         else dex_pairs.liquidity_usd
+                                     end
+        "#,
+        source as _,
+        provider,
+        &addresses as _,
+        &left_tokens as _,
+        &right_tokens as _,
+        &liquidity as _,
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+    .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    fn listed(pair: &str, liquidity: f64) -> NewDexPair {
+        NewDexPair {
+            pair: pair.to_string(),
+            left_token: "0:dexleft".to_string(),
+            right_token: "0:dexright".to_string(),
+            liquidity_usd: Some(liquidity),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn routes_only_pairs_with_known_decimals_and_keeps_their_provider() {
+        let mut tx = test_db::begin().await;
+
+        sqlx::query(
+            "insert into tokens (address, decimals, updated) values ('0:dexleft', 9, now())",
+        )
+        .execute(&mut tx)
+        .await
+        .unwrap();
+
+        save_dex_pairs(
+            &mut tx,
+            BcName::Venom,
+            "first",
+            &[listed("0:dexpair", 10.0)],
+        )
+        .await
+        .unwrap();
+        save_dex_pairs(
+            &mut tx,
+            BcName::Venom,
+            "second",
+            &[listed("0:dexpair", 20.0)],
+        )
+        .await
+        .unwrap();
+
+        let row: (Option<i32>, Option<i32>, Option<String>, Option<f64>) = sqlx::query_as(
+            "select left_decimals, right_decimals, provider, liquidity_usd::float8
+             from dex_pairs where pair = '0:dexpair'",
+        )
+        .fetch_one(&mut tx)
+        .await
+        .unwrap();
+        assert_eq!(row, (Some(9), None, Some("first".to_string()), Some(10.0)));
+
+        sqlx::query(
+            "insert into tokens (address, decimals, updated) values ('0:dexright', 6, now())",
+        )
+        .execute(&mut tx)
+        .await
+        .unwrap();
+        save_dex_pairs(
+            &mut tx,
+            BcName::Venom,
+            "first",
+            &[listed("0:dexpair", 30.0)],
+        )
+        .await
+        .unwrap();
+
+        let row: (Option<i32>, Option<i32>, Option<f64>) = sqlx::query_as(
+            "select left_decimals, right_decimals, liquidity_usd::float8
+             from dex_pairs where pair = '0:dexpair'",
+        )
+        .fetch_one(&mut tx)
+        .await
+        .unwrap();
+        assert_eq!(row, (Some(9), Some(6), Some(30.0)));
+    }
+}
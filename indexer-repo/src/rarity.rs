@@ -0,0 +1,683 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+
+use crate::types::RarityAlgorithm;
+
+#[derive(Clone)]
+pub struct RarityModel {
+    pool: PgPool,
+}
+
+/// Collection with nfts whose attributes or burn state changed since they were scored.
+pub struct DirtyCollection {
+    pub collection: String,
+    pub marked_at: NaiveDateTime,
+}
+
+pub struct RankedNft {
+    pub address: String,
+    pub name: Option<String>,
+    pub owner: Option<String>,
+    pub score: f64,
+    pub rank: i32,
+}
+
+pub struct NftRarity {
+    pub algorithm: RarityAlgorithm,
+    pub score: f64,
+    pub rank: i32,
+}
+
+/// Position after the last returned nft, rarest first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RarityCursor {
+    pub rank: i32,
+    pub nft: String,
+}
+
+impl From<&RankedNft> for RarityCursor {
+    fn from(nft: &RankedNft) -> Self {
+        Self {
+            rank: nft.rank,
+            nft: nft.address.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for RarityCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.rank, self.nft)
+    }
+}
+
+impl std::str::FromStr for RarityCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (rank, nft) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Malformed cursor"))?;
+
+        Ok(Self {
+            rank: rank.parse()?,
+            nft: nft.to_string(),
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct RankFilter {
+    pub owner: Option<String>,
+    pub min_rank: Option<i32>,
+    pub max_rank: Option<i32>,
+}
+
+/// Count of nfts with a trait value, `None` counts the nfts lacking the trait.
+struct TraitCount {
+    trait_type: String,
+    value: Option<String>,
+    nft_count: i32,
+}
+
+impl RarityModel {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn get_dirty_collections(&self, limit: i64) -> Result<Vec<DirtyCollection>> {
+        sqlx::query_as!(
+            DirtyCollection,
+            r#"
+                select collection as "collection!", max(marked_at) as "marked_at!"
+                from rarity_dirty_nfts
+                group by collection
+                order by min(marked_at)
+                limit $1
+            "#,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    /// Applies the trait changes of the dirty nfts to the trait counts and
+    /// rescores the nfts holding a trait value whose count changed. Nfts
+    /// marked again after `dirty.marked_at` stay dirty.
+    pub async fn recompute_collection(&self, dirty: &DirtyCollection) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        update_collection(&mut tx, dirty).await?;
+        tx.commit().await.map_err(|e| anyhow!(e))
+    }
+
+    /// Nfts of a collection from the rarest one.
+    pub async fn get_ranked_nfts(
+        &self,
+        collection: &str,
+        algorithm: RarityAlgorithm,
+        filter: &RankFilter,
+        after: Option<&RarityCursor>,
+        limit: i64,
+    ) -> Result<Vec<RankedNft>> {
+        get_ranked_nfts(&self.pool, collection, algorithm, filter, after, limit).await
+    }
+
+    pub async fn get_nft_rarity(&self, nft: &str) -> Result<Vec<NftRarity>> {
+        sqlx::query_as!(
+            NftRarity,
+            r#"
+                select
+                    r.algorithm as "algorithm: RarityAlgorithm",
+                    rarity_score(r.algorithm, r.raw_score, c.supply, c.trait_types, c.entropy) as "score!",
+                    r.rank
+                from nft_rarity r
+                    join collection_rarity c on c.collection = r.collection
+                where r.nft = $1
+                order by r.algorithm
+            "#,
+            nft
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+}
+
+async fn get_ranked_nfts(
+    executor: impl PgExecutor<'_>,
+    collection: &str,
+    algorithm: RarityAlgorithm,
+    filter: &RankFilter,
+    after: Option<&RarityCursor>,
+    limit: i64,
+) -> Result<Vec<RankedNft>> {
+    sqlx::query_as!(
+        RankedNft,
+        r#"
+            select
+                r.nft as "address!",
+                n.name::text,
+                n.owner::text,
+                rarity_score(r.algorithm, r.raw_score, c.supply, c.trait_types, c.entropy) as "score!",
+                r.rank
+            from nft_rarity r
+                join collection_rarity c on c.collection = r.collection
+                left join nft n on n.address = r.nft
+            where r.collection = $1
+              and r.algorithm = $2
+              and ($3::int is null or r.rank >= $3)
+              and ($4::int is null or r.rank <= $4)
+              and ($5::int is null or (r.rank, r.nft) > ($5, $6::varchar))
+              and ($7::varchar is null or n.owner = $7)
+            order by r.rank, r.nft
+            limit $8
+        "#,
+        collection,
+        algorithm as _,
+        filter.min_rank,
+        filter.max_rank,
+        after.map(|c| c.rank),
+        after.map(|c| c.nft.as_str()),
+        filter.owner,
+        limit
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+}
+
+async fn update_collection(
+    tx: &mut Transaction<'_, Postgres>,
+    dirty: &DirtyCollection,
+) -> Result<()> {
+    let nfts = sqlx::query_scalar!(
+        r#"
+            delete from rarity_dirty_nfts
+            where collection = $1 and marked_at <= $2
+            returning nft as "nft!"
+        "#,
+        dirty.collection as _,
+        dirty.marked_at
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let before = get_trait_counts(&mut *tx, &dirty.collection).await?;
+    count_traits(tx, &dirty.collection, &nfts).await?;
+    let after = get_trait_counts(&mut *tx, &dirty.collection).await?;
+
+    let (changed_types, changed_values) = changed_traits(&before, &after);
+    score_nfts(
+        tx,
+        &dirty.collection,
+        &nfts,
+        &changed_types,
+        &changed_values,
+    )
+    .await?;
+    rank_nfts(tx, &dirty.collection).await
+}
+
+async fn get_trait_counts(
+    executor: impl PgExecutor<'_>,
+    collection: &str,
+) -> Result<Vec<TraitCount>> {
+    sqlx::query_as!(
+        TraitCount,
+        r#"
+            select trait_type::text as "trait_type!", value, nft_count
+            from collection_traits
+            where collection = $1
+        "#,
+        collection
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| anyhow!(e))
+}
+
+/// Trait values whose count differs, as the parallel arrays `score_nfts` takes.
+fn changed_traits(
+    before: &[TraitCount],
+    after: &[TraitCount],
+) -> (Vec<String>, Vec<Option<String>>) {
+    let counts = |traits: &[TraitCount]| {
+        traits
+            .iter()
+            .map(|t| ((t.trait_type.clone(), t.value.clone()), t.nft_count))
+            .collect::<HashMap<_, _>>()
+    };
+    let before = counts(before);
+    let after = counts(after);
+
+    before
+        .keys()
+        .chain(after.keys().filter(|key| !before.contains_key(*key)))
+        .filter(|key| before.get(*key) != after.get(*key))
+        .cloned()
+        .unzip()
+}
+
+/// Moves the counts of the dirty nfts from the trait values they were scored
+/// with to their current ones. Nfts lacking a trait count towards its null
+/// value, so missing a common trait is rare too.
+async fn count_traits(
+    tx: &mut Transaction<'_, Postgres>,
+    collection: &str,
+    nfts: &[String],
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+            with delta as (
+                select trait_type, value, sum(d) as d
+                from (
+                    select trait_type, value, 1 as d
+                    from nft_trait_values
+                    where nft = any($2)
+                    union all
+                    select trait_type, value, -1
+                    from nft_rarity_traits
+                    where nft = any($2)
+                ) c
+                group by trait_type, value
+                having sum(d) <> 0
+            )
+            insert into collection_traits (collection, trait_type, value, nft_count, frequency)
+            select $1::varchar, trait_type, value, d, 0
+            from delta
+            on conflict (collection, trait_type, coalesce(value, ''), (value is null)) do update
+                set nft_count = collection_traits.nft_count + excluded.nft_count
+        "#,
+        collection as _,
+        nfts as _
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+            delete from nft_rarity_traits
+            where nft = any($1)
+        "#,
+        nfts as _
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+            insert into nft_rarity_traits (nft, collection, trait_type, value)
+            select nft, collection, trait_type, value
+            from nft_trait_values
+            where nft = any($1)
+        "#,
+        nfts as _
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let supply = sqlx::query_scalar!(
+        r#"
+            select count(*) as "supply!"
+            from nft
+            where collection = $1 and not burned
+        "#,
+        collection
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+            with present as (
+                select trait_type, sum(nft_count) as nft_count
+                from collection_traits
+                where collection = $1 and value is not null and nft_count > 0
+                group by trait_type
+            )
+            insert into collection_traits (collection, trait_type, value, nft_count, frequency)
+            select $1, t.trait_type, null, $2 - coalesce(p.nft_count, $2), 0
+            from (select distinct trait_type from collection_traits where collection = $1) t
+                left join present p on p.trait_type = t.trait_type
+            on conflict (collection, trait_type, coalesce(value, ''), (value is null)) do update
+                set nft_count = excluded.nft_count
+        "#,
+        collection,
+        supply
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+            delete from collection_traits
+            where collection = $1 and nft_count <= 0
+        "#,
+        collection
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+            update collection_traits
+            set frequency = nft_count::float8 / $2
+            where collection = $1
+        "#,
+        collection,
+        supply as f64
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+            insert into collection_rarity (collection, supply, trait_types, entropy)
+            select
+                $1::varchar,
+                $2,
+                count(distinct trait_type),
+                coalesce(-sum(frequency * ln(frequency) / ln(2)), 0)
+            from collection_traits
+            where collection = $1
+            on conflict (collection) do update set
+                supply = excluded.supply,
+                trait_types = excluded.trait_types,
+                entropy = excluded.entropy
+        "#,
+        collection,
+        supply as i32
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| anyhow!(e))
+    .map(|_| ())
+}
+
+/// Rescores the dirty nfts and the ones holding a changed trait value. Raw
+/// scores are the sum of ln(nft_count) of the trait values, they don't move
+/// with the supply and rank the same way as the sum of ln(frequency).
+async fn score_nfts(
+    tx: &mut Transaction<'_, Postgres>,
+    collection: &str,
+    nfts: &[String],
+    changed_types: &[String],
+    changed_values: &[Option<String>],
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+            delete from nft_rarity
+            where nft = any($1)
+        "#,
+        nfts as _
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+            with changed as (
+                select *
+                from unnest($3::varchar[], $4::text[]) as c (trait_type, value)
+            ),
+            targets as (
+                select address as nft
+                from nft
+                where address = any($2) and collection = $1 and not burned
+                union
+                select t.nft
+                from nft_rarity_traits t
+                    join changed c on c.trait_type = t.trait_type and c.value = t.value
+                where t.collection = $1
+                union
+                select r.nft
+                from nft_rarity r
+                    join changed c on c.value is null
+                where r.collection = $1
+                  and r.algorithm = 'statistical'
+                  and not exists (
+                    select 1
+                    from nft_rarity_traits t
+                    where t.nft = r.nft and t.trait_type = c.trait_type
+                  )
+            ),
+            traits as (
+                select trait_type, value, nft_count
+                from collection_traits
+                where collection = $1
+            ),
+            pairs as (
+                select tg.nft, tt.trait_type, v.value
+                from targets tg
+                    cross join (select distinct trait_type from traits) tt
+                    left join nft_rarity_traits v on v.nft = tg.nft and v.trait_type = tt.trait_type
+            ),
+            scores as (
+                select
+                    tg.nft,
+                    coalesce(sum(ln(t.nft_count)), 0) as raw_score,
+                    count(p.value) as trait_count
+                from targets tg
+                    left join pairs p on p.nft = tg.nft
+                    left join traits t on t.trait_type = p.trait_type
+                        and t.value is not distinct from p.value
+                group by tg.nft
+            )
+            insert into nft_rarity (nft, collection, algorithm, raw_score, trait_count, rank)
+            select s.nft, $1, a.algorithm, s.raw_score, s.trait_count, 0
+            from scores s
+                cross join unnest(enum_range(null::rarity_algorithm)) a (algorithm)
+            on conflict (nft, algorithm) do update set
+                raw_score = excluded.raw_score,
+                trait_count = excluded.trait_count
+        "#,
+        collection,
+        nfts as _,
+        changed_types as _,
+        changed_values as _
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| anyhow!(e))
+    .map(|_| ())
+}
+
+/// Ranks from the lowest raw score, trait count nfts are scored by how many
+/// share their number of traits. Only the rows whose rank moved are written.
+async fn rank_nfts(tx: &mut Transaction<'_, Postgres>, collection: &str) -> Result<()> {
+    sqlx::query!(
+        r#"
+            update nft_rarity r
+            set raw_score = s.raw_score, rank = s.rank
+            from (
+                select nft, algorithm, raw_score, rank() over (partition by algorithm order by raw_score)::int as rank
+                from (
+                    select
+                        nft,
+                        algorithm,
+                        case
+                            when algorithm = 'trait_count'
+                                then count(*) over (partition by algorithm, trait_count)::float8
+                            else raw_score
+                        end as raw_score
+                    from nft_rarity
+                    where collection = $1
+                ) g
+            ) s
+            where r.nft = s.nft
+              and r.algorithm = s.algorithm
+              and (r.raw_score, r.rank) is distinct from (s.raw_score, s.rank)
+        "#,
+        collection
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| anyhow!(e))
+    .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    const COLLECTION: &str = "0:c36";
+
+    fn count(trait_type: &str, value: Option<&str>, nft_count: i32) -> TraitCount {
+        TraitCount {
+            trait_type: trait_type.to_string(),
+            value: value.map(str::to_string),
+            nft_count,
+        }
+    }
+
+    #[test]
+    fn changed_traits_include_added_and_removed_values() {
+        let before = [
+            count("Color", Some("red"), 2),
+            count("Color", Some("blue"), 1),
+            count("Hat", None, 3),
+        ];
+        let after = [
+            count("Color", Some("red"), 2),
+            count("Color", Some("blue"), 2),
+            count("Hat", Some("cap"), 1),
+        ];
+
+        let (types, values) = changed_traits(&before, &after);
+        let mut changed = types.into_iter().zip(values).collect::<Vec<_>>();
+        changed.sort();
+
+        assert_eq!(
+            changed,
+            [
+                ("Color".to_string(), Some("blue".to_string())),
+                ("Hat".to_string(), None),
+                ("Hat".to_string(), Some("cap".to_string())),
+            ]
+        );
+    }
+
+    async fn update(tx: &mut Transaction<'_, Postgres>) {
+        let dirty = DirtyCollection {
+            collection: COLLECTION.to_string(),
+            marked_at: NaiveDateTime::MAX,
+        };
+        update_collection(tx, &dirty).await.unwrap();
+    }
+
+    async fn scores(tx: &mut Transaction<'_, Postgres>) -> Vec<(String, String, i64, i32)> {
+        sqlx::query_as(
+            r#"
+                select nft::text, algorithm::text, round(raw_score * 1e9)::bigint, rank
+                from nft_rarity
+                where collection = $1
+                order by nft, algorithm
+            "#,
+        )
+        .bind(COLLECTION)
+        .fetch_all(&mut *tx)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn incremental_update_matches_full_recount() {
+        let mut tx = test_db::begin().await;
+
+        sqlx::query(
+            r#"
+                insert into nft (address, collection, owner, manager, burned, updated, owner_update_lt, manager_update_lt, id)
+                select a, $1, '0:o36', '0:o36', false, now(), 1, 1, 1
+                from unnest(array['0:n36a', '0:n36b', '0:n36c', '0:n36d']) a
+            "#,
+        )
+        .bind(COLLECTION)
+        .execute(&mut tx)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"
+                insert into nft_attributes (nft, collection, raw, trait_type, value)
+                select nft, $1, '{}', trait_type, to_jsonb(value)
+                from (values
+                    ('0:n36a', 'Color', 'red'), ('0:n36a', 'Hat', 'cap'),
+                    ('0:n36b', 'Color', 'red'),
+                    ('0:n36c', 'Color', 'blue'), ('0:n36c', 'Hat', 'cap'),
+                    ('0:n36d', 'Color', 'red')
+                ) v (nft, trait_type, value)
+            "#,
+        )
+        .bind(COLLECTION)
+        .execute(&mut tx)
+        .await
+        .unwrap();
+
+        update(&mut tx).await;
+
+        let rarest = get_ranked_nfts(
+            &mut tx,
+            COLLECTION,
+            RarityAlgorithm::Statistical,
+            &RankFilter::default(),
+            None,
+            1,
+        )
+        .await
+        .unwrap();
+        assert_eq!(rarest[0].address, "0:n36c");
+        assert_eq!(rarest[0].rank, 1);
+        assert!((rarest[0].score - (1.0f64 / 8.0).ln()).abs() < 1e-9);
+
+        // Only the burned nft and the one that got a hat are marked
+        sqlx::query("update nft set burned = true where address = '0:n36b'")
+            .execute(&mut tx)
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"
+                insert into nft_attributes (nft, collection, raw, trait_type, value)
+                values ('0:n36d', $1, '{}', 'Hat', '"crown"')
+            "#,
+        )
+        .bind(COLLECTION)
+        .execute(&mut tx)
+        .await
+        .unwrap();
+
+        update(&mut tx).await;
+        let incremental = scores(&mut tx).await;
+
+        let statistical = incremental
+            .iter()
+            .filter(|s| s.1 == "statistical")
+            .map(|s| (s.0.as_str(), s.3))
+            .collect::<Vec<_>>();
+        assert_eq!(statistical, [("0:n36a", 3), ("0:n36c", 1), ("0:n36d", 1)]);
+
+        sqlx::query(
+            r#"
+                with
+                    r as (delete from nft_rarity where collection = $1),
+                    t as (delete from nft_rarity_traits where collection = $1),
+                    c as (delete from collection_traits where collection = $1)
+                insert into rarity_dirty_nfts (nft, collection, marked_at)
+                select address, collection, now()
+                from nft
+                where collection = $1
+                on conflict do nothing
+            "#,
+        )
+        .bind(COLLECTION)
+        .execute(&mut tx)
+        .await
+        .unwrap();
+
+        update(&mut tx).await;
+        assert_eq!(scores(&mut tx).await, incremental);
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::{anyhow, Result};
+use sqlx::PgPool;
+
+#[derive(Clone)]
+pub struct SearchModel {
+    pool: PgPool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchKind {
+    Nft,
+    Collection,
+    User,
+}
+
+impl SearchKind {
+    pub const ALL: [SearchKind; 3] = [SearchKind::Nft, SearchKind::Collection, SearchKind::User];
+}
+
+pub struct SearchHit {
+    pub kind: SearchKind,
+    pub address: String,
+    pub name: Option<String>,
+    /// Collection of an nft hit
+    pub collection: Option<String>,
+    /// The query is the address of the hit
+    pub exact: bool,
+    pub rank: f32,
+}
+
+struct HitRow {
+    address: String,
+    name: Option<String>,
+    collection: Option<String>,
+    exact: bool,
+    rank: f32,
+}
+
+impl HitRow {
+    fn into_hit(self, kind: SearchKind) -> SearchHit {
+        SearchHit {
+            kind,
+            address: self.address,
+            name: self.name,
+            collection: self.collection,
+            exact: self.exact,
+            rank: self.rank,
+        }
+    }
+}
+
+/// `tsquery` matching every word of the text by prefix, `None` if there are no words.
+pub fn prefix_query(text: &str) -> Option<String> {
+    let words = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| format!("{}:*", w.to_lowercase()))
+        .collect::<Vec<_>>();
+
+    (!words.is_empty()).then(|| words.join(" & "))
+}
+
+fn is_address(text: &str) -> bool {
+    match text.split_once(':') {
+        Some((workchain, hex)) => {
+            workchain.parse::<i8>().is_ok()
+                && hex.len() == 64
+                && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        None => false,
+    }
+}
+
+impl SearchModel {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Hits of the requested kinds, exact address hits first, then by rank.
+    /// At most `limit` hits of every kind are returned.
+    pub async fn search(
+        &self,
+        text: &str,
+        kinds: &[SearchKind],
+        limit: i64,
+    ) -> Result<Vec<SearchHit>> {
+        let text = text.trim();
+        let query = prefix_query(text);
+        let address = is_address(text).then(|| text.to_lowercase());
+
+        if query.is_none() && address.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let wants = |kind| kinds.contains(&kind);
+        let (nfts, collections, users) = tokio::try_join!(
+            self.search_nfts(wants(SearchKind::Nft), &query, &address, limit),
+            self.search_collections(wants(SearchKind::Collection), &query, &address, limit),
+            self.search_users(wants(SearchKind::User), &query, &address, limit),
+        )?;
+
+        let mut hits = nfts
+            .into_iter()
+            .map(|h| h.into_hit(SearchKind::Nft))
+            .chain(
+                collections
+                    .into_iter()
+                    .map(|h| h.into_hit(SearchKind::Collection)),
+            )
+            .chain(users.into_iter().map(|h| h.into_hit(SearchKind::User)))
+            .collect::<Vec<_>>();
+
+        hits.sort_by(|a, b| b.exact.cmp(&a.exact).then(b.rank.total_cmp(&a.rank)));
+
+        Ok(hits)
+    }
+
+    async fn search_nfts(
+        &self,
+        enabled: bool,
+        query: &Option<String>,
+        address: &Option<String>,
+        limit: i64,
+    ) -> Result<Vec<HitRow>> {
+        if !enabled {
+            return Ok(Vec::new());
+        }
+
+        sqlx::query_as!(
+            HitRow,
+            r#"
+                select
+                    address as "address!",
+                    name::text,
+                    collection::text,
+                    address is not distinct from $2 as "exact!",
+                    case
+                        when address = $2 then 1
+                        else ts_rank(name_search_vector(name, description), to_tsquery('simple', $1))
+                    end as "rank!"
+                from nft
+                where not burned
+                  and (address = $2
+                       or name_search_vector(name, description) @@ to_tsquery('simple', $1))
+                order by 5 desc, address
+                limit $3
+            "#,
+            query.as_deref(),
+            address.as_deref(),
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    /// Custom names of collections replace the ones from their contracts.
+    async fn search_collections(
+        &self,
+        enabled: bool,
+        query: &Option<String>,
+        address: &Option<String>,
+        limit: i64,
+    ) -> Result<Vec<HitRow>> {
+        if !enabled {
+            return Ok(Vec::new());
+        }
+
+        sqlx::query_as!(
+            HitRow,
+            r#"
+                with matched as (
+                    select c.address, ts_rank(name_search_vector(c.name, c.description), to_tsquery('simple', $1)) as rank
+                    from nft_collection c
+                        left join nft_collection_custom ncc on ncc.address = c.address
+                    where coalesce(ncc.name, ncc.description) is null
+                      and name_search_vector(c.name, c.description) @@ to_tsquery('simple', $1)
+
+                    union all
+
+                    select ncc.address, ts_rank(
+                        name_search_vector(coalesce(ncc.name, c.name), coalesce(ncc.description, c.description)),
+                        to_tsquery('simple', $1)
+                    )
+                    from nft_collection_custom ncc
+                        join nft_collection c on c.address = ncc.address
+                    where name_search_vector(coalesce(ncc.name, c.name), coalesce(ncc.description, c.description))
+                        @@ to_tsquery('simple', $1)
+
+                    union all
+
+                    select address, 1
+                    from nft_collection
+                    where address = $2
+                )
+                select
+                    m.address as "address!",
+                    coalesce(ncc.name, c.name) as name,
+                    null::text as collection,
+                    m.address is not distinct from $2 as "exact!",
+                    max(m.rank) as "rank!"
+                from matched m
+                    join nft_collection c on c.address = m.address
+                    left join nft_collection_custom ncc on ncc.address = m.address
+                group by m.address, ncc.name, c.name
+                order by 5 desc, m.address
+                limit $3
+            "#,
+            query.as_deref(),
+            address.as_deref(),
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    async fn search_users(
+        &self,
+        enabled: bool,
+        query: &Option<String>,
+        address: &Option<String>,
+        limit: i64,
+    ) -> Result<Vec<HitRow>> {
+        if !enabled {
+            return Ok(Vec::new());
+        }
+
+        sqlx::query_as!(
+            HitRow,
+            r#"
+                select
+                    address as "address!",
+                    username::text as name,
+                    null::text as collection,
+                    address is not distinct from $2 as "exact!",
+                    case
+                        when address = $2 then 1
+                        else ts_rank(name_search_vector(username, null), to_tsquery('simple', $1))
+                    end as "rank!"
+                from users
+                where address = $2
+                   or name_search_vector(username, null) @@ to_tsquery('simple', $1)
+                order by 5 desc, address
+                limit $3
+            "#,
+            query.as_deref(),
+            address.as_deref(),
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_prefix_queries() {
+        assert_eq!(
+            prefix_query("Venom  Punk #12").as_deref(),
+            Some("venom:* & punk:* & 12:*")
+        );
+        assert_eq!(prefix_query("it's"), Some("it:* & s:*".to_string()));
+        assert_eq!(prefix_query(" &|!:* "), None);
+    }
+
+    #[test]
+    fn detects_addresses() {
+        assert!(is_address(
+            "0:5a3b0a8e1b6c2f4e9d7c1a0b3e5f7d9c2a4b6e8f0a1c3e5d7b9f1a3c5e7d9b1f"
+        ));
+        assert!(!is_address("0:5a3b"));
+        assert!(!is_address("punk"));
+    }
+}
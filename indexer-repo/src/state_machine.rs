@@ -0,0 +1,214 @@
+use crate::types::{AuctionStatus, DirectBuyState, DirectSellState};
+
+/// Allowed state graph of an offer contract.
+///
+/// `Expired` is set by the expiry sweeper by chain time, so an on-chain
+/// result that was still in flight may follow it. Repeating the current state
+/// is always allowed: the same event can be reprocessed after a consumer restart.
+pub trait OfferStateMachine: Clone + PartialEq {
+    fn can_transition(&self, to: &Self) -> bool;
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TransitionCheck {
+    Valid,
+    /// The incoming change is older than the stored state and is already reflected in it.
+    Stale,
+    Invalid,
+}
+
+pub fn check_transition<S: OfferStateMachine>(
+    current: &S,
+    current_lt: i64,
+    to: &S,
+    to_lt: i64,
+) -> TransitionCheck {
+    if to_lt < current_lt {
+        TransitionCheck::Stale
+    } else if current == to || current.can_transition(to) {
+        TransitionCheck::Valid
+    } else {
+        TransitionCheck::Invalid
+    }
+}
+
+impl OfferStateMachine for DirectSellState {
+    fn can_transition(&self, to: &Self) -> bool {
+        use DirectSellState::*;
+
+        matches!(
+            (self, to),
+            (Create, AwaitNft | Active | Cancelled)
+                | (AwaitNft, Active | Cancelled)
+                | (Active, Filled | Cancelled | Expired)
+                | (Expired, Filled | Cancelled)
+        )
+    }
+}
+
+impl OfferStateMachine for DirectBuyState {
+    fn can_transition(&self, to: &Self) -> bool {
+        use DirectBuyState::*;
+
+        matches!(
+            (self, to),
+            (Create, AwaitTokens | Active | Cancelled)
+                | (AwaitTokens, Active | Cancelled)
+                | (Active, Filled | Cancelled | Expired)
+                | (Expired, Filled | Cancelled)
+        )
+    }
+}
+
+impl OfferStateMachine for AuctionStatus {
+    fn can_transition(&self, to: &Self) -> bool {
+        use AuctionStatus::*;
+
+        matches!(
+            (self, to),
+            (Created, Active | Cancelled)
+                | (Active, Completed | Cancelled | Expired)
+                | (Expired, Completed | Cancelled)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn direct_sell_state() -> impl Strategy<Value = DirectSellState> {
+        (0u8..6).prop_map(DirectSellState::from)
+    }
+
+    fn direct_buy_state() -> impl Strategy<Value = DirectBuyState> {
+        (0u8..6).prop_map(DirectBuyState::from)
+    }
+
+    fn auction_status() -> impl Strategy<Value = AuctionStatus> {
+        prop_oneof![
+            (0u8..4).prop_map(AuctionStatus::from),
+            Just(AuctionStatus::Expired)
+        ]
+    }
+
+    fn rank_sell(s: &DirectSellState) -> u8 {
+        match s {
+            DirectSellState::Create => 0,
+            DirectSellState::AwaitNft => 1,
+            DirectSellState::Active => 2,
+            DirectSellState::Expired => 3,
+            DirectSellState::Filled | DirectSellState::Cancelled => 4,
+        }
+    }
+
+    fn rank_buy(s: &DirectBuyState) -> u8 {
+        match s {
+            DirectBuyState::Create => 0,
+            DirectBuyState::AwaitTokens => 1,
+            DirectBuyState::Active => 2,
+            DirectBuyState::Expired => 3,
+            DirectBuyState::Filled | DirectBuyState::Cancelled => 4,
+        }
+    }
+
+    fn rank_auction(s: &AuctionStatus) -> u8 {
+        match s {
+            AuctionStatus::Created => 0,
+            AuctionStatus::Active => 1,
+            AuctionStatus::Expired => 2,
+            AuctionStatus::Completed | AuctionStatus::Cancelled => 3,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn direct_sell_never_goes_back(from in direct_sell_state(), to in direct_sell_state()) {
+            if from.can_transition(&to) {
+                prop_assert!(rank_sell(&from) < rank_sell(&to));
+            }
+        }
+
+        #[test]
+        fn direct_buy_never_goes_back(from in direct_buy_state(), to in direct_buy_state()) {
+            if from.can_transition(&to) {
+                prop_assert!(rank_buy(&from) < rank_buy(&to));
+            }
+        }
+
+        #[test]
+        fn auction_never_goes_back(from in auction_status(), to in auction_status()) {
+            if from.can_transition(&to) {
+                prop_assert!(rank_auction(&from) < rank_auction(&to));
+            }
+        }
+
+        #[test]
+        fn final_states_are_terminal(to in direct_sell_state(), buy_to in direct_buy_state(), auc_to in auction_status()) {
+            prop_assert!(!DirectSellState::Filled.can_transition(&to));
+            prop_assert!(!DirectSellState::Cancelled.can_transition(&to));
+            prop_assert!(!DirectBuyState::Filled.can_transition(&buy_to));
+            prop_assert!(!DirectBuyState::Cancelled.can_transition(&buy_to));
+            prop_assert!(!AuctionStatus::Completed.can_transition(&auc_to));
+            prop_assert!(!AuctionStatus::Cancelled.can_transition(&auc_to));
+        }
+
+        #[test]
+        fn only_active_offers_get_filled(from in direct_sell_state(), buy_from in direct_buy_state()) {
+            prop_assert_eq!(
+                from.can_transition(&DirectSellState::Filled),
+                from == DirectSellState::Active || from == DirectSellState::Expired
+            );
+            prop_assert_eq!(
+                buy_from.can_transition(&DirectBuyState::Filled),
+                buy_from == DirectBuyState::Active || buy_from == DirectBuyState::Expired
+            );
+        }
+
+        #[test]
+        fn every_state_is_reachable_from_create(to in direct_sell_state()) {
+            let mut reached = vec![DirectSellState::Create];
+            let mut i = 0;
+            while i < reached.len() {
+                for next in (0u8..6).map(DirectSellState::from) {
+                    if reached[i].can_transition(&next) && !reached.contains(&next) {
+                        reached.push(next);
+                    }
+                }
+                i += 1;
+            }
+            prop_assert!(reached.contains(&to));
+        }
+
+        #[test]
+        fn older_changes_are_stale(
+            current in direct_sell_state(),
+            to in direct_sell_state(),
+            lt in 1i64..i64::MAX,
+            back in 1i64..1_000,
+        ) {
+            prop_assert_eq!(
+                check_transition(&current, lt, &to, lt.saturating_sub(back)),
+                TransitionCheck::Stale
+            );
+        }
+
+        #[test]
+        fn check_follows_graph(
+            current in direct_buy_state(),
+            to in direct_buy_state(),
+            lt in 0i64..i64::MAX / 2,
+            forward in 0i64..1_000,
+        ) {
+            let expected = if current == to || current.can_transition(&to) {
+                TransitionCheck::Valid
+            } else {
+                TransitionCheck::Invalid
+            };
+
+            prop_assert_eq!(check_transition(&current, lt, &to, lt + forward), expected);
+        }
+    }
+}
@@ -0,0 +1,441 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use sqlx::{types::BigDecimal, PgPool, Postgres, Transaction};
+
+use crate::types::StatsPeriod;
+
+#[derive(Clone)]
+pub struct CollectionStatsModel {
+    pool: PgPool,
+}
+
+pub struct CollectionStatsBucket {
+    pub bucket: NaiveDateTime,
+    pub floor_price: Option<BigDecimal>,
+    pub floor_price_token: Option<String>,
+    pub floor_price_usd: Option<BigDecimal>,
+    pub volume_usd: BigDecimal,
+    /// Volume per payment token, amounts are strings
+    pub volume_token: serde_json::Value,
+    pub sales_count: i32,
+    pub unique_buyers: i32,
+    pub unique_sellers: i32,
+    pub owners_count: Option<i32>,
+    pub listed_count: Option<i32>,
+}
+
+impl CollectionStatsModel {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Re-aggregates every bucket holding sales saved since the last run and
+    /// snapshots floor, owners and listings into the current buckets. Sales
+    /// made within `lookback_sec` of chain time are aggregated again on the next
+    /// run, as their usd prices are filled in later.
+    pub async fn update_stats(&self, lookback_sec: i64) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        update_stats(&mut tx, lookback_sec).await?;
+        tx.commit().await.map_err(|e| anyhow!(e))
+    }
+
+    /// Buckets of a collection in chronological order.
+    pub async fn get_stats(
+        &self,
+        collection: &str,
+        period: StatsPeriod,
+        from: Option<NaiveDateTime>,
+        to: Option<NaiveDateTime>,
+        limit: i64,
+    ) -> Result<Vec<CollectionStatsBucket>> {
+        sqlx::query_as!(
+            CollectionStatsBucket,
+            r#"
+                select
+                    bucket,
+                    floor_price,
+                    floor_price_token::text,
+                    floor_price_usd,
+                    volume_usd,
+                    volume_token,
+                    sales_count,
+                    unique_buyers,
+                    unique_sellers,
+                    owners_count,
+                    listed_count
+                from collection_stats
+                where collection = $1
+                  and period = $2
+                  and ($3::timestamp is null or bucket >= $3)
+                  and ($4::timestamp is null or bucket < $4)
+                order by bucket
+                limit $5
+            "#,
+            collection,
+            period as _,
+            from,
+            to,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+}
+
+async fn update_stats(tx: &mut Transaction<'_, Postgres>, lookback_sec: i64) -> Result<()> {
+    let progress = sqlx::query!(
+        r#"
+            select sales_from, last_price_history_id
+            from collection_stats_progress
+            for update
+        "#
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    // Stats follow the latest indexed event, not wall clock
+    let Some(chain_time) = sqlx::query_scalar!(
+        r#"
+            select to_timestamp(max(created_at)) at time zone 'utc'
+            from nft_events
+        "#
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    else {
+        return Ok(());
+    };
+
+    let last_id = sqlx::query_scalar!(
+        r#"
+            select coalesce(max(id), 0) as "id!"
+            from nft_price_history
+        "#
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let sales = SalesRange {
+        after_id: progress.last_price_history_id,
+        last_id,
+        completed_from: progress.sales_from,
+    };
+
+    for period in StatsPeriod::ALL {
+        aggregate_sales(tx, period, &sales).await?;
+        snapshot_market(tx, period, chain_time).await?;
+    }
+
+    sqlx::query!(
+        r#"
+            update collection_stats_progress
+            set sales_from = $1::timestamp - make_interval(secs => $2),
+                last_price_history_id = $3
+        "#,
+        chain_time,
+        lookback_sec as f64,
+        last_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Sales whose buckets are aggregated again: the ones saved since the last run
+/// and the ones made recently enough to still be waiting for a usd price.
+struct SalesRange {
+    after_id: i64,
+    last_id: i64,
+    completed_from: NaiveDateTime,
+}
+
+async fn aggregate_sales(
+    tx: &mut Transaction<'_, Postgres>,
+    period: StatsPeriod,
+    range: &SalesRange,
+) -> Result<()> {
+    // Auction deals are taken from the auction and its top bid,
+    // direct sells and buys from the event that filled them
+    sqlx::query!(
+        r#"
+            with touched as (
+                select distinct collection, date_trunc($1::stats_period::text, completed_at) as bucket
+                from nft_price_history
+                where (id > $2 and id <= $3) or completed_at >= $4
+            ),
+            sales as (
+                select
+                    ph.collection,
+                    t.bucket,
+                    ph.price,
+                    ph.price_token,
+                    ph.usd_price,
+                    case
+                        when ph.source_type = 'auctionBid' then a.nft_owner::text
+                        else ev.args ->> 'old_owner'
+                    end as seller,
+                    case
+                        when ph.source_type = 'auctionBid' then bid.buyer::text
+                        else ev.args ->> 'new_owner'
+                    end as buyer
+                from touched t
+                join nft_price_history ph
+                    on ph.collection = t.collection
+                    and ph.completed_at >= t.bucket
+                    and ph.completed_at < t.bucket + ('1 ' || $1::stats_period::text)::interval
+                left join nft_auction a
+                    on ph.source_type = 'auctionBid' and a.address = ph.source
+                left join lateral (
+                    select b.buyer
+                    from nft_auction_bid b
+                    where ph.source_type = 'auctionBid'
+                      and b.auction = ph.source
+                      and not b.declined
+                    order by b.price desc
+                    limit 1
+                ) bid on true
+                left join lateral (
+                    select e.args
+                    from nft_events e
+                    where ph.source_type <> 'auctionBid'
+                      and e.address = ph.source
+                      and e.computed_event_kind in ('sell_purchased', 'offer_filled')
+                    order by e.created_lt, e.id
+                    limit 1
+                ) ev on true
+                where ph.id <= $3
+            ),
+            token_volumes as (
+                select collection, bucket, jsonb_object_agg(price_token, volume) as volume_token
+                from (
+                    select collection, bucket, price_token, sum(price)::text as volume
+                    from sales
+                    group by collection, bucket, price_token
+                ) v
+                group by collection, bucket
+            )
+            insert into collection_stats (
+                collection,
+                period,
+                bucket,
+                volume_usd,
+                volume_token,
+                sales_count,
+                unique_buyers,
+                unique_sellers,
+                updated
+            )
+            select
+                s.collection,
+                $1,
+                s.bucket,
+                coalesce(sum(s.usd_price), 0),
+                tv.volume_token,
+                count(*),
+                count(distinct s.buyer),
+                count(distinct s.seller),
+                now()
+            from sales s
+            join token_volumes tv on tv.collection = s.collection and tv.bucket = s.bucket
+            group by s.collection, s.bucket, tv.volume_token
+            on conflict (collection, period, bucket) do update set
+                volume_usd = excluded.volume_usd,
+                volume_token = excluded.volume_token,
+                sales_count = excluded.sales_count,
+                unique_buyers = excluded.unique_buyers,
+                unique_sellers = excluded.unique_sellers,
+                updated = excluded.updated
+        "#,
+        period as _,
+        range.after_id,
+        range.last_id,
+        range.completed_from
+    )
+    .execute(tx)
+    .await
+    .map_err(|e| anyhow!(e))
+    .map(|_| ())
+}
+
+/// Past buckets keep the last snapshot taken within them.
+async fn snapshot_market(
+    tx: &mut Transaction<'_, Postgres>,
+    period: StatsPeriod,
+    chain_time: NaiveDateTime,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+            with active_sells as (
+                select s.collection, s.price, s.price_token, s.price * p.usd_price as usd_price
+                from nft_direct_sell s
+                    join offers_whitelist ow on ow.address = s.address
+                    left join token_usd_prices p on p.token = s.price_token
+                where s.state = 'active'
+                  and (s.expired_at = to_timestamp(0) or s.expired_at > $2)
+            ),
+            floors as (
+                select distinct on (collection) collection, price, price_token, usd_price
+                from active_sells
+                order by collection, usd_price nulls last
+            ),
+            listed as (
+                select collection, count(*) as listed_count
+                from (
+                    select collection from active_sells
+                    union all
+                    select a.collection
+                    from nft_auction a
+                        join offers_whitelist ow on ow.address = a.address
+                    where a.status = 'active'
+                      and (a.finished_at = to_timestamp(0) or a.finished_at > $2)
+                ) l
+                group by collection
+            ),
+            owners as (
+                select collection, count(distinct owner) as owners_count
+                from nft
+                where not burned
+                group by collection
+            )
+            insert into collection_stats (
+                collection,
+                period,
+                bucket,
+                floor_price,
+                floor_price_token,
+                floor_price_usd,
+                owners_count,
+                listed_count,
+                updated
+            )
+            select
+                o.collection,
+                $1,
+                date_trunc($1::stats_period::text, $2::timestamp),
+                f.price,
+                f.price_token,
+                f.usd_price,
+                o.owners_count,
+                coalesce(l.listed_count, 0),
+                now()
+            from owners o
+                left join floors f on f.collection = o.collection
+                left join listed l on l.collection = o.collection
+            on conflict (collection, period, bucket) do update set
+                floor_price = excluded.floor_price,
+                floor_price_token = excluded.floor_price_token,
+                floor_price_usd = excluded.floor_price_usd,
+                owners_count = excluded.owners_count,
+                listed_count = excluded.listed_count,
+                updated = excluded.updated
+        "#,
+        period as _,
+        chain_time
+    )
+    .execute(tx)
+    .await
+    .map_err(|e| anyhow!(e))
+    .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    // Ahead of any real event, 400 seconds into an hour
+    const CHAIN_TIME: i64 = 4_000_000_000;
+
+    async fn hourly(
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Vec<(i64, i32, BigDecimal, Option<i32>)> {
+        sqlx::query_as(
+            r#"
+                select extract(epoch from bucket)::bigint, sales_count, volume_usd, owners_count
+                from collection_stats
+                where collection = '0:c35' and period = 'hour'
+                order by bucket
+            "#,
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn aggregates_sales_by_completion_and_snapshots_at_chain_time() {
+        let mut tx = test_db::begin().await;
+
+        sqlx::query(
+            r#"
+                insert into nft_events (event_cat, event_type, address, created_lt, created_at, message_hash)
+                values ('nft', 'nft_created', '0:n35', 1, $1, 'test:n35')
+            "#,
+        )
+        .bind(CHAIN_TIME)
+        .execute(&mut tx)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"
+                update collection_stats_progress
+                set last_price_history_id = (select coalesce(max(id), 0) from nft_price_history),
+                    sales_from = to_timestamp($1) at time zone 'utc'
+            "#,
+        )
+        .bind(CHAIN_TIME as f64)
+        .execute(&mut tx)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"
+                insert into nft (address, collection, owner, manager, burned, updated, owner_update_lt, manager_update_lt, id)
+                values ('0:n35', '0:c35', '0:o35', '0:o35', false, now(), 1, 1, 1)
+            "#,
+        )
+        .execute(&mut tx)
+        .await
+        .unwrap();
+        // The auction started a month before it was completed
+        sqlx::query(
+            r#"
+                insert into nft_price_history (source, source_type, ts, completed_at, price, price_token, nft, collection, usd_price)
+                values
+                    ('0:a35', 'auctionBid', to_timestamp($1 - 2592000) at time zone 'utc', to_timestamp($1 - 1800) at time zone 'utc', 5, '0:t35', '0:n35', '0:c35', null),
+                    ('0:s35', 'directSell', to_timestamp($1 - 1800) at time zone 'utc', to_timestamp($1 - 1800) at time zone 'utc', 3, '0:t35', '0:n35', '0:c35', 3)
+            "#,
+        )
+        .bind(CHAIN_TIME as f64)
+        .execute(&mut tx)
+        .await
+        .unwrap();
+
+        update_stats(&mut tx, 86_400).await.unwrap();
+
+        let sales_bucket = (CHAIN_TIME - 1800) / 3600 * 3600;
+        let market_bucket = CHAIN_TIME / 3600 * 3600;
+        assert_eq!(
+            hourly(&mut tx).await,
+            [
+                (sales_bucket, 2, BigDecimal::from(3), None),
+                (market_bucket, 0, BigDecimal::from(0), Some(1)),
+            ]
+        );
+
+        // The auction gets its usd price after the run and is picked up by the lookback
+        sqlx::query("update nft_price_history set usd_price = 5 where source = '0:a35'")
+            .execute(&mut tx)
+            .await
+            .unwrap();
+
+        update_stats(&mut tx, 86_400).await.unwrap();
+
+        assert_eq!(
+            hourly(&mut tx).await[0],
+            (sales_bucket, 2, BigDecimal::from(8), None)
+        );
+    }
+}
@@ -0,0 +1,13 @@
+use sqlx::{PgPool, Postgres, Transaction};
+
+/// Pool of the migrated database at `DATABASE_URL`
+pub async fn pool() -> PgPool {
+    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is set");
+
+    PgPool::connect(&url).await.expect("database is reachable")
+}
+
+/// Transaction on the test database, rolled back when dropped
+pub async fn begin() -> Transaction<'static, Postgres> {
+    pool().await.begin().await.expect("transaction starts")
+}
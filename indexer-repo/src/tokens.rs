@@ -0,0 +1,131 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+
+#[derive(Clone)]
+pub struct TokenModel {
+    pool: PgPool,
+}
+
+/// Metadata of a tip-3 root.
+pub struct TokenInfo {
+    pub address: String,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<i32>,
+    pub icon: Option<String>,
+    pub updated: NaiveDateTime,
+}
+
+impl TokenModel {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Payment tokens of offers and price history and tokens of dex pairs
+    /// missing in the registry.
+    /// Roots that failed to read are retried after `retry_after_sec`.
+    pub async fn get_tokens_for_update(
+        &self,
+        retry_after_sec: i64,
+        limit: i64,
+    ) -> Result<Vec<String>> {
+        sqlx::query_scalar!(
+            r#"
+                with seen as (
+                    select price_token as token from nft_direct_sell
+                    union
+                    select price_token from nft_direct_buy
+                    union
+                    select price_token from nft_auction where price_token is not null
+                    union
+                    select price_token from nft_price_history
+                    union
+                    select left_token from dex_pairs
+                    union
+                    select right_token from dex_pairs
+                )
+                select s.token as "token!"
+                from seen s
+                left join tokens t on t.address = s.token
+                where t.address is null
+                   or (t.failed and t.updated < now()::timestamp - make_interval(secs => $1))
+                limit $2
+            "#,
+            retry_after_sec as f64,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn save_token(&self, token: &TokenInfo) -> Result<()> {
+        sqlx::query!(
+            r#"
+                insert into tokens (address, name, symbol, decimals, icon, failed, updated)
+                values ($1, $2, $3, $4, $5, false, $6)
+                on conflict (address) do update set
+                    name = excluded.name,
+                    symbol = excluded.symbol,
+                    decimals = excluded.decimals,
+                    icon = excluded.icon,
+                    failed = false,
+                    updated = excluded.updated
+            "#,
+            token.address as _,
+            token.name,
+            token.symbol,
+            token.decimals,
+            token.icon,
+            token.updated
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+        .map(|_| ())
+    }
+
+    /// Keeps metadata read before, the root is retried later.
+    pub async fn mark_failed(&self, address: &str, updated: NaiveDateTime) -> Result<()> {
+        sqlx::query!(
+            r#"
+                insert into tokens (address, failed, updated)
+                values ($1, true, $2)
+                on conflict (address) do update set
+                    failed = true,
+                    updated = excluded.updated
+            "#,
+            address as _,
+            updated
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+        .map(|_| ())
+    }
+
+    /// Tokens read successfully, all of them when `addresses` is `None`.
+    pub async fn get_tokens(&self, addresses: Option<&[String]>) -> Result<Vec<TokenInfo>> {
+        sqlx::query_as!(
+            TokenInfo,
+            r#"
+                select
+                    address as "address!",
+                    name,
+                    symbol,
+                    decimals,
+                    icon,
+                    updated
+                from tokens
+                where decimals is not null
+                and ($1::varchar[] is null or address = any($1))
+                order by address
+            "#,
+            addresses
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+}
@@ -1,8 +1,9 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug, Serialize, sqlx::Type)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
 #[sqlx(type_name = "event_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum EventType {
     AuctionDeployed,
     AuctionCreated,
@@ -12,14 +13,17 @@ pub enum EventType {
     AuctionBidDeclined,
     AuctionCancelled,
     AuctionComplete,
+    AuctionExpired,
 
     DirectBuyDeployed,
     DirectBuyDeclined,
     DirectBuyStateChanged,
+    DirectBuyExpired,
 
     DirectSellDeployed,
     DirectSellDeclined,
     DirectSellStateChanged,
+    DirectSellExpired,
 
     NftOwnerChanged,
     NftManagerChanged,
@@ -43,6 +47,7 @@ impl sqlx::postgres::PgHasArrayType for EventType {
 
 #[derive(Copy, Clone, Debug, Serialize, sqlx::Type)]
 #[sqlx(type_name = "event_category", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum EventCategory {
     Auction,
     DirectBuy,
@@ -58,7 +63,35 @@ impl sqlx::postgres::PgHasArrayType for EventCategory {
     }
 }
 
-#[derive(Clone, Debug, Serialize, sqlx::Type)]
+/// Marketplace level kind of an event, computed by a trigger on `nft_events`
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "event_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Mint,
+    Transfer,
+    AuctionActive,
+    AuctionBidPlaced,
+    AuctionCanceled,
+    AuctionComplete,
+    AuctionExpired,
+    OfferActive,
+    OfferFilled,
+    OfferCanceled,
+    OfferExpired,
+    SellActive,
+    SellPurchased,
+    SellCanceled,
+    SellExpired,
+}
+
+impl sqlx::postgres::PgHasArrayType for EventKind {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_event_kind")
+    }
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq, Eq, sqlx::Type)]
 #[sqlx(type_name = "auction_status", rename_all = "snake_case")]
 pub enum AuctionStatus {
     Created = 0,
@@ -68,8 +101,15 @@ pub enum AuctionStatus {
     Expired,
 }
 
-#[derive(Clone, Debug, Serialize, PartialEq, Eq, sqlx::Type)]
+impl sqlx::postgres::PgHasArrayType for AuctionStatus {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_auction_status")
+    }
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq, Eq, Hash, sqlx::Type)]
 #[sqlx(type_name = "direct_sell_state", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum DirectSellState {
     Create = 0,
     AwaitNft,
@@ -85,8 +125,9 @@ impl sqlx::postgres::PgHasArrayType for DirectSellState {
     }
 }
 
-#[derive(Clone, Debug, Serialize, PartialEq, Eq, sqlx::Type)]
+#[derive(Clone, Debug, Serialize, PartialEq, Eq, Hash, sqlx::Type)]
 #[sqlx(type_name = "direct_buy_state", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum DirectBuyState {
     Create = 0,
     AwaitTokens,
@@ -116,6 +157,31 @@ impl sqlx::postgres::PgHasArrayType for NftPriceSource {
     }
 }
 
+/// Bucket size of collection stats, named after the `date_trunc` field
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "stats_period", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum StatsPeriod {
+    Hour,
+    Day,
+}
+
+impl StatsPeriod {
+    pub const ALL: [StatsPeriod; 2] = [StatsPeriod::Hour, StatsPeriod::Day];
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "rarity_algorithm", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum RarityAlgorithm {
+    /// Sum of the log frequencies of the traits
+    Statistical,
+    /// Information content of the traits, normalized by the collection entropy
+    InformationContent,
+    /// Frequency of the number of traits
+    TraitCount,
+}
+
 impl From<u8> for DirectSellState {
     fn from(state: u8) -> Self {
         match state {
@@ -163,6 +229,48 @@ pub enum BcName {
     Venom,
 }
 
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "webhook_notification_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookNotificationKind {
+    Outbid,
+    ListingFilled,
+    OfferReceived,
+    AuctionEnding,
+}
+
+impl sqlx::postgres::PgHasArrayType for WebhookNotificationKind {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_webhook_notification_kind")
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "webhook_delivery_status", rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "media_kind", rename_all = "snake_case")]
+pub enum MediaKind {
+    NftPreview,
+    CollectionLogo,
+    CollectionWallpaper,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "media_status", rename_all = "snake_case")]
+pub enum MediaStatus {
+    Ready,
+    /// Retried at `next_attempt_at` unless it's out of attempts
+    Failed,
+    /// Not an image or too large, not retried until the source changes
+    Unsupported,
+}
+
 pub struct NftCollection {
     pub address: String,
     pub nft_first_mint: NaiveDateTime,
@@ -179,9 +287,36 @@ pub struct NftCollectionMeta {
     pub wallpaper: Option<String>,
 }
 
+pub struct OfferStateAnomaly {
+    pub address: String,
+    pub transition: OfferStateTransition,
+    pub current_tx_lt: i64,
+    pub incoming_tx_lt: i64,
+    pub context: serde_json::Value,
+}
+
+/// Rejected state change of an offer, `from` -> `to`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OfferStateTransition {
+    Auction(AuctionStatus, AuctionStatus),
+    DirectSell(DirectSellState, DirectSellState),
+    DirectBuy(DirectBuyState, DirectBuyState),
+}
+
+impl OfferStateTransition {
+    pub fn offer_type(&self) -> EventCategory {
+        match self {
+            Self::Auction(..) => EventCategory::Auction,
+            Self::DirectSell(..) => EventCategory::DirectSell,
+            Self::DirectBuy(..) => EventCategory::DirectBuy,
+        }
+    }
+}
+
 pub mod decoded {
     use crate::types::{DirectBuyState, DirectSellState, EventCategory, EventType, NftPriceSource};
     use chrono::NaiveDateTime;
+    use serde::Serialize;
     use sqlx::types::BigDecimal;
 
     #[derive(Clone, Debug)]
@@ -189,6 +324,8 @@ pub mod decoded {
         pub source: String,
         pub source_type: NftPriceSource,
         pub created_at: NaiveDateTime,
+        /// When the deal was made, the auction start is its `created_at`
+        pub completed_at: NaiveDateTime,
         pub price: BigDecimal,
         pub price_token: String,
         pub usd_price: Option<BigDecimal>,
@@ -196,7 +333,7 @@ pub mod decoded {
         pub collection: String,
     }
 
-    #[derive(Clone, Debug)]
+    #[derive(Clone, Debug, Serialize)]
     pub struct EventRecord {
         pub event_category: EventCategory,
         pub event_type: EventType,
@@ -208,6 +345,7 @@ pub mod decoded {
         pub nft: Option<String>,
         pub collection: Option<String>,
 
+        #[serde(rename = "args")]
         pub raw_data: serde_json::Value,
     }
 
@@ -273,10 +411,12 @@ pub mod decoded {
     pub struct AuctionComplete {
         pub address: String,
         pub max_bid: BigDecimal,
+        pub tx_lt: i64,
     }
 
     pub struct AuctionCancelled {
         pub address: String,
+        pub tx_lt: i64,
     }
 
     pub struct CollectionFee {
@@ -318,6 +458,22 @@ pub mod decoded {
         pub tx_lt: i64,
     }
 
+    pub struct DirectSellStateTransition {
+        pub address: String,
+        pub from: DirectSellState,
+        pub to: DirectSellState,
+        pub tx_lt: i64,
+        pub created_at: NaiveDateTime,
+    }
+
+    pub struct DirectBuyStateTransition {
+        pub address: String,
+        pub from: DirectBuyState,
+        pub to: DirectBuyState,
+        pub tx_lt: i64,
+        pub created_at: NaiveDateTime,
+    }
+
     pub struct OfferDeployed {
         pub address: String,
         pub root: String,
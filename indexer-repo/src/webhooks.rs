@@ -0,0 +1,307 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+
+use crate::types::{WebhookDeliveryStatus, WebhookNotificationKind};
+
+#[derive(Clone)]
+pub struct WebhookModel {
+    pool: PgPool,
+}
+
+pub struct NewWebhook {
+    pub url: String,
+    pub secret: String,
+    pub owner: Option<String>,
+    pub nft: Option<String>,
+    pub collection: Option<String>,
+    pub kinds: Vec<WebhookNotificationKind>,
+}
+
+pub struct CreatedWebhook {
+    pub id: i64,
+    /// Credential for managing the webhook, only its hash is stored
+    pub management_token: String,
+}
+
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub kind: WebhookNotificationKind,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    pub last_response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+}
+
+pub struct PendingDelivery {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub url: String,
+    pub secret: String,
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+}
+
+pub struct DeliveryAttempt {
+    pub delivery_id: i64,
+    pub attempt: i32,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+    pub status: WebhookDeliveryStatus,
+    pub retry_after_sec: i64,
+}
+
+impl WebhookModel {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create_webhook(&self, webhook: &NewWebhook) -> Result<CreatedWebhook> {
+        sqlx::query_as!(
+            CreatedWebhook,
+            r#"
+                with token as (
+                    select replace(gen_random_uuid()::text || gen_random_uuid()::text, '-', '') as value
+                )
+                insert into webhooks (url, secret, owner, nft, collection, kinds, management_token_hash)
+                select $1, $2, $3, $4, $5, $6, sha256(convert_to(token.value, 'utf8'))
+                from token
+                returning id, (select value from token) as "management_token!"
+            "#,
+            webhook.url,
+            webhook.secret,
+            webhook.owner as _,
+            webhook.nft as _,
+            webhook.collection as _,
+            webhook.kinds as _,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn delete_webhook(&self, id: i64, management_token: &str) -> Result<bool> {
+        sqlx::query!(
+            r#"
+                delete from webhooks
+                where id = $1 and management_token_hash = sha256(convert_to($2, 'utf8'))
+            "#,
+            id,
+            management_token,
+        )
+        .execute(&self.pool)
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn get_deliveries(
+        &self,
+        webhook_id: i64,
+        management_token: &str,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+                select
+                    d.id,
+                    d.kind as "kind: WebhookNotificationKind",
+                    d.status as "status: WebhookDeliveryStatus",
+                    d.attempts,
+                    a.response_status as "last_response_status?",
+                    a.error as "last_error?",
+                    d.created_at,
+                    d.delivered_at
+                from webhook_deliveries d
+                join webhooks w on w.id = d.webhook_id
+                left join webhook_delivery_attempts a
+                    on a.delivery_id = d.id and a.attempt = d.attempts
+                where d.webhook_id = $1
+                  and w.management_token_hash = sha256(convert_to($2, 'utf8'))
+                order by d.id desc
+                limit $3
+            "#,
+            webhook_id,
+            management_token,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    /// Queues a notification for auctions finishing within `window_sec` of chain time,
+    /// addressed to the seller and to the current highest bidder.
+    pub async fn enqueue_auction_ending(&self, window_sec: i64) -> Result<u64> {
+        sqlx::query!(
+            r#"
+                with chain as (
+                    select to_timestamp(max(created_at)) at time zone 'utc' as time
+                    from nft_events
+                ),
+                ending as (
+                    select a.*
+                    from nft_auction a, chain
+                    where a.status = 'active'
+                      and a.finished_at > chain.time
+                      and a.finished_at <= chain.time + $1::bigint * interval '1 second'
+                ),
+                notifications as (
+                    select e.address, e.nft, e.collection, e.finished_at, e.max_bid, e.nft_owner::text as recipient
+                    from ending e
+
+                    union all
+
+                    select e.address, e.nft, e.collection, e.finished_at, e.max_bid, top.buyer::text
+                    from ending e
+                    join lateral (
+                        select b.buyer
+                        from nft_auction_bid b
+                        where b.auction = e.address and not b.declined
+                        order by b.tx_lt desc
+                        limit 1
+                    ) top on true
+                )
+                insert into webhook_deliveries (webhook_id, kind, dedup_key, payload)
+                select
+                    w.id,
+                    'auction_ending',
+                    'auction_ending:' || n.address || ':' || n.recipient,
+                    jsonb_build_object(
+                        'kind', 'auction_ending',
+                        'recipient', n.recipient,
+                        'address', n.address,
+                        'nft', n.nft,
+                        'collection', n.collection,
+                        'max_bid', n.max_bid::text,
+                        'finished_at', extract(epoch from n.finished_at)::bigint
+                    )
+                from notifications n
+                join webhooks w on
+                    (w.owner is null or w.owner = n.recipient)
+                    and (w.nft is null or w.nft = n.nft)
+                    and (w.collection is null or w.collection = n.collection)
+                    and (w.kinds = '{}' or 'auction_ending' = any(w.kinds))
+                on conflict (webhook_id, dedup_key) do nothing
+            "#,
+            window_sec,
+        )
+        .execute(&self.pool)
+        .await
+        .map(|r| r.rows_affected())
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn get_due_deliveries(&self, limit: i64) -> Result<Vec<PendingDelivery>> {
+        sqlx::query_as!(
+            PendingDelivery,
+            r#"
+                select
+                    d.id,
+                    d.webhook_id,
+                    w.url,
+                    w.secret,
+                    d.payload,
+                    d.attempts
+                from webhook_deliveries d
+                join webhooks w on w.id = d.webhook_id
+                where d.status = 'pending' and d.next_attempt_at <= now()
+                order by d.next_attempt_at
+                limit $1
+            "#,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| anyhow!(e))
+    }
+
+    pub async fn record_delivery_attempt(&self, attempt: &DeliveryAttempt) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+                insert into webhook_delivery_attempts (
+                    delivery_id,
+                    attempt,
+                    response_status,
+                    error,
+                    duration_ms
+                )
+                values ($1, $2, $3, $4, $5)
+                on conflict do nothing
+            "#,
+            attempt.delivery_id,
+            attempt.attempt,
+            attempt.response_status,
+            attempt.error,
+            attempt.duration_ms,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                update webhook_deliveries set
+                    attempts = $2,
+                    status = $3::webhook_delivery_status,
+                    next_attempt_at = now() + $4::bigint * interval '1 second',
+                    delivered_at = case when $3::webhook_delivery_status = 'delivered' then now() end
+                where id = $1
+            "#,
+            attempt.delivery_id,
+            attempt.attempt,
+            attempt.status as _,
+            attempt.retry_after_sec,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_db;
+
+    #[tokio::test]
+    #[ignore = "needs the migrated database at DATABASE_URL"]
+    async fn webhook_is_managed_with_its_token() {
+        let pool = test_db::pool().await;
+        let model = WebhookModel::new(pool);
+
+        let created = model
+            .create_webhook(&NewWebhook {
+                url: "https://example.com/hook".to_string(),
+                secret: "signing-secret-0123".to_string(),
+                owner: None,
+                nft: None,
+                collection: None,
+                kinds: vec![],
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(created.management_token.len(), 64);
+        assert!(!model
+            .delete_webhook(created.id, "signing-secret-0123")
+            .await
+            .unwrap());
+        assert!(model
+            .get_deliveries(created.id, &created.management_token, 10)
+            .await
+            .is_ok());
+        assert!(model
+            .delete_webhook(created.id, &created.management_token)
+            .await
+            .unwrap());
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+use data_reader::{PriceProviders, Repricer};
+use indexer_repo::price::RepriceFilter;
+use sqlx::PgPool;
+
+use crate::settings::config::Config;
+use crate::utils::timestamp_to_datetime;
+
+/// Runs the indexer when no command is given
+#[derive(Parser)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Recompute usd prices of the price history
+    Reprice(RepriceArgs),
+}
+
+#[derive(Args)]
+pub struct RepriceArgs {
+    /// Unix time the rows are created at or after
+    #[arg(long)]
+    from: Option<i64>,
+    /// Unix time the rows are created before
+    #[arg(long)]
+    to: Option<i64>,
+    /// Price token address
+    #[arg(long)]
+    token: Option<String>,
+    /// Collection address
+    #[arg(long)]
+    collection: Option<String>,
+    /// Request the candles of the route pairs from the providers again
+    #[arg(long, conflicts_with = "dry_run")]
+    refetch_candles: bool,
+    /// Print old and new usd prices from the stored candles without writing anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+pub async fn reprice(config: &Config, pool: PgPool, args: RepriceArgs) -> Result<()> {
+    let filter = RepriceFilter {
+        from: args.from.map(timestamp_to_datetime),
+        to: args.to.map(timestamp_to_datetime),
+        token: args.token,
+        collection: args.collection,
+    };
+
+    let repricer = Repricer::new(
+        pool,
+        config.bc_name,
+        PriceProviders::from_config(&config.price_providers)?,
+        config.price_request_interval_ms,
+    )
+    .await?;
+
+    if args.refetch_candles {
+        repricer.refetch_candles(&filter).await?;
+    }
+
+    println!("id\ttoken\told_usd_price\tnew_usd_price\troute");
+
+    let (mut total, mut changed) = (0, 0);
+    let mut after_id = 0;
+    while let Some((last_id, repriced)) = repricer
+        .reprice_batch(&filter, after_id, args.dry_run)
+        .await?
+    {
+        for row in repriced {
+            total += 1;
+            if row.old_usd_price.as_ref() != Some(&row.new_usd_price) {
+                changed += 1;
+            }
+
+            println!(
+                "{}\t{}\t{}\t{}\t{}",
+                row.id,
+                row.token,
+                row.old_usd_price
+                    .map(|p| p.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                row.new_usd_price,
+                row.route.join(",")
+            );
+        }
+
+        after_id = last_id;
+    }
+
+    if args.dry_run {
+        println!("{total} rows repriced, {changed} would change (dry run, nothing saved)");
+    } else {
+        println!("{total} rows repriced, {changed} changed");
+    }
+
+    Ok(())
+}
@@ -1,12 +1,21 @@
+use crate::commands::{Cli, Command};
 use crate::settings::config::Config;
 use anyhow::Result;
-use data_reader::{MetaReaderContext, PriceReader};
-use indexer_api::run_api;
+use clap::Parser;
+use data_reader::{
+    CollectionStatsContext, ExpirySweeperContext, FiatRateUpdaterContext, MediaProcessorContext,
+    MetaReaderContext, OffchainResolver, PriceProviders, PriceReader, RarityUpdaterContext,
+    TokenRegistryContext, WebhookSenderContext,
+};
+use indexer_api::{run_api, ApiKeys};
 use std::net::SocketAddr;
 use std::panic;
 use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::broadcast;
 
 mod abi;
+mod commands;
 mod models;
 mod parser;
 mod persistence;
@@ -16,8 +25,12 @@ mod utils;
 extern crate num;
 extern crate num_derive;
 
+const LIVE_EVENTS_CAPACITY: usize = 4096;
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
     let default_hook = panic::take_hook();
     panic::set_hook(Box::new(move |panic_info| {
         default_hook(panic_info);
@@ -41,40 +54,127 @@ async fn main() -> Result<()> {
         .run(&pg_pool)
         .await?;
 
-    let jrpc_client = settings::get_jrpc_client(&config).await?;
-    log::info!("Connected to jrpc endpoint");
+    if let Some(Command::Reprice(args)) = cli.command {
+        return commands::reprice(&config, pg_pool, args).await;
+    }
+
+    let (live_events, _) = broadcast::channel(LIVE_EVENTS_CAPACITY);
+
+    let jrpc_pool = settings::get_jrpc_pool(&config).await?;
+    log::info!("Connected to jrpc endpoints");
+
+    let price_providers = PriceProviders::from_config(&config.price_providers)?;
 
     let price_reader = PriceReader::new(
         pg_pool.clone(),
         config.bc_name,
+        price_providers,
         config.idle_after_price_loop_sec,
         config.price_update_frequency_sec,
+        config.price_request_interval_ms,
     )
     .await;
 
     tokio::spawn(price_reader.clone().run_db_updater());
 
+    let offchain_resolver = config
+        .offchain_meta
+        .as_ref()
+        .map(OffchainResolver::from_config)
+        .transpose()?
+        .map(Arc::new);
+
     let meta_reader_context = MetaReaderContext {
-        jrpc_client: jrpc_client.clone(),
+        jrpc_pool: jrpc_pool.clone(),
+        offchain: offchain_resolver.clone(),
         pool: pg_pool.clone(),
-        jrpc_req_latency_millis: config.jrpc_req_latency_millis,
+        concurrency: config.meta_reader_concurrency,
         idle_after_loop: config.idle_after_meta_loop_sec,
+        metrics: Default::default(),
     };
 
     tokio::spawn(data_reader::run_meta_reader(meta_reader_context.clone()));
 
+    let expiry_sweeper_context = ExpirySweeperContext {
+        pool: pg_pool.clone(),
+        idle_after_loop: config.idle_after_expiry_loop_sec,
+        live_events: live_events.clone(),
+    };
+
+    tokio::spawn(data_reader::run_expiry_sweeper(expiry_sweeper_context));
+
+    let webhook_sender_context = WebhookSenderContext {
+        pool: pg_pool.clone(),
+        idle_after_loop: config.idle_after_webhook_loop_sec,
+        auction_ending_window_sec: config.webhook_auction_ending_window_sec,
+    };
+
+    tokio::spawn(data_reader::run_webhook_sender(webhook_sender_context));
+
+    let collection_stats_context = CollectionStatsContext {
+        pool: pg_pool.clone(),
+        idle_after_loop: config.idle_after_stats_loop_sec,
+    };
+
+    tokio::spawn(data_reader::run_collection_stats(collection_stats_context));
+
+    let rarity_updater_context = RarityUpdaterContext {
+        pool: pg_pool.clone(),
+        idle_after_loop: config.idle_after_rarity_loop_sec,
+    };
+
+    tokio::spawn(data_reader::run_rarity_updater(rarity_updater_context));
+
+    let token_registry_context = TokenRegistryContext {
+        jrpc_pool: jrpc_pool.clone(),
+        pool: pg_pool.clone(),
+        jrpc_req_latency_millis: config.jrpc_req_latency_millis,
+        idle_after_loop: config.idle_after_token_loop_sec,
+    };
+
+    tokio::spawn(data_reader::run_token_registry(token_registry_context));
+
+    if let Some(fiat_rates) = &config.fiat_rates {
+        let fiat_rate_updater_context =
+            FiatRateUpdaterContext::from_config(pg_pool.clone(), fiat_rates)?;
+
+        tokio::spawn(data_reader::run_fiat_rate_updater(
+            fiat_rate_updater_context,
+        ));
+    }
+
+    if let Some(media) = &config.media {
+        let resolver = match &offchain_resolver {
+            Some(resolver) => resolver.clone(),
+            None => Arc::new(OffchainResolver::from_config(&Default::default())?),
+        };
+        let media_processor_context =
+            MediaProcessorContext::from_config(pg_pool.clone(), media, resolver)?;
+
+        tokio::spawn(data_reader::run_media_processor(media_processor_context));
+    }
+
     tokio::spawn(parser::start_parsing(
         config.clone(),
         pg_pool.clone(),
-        price_reader,
+        price_reader.clone(),
+        live_events.clone(),
     ));
 
     let socket_addr: SocketAddr =
         SocketAddr::from_str(&config.server_api_url).expect("Invalid socket addr");
 
-    run_api(&socket_addr, meta_reader_context)
-        .await
-        .expect("Failed to run server");
+    let api_keys = ApiKeys::new(config.api_keys.clone().unwrap_or_default());
+
+    run_api(
+        &socket_addr,
+        meta_reader_context,
+        live_events,
+        price_reader,
+        api_keys,
+    )
+    .await
+    .expect("Failed to run server");
 
     Ok(())
 }
@@ -8,11 +8,14 @@ use data_reader::PriceReader;
 use futures::channel::mpsc::{Receiver, Sender};
 use futures::{future, SinkExt, StreamExt};
 use indexer_repo::batch::*;
-use indexer_repo::types::NftCollection;
+use indexer_repo::events::{LiveEvent, OfferStateChange};
+use indexer_repo::types::{EventType, NftCollection};
 use nekoton_abi::transaction_parser::{ExtractedOwned, ParsedType};
 use nekoton_abi::UnpackAbiPlain;
 use sqlx::PgPool;
+use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use transaction_buffer::models::{BufferedConsumerChannels, RawTransaction};
 
 const EVENTS_PER_ITERATION: usize = 1000;
@@ -21,6 +24,7 @@ pub async fn start_parsing(
     config: settings::config::Config,
     pg_pool: PgPool,
     price_reader: Arc<PriceReader>,
+    live_events: broadcast::Sender<LiveEvent>,
 ) -> Result<()> {
     let BufferedConsumerChannels {
         rx_parsed_events,
@@ -35,6 +39,7 @@ pub async fn start_parsing(
         tx_commit,
         pg_pool,
         price_reader,
+        live_events,
     ));
 
     notify_for_services.notified().await;
@@ -47,6 +52,7 @@ pub async fn run_nft_indexer(
     mut tx_commit: Sender<()>,
     pool: PgPool,
     price_reader: Arc<PriceReader>,
+    live_events: broadcast::Sender<LiveEvent>,
 ) {
     log::info!("Start nft indexer...");
 
@@ -94,9 +100,15 @@ pub async fn run_nft_indexer(
         }
 
         let now = std::time::Instant::now();
-        save_to_db(&pool, &price_reader, data, &mut collection_queue)
-            .await
-            .expect("Error saving to DB");
+        save_to_db(
+            &pool,
+            &price_reader,
+            data,
+            &mut collection_queue,
+            &live_events,
+        )
+        .await
+        .expect("Error saving to DB");
         let elapsed = now.elapsed();
 
         log::info!("METRIC | Saving to db, elapsed {}ms", elapsed.as_millis());
@@ -112,6 +124,7 @@ async fn save_to_db(
     price_reader: &PriceReader,
     data: Vec<Decoded>,
     collections_queue: &mut CollectionsQueue,
+    live_events: &broadcast::Sender<LiveEvent>,
 ) -> Result<()> {
     let mut collections = Vec::with_capacity(EVENTS_PER_ITERATION);
     let mut nft_created = Vec::with_capacity(EVENTS_PER_ITERATION);
@@ -129,8 +142,10 @@ async fn save_to_db(
     let mut fees_update = Vec::with_capacity(EVENTS_PER_ITERATION);
     let mut direct_sell_deployed = Vec::with_capacity(EVENTS_PER_ITERATION);
     let mut direct_sell_state_changed = Vec::with_capacity(EVENTS_PER_ITERATION);
+    let mut direct_sell_transitions = Vec::with_capacity(EVENTS_PER_ITERATION);
     let mut direct_buy_deployed = Vec::with_capacity(EVENTS_PER_ITERATION);
     let mut direct_buy_state_changed = Vec::with_capacity(EVENTS_PER_ITERATION);
+    let mut direct_buy_transitions = Vec::with_capacity(EVENTS_PER_ITERATION);
     let mut deployed_offers = Vec::with_capacity(EVENTS_PER_ITERATION);
 
     for element in data {
@@ -163,8 +178,9 @@ async fn save_to_db(
                 direct_sell_deployed.push(ds);
                 deployed_offers.push(offer);
             }
-            Decoded::DirectSellStateChanged((ds, price)) => {
+            Decoded::DirectSellStateChanged((ds, transition, price)) => {
                 direct_sell_state_changed.push(ds);
+                direct_sell_transitions.push(transition);
                 if price.is_some() {
                     prices.push(price.unwrap());
                 }
@@ -173,8 +189,9 @@ async fn save_to_db(
                 direct_buy_deployed.push(db);
                 deployed_offers.push(offer);
             }
-            Decoded::DirectBuyStateChanged((db, price)) => {
+            Decoded::DirectBuyStateChanged((db, transition, price)) => {
                 direct_buy_state_changed.push(db);
+                direct_buy_transitions.push(transition);
                 if price.is_some() {
                     prices.push(price.unwrap());
                 }
@@ -201,8 +218,10 @@ async fn save_to_db(
         fees_update: {},
         direct_sell_deployed: {},
         direct_sell_state_changed: {},
+        direct_sell_transitions: {},
         direct_buy_deployed: {},
         direct_buy_state_changed: {},
+        direct_buy_transitions: {},
         deployed_offers: {},
         "#,
         raw_events.len(),
@@ -221,8 +240,10 @@ async fn save_to_db(
         fees_update.len(),
         direct_sell_deployed.len(),
         direct_sell_state_changed.len(),
+        direct_sell_transitions.len(),
         direct_buy_deployed.len(),
         direct_buy_state_changed.len(),
+        direct_buy_transitions.len(),
         deployed_offers.len(),
     );
 
@@ -236,8 +257,9 @@ async fn save_to_db(
         update_collection_fee(&mut pg_pool_tx, &fees_update).await?;
     }
 
+    let mut event_ids = HashMap::new();
     if !raw_events.is_empty() {
-        save_raw_event(&mut pg_pool_tx, &raw_events).await?;
+        event_ids = save_raw_event(&mut pg_pool_tx, &raw_events).await?;
     }
 
     if !nft_created.is_empty() {
@@ -293,12 +315,28 @@ async fn save_to_db(
         save_direct_sell(&mut pg_pool_tx, &direct_sell_deployed).await?;
     }
 
+    let mut rejected_direct_sell_changes = RejectedStateChanges::new();
     if !direct_sell_state_changed.is_empty() {
-        update_direct_sell_state(&mut pg_pool_tx, &mut direct_sell_state_changed).await?;
+        rejected_direct_sell_changes =
+            update_direct_sell_state(&mut pg_pool_tx, &mut direct_sell_state_changed).await?;
+        save_direct_sell_state_transitions(
+            &mut pg_pool_tx,
+            &direct_sell_transitions,
+            &rejected_direct_sell_changes,
+        )
+        .await?;
     }
 
+    let mut rejected_direct_buy_changes = RejectedStateChanges::new();
     if !direct_buy_state_changed.is_empty() {
-        update_direct_buy_state(&mut pg_pool_tx, &mut direct_buy_state_changed).await?;
+        rejected_direct_buy_changes =
+            update_direct_buy_state(&mut pg_pool_tx, &mut direct_buy_state_changed).await?;
+        save_direct_buy_state_transitions(
+            &mut pg_pool_tx,
+            &direct_buy_transitions,
+            &rejected_direct_buy_changes,
+        )
+        .await?;
     }
 
     if !prices.is_empty() {
@@ -306,15 +344,71 @@ async fn save_to_db(
             price.usd_price = price_reader
                 .get_current_usd_price(
                     price.price_token.as_str(),
-                    price.created_at.timestamp() as u64,
+                    price.completed_at.timestamp() as u64,
                 )
                 .await;
         }
         save_price_history(&mut pg_pool_tx, &prices).await?;
     }
 
+    if !raw_events.is_empty() {
+        let message_hashes = raw_events
+            .iter()
+            .map(|e| e.message_hash.as_str())
+            .collect::<Vec<_>>();
+        enqueue_webhook_notifications(&mut pg_pool_tx, &message_hashes).await?;
+    }
+
     pg_pool_tx.commit().await?;
 
+    // State changes rejected as anomalies are streamed as bare events,
+    // the same way `get_events_since` reads them back from the history
+    let mut state_changes = HashMap::new();
+    for t in direct_sell_transitions {
+        if !rejected_direct_sell_changes.contains(&(t.address.clone(), t.tx_lt, t.to.clone())) {
+            let change = OfferStateChange::DirectSell {
+                from: t.from,
+                to: t.to,
+            };
+            state_changes.insert((t.address, t.tx_lt), change);
+        }
+    }
+    for t in direct_buy_transitions {
+        if !rejected_direct_buy_changes.contains(&(t.address.clone(), t.tx_lt, t.to.clone())) {
+            let change = OfferStateChange::DirectBuy {
+                from: t.from,
+                to: t.to,
+            };
+            state_changes.insert((t.address, t.tx_lt), change);
+        }
+    }
+
+    // Events stored before, e.g. when a batch is replayed, were streamed then
+    let mut committed = raw_events
+        .into_iter()
+        .filter_map(|event| {
+            let id = event_ids.remove(&event.message_hash)?;
+            let state_change = match event.event_type {
+                EventType::DirectSellStateChanged | EventType::DirectBuyStateChanged => {
+                    state_changes.remove(&(event.address.clone(), event.created_lt))
+                }
+                _ => None,
+            };
+
+            Some(LiveEvent {
+                id,
+                event,
+                state_change,
+            })
+        })
+        .collect::<Vec<_>>();
+    committed.sort_by_key(LiveEvent::position);
+
+    for event in committed {
+        // Sending fails only when nobody is subscribed
+        let _ = live_events.send(event);
+    }
+
     Ok(())
 }
 
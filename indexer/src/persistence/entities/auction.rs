@@ -142,12 +142,14 @@ impl Decode for AuctionComplete {
         let auc = decoded::AuctionComplete {
             address: ctx.tx_data.get_account(),
             max_bid: u128_to_bigdecimal(self.value),
+            tx_lt: ctx.tx_data.logical_time() as i64,
         };
 
         let price_hist = decoded::NftPriceHistory {
             source: ctx.tx_data.get_account(),
             source_type: NftPriceSource::AuctionBid,
             created_at: timestamp_to_datetime(self.value2.start_time.try_into()?),
+            completed_at: timestamp_to_datetime(ctx.tx_data.get_timestamp()),
             price: u128_to_bigdecimal(self.value),
             price_token: self.value2.payment_token.to_string(),
             usd_price: None,
@@ -179,6 +181,7 @@ impl Decode for AuctionCancelled {
     fn decode(&self, ctx: &DecodeContext) -> Result<Decoded> {
         let auc = decoded::AuctionCancelled {
             address: ctx.tx_data.get_account(),
+            tx_lt: ctx.tx_data.logical_time() as i64,
         };
 
         Ok(Decoded::AuctionCancelled(auc))
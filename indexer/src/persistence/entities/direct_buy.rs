@@ -12,10 +12,6 @@ impl Decode for DirectBuyStateChanged {
     fn decode(&self, ctx: &DecodeContext) -> Result<Decoded> {
         let state = self.to.into();
 
-        if state == DirectBuyState::Create || state == DirectBuyState::AwaitTokens {
-            return Ok(Decoded::ShouldSkip);
-        }
-
         let finished_at = if state == DirectBuyState::Filled {
             Some(timestamp_to_datetime(ctx.tx_data.get_timestamp()))
         } else {
@@ -27,6 +23,7 @@ impl Decode for DirectBuyStateChanged {
                 source: ctx.tx_data.get_account(),
                 source_type: NftPriceSource::DirectBuy,
                 created_at: finished_at.unwrap(),
+                completed_at: finished_at.unwrap(),
                 price: u128_to_bigdecimal(self.value2._price),
                 price_token: self.value2.spent_token.to_string(),
                 usd_price: None,
@@ -53,7 +50,19 @@ impl Decode for DirectBuyStateChanged {
             tx_lt: ctx.tx_data.logical_time() as i64,
         };
 
-        Ok(Decoded::DirectBuyStateChanged((direct_buy, price_history)))
+        let transition = decoded::DirectBuyStateTransition {
+            address: ctx.tx_data.get_account(),
+            from: self.from.into(),
+            to: direct_buy.state.clone(),
+            tx_lt: direct_buy.tx_lt,
+            created_at: direct_buy.updated,
+        };
+
+        Ok(Decoded::DirectBuyStateChanged((
+            direct_buy,
+            transition,
+            price_history,
+        )))
     }
 
     fn decode_event(&self, ctx: &DecodeContext) -> Result<Decoded> {
@@ -12,10 +12,6 @@ impl Decode for DirectSellStateChanged {
     fn decode(&self, ctx: &DecodeContext) -> Result<Decoded> {
         let state = self.to.into();
 
-        if state == DirectSellState::Create || state == DirectSellState::AwaitNft {
-            return Ok(Decoded::ShouldSkip);
-        }
-
         let finished_at = if state == DirectSellState::Filled {
             Some(timestamp_to_datetime(ctx.tx_data.get_timestamp()))
         } else {
@@ -27,6 +23,7 @@ impl Decode for DirectSellStateChanged {
                 source: ctx.tx_data.get_account(),
                 source_type: NftPriceSource::DirectSell,
                 created_at: finished_at.unwrap(),
+                completed_at: finished_at.unwrap(),
                 price: u128_to_bigdecimal(self.value2._price),
                 price_token: self.value2.token.to_string(),
                 usd_price: None,
@@ -53,8 +50,17 @@ impl Decode for DirectSellStateChanged {
             tx_lt: ctx.tx_data.logical_time() as i64,
         };
 
+        let transition = decoded::DirectSellStateTransition {
+            address: ctx.tx_data.get_account(),
+            from: self.from.into(),
+            to: direct_sell.state.clone(),
+            tx_lt: direct_sell.tx_lt,
+            created_at: direct_sell.updated,
+        };
+
         Ok(Decoded::DirectSellStateChanged((
             direct_sell,
+            transition,
             price_history,
         )))
     }
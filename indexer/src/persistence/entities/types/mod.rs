@@ -15,7 +15,13 @@ pub enum Decoded {
     RawEventRecord(EventRecord),
     AuctionRulesChanged(CollectionFee),
     DirectBuyDeployed((DirectBuy, OfferDeployed)),
-    DirectBuyStateChanged((DirectBuy, Option<NftPriceHistory>)),
+    DirectBuyStateChanged((DirectBuy, DirectBuyStateTransition, Option<NftPriceHistory>)),
     DirectSellDeployed((DirectSell, OfferDeployed)),
-    DirectSellStateChanged((DirectSell, Option<NftPriceHistory>)),
+    DirectSellStateChanged(
+        (
+            DirectSell,
+            DirectSellStateTransition,
+            Option<NftPriceHistory>,
+        ),
+    ),
 }
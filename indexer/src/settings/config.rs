@@ -1,3 +1,4 @@
+use data_reader::{FiatRatesConfig, MediaConfig, OffchainMetaConfig, PriceProviderConfig};
 use indexer_repo::types::BcName;
 use serde::Deserialize;
 use std::collections::HashMap;
@@ -13,12 +14,31 @@ pub struct Config {
     pub states_rpc_endpoints: Vec<Url>,
     pub kafka_settings: HashMap<String, String>,
     pub server_api_url: String,
+    /// Keys of the clients allowed to manage webhooks, see `X-Api-Key`
+    pub api_keys: Option<Vec<String>>,
     pub terminate_open_connections: Option<bool>,
     pub jrpc_req_latency_millis: u64,
+    /// Request rate limit of every states endpoint
+    pub jrpc_requests_per_sec: u32,
+    pub meta_reader_concurrency: usize,
     pub bc_name: BcName,
     pub idle_after_price_loop_sec: u64,
     pub idle_after_meta_loop_sec: u64,
+    pub idle_after_expiry_loop_sec: u64,
+    pub idle_after_webhook_loop_sec: u64,
+    pub idle_after_stats_loop_sec: u64,
+    pub idle_after_rarity_loop_sec: u64,
+    pub idle_after_token_loop_sec: u64,
+    pub webhook_auction_ending_window_sec: u64,
     pub price_update_frequency_sec: u64,
+    pub price_request_interval_ms: u64,
+    pub price_providers: HashMap<String, PriceProviderConfig>,
+    /// Fiat rates are not updated without a provider
+    pub fiat_rates: Option<FiatRatesConfig>,
+    /// Metadata is read only on-chain without it
+    pub offchain_meta: Option<OffchainMetaConfig>,
+    /// Media is not copied without a storage
+    pub media: Option<MediaConfig>,
 }
 
 impl Default for Config {
@@ -34,6 +54,8 @@ impl Config {
                 .separator("__")
                 .list_separator(",")
                 .with_list_parse_key("states_rpc_endpoints")
+                .with_list_parse_key("api_keys")
+                .with_list_parse_key("offchain_meta.schemes")
                 .try_parsing(true),
         );
         if std::path::Path::new("Settings.toml").exists() {
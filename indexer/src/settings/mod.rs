@@ -2,14 +2,13 @@ use crate::abi::declare_abi::*;
 use crate::abi::scope;
 use crate::settings::config::Config;
 use anyhow::Result;
+use data_reader::JrpcPool;
 use sqlx::PgPool;
 use std::{collections::HashMap, sync::Arc};
 use transaction_buffer::models::{
     AnyExtractable, BufferedConsumerChannels, BufferedConsumerConfig,
 };
 use transaction_buffer::start_parsing_and_get_channels;
-use transaction_consumer::JrpcClient;
-use transaction_consumer::JrpcClientOptions;
 use transaction_consumer::{ConsumerOptions, TransactionConsumer};
 
 pub mod config;
@@ -105,10 +104,11 @@ fn get_extractable_name(extractable: &AnyExtractable) -> String {
     }
 }
 
-pub async fn get_jrpc_client(config: &Config) -> Result<JrpcClient> {
-    JrpcClient::new(
+/// One client per states endpoint, so each of them is rate limited on its own.
+/// Endpoints unreachable at start are connected once they are up.
+pub async fn get_jrpc_pool(config: &Config) -> Result<Arc<JrpcPool>> {
+    Ok(Arc::new(JrpcPool::new(
         config.states_rpc_endpoints.clone(),
-        JrpcClientOptions::default(),
-    )
-    .await
+        config.jrpc_requests_per_sec,
+    )?))
 }